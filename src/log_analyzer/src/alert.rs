@@ -0,0 +1,112 @@
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sigma_log_parser::log_event::LogEvent;
+use sigma_rule_parser::structs::sigma_rule::SigmaRule;
+use crate::rule_set::RuleMatch;
+
+/// A rule that matched a log event, with what is needed to act on it without the rule: the fields of the event that the rule
+///     looked at, and the event itself. Alerts are written to the sinks as a JSON object.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Alert {
+    pub id: String,
+    pub title: String,
+    pub level: String,
+    pub tags: Vec<String>,
+    /// The time the event was evaluated at, in RFC 3339 format (UTC)
+    pub timestamp: String,
+    /// The values of the fields of the Detection by their name in the rule, or the group-by values of a correlation
+    pub matched_fields: BTreeMap<String, Value>,
+    pub event: LogEvent,
+}
+
+impl Alert {
+    pub fn new(rule: &SigmaRule, matched_fields: BTreeMap<String, Value>, event: &LogEvent, timestamp: SystemTime) -> Alert {
+        Alert {
+            id: rule.id.clone(),
+            title: rule.title.clone(),
+            level: rule.level.clone(),
+            tags: rule.tags.clone(),
+            timestamp: format_timestamp(timestamp),
+            matched_fields,
+            event: event.clone(),
+        }
+    }
+}
+
+impl From<&Alert> for RuleMatch {
+    fn from(alert: &Alert) -> Self {
+        Self {
+            id: alert.id.clone(),
+            title: alert.title.clone(),
+            level: alert.level.clone(),
+        }
+    }
+}
+
+/// Formats a time as `2024-01-31T12:00:00.000Z`. Times before 1970 are formatted as the epoch.
+fn format_timestamp(timestamp: SystemTime) -> String {
+    let since_epoch = timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (days, time) = (seconds / 86_400, seconds % 86_400);
+
+    // the civil date of a day since the epoch, see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year, month, day, time / 3600, time % 3600 / 60, time % 60, since_epoch.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use serde_json::json;
+    use super::*;
+
+    #[test]
+    fn format_timestamps() {
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        assert_eq!(format_timestamp(UNIX_EPOCH + Duration::from_millis(951_782_400_123)), "2000-02-29T00:00:00.123Z");
+        assert_eq!(format_timestamp(UNIX_EPOCH + Duration::from_secs(1_704_067_199)), "2023-12-31T23:59:59.000Z");
+        assert_eq!(format_timestamp(UNIX_EPOCH - Duration::from_secs(1)), "1970-01-01T00:00:00.000Z");
+    }
+
+    #[test]
+    fn serialize_alerts() {
+        let rule: SigmaRule = serde_yaml::from_str(r#"
+            title: Whoami
+            id: whoami
+            level: low
+            tags:
+              - attack.discovery
+            detection:
+              selection:
+                Image|endswith: '\whoami.exe'
+              condition: selection
+        "#).unwrap();
+        let event = LogEvent::from(json!({"Image": "C:\\whoami.exe", "User": "admin"}));
+        let alert = Alert::new(&rule, BTreeMap::from([("Image".to_string(), json!("C:\\whoami.exe"))]), &event, UNIX_EPOCH);
+
+        assert_eq!(serde_json::to_value(&alert).unwrap(), json!({
+            "id": "whoami",
+            "title": "Whoami",
+            "level": "low",
+            "tags": ["attack.discovery"],
+            "timestamp": "1970-01-01T00:00:00.000Z",
+            "matched_fields": {"Image": "C:\\whoami.exe"},
+            "event": {"Image": "C:\\whoami.exe", "User": "admin"},
+        }));
+        assert_eq!(RuleMatch::from(&alert), RuleMatch::from(&rule));
+    }
+}
@@ -0,0 +1,144 @@
+pub mod explain;
+pub mod scan;
+pub mod serve;
+pub mod validate;
+
+use std::path::PathBuf;
+use anyhow::Error;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use sigma_rule_parser::structs::sigma_rule::Logsource;
+use crate::cli::scan::ScanOptions;
+use crate::config::RoutingConfig;
+
+/// Evaluates Sigma rules against log events.
+#[derive(Parser, Debug)]
+#[command(name = "log-analyzer", version)]
+pub struct Cli {
+    /// The log4rs config file. Logging is disabled when the file can not be read.
+    #[arg(long, global = true, default_value = "config/log4rs.yaml")]
+    pub log_config: PathBuf,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand, Debug, PartialEq)]
+pub enum Command {
+    /// Loads and builds every rule of a rules directory, and prints why each rule that can not be used was skipped
+    Validate {
+        rules_dir: PathBuf,
+        #[arg(long, value_enum, default_value_t = ReportFormat::Table)]
+        format: ReportFormat,
+    },
+    /// Evaluates every event of the log files of a path against the rules of a rules directory, and prints the matches with their file and line.
+    /// A directory is searched for JSON (.json, .ndjson, .jsonl) and CSV (.csv) files, which may be compressed (.gz)
+    Scan {
+        rules_dir: PathBuf,
+        path: PathBuf,
+        /// The logsource of the events, so they are only evaluated against the rules of the logsource. An event may name its own logsource in its logsource field.
+        #[command(flatten)]
+        logsource: LogsourceArgs,
+        /// A bundled field mapping profile (ecs, sysmon, zeek or cloudtrail) that matches the schema of the log file, may be repeated
+        #[arg(long)]
+        profile: Vec<String>,
+        /// A field mapping file that translates the field names of the rules to the fields of the log file, may be repeated
+        #[arg(long)]
+        field_mappings: Vec<String>,
+        /// The number of threads that evaluate the events, the number of CPU cores by default
+        #[arg(long)]
+        threads: Option<usize>,
+        #[arg(long, value_enum, default_value_t = ScanFormat::Text)]
+        format: ScanFormat,
+        /// Skips the rules with an aggregation and the correlations, which are otherwise evaluated in the order of the events on a single thread
+        #[arg(long)]
+        stateless: bool,
+    },
+    /// Runs the HTTP server that evaluates the log events it receives
+    Serve {
+        #[arg(long, default_value = "config/log_analyzer.yml")]
+        config: PathBuf,
+    },
+    /// Prints how the rules of a rule file are parsed, and why they can not be built
+    Explain {
+        rule_file: PathBuf,
+    },
+}
+
+#[derive(Args, Debug, Default, PartialEq)]
+pub struct LogsourceArgs {
+    #[arg(long, default_value = "")]
+    pub category: String,
+    #[arg(long, default_value = "")]
+    pub product: String,
+    #[arg(long, default_value = "")]
+    pub service: String,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ReportFormat {
+    Table,
+    Json,
+}
+
+/// How the matches of a scan are printed, either as `file:line: [level] title (id)` or as a JSON object per line.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ScanFormat {
+    Text,
+    Json,
+}
+
+pub fn run(cli: Cli) -> Result<(), Error> {
+    match cli.command {
+        Command::Validate { rules_dir, format } => validate::run(&rules_dir, format),
+        Command::Scan { rules_dir, path, logsource, profile, field_mappings, threads, format, stateless } => {
+            let logsource = Logsource { category: logsource.category, product: logsource.product, service: logsource.service, ..Default::default() };
+            let options = ScanOptions { logsource, fields: RoutingConfig::default().fields, threads: threads.unwrap_or(0), format, stateless };
+            scan::run(&rules_dir, &path, &profile, &field_mappings, options)
+        }
+        Command::Serve { config } => serve::run(&config),
+        Command::Explain { rule_file } => explain::run(&rule_file),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_subcommands() {
+        let cli = Cli::try_parse_from(["log-analyzer", "validate", "rules", "--format", "json"]).unwrap();
+        assert_eq!(cli.command, Command::Validate { rules_dir: PathBuf::from("rules"), format: ReportFormat::Json });
+        assert_eq!(cli.log_config, PathBuf::from("config/log4rs.yaml"));
+
+        let cli = Cli::try_parse_from(["log-analyzer", "scan", "rules", "logs", "--log-config", "log4rs.yaml", "--product", "windows", "--profile", "ecs", "--field-mappings", "custom.yml", "--threads", "4", "--stateless"]).unwrap();
+        assert_eq!(
+            cli.command,
+            Command::Scan {
+                rules_dir: PathBuf::from("rules"),
+                path: PathBuf::from("logs"),
+                logsource: LogsourceArgs { product: "windows".to_string(), ..Default::default() },
+                profile: vec!["ecs".to_string()],
+                field_mappings: vec!["custom.yml".to_string()],
+                threads: Some(4),
+                format: ScanFormat::Text,
+                stateless: true,
+            }
+        );
+        assert_eq!(cli.log_config, PathBuf::from("log4rs.yaml"));
+
+        let cli = Cli::try_parse_from(["log-analyzer", "serve"]).unwrap();
+        assert_eq!(cli.command, Command::Serve { config: PathBuf::from("config/log_analyzer.yml") });
+
+        let cli = Cli::try_parse_from(["log-analyzer", "explain", "rule.yml"]).unwrap();
+        assert_eq!(cli.command, Command::Explain { rule_file: PathBuf::from("rule.yml") });
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        assert!(Cli::try_parse_from(["log-analyzer"]).is_err());
+        assert!(Cli::try_parse_from(["log-analyzer", "validate"]).is_err());
+        assert!(Cli::try_parse_from(["log-analyzer", "validate", "rules", "--format", "xml"]).is_err());
+        assert!(Cli::try_parse_from(["log-analyzer", "scan", "rules"]).is_err());
+        assert!(Cli::try_parse_from(["log-analyzer", "scan", "rules", "logs", "--format", "table"]).is_err());
+    }
+}
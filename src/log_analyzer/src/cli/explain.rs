@@ -0,0 +1,266 @@
+use std::fmt::Write;
+use std::path::Path;
+use anyhow::Error;
+use sigma_rule_parser::detection_builder::{build, build_conditions};
+use sigma_rule_parser::sigma_file::sigma_rule::{load_sigma_rules, read_condition};
+use sigma_rule_parser::structs::detection::Detection;
+use sigma_rule_parser::structs::detection_condition::Operator;
+use sigma_rule_parser::structs::detection_field::DetectionField;
+use sigma_rule_parser::structs::detection_logic::DetectionLogic;
+use sigma_rule_parser::structs::sigma_rule::SigmaRule;
+
+pub fn run(rule_file: &Path) -> Result<(), Error> {
+    let (sigma_rules, report) = load_sigma_rules(rule_file.display().to_string())?;
+
+    for entry in report.failures() {
+        println!("{} could not be loaded - {}\n", entry.path, entry.reason.as_deref().unwrap_or_default());
+    }
+    for rule in &sigma_rules {
+        println!("{}", explain(rule));
+    }
+
+    Ok(())
+}
+
+/// Describes a Sigma rule as it is understood by the log analyzer: the Detection tree that the condition was parsed into,
+///     the values each search identifier compares, and the aggregation or correlation of the rule.
+/// A rule that can not be built is described with the reason.
+pub fn explain(rule: &SigmaRule) -> String {
+    let mut explanation = String::new();
+    let _ = writeln!(explanation, "Rule: {} ({})", rule.title, rule.id);
+    if !rule.level.is_empty() {
+        let _ = writeln!(explanation, "Level: {}", rule.level);
+    }
+
+    let logsource = [("category", &rule.logsource.category), ("product", &rule.logsource.product), ("service", &rule.logsource.service)]
+        .iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<String>>();
+    if !logsource.is_empty() {
+        let _ = writeln!(explanation, "Logsource: {}", logsource.join(", "));
+    }
+
+    if let Some(correlation) = rule.correlation.as_ref() {
+        let _ = writeln!(explanation, "Correlation: {:?} of {} within {}", correlation.correlation_type, correlation.rules.join(", "), correlation.timespan);
+        if !correlation.group_by.is_empty() {
+            let _ = writeln!(explanation, "Group by: {}", correlation.group_by.join(", "));
+        }
+        if let Some(condition) = correlation.condition.as_ref() {
+            let _ = writeln!(explanation, "Condition: {:?}", condition);
+        }
+        return explanation;
+    }
+
+    if let Some(Ok(condition)) = rule.detection.get("condition").map(read_condition) {
+        let _ = writeln!(explanation, "Condition: {}", condition);
+    }
+
+    match build(rule.clone()) {
+        Ok(detection) => {
+            let _ = writeln!(explanation, "Detection:");
+            explain_detection(&detection, 1, &mut explanation);
+        }
+        Err(err) => {
+            let _ = writeln!(explanation, "Can not be built - {}", err);
+            return explanation;
+        }
+    }
+
+    let conditions = build_conditions(rule).unwrap_or_default();
+    for (number, aggregation) in conditions.iter().enumerate().filter_map(|(number, (_, aggregation))| Some((number + 1, aggregation.as_ref()?))) {
+        if conditions.len() > 1 {
+            let _ = write!(explanation, "Condition {} - ", number);
+        }
+        let _ = write!(
+            explanation,
+            "Aggregation: {:?}({}) by {} {:?} {}",
+            aggregation.function,
+            aggregation.field.as_deref().unwrap_or_default(),
+            aggregation.group_by.as_deref().unwrap_or("-"),
+            aggregation.operator,
+            aggregation.threshold,
+        );
+        match aggregation.timeframe {
+            Some(timeframe) => { let _ = writeln!(explanation, " within {}s", timeframe.as_secs()); }
+            None => { let _ = writeln!(explanation); }
+        }
+    }
+
+    explanation
+}
+
+/// Every condition is written on its own line, prefixed with the operator that joins it to the condition before it.
+/// Parentheses and quantifiers are written with their conditions nested below them.
+fn explain_detection(detection: &Detection, depth: usize, explanation: &mut String) {
+    for (index, condition) in detection.conditions.iter().flatten().enumerate() {
+        // conditions without an operator are joined by the operator of the detection, as they are in the evaluator
+        let operator = match condition.operator.as_ref().or(detection.operator.as_ref().filter(|_| index > 0)) {
+            Some(Operator::And) => "and ",
+            Some(Operator::Or) => "or ",
+            None => "",
+        };
+        let negation = if condition.is_negated == Some(true) { "not " } else { "" };
+
+        match (condition.nested_detections.as_ref(), condition.search_identifier.as_ref()) {
+            (Some(nested_detection), _) => {
+                let _ = writeln!(explanation, "{}{}{}({})", indent(depth), operator, negation, condition_text(&condition.metadata.parser_result));
+                explain_detection(nested_detection, depth + 1, explanation);
+            }
+            (None, Some(search_identifier)) => {
+                let _ = writeln!(explanation, "{}{}{}{}", indent(depth), operator, negation, search_identifier);
+                explain_logic(&condition.detection_logic, depth + 1, explanation);
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+/// The parser result of a condition starts with the operator and negation that were parsed with it, which are already written.
+fn condition_text(parser_result: &str) -> &str {
+    let mut text = parser_result.trim();
+    while let Some((word, remaining)) = text.split_once(' ') {
+        match word.to_lowercase().as_str() {
+            "and" | "or" | "not" => text = remaining.trim_start(),
+            _ => break,
+        }
+    }
+    text
+}
+
+fn explain_logic(logic: &DetectionLogic, depth: usize, explanation: &mut String) {
+    if let Some(value) = logic.value.as_ref() {
+        let _ = writeln!(explanation, "{}'{}'", indent(depth), value);
+    }
+
+    for (field, logic) in logic.and.iter().flatten() {
+        match values(logic) {
+            Some(values) => { let _ = writeln!(explanation, "{}{}: {}", indent(depth), field_name(field), values); }
+            None => {
+                let _ = writeln!(explanation, "{}{}:", indent(depth), field_name(field));
+                explain_logic(logic, depth + 1, explanation);
+            }
+        }
+    }
+
+    if let Some(or) = logic.or.as_ref() {
+        let _ = writeln!(explanation, "{}any of:", indent(depth));
+        for logic in or {
+            explain_logic(logic, depth + 1, explanation);
+        }
+    }
+}
+
+/// The values of a field are written on a single line, when the field compares values instead of nested logic.
+fn values(logic: &DetectionLogic) -> Option<String> {
+    if let Some(value) = logic.value.as_ref() {
+        return Some(format!("'{}'", value));
+    }
+    if logic.is_null() {
+        return Some("null".to_string());
+    }
+
+    let values = logic.or.as_ref()?
+        .iter()
+        .map(|logic| match (logic.value.as_ref(), logic.and.as_ref(), logic.or.as_ref()) {
+            (Some(value), None, None) => Some(format!("'{}'", value)),
+            (None, None, None) => Some("null".to_string()),
+            _ => None,
+        })
+        .collect::<Option<Vec<String>>>()?;
+
+    Some(format!("[{}]", values.join(", ")))
+}
+
+fn field_name(field: &DetectionField) -> String {
+    let mut name = vec![field.name.clone()];
+    name.extend(field.modifiers.iter().map(|modifier| format!("{:?}", modifier).to_lowercase()));
+    name.join("|")
+}
+
+fn indent(depth: usize) -> String {
+    "  ".repeat(depth)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn explain_rule() {
+        let rule: SigmaRule = serde_yaml::from_str(r#"
+            title: Whoami
+            id: whoami
+            level: low
+            logsource:
+              category: process_creation
+              product: windows
+            detection:
+              selection:
+                Image|endswith:
+                  - '\whoami.exe'
+                  - '\id.exe'
+              filter:
+                User: SYSTEM
+              condition: selection and not filter
+        "#).unwrap();
+
+        assert_eq!(
+            explain(&rule),
+            [
+                "Rule: Whoami (whoami)",
+                "Level: low",
+                "Logsource: category=process_creation, product=windows",
+                "Condition: selection and not filter",
+                "Detection:",
+                "  selection",
+                "    Image|endswith: ['\\whoami.exe', '\\id.exe']",
+                "  and not filter",
+                "    User: 'SYSTEM'",
+                "",
+            ].join("\n")
+        );
+    }
+
+    #[test]
+    fn explain_rule_that_can_not_be_built() {
+        let rule: SigmaRule = serde_yaml::from_str(r#"
+            title: Unsupported
+            id: unsupported
+            detection:
+              selection:
+                CommandLine|expand: '%ProgramData%'
+              condition: selection
+        "#).unwrap();
+
+        assert!(explain(&rule).ends_with("Can not be built - unsupported value modifier 'expand'\n"));
+    }
+
+    #[test]
+    fn explain_quantifier() {
+        let rule: SigmaRule = serde_yaml::from_str(r#"
+            title: Quantifier
+            id: quantifier
+            detection:
+              selection:
+                Image|endswith: '\cmd.exe'
+              filter_bat:
+                CommandLine|contains: ' /c '
+              filter_null:
+                CommandLine: ''
+              condition: selection and not 1 of filter_*
+        "#).unwrap();
+
+        assert!(explain(&rule).ends_with(&[
+            "Detection:",
+            "  selection",
+            "    Image|endswith: '\\cmd.exe'",
+            "  and not (1 of filter_*)",
+            "    filter_bat",
+            "      CommandLine|contains: ' /c '",
+            "    or filter_null",
+            "      CommandLine: ''",
+            "",
+        ].join("\n")));
+    }
+}
@@ -0,0 +1,409 @@
+mod log_file;
+mod timestamp;
+
+use std::collections::BTreeMap;
+use std::io::{stdout, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex, PoisonError, RwLock};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::{anyhow, Error};
+use log::{info, warn};
+use serde::Serialize;
+use sigma_log_parser::log_event::LogEvent;
+use sigma_rule_parser::structs::sigma_rule::Logsource;
+use walkdir::WalkDir;
+use crate::cli::ScanFormat;
+use crate::config::LogsourceFields;
+use crate::field_mappings::FieldMappings;
+use crate::routing::event_logsource;
+use crate::rule_set::{RuleMatch, RuleSet};
+use self::log_file::{LogFormat, Record};
+use self::timestamp::event_timestamp;
+
+/// The number of records that are read from a file before they are handed to a thread to evaluate.
+const BATCH_SIZE: usize = 256;
+
+/// The number of events that were read from the log files, and how many of them matched.
+#[derive(Default, Debug, PartialEq)]
+pub struct ScanSummary {
+    pub files: usize,
+    pub failed_files: usize,
+    pub events: usize,
+    pub invalid_events: usize,
+    pub matches: usize,
+}
+
+/// How the log files are scanned.
+#[derive(Debug)]
+pub struct ScanOptions {
+    /// The logsource of the events, unless the fields of an event name another logsource
+    pub logsource: Logsource,
+    pub fields: LogsourceFields,
+    /// The number of threads that evaluate the events, 0 for the number of CPU cores
+    pub threads: usize,
+    pub format: ScanFormat,
+    /// Skips the rules with an aggregation and the correlations, which are evaluated in the order of the events on a single thread
+    pub stateless: bool,
+}
+
+/// A rule that matched the event of a line of a log file.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct FileMatch {
+    pub file: String,
+    pub line: usize,
+    #[serde(flatten)]
+    pub rule: RuleMatch,
+}
+
+/// The records of a file that a thread evaluates. The sequence is the order of the batch, in which the matches are written.
+struct Batch {
+    sequence: usize,
+    file: Arc<str>,
+    records: Vec<Record>,
+}
+
+#[derive(Default)]
+struct BatchResult {
+    file: Arc<str>,
+    events: usize,
+    invalid_events: usize,
+    matches: Vec<FileMatch>,
+    /// The events that the stateful rules are evaluated against once the batch is next in order, with their line and logsource
+    stateful_events: Vec<(usize, LogEvent, Logsource)>,
+}
+
+pub fn run(rules_dir: &Path, path: &Path, field_mapping_profiles: &[String], field_mapping_files: &[String], options: ScanOptions) -> Result<(), Error> {
+    let field_mappings = FieldMappings::load(field_mapping_profiles, field_mapping_files)?;
+    let mut rule_set = RuleSet::load(&rules_dir.display().to_string())?.with_field_mappings(&field_mappings);
+    let stateful = rule_set.rules().iter().filter(|compiled_rule| compiled_rule.is_stateful()).count();
+    match options.stateless {
+        true => info!("Loaded {} rules, {} of them with an aggregation or a correlation that are skipped", rule_set.len(), stateful),
+        false => info!("Loaded {} rules, {} of them with an aggregation or a correlation that are evaluated in the order of the events", rule_set.len(), stateful),
+    }
+
+    let files = log_files(path)?;
+    let threads = match options.threads {
+        0 => thread::available_parallelism().map(usize::from).unwrap_or(1),
+        threads => threads,
+    };
+    let options = ScanOptions { threads, ..options };
+    let summary = scan(&mut rule_set, &files, &options, &mut stdout().lock())?;
+    info!(
+        "Scanned {} events ({} invalid) of {} files ({} failed), {} matches",
+        summary.events, summary.invalid_events, summary.files, summary.failed_files, summary.matches
+    );
+
+    Ok(())
+}
+
+/// The log files of a path. A file is always scanned, of a directory only the files of a known format are, see LogFormat.
+pub fn log_files(path: &Path) -> Result<Vec<PathBuf>, Error> {
+    let metadata = path.metadata().map_err(|err| anyhow!("Could not read {} - {}", path.display(), err))?;
+    if !metadata.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let mut files = Vec::new();
+    for entry in WalkDir::new(path).follow_links(true).sort_by_file_name() {
+        let entry = entry?;
+        if entry.file_type().is_file() && LogFormat::of(entry.path()).is_some() {
+            files.push(entry.into_path());
+        }
+    }
+    Ok(files)
+}
+
+/// Evaluates the events of every log file against the Detections of the rules, and writes a line for every rule that matched an event.
+/// The files are read on a thread of their own, while the events are evaluated on the given number of threads.
+/// The matches are written in the order of the files and their lines. Files of an unknown format are read as newline delimited JSON.
+///
+/// Rules with an aggregation and correlations need the events in order, so they are evaluated as the results of the threads are written,
+///     at the time of each event (see event_timestamp). Events without a time are placed at the time of the event before them.
+pub fn scan<W: Write>(rule_set: &mut RuleSet, files: &[PathBuf], options: &ScanOptions, output: &mut W) -> Result<ScanSummary, Error> {
+    let mut summary = ScanSummary { files: files.len(), ..ScanSummary::default() };
+    let stateful = !options.stateless && rule_set.has_stateful_rules();
+    let mut timestamp = UNIX_EPOCH;
+    // the threads detect with a shared RuleSet, which is only written to between batches
+    let rule_set = RwLock::new(rule_set);
+    let (batch_sender, batch_receiver) = mpsc::sync_channel::<Batch>(options.threads * 2);
+    let (result_sender, result_receiver) = mpsc::channel::<(usize, BatchResult)>();
+    let batch_receiver = Arc::new(Mutex::new(batch_receiver));
+
+    thread::scope(|scope| {
+        let reader = scope.spawn(move || read_files(files, batch_sender));
+
+        for _ in 0..options.threads.max(1) {
+            let batch_receiver = batch_receiver.clone();
+            let result_sender = result_sender.clone();
+            let rule_set = &rule_set;
+            scope.spawn(move || loop {
+                // the lock is released before the batch is evaluated
+                let batch = batch_receiver.lock().unwrap_or_else(PoisonError::into_inner).recv();
+                let batch = match batch {
+                    Ok(batch) => batch,
+                    Err(_) => return,
+                };
+                let sequence = batch.sequence;
+                let result = evaluate_batch(&rule_set.read().unwrap_or_else(PoisonError::into_inner), batch, options, stateful);
+                if result_sender.send((sequence, result)).is_err() {
+                    return;
+                }
+            });
+        }
+        // the workers hold the only receivers and senders, so the channels close once the workers return
+        drop(batch_receiver);
+        drop(result_sender);
+
+        let mut pending = BTreeMap::new();
+        let mut next = 0;
+        for (sequence, result) in result_receiver {
+            pending.insert(sequence, result);
+            while let Some(mut result) = pending.remove(&next) {
+                if !result.stateful_events.is_empty() {
+                    evaluate_stateful(&mut rule_set.write().unwrap_or_else(PoisonError::into_inner), &mut result, &mut timestamp);
+                }
+                summary.events += result.events;
+                summary.invalid_events += result.invalid_events;
+                summary.matches += result.matches.len();
+                write_matches(output, &result.matches, options.format)?;
+                next += 1;
+            }
+        }
+
+        summary.failed_files = reader.join().map_err(|_| anyhow!("Reading the log files failed"))?;
+        Ok(summary)
+    })
+}
+
+/// Reads the records of every file into batches, and returns the number of files that could not be read to the end.
+fn read_files(files: &[PathBuf], sender: mpsc::SyncSender<Batch>) -> usize {
+    let mut sequence = 0;
+    let mut failed_files = 0;
+
+    for path in files {
+        let file: Arc<str> = Arc::from(path.display().to_string());
+        let format = LogFormat::of(path).unwrap_or(LogFormat::Json);
+        let records = match log_file::open(path).and_then(|reader| log_file::read_records(reader, format)) {
+            Ok(records) => records,
+            Err(err) => {
+                warn!("Could not read {} - {}", file, err);
+                failed_files += 1;
+                continue;
+            }
+        };
+
+        let mut send = |records: Vec<Record>| {
+            sequence += 1;
+            sender.send(Batch { sequence: sequence - 1, file: file.clone(), records }).is_ok()
+        };
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        for record in records {
+            match record {
+                Ok(record) => batch.push(record),
+                Err(err) => {
+                    warn!("Stopped reading {} - {}", file, err);
+                    failed_files += 1;
+                    break;
+                }
+            }
+            if batch.len() == BATCH_SIZE && !send(std::mem::replace(&mut batch, Vec::with_capacity(BATCH_SIZE))) {
+                return failed_files;
+            }
+        }
+        if !batch.is_empty() && !send(batch) {
+            return failed_files;
+        }
+    }
+
+    failed_files
+}
+
+fn evaluate_batch(rule_set: &RuleSet, batch: Batch, options: &ScanOptions, stateful: bool) -> BatchResult {
+    let mut result = BatchResult { file: batch.file.clone(), ..BatchResult::default() };
+
+    for record in batch.records {
+        result.events += 1;
+        let event = match record.event {
+            Ok(event) => event,
+            Err(err) => {
+                warn!("Skipping {} line {} - {}", batch.file, record.line, err);
+                result.invalid_events += 1;
+                continue;
+            }
+        };
+
+        let logsource = event_logsource(&options.fields, &event, &options.logsource);
+        for rule in rule_set.detect(&event, &logsource) {
+            result.matches.push(FileMatch { file: batch.file.to_string(), line: record.line, rule });
+        }
+        if stateful {
+            result.stateful_events.push((record.line, event, logsource));
+        }
+    }
+
+    result
+}
+
+/// Evaluates the events of a batch against the stateful rules, and merges their matches into the matches of the batch by line.
+/// The timestamp is the time of the last event that had one.
+fn evaluate_stateful(rule_set: &mut RuleSet, result: &mut BatchResult, timestamp: &mut SystemTime) {
+    for (line, event, logsource) in result.stateful_events.drain(..) {
+        *timestamp = event_timestamp(&event).unwrap_or(*timestamp);
+        for alert in rule_set.stateful_alerts(&event, &logsource, *timestamp) {
+            result.matches.push(FileMatch { file: result.file.to_string(), line, rule: RuleMatch::from(&alert) });
+        }
+    }
+    result.matches.sort_by_key(|file_match| file_match.line);
+}
+
+fn write_matches<W: Write>(output: &mut W, matches: &[FileMatch], format: ScanFormat) -> Result<(), Error> {
+    for file_match in matches {
+        match format {
+            ScanFormat::Text => {
+                let FileMatch { file, line, rule } = file_match;
+                writeln!(output, "{}:{}: [{}] {} ({})", file, line, rule.level, rule.title, rule.id)?;
+            }
+            ScanFormat::Json => writeln!(output, "{}", serde_json::to_string(file_match)?)?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Write as _;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use sigma_rule_parser::sigma_file::load_report::LoadReport;
+    use sigma_rule_parser::structs::sigma_rule::SigmaRule;
+    use crate::config::RoutingConfig;
+    use super::*;
+
+    fn rule_set() -> RuleSet {
+        let rules = [
+            r#"
+                title: Whoami
+                id: whoami
+                level: low
+                logsource:
+                  product: windows
+                detection:
+                  selection:
+                    Image|endswith: '\whoami.exe'
+                  condition: selection
+            "#,
+            r#"
+                title: Many whoami
+                id: many_whoami
+                detection:
+                  selection:
+                    Image|endswith: '\whoami.exe'
+                  condition: selection | count() > 1
+            "#,
+        ];
+        let rules = rules.iter().map(|rule| serde_yaml::from_str::<SigmaRule>(rule).unwrap()).collect();
+        RuleSet::from_rules(rules, LoadReport::default())
+    }
+
+    fn options(threads: usize, format: ScanFormat) -> ScanOptions {
+        ScanOptions { logsource: Logsource::default(), fields: RoutingConfig::default().fields, threads, format, stateless: true }
+    }
+
+    #[test]
+    fn scan_log_files_of_directory() {
+        let dir = std::env::temp_dir().join(format!("log_analyzer_scan_{}", std::process::id()));
+        fs::create_dir_all(dir.join("archive")).unwrap();
+
+        // enough lines for many batches, which are evaluated out of order and written in order
+        let mut lines = vec!["{\"Image\": \"cmd.exe\"}"; 1000];
+        lines[0] = "{\"Image\": \"C:\\\\whoami.exe\"}";
+        lines[600] = "{\"Image\": \"C:\\\\whoami.exe\", \"logsource\": {\"product\": \"linux\"}}";
+        lines[999] = "{\"Image\": \"C:\\\\whoami.exe\"}";
+        fs::write(dir.join("a.ndjson"), lines.join("\n")).unwrap();
+
+        let mut encoder = GzEncoder::new(fs::File::create(dir.join("archive").join("b.csv.gz")).unwrap(), Compression::default());
+        encoder.write_all(b"Image,CommandLine\nC:\\cmd.exe,\"cmd /c\nwhoami\"\nC:\\whoami.exe,whoami\na,b,c\n").unwrap();
+        encoder.finish().unwrap();
+        fs::write(dir.join("c.json"), "[\n  {\"Image\": \"C:\\\\whoami.exe\"}\n]").unwrap();
+        fs::write(dir.join("notes.txt"), "not a log").unwrap();
+
+        let files = log_files(&dir).unwrap();
+        assert_eq!(files, vec![dir.join("a.ndjson"), dir.join("archive").join("b.csv.gz"), dir.join("c.json")]);
+
+        let mut output = Vec::new();
+        let summary = scan(&mut rule_set(), &files, &options(4, ScanFormat::Text), &mut output).unwrap();
+        assert_eq!(summary, ScanSummary { files: 3, failed_files: 0, events: 1004, invalid_events: 1, matches: 4 });
+
+        let file = |name: &str| dir.join(name).display().to_string();
+        let expected = [
+            format!("{}:1: [low] Whoami (whoami)", file("a.ndjson")),
+            format!("{}:1000: [low] Whoami (whoami)", file("a.ndjson")),
+            format!("{}:4: [low] Whoami (whoami)", dir.join("archive").join("b.csv.gz").display()),
+            format!("{}:2: [low] Whoami (whoami)", file("c.json")),
+        ];
+        assert_eq!(String::from_utf8(output).unwrap(), expected.join("\n") + "\n");
+
+        let mut output = Vec::new();
+        scan(&mut rule_set(), &[dir.join("c.json"), dir.join("missing.json")], &options(1, ScanFormat::Json), &mut output).unwrap();
+        let output: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(output, serde_json::json!({"file": file("c.json"), "line": 2, "id": "whoami", "title": "Whoami", "level": "low"}));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn scan_stateful_rules_in_order_of_events() {
+        let rules = [
+            r#"
+                title: Whoami
+                id: whoami
+                level: low
+                detection:
+                  selection:
+                    Image|endswith: '\whoami.exe'
+                  condition: selection
+            "#,
+            r#"
+                title: Whoami burst
+                id: whoami_burst
+                level: high
+                detection:
+                  selection:
+                    Image|endswith: '\whoami.exe'
+                  timeframe: 1m
+                  condition: selection | count() > 1
+            "#,
+        ];
+        let rule_set = || RuleSet::from_rules(rules.iter().map(|rule| serde_yaml::from_str::<SigmaRule>(rule).unwrap()).collect(), LoadReport::default());
+        let path = std::env::temp_dir().join(format!("log_analyzer_scan_stateful_{}.ndjson", std::process::id()));
+
+        // the events are spread over batches, and events without a time are at the time of the event before them
+        let mut lines = vec!["{\"Image\": \"cmd.exe\"}".to_string(); 600];
+        lines[0] = "{\"Image\": \"C:\\\\whoami.exe\", \"@timestamp\": \"2024-01-01T00:00:00Z\"}".to_string();
+        lines[298] = "{\"Image\": \"cmd.exe\", \"@timestamp\": \"2024-01-01T00:00:30Z\"}".to_string();
+        lines[299] = "{\"Image\": \"C:\\\\whoami.exe\"}".to_string();
+        lines[599] = "{\"Image\": \"C:\\\\whoami.exe\", \"@timestamp\": \"2024-01-01T00:05:00Z\"}".to_string();
+        fs::write(&path, lines.join("\n")).unwrap();
+
+        let mut output = Vec::new();
+        let stateful = ScanOptions { stateless: false, ..options(4, ScanFormat::Text) };
+        let summary = scan(&mut rule_set(), std::slice::from_ref(&path), &stateful, &mut output).unwrap();
+        assert_eq!(summary.matches, 4);
+
+        let file = path.display();
+        let expected = [
+            format!("{}:1: [low] Whoami (whoami)", file),
+            format!("{}:300: [low] Whoami (whoami)", file),
+            format!("{}:300: [high] Whoami burst (whoami_burst)", file),
+            format!("{}:600: [low] Whoami (whoami)", file),
+        ];
+        assert_eq!(String::from_utf8(output).unwrap(), expected.join("\n") + "\n");
+
+        let summary = scan(&mut rule_set(), std::slice::from_ref(&path), &options(4, ScanFormat::Text), &mut Vec::new()).unwrap();
+        assert_eq!(summary.matches, 3);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
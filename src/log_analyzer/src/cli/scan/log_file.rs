@@ -0,0 +1,417 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+use flate2::read::MultiGzDecoder;
+use serde::Deserialize;
+use serde_json::{Deserializer, Map, Value};
+use sigma_log_parser::log_event::LogEvent;
+
+/// An event of a log file, with the number of the line it starts on. Events that could not be read carry the reason instead.
+#[derive(Debug, PartialEq)]
+pub struct Record {
+    pub line: usize,
+    pub event: Result<LogEvent, String>,
+}
+
+/// The records of a log file, which stop at the first error the file can not be read past.
+pub type Records = Box<dyn Iterator<Item = io::Result<Record>> + Send>;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogFormat {
+    /// Newline delimited JSON objects, or a JSON array of objects
+    Json,
+    /// Comma separated values, with the field names in the first row
+    Csv,
+}
+
+impl LogFormat {
+    /// The format of a log file by its extension, which is the extension before .gz for compressed files.
+    pub fn of(path: &Path) -> Option<LogFormat> {
+        let name = path.file_name()?.to_str()?.to_lowercase();
+        let name = name.strip_suffix(".gz").unwrap_or(&name);
+        match Path::new(name).extension()?.to_str()? {
+            "json" | "ndjson" | "jsonl" => Some(LogFormat::Json),
+            "csv" => Some(LogFormat::Csv),
+            _ => None,
+        }
+    }
+}
+
+/// Opens a log file, which is decompressed while it is read when its name ends with .gz.
+pub fn open(path: &Path) -> io::Result<Box<dyn BufRead + Send>> {
+    let file = File::open(path)?;
+    let compressed = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("gz"));
+    Ok(match compressed {
+        // logrotate and many shippers append gzip members to a file, which a plain GzDecoder stops after the first of
+        true => Box::new(BufReader::new(MultiGzDecoder::new(file))),
+        false => Box::new(BufReader::new(file)),
+    })
+}
+
+pub fn read_records<R: BufRead + Send + 'static>(reader: R, format: LogFormat) -> io::Result<Records> {
+    match format {
+        LogFormat::Json => json_records(reader),
+        LogFormat::Csv => Ok(Box::new(CsvRecords { reader, header: None, line: 0 })),
+    }
+}
+
+/// A file that starts with `[` is read as a single JSON array, every other file is read line by line.
+fn json_records<R: BufRead + Send + 'static>(mut reader: R) -> io::Result<Records> {
+    let mut line = 1;
+    let array = loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            return Ok(Box::new(std::iter::empty()));
+        }
+        let whitespace = buffer.iter().take_while(|byte| byte.is_ascii_whitespace()).count();
+        let first = buffer.get(whitespace).copied();
+        line += count_lines(&buffer[..whitespace]);
+        reader.consume(whitespace);
+        if let Some(first) = first {
+            break first == b'[';
+        }
+    };
+
+    if array {
+        reader.consume(1);
+        return Ok(Box::new(ArrayRecords { reader, line, started: false, done: false }));
+    }
+
+    let mut buffer = Vec::new();
+    Ok(Box::new(std::iter::from_fn(move || {
+        loop {
+            buffer.clear();
+            match reader.read_until(b'\n', &mut buffer) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(err) => return Some(Err(err)),
+            }
+            line += 1;
+            let text = String::from_utf8_lossy(&buffer);
+            if !text.trim().is_empty() {
+                let event = LogEvent::from_json(&text).map_err(|err| err.to_string());
+                return Some(Ok(Record { line: line - 1, event }));
+            }
+        }
+    })))
+}
+
+/// Reads the elements of a JSON array one at a time, starting after the opening bracket, with the line every element starts on.
+/// Only the element that is read is kept in memory, so archived arrays of any size are scanned as they are read.
+struct ArrayRecords<R> {
+    reader: R,
+    line: usize,
+    /// Whether an element was read, after which the elements are separated by commas
+    started: bool,
+    done: bool,
+}
+
+/// Counts the lines of the bytes that the JSON deserializer reads.
+struct LineCounter<'a, R> {
+    reader: &'a mut R,
+    lines: usize,
+}
+
+impl<R: Read> Read for LineCounter<'_, R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let length = self.reader.read(buffer)?;
+        self.lines += count_lines(&buffer[..length]);
+        Ok(length)
+    }
+}
+
+impl<R: BufRead> ArrayRecords<R> {
+    /// Skips whitespace, and returns the next byte without reading it.
+    fn peek(&mut self) -> io::Result<Option<u8>> {
+        loop {
+            let buffer = self.reader.fill_buf()?;
+            if buffer.is_empty() {
+                return Ok(None);
+            }
+            let whitespace = buffer.iter().take_while(|byte| byte.is_ascii_whitespace()).count();
+            let next = buffer.get(whitespace).copied();
+            self.line += count_lines(&buffer[..whitespace]);
+            self.reader.consume(whitespace);
+            if next.is_some() {
+                return Ok(next);
+            }
+        }
+    }
+
+    /// Reads the bytes of a number or literal, up to the whitespace, comma or bracket that follows it.
+    fn read_token(&mut self) -> io::Result<Vec<u8>> {
+        let mut token = Vec::new();
+        loop {
+            let buffer = self.reader.fill_buf()?;
+            let length = buffer.iter().take_while(|byte| !byte.is_ascii_whitespace() && !matches!(byte, b',' | b']')).count();
+            let end = buffer.is_empty() || length < buffer.len();
+            token.extend_from_slice(&buffer[..length]);
+            self.reader.consume(length);
+            if end {
+                return Ok(token);
+            }
+        }
+    }
+
+    fn read_element(&mut self) -> io::Result<Option<Record>> {
+        let mut next = self.peek()?;
+        match next {
+            Some(b']') => return Ok(None),
+            Some(b',') if self.started => {
+                self.reader.consume(1);
+                next = self.peek()?;
+            }
+            Some(_) if self.started => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("expected , or ] at line {}", self.line)));
+            }
+            _ => {}
+        }
+        self.started = true;
+
+        let line = self.line;
+        let value = match next {
+            None => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "unterminated JSON array")),
+            // objects, arrays and strings end on their closing character, so the deserializer does not read past the element
+            Some(b'{' | b'[' | b'"') => {
+                let mut counter = LineCounter { reader: &mut self.reader, lines: 0 };
+                let value = Value::deserialize(&mut Deserializer::from_reader(&mut counter));
+                self.line += counter.lines;
+                value
+            }
+            Some(_) => serde_json::from_slice(&self.read_token()?),
+        };
+
+        let event = match value.map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("invalid JSON array at line {} - {}", line, err)))? {
+            Value::Object(fields) => Ok(LogEvent::new(fields)),
+            _ => Err("JSON log must be an object".to_string()),
+        };
+        Ok(Some(Record { line, event }))
+    }
+}
+
+impl<R: BufRead> Iterator for ArrayRecords<R> {
+    type Item = io::Result<Record>;
+
+    /// The records stop after the closing bracket, or after the first error.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let record = self.read_element().transpose();
+        self.done = !matches!(record, Some(Ok(_)));
+        record
+    }
+}
+
+fn count_lines(bytes: &[u8]) -> usize {
+    bytes.iter().filter(|byte| **byte == b'\n').count()
+}
+
+/// Reads CSV as described by RFC 4180, where quoted values may contain commas, quotes ("") and line breaks.
+/// Every row is an event of the values of its columns, named by the header. Empty values are left out, as the field is not set.
+struct CsvRecords<R> {
+    reader: R,
+    header: Option<Vec<String>>,
+    line: usize,
+}
+
+impl<R: BufRead> CsvRecords<R> {
+    /// Reads the values of the next row that is not empty, and the line the row starts on.
+    fn read_row(&mut self) -> io::Result<Option<(usize, Vec<String>)>> {
+        let mut buffer = Vec::new();
+        loop {
+            let start = self.line + 1;
+            let mut values = Vec::new();
+            let mut value = String::new();
+            let mut quoted = false;
+
+            loop {
+                buffer.clear();
+                if self.reader.read_until(b'\n', &mut buffer)? == 0 {
+                    if quoted {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unterminated quote in the row of line {}", start)));
+                    }
+                    if values.is_empty() && value.is_empty() {
+                        return Ok(None);
+                    }
+                    break;
+                }
+                self.line += 1;
+
+                let text = String::from_utf8_lossy(&buffer);
+                let mut chars = text.chars().peekable();
+                while let Some(char) = chars.next() {
+                    match char {
+                        '"' if quoted && chars.peek() == Some(&'"') => {
+                            chars.next();
+                            value.push('"');
+                        }
+                        '"' => quoted = !quoted,
+                        char if quoted => value.push(char),
+                        ',' => values.push(std::mem::take(&mut value)),
+                        '\r' | '\n' => {}
+                        char => value.push(char),
+                    }
+                }
+                if !quoted {
+                    break;
+                }
+            }
+
+            values.push(value);
+            if values.len() > 1 || !values[0].is_empty() {
+                return Ok(Some((start, values)));
+            }
+        }
+    }
+
+    fn event(header: &[String], values: Vec<String>) -> Result<LogEvent, String> {
+        if values.len() > header.len() {
+            return Err(format!("the row has {} values, the header has {} columns", values.len(), header.len()));
+        }
+
+        let fields = header.iter()
+            .zip(values)
+            .filter(|(_, value)| !value.is_empty())
+            .map(|(name, value)| (name.clone(), Value::String(value)))
+            .collect::<Map<String, Value>>();
+        Ok(LogEvent::new(fields))
+    }
+}
+
+impl<R: BufRead> Iterator for CsvRecords<R> {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.header.is_none() {
+            let mut header = match self.read_row() {
+                Ok(Some((_, header))) => header,
+                Ok(None) => return None,
+                Err(err) => return Some(Err(err)),
+            };
+            header[0] = header[0].trim_start_matches('\u{feff}').to_string();
+            self.header = Some(header.into_iter().map(|name| name.trim().to_string()).collect());
+        }
+
+        match self.read_row() {
+            Ok(Some((line, values))) => {
+                let event = CsvRecords::<R>::event(self.header.as_deref().unwrap_or_default(), values);
+                Some(Ok(Record { line, event }))
+            }
+            Ok(None) => None,
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use serde_json::json;
+    use super::*;
+
+    fn read_all(log: &'static str, format: LogFormat) -> Vec<(usize, Result<Value, String>)> {
+        read_records(log.as_bytes(), format).unwrap()
+            .map(|record| record.map(|record| (record.line, record.event.map(|event| serde_json::to_value(event).unwrap()))))
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn log_formats() {
+        assert_eq!(LogFormat::of(Path::new("logs/events.ndjson")), Some(LogFormat::Json));
+        assert_eq!(LogFormat::of(Path::new("events.JSON.gz")), Some(LogFormat::Json));
+        assert_eq!(LogFormat::of(Path::new("proxy.csv.gz")), Some(LogFormat::Csv));
+        assert_eq!(LogFormat::of(Path::new("events.gz")), None);
+        assert_eq!(LogFormat::of(Path::new("syslog.1")), None);
+    }
+
+    #[test]
+    fn read_json_lines_and_arrays() {
+        let log = "\n{\"a\": 1}\n\n[1]\n{\"b\": \"x\"}";
+        let records = read_all(log, LogFormat::Json);
+        assert_eq!(records[0], (2, Ok(json!({"a": 1}))));
+        assert_eq!(records[1].0, 4);
+        assert!(records[1].1.is_err());
+        assert_eq!(records[2], (5, Ok(json!({"b": "x"}))));
+
+        let log = "  \n[\n  {\"a\": 1},\n  {\n    \"b\": [1, 2]\n  }, 2\n]\n";
+        assert_eq!(read_all(log, LogFormat::Json), vec![
+            (3, Ok(json!({"a": 1}))),
+            (4, Ok(json!({"b": [1, 2]}))),
+            (6, Err("JSON log must be an object".to_string())),
+        ]);
+        assert!(read_all("[]", LogFormat::Json).is_empty());
+
+        let mut records = read_records("[{\"a\": 1},\n{\"a\": ".as_bytes(), LogFormat::Json).unwrap();
+        assert_eq!(records.next().unwrap().unwrap().line, 1);
+        assert!(records.next().unwrap().is_err());
+        assert!(records.next().is_none());
+
+        let log = "[\"text\", true,null ,-1.5e3,[{\"a\": 1}]\n, {\"c\": \"]\"}]";
+        let records = read_all(log, LogFormat::Json);
+        assert_eq!(records.len(), 6);
+        assert!(records[..5].iter().all(|(line, event)| *line == 1 && event.is_err()));
+        assert_eq!(records[5], (2, Ok(json!({"c": "]"}))));
+
+        let mut records = read_records("[{\"a\": 1} {\"a\": 2}]".as_bytes(), LogFormat::Json).unwrap();
+        assert!(records.next().unwrap().is_ok());
+        assert_eq!(records.next().unwrap().unwrap_err().to_string(), "expected , or ] at line 1");
+        let mut records = read_records("[tru]".as_bytes(), LogFormat::Json).unwrap();
+        assert!(records.next().unwrap().unwrap_err().to_string().starts_with("invalid JSON array at line 1"));
+    }
+
+    /// The elements of an array are read as the records are, not when the file is opened.
+    #[test]
+    fn stream_json_arrays() {
+        struct Endless;
+        impl Read for Endless {
+            fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+                let element = b"{\"a\": 1},\n";
+                let length = buffer.len().min(element.len());
+                buffer[..length].copy_from_slice(&element[..length]);
+                Ok(length)
+            }
+        }
+
+        let reader = BufReader::new(io::Cursor::new(b"[".to_vec()).chain(Endless));
+        let records = read_records(reader, LogFormat::Json).unwrap().take(1000).collect::<io::Result<Vec<Record>>>().unwrap();
+        assert_eq!(records[999].line, 1000);
+        assert_eq!(records[999].event.as_ref().unwrap().get("a"), Some(&json!(1)));
+    }
+
+    #[test]
+    fn read_csv() {
+        let log = "\u{feff}Image, CommandLine,User\r\nC:\\whoami.exe,\"whoami /all, \"\"quoted\"\"\",\n\nC:\\cmd.exe,\"multi\nline\",admin\na,b,c,d\n";
+        assert_eq!(read_all(log, LogFormat::Csv), vec![
+            (2, Ok(json!({"Image": "C:\\whoami.exe", "CommandLine": "whoami /all, \"quoted\""}))),
+            (4, Ok(json!({"Image": "C:\\cmd.exe", "CommandLine": "multi\nline", "User": "admin"}))),
+            (6, Err("the row has 4 values, the header has 3 columns".to_string())),
+        ]);
+
+        let mut records = read_records("a\n\"unterminated\n".as_bytes(), LogFormat::Csv).unwrap();
+        assert!(records.next().unwrap().is_err());
+    }
+
+    #[test]
+    fn open_compressed_files() {
+        let dir = std::env::temp_dir().join(format!("log_analyzer_log_file_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("events.ndjson.gz");
+
+        // two gzip members, as written by appending to a compressed file
+        let mut file = File::create(&path).unwrap();
+        for line in ["{\"a\": 1}\n", "{\"a\": 2}\n"] {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(line.as_bytes()).unwrap();
+            file.write_all(&encoder.finish().unwrap()).unwrap();
+        }
+
+        let records = read_records(open(&path).unwrap(), LogFormat::Json).unwrap().collect::<io::Result<Vec<Record>>>().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(records.iter().map(|record| record.line).collect::<Vec<usize>>(), vec![1, 2]);
+        assert_eq!(records[1].event.as_ref().unwrap().get("a"), Some(&json!(2)));
+    }
+}
@@ -0,0 +1,97 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde_json::Value;
+use sigma_log_parser::log_event::LogEvent;
+
+/// The fields that hold the time of an event, in the order they are read: ECS, Sysmon, Zeek, CloudTrail and common names.
+const TIMESTAMP_FIELDS: [&str; 7] = ["@timestamp", "UtcTime", "ts", "eventTime", "timestamp", "EventTime", "TimeCreated.SystemTime"];
+
+/// The time of an event, read from the first timestamp field that it has and that can be parsed.
+pub fn event_timestamp(event: &LogEvent) -> Option<SystemTime> {
+    TIMESTAMP_FIELDS.iter().filter_map(|field| event.get(field)).find_map(parse_timestamp)
+}
+
+/// Parses a time as seconds or milliseconds since the epoch, or as `2024-01-31T12:00:00.000Z` where the separator may be a space,
+///     the fraction is optional, and a time without an offset is UTC.
+fn parse_timestamp(value: &Value) -> Option<SystemTime> {
+    let value = match value {
+        Value::Number(number) => return epoch_timestamp(number.as_f64()?),
+        Value::String(value) => value.trim(),
+        _ => return None,
+    };
+    if let Ok(number) = value.parse::<f64>() {
+        return epoch_timestamp(number);
+    }
+
+    let (date, time) = value.split_once(['T', 't', ' '])?;
+    let mut date = date.splitn(3, '-').map(str::parse::<i64>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+
+    let (time, offset) = time.find(['Z', 'z', '+', '-']).map_or((time, ""), |index| time.split_at(index));
+    let offset = match offset {
+        "" | "Z" | "z" => 0,
+        _ => {
+            let (hours, minutes) = offset[1..].split_once(':').unwrap_or((offset[1..].get(..2)?, offset[1..].get(2..)?));
+            let seconds = hours.parse::<i64>().ok()? * 3600 + minutes.parse::<i64>().ok()? * 60;
+            if offset.starts_with('-') { -seconds } else { seconds }
+        }
+    };
+
+    let (time, fraction) = time.split_once('.').unwrap_or((time, ""));
+    let mut time = time.splitn(3, ':').map(str::parse::<i64>);
+    let (hour, minute, second) = (time.next()?.ok()?, time.next()?.ok()?, time.next()?.ok()?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    // the fraction is read up to nanoseconds
+    let nanos = format!("{:0<9}", fraction.get(..fraction.len().min(9))?).parse::<u32>().ok()?;
+
+    let seconds = days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second - offset;
+    Some(UNIX_EPOCH + Duration::new(u64::try_from(seconds).ok()?, nanos))
+}
+
+/// Numbers above 10^11 are milliseconds, as seconds would be after the year 5000.
+fn epoch_timestamp(number: f64) -> Option<SystemTime> {
+    let seconds = if number > 1e11 { number / 1000.0 } else { number };
+    Duration::try_from_secs_f64(seconds).ok().map(|duration| UNIX_EPOCH + duration)
+}
+
+/// The days since the epoch of a civil date, see http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    #[test]
+    fn parse_timestamps() {
+        let at = |seconds: u64, millis: u64| Some(UNIX_EPOCH + Duration::from_secs(seconds) + Duration::from_millis(millis));
+
+        assert_eq!(parse_timestamp(&json!("1970-01-01T00:00:00Z")), at(0, 0));
+        assert_eq!(parse_timestamp(&json!("2000-02-29T00:00:00.123Z")), at(951_782_400, 123));
+        assert_eq!(parse_timestamp(&json!("2023-12-31 23:59:59.5")), at(1_704_067_199, 500));
+        assert_eq!(parse_timestamp(&json!("2024-01-01T01:30:00+01:30")), at(1_704_067_200, 0));
+        assert_eq!(parse_timestamp(&json!("2023-12-31T22:00:00-0200")), at(1_704_067_200, 0));
+        assert_eq!(parse_timestamp(&json!(1_704_067_200)), at(1_704_067_200, 0));
+        assert_eq!(parse_timestamp(&json!(1_704_067_200_250_u64)), at(1_704_067_200, 250));
+        assert_eq!(parse_timestamp(&json!("1704067200.5")), at(1_704_067_200, 500));
+
+        assert_eq!(parse_timestamp(&json!("2024-13-01T00:00:00Z")), None);
+        assert_eq!(parse_timestamp(&json!("yesterday")), None);
+        assert_eq!(parse_timestamp(&json!(true)), None);
+    }
+
+    #[test]
+    fn read_event_timestamp() {
+        let event = LogEvent::from(json!({"timestamp": "not a time", "TimeCreated": {"SystemTime": "1970-01-01T00:01:00Z"}}));
+        assert_eq!(event_timestamp(&event), Some(UNIX_EPOCH + Duration::from_secs(60)));
+        assert_eq!(event_timestamp(&LogEvent::from(json!({"Image": "cmd.exe"}))), None);
+    }
+}
@@ -0,0 +1,76 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use anyhow::{anyhow, Error};
+use futures_util::future::{select_all, LocalBoxFuture};
+use futures_util::FutureExt as _;
+use log::info;
+use crate::config::{AppConfig, InputConfig};
+use crate::field_mappings::FieldMappings;
+use crate::inputs::file::FileTailer;
+use crate::inputs::kafka::KafkaConsumer;
+use crate::inputs::syslog;
+use crate::pipeline::Pipeline;
+use crate::rule_set::RuleSet;
+use crate::server::create_server;
+use crate::sinks;
+
+/// How often the events of aggregations and correlations that fell out of their window are evicted.
+const EXPIRE_INTERVAL: Duration = Duration::from_secs(60);
+
+pub fn run(config: &Path) -> Result<(), Error> {
+    let config = AppConfig::load(config)?;
+    let field_mappings = FieldMappings::load(&config.field_mappings.profiles, &config.field_mappings.files)?;
+    let rule_set = RuleSet::load_dirs(&config.rules.dirs)?.with_field_mappings(&field_mappings);
+    info!("Loaded {} rules - {:?}", rule_set.len(), rule_set.report().summary());
+    info!("{} rules are only evaluated for the events that contain their literals", rule_set.prefilter().filtered());
+
+    let sinks = config.sinks.iter().map(sinks::create).collect::<Result<Vec<_>, _>>()?;
+    let pipeline = Arc::new(Pipeline::new(rule_set, config.routing.clone()).with_sinks(sinks));
+    actix_web::rt::System::new().block_on(run_inputs(&config, pipeline))
+}
+
+/// Runs every input until one of them fails.
+async fn run_inputs(config: &AppConfig, pipeline: Arc<Pipeline>) -> Result<(), Error> {
+    let mut inputs: Vec<LocalBoxFuture<Result<(), Error>>> = Vec::new();
+
+    for input in &config.inputs {
+        match input {
+            InputConfig::Http => {
+                info!("Listening on {}:{}", config.server.host, config.server.port);
+                let server = create_server(&config.server, pipeline.clone())?;
+                inputs.push(async move { server.await.map_err(|err| anyhow!("HTTP server failed - {}", err)) }.boxed_local());
+            }
+            InputConfig::Syslog { protocol, address, logsource } => {
+                let address = address.parse()?;
+                let listener = syslog::listen(*protocol, address, logsource.clone(), pipeline.clone());
+                inputs.push(async move { listener.await.map_err(|err| anyhow!("Syslog input {} failed - {}", address, err)) }.boxed_local());
+            }
+            InputConfig::File { paths, checkpoint, format, logsource } => {
+                let tailer = FileTailer::new(paths, checkpoint.as_deref(), *format, logsource.clone(), pipeline.clone())?;
+                // the files are read with blocking IO, so the tailer runs on a thread of its own
+                let tailer = actix_web::rt::task::spawn_blocking(move || tailer.run());
+                inputs.push(async move { tailer.await.map_err(|err| anyhow!("File input failed - {}", err))? }.boxed_local());
+            }
+            InputConfig::Kafka { brokers, topics, group_id, logsource } => {
+                let consumer = KafkaConsumer::new(brokers, topics, group_id, logsource.clone(), pipeline.clone());
+                // the consumer fetches with blocking IO as well
+                let consumer = actix_web::rt::task::spawn_blocking(move || consumer.run());
+                inputs.push(async move { consumer.await.map_err(|err| anyhow!("Kafka input failed - {}", err))? }.boxed_local());
+            }
+        }
+    }
+
+    if inputs.is_empty() {
+        return Err(anyhow!("No input to receive events from"));
+    }
+    // groups that stop receiving events are otherwise kept until they receive an event again
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(EXPIRE_INTERVAL);
+        loop {
+            interval.tick().await;
+            pipeline.expire(SystemTime::now());
+        }
+    });
+    select_all(inputs).await.0
+}
@@ -0,0 +1,16 @@
+use std::path::Path;
+use anyhow::Error;
+use crate::cli::ReportFormat;
+use crate::rule_set::RuleSet;
+
+/// Prints the LoadReport of a rules directory, so it is known how many of the rules are usable.
+pub fn run(rules_dir: &Path, format: ReportFormat) -> Result<(), Error> {
+    let rule_set = RuleSet::load(&rules_dir.display().to_string())?;
+
+    match format {
+        ReportFormat::Table => println!("{}", rule_set.report()),
+        ReportFormat::Json => println!("{}", rule_set.report().to_json()?),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,468 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::net::SocketAddr;
+use std::path::Path;
+use serde::Deserialize;
+use sigma_rule_parser::structs::sigma_rule::Logsource;
+use thiserror::Error;
+use crate::field_mappings::profiles::{bundled_profile, profile_names};
+
+/// The config file of the log analyzer, i.e. config/log_analyzer.yml
+/// Every field has a default, so the file only needs the values that differ.
+/// Environment variables prefixed with LOG_ANALYZER_ override the values of the file, see AppConfig::apply_env_overrides.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppConfig {
+    pub rules: RulesConfig,
+    pub field_mappings: FieldMappingsConfig,
+    pub server: ServerConfig,
+    pub routing: RoutingConfig,
+    pub inputs: Vec<InputConfig>,
+    pub sinks: Vec<SinkConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RulesConfig {
+    pub dirs: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct FieldMappingsConfig {
+    /// The bundled profiles, i.e. ecs, sysmon, zeek or cloudtrail. The files are applied after the profiles.
+    pub profiles: Vec<String>,
+    pub files: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+    /// The largest request body that is accepted, in bytes
+    pub max_payload_size: usize,
+}
+
+/// How the logsource of an event is found, so the event is only evaluated against the rules of its logsource.
+/// An event field replaces the value of the request header or the input config.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RoutingConfig {
+    /// The event fields that hold the logsource, may be dotted paths
+    pub fields: LogsourceFields,
+    /// The request headers of the HTTP endpoints that hold the logsource
+    pub headers: LogsourceFields,
+}
+
+/// The names of the values that hold the category, product and service of a logsource. An empty name is not read.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogsourceFields {
+    pub category: String,
+    pub product: String,
+    pub service: String,
+}
+
+/// The sources that log events are read from. The logsource of an input tags every event it reads.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum InputConfig {
+    /// The endpoints of the HTTP server
+    Http,
+    Syslog {
+        protocol: SyslogProtocol,
+        address: String,
+        #[serde(default)]
+        logsource: Logsource,
+    },
+    /// Tails files, and follows them when they are rotated. The read offsets are kept in the checkpoint file, when there is one.
+    File {
+        paths: Vec<String>,
+        checkpoint: Option<String>,
+        #[serde(default)]
+        format: LineFormat,
+        #[serde(default)]
+        logsource: Logsource,
+    },
+    Kafka {
+        brokers: Vec<String>,
+        topics: Vec<String>,
+        group_id: String,
+        #[serde(default)]
+        logsource: Logsource,
+    },
+}
+
+/// The format of the lines of a file. Auto reads lines that start with { as JSON, and other lines as text.
+/// Text lines are events with a single message field. Syslog lines may omit the priority, as syslog daemons do not write it to files.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LineFormat {
+    #[default]
+    Auto,
+    Json,
+    Syslog,
+    Text,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SyslogProtocol {
+    Udp,
+    Tcp,
+}
+
+/// The destinations that alerts are written to.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum SinkConfig {
+    Stdout,
+    File {
+        path: String,
+        /// The size in bytes at which the file is rotated
+        max_size: u64,
+        /// The number of rotated files that are kept
+        max_files: usize,
+    },
+    Webhook {
+        url: String,
+    },
+    Kafka {
+        brokers: Vec<String>,
+        topic: String,
+    },
+}
+
+#[derive(Debug, PartialEq, Error)]
+pub enum ConfigError {
+    #[error("could not read config {path}: {message}")]
+    Io { path: String, message: String },
+
+    #[error("invalid config {path}: {message}")]
+    Yaml { path: String, message: String },
+
+    #[error("invalid value '{value}' of environment variable {name}: {message}")]
+    InvalidEnv { name: String, value: String, message: String },
+
+    #[error("invalid config, {0}")]
+    Invalid(String),
+}
+
+const ENV_PREFIX: &str = "LOG_ANALYZER_";
+
+impl AppConfig {
+    /// Reads the config file, applies the environment variable overrides and validates the result.
+    pub fn load(path: &Path) -> Result<AppConfig, ConfigError> {
+        let display_path = path.display().to_string();
+        let file = File::open(path).map_err(|err| ConfigError::Io { path: display_path.clone(), message: err.to_string() })?;
+        let mut config: AppConfig = serde_yaml::from_reader(file).map_err(|err| ConfigError::Yaml { path: display_path, message: err.to_string() })?;
+
+        config.apply_env_overrides(|name| std::env::var(name).ok())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Overrides the values of the config file with environment variables:
+    ///     LOG_ANALYZER_RULES_DIRS, LOG_ANALYZER_FIELD_MAPPINGS_PROFILES and LOG_ANALYZER_FIELD_MAPPINGS_FILES are comma separated lists,
+    ///     LOG_ANALYZER_SERVER_HOST, LOG_ANALYZER_SERVER_PORT and LOG_ANALYZER_SERVER_MAX_PAYLOAD_SIZE are single values.
+    /// Inputs and sinks can only be configured in the config file.
+    pub fn apply_env_overrides<F: Fn(&str) -> Option<String>>(&mut self, env: F) -> Result<(), ConfigError> {
+        let var = |name: &str| env(&format!("{}{}", ENV_PREFIX, name)).map(|value| (format!("{}{}", ENV_PREFIX, name), value));
+
+        if let Some((_, dirs)) = var("RULES_DIRS") {
+            self.rules.dirs = split_list(&dirs);
+        }
+        if let Some((_, profiles)) = var("FIELD_MAPPINGS_PROFILES") {
+            self.field_mappings.profiles = split_list(&profiles);
+        }
+        if let Some((_, files)) = var("FIELD_MAPPINGS_FILES") {
+            self.field_mappings.files = split_list(&files);
+        }
+        if let Some((_, host)) = var("SERVER_HOST") {
+            self.server.host = host;
+        }
+        if let Some((name, port)) = var("SERVER_PORT") {
+            self.server.port = port.parse().map_err(|err: std::num::ParseIntError| ConfigError::InvalidEnv { name, value: port, message: err.to_string() })?;
+        }
+        if let Some((name, size)) = var("SERVER_MAX_PAYLOAD_SIZE") {
+            self.server.max_payload_size = size.parse().map_err(|err: std::num::ParseIntError| ConfigError::InvalidEnv { name, value: size, message: err.to_string() })?;
+        }
+
+        Ok(())
+    }
+
+    /// Validates the values that would otherwise only fail once they are used, i.e. a rules directory that does not exist.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.rules.dirs.is_empty() {
+            return Err(ConfigError::Invalid("rules.dirs must name at least one rules directory".to_string()));
+        }
+        for dir in &self.rules.dirs {
+            if !Path::new(dir).is_dir() {
+                return Err(ConfigError::Invalid(format!("rules directory {} does not exist", dir)));
+            }
+        }
+        for profile in &self.field_mappings.profiles {
+            if bundled_profile(profile).is_none() {
+                return Err(ConfigError::Invalid(format!("unknown field mapping profile {}, expected one of {}", profile, profile_names().join(", "))));
+            }
+        }
+        for file in &self.field_mappings.files {
+            if !Path::new(file).is_file() {
+                return Err(ConfigError::Invalid(format!("field mapping file {} does not exist", file)));
+            }
+        }
+
+        if self.server.host.is_empty() {
+            return Err(ConfigError::Invalid("server.host must not be empty".to_string()));
+        }
+        if self.server.max_payload_size == 0 {
+            return Err(ConfigError::Invalid("server.max_payload_size must be greater than 0".to_string()));
+        }
+
+        for input in &self.inputs {
+            input.validate()?;
+        }
+        // the kafka input keeps its offsets in the group without joining it, so inputs of the same group would overwrite each other's offsets
+        let mut group_ids = HashSet::new();
+        for input in &self.inputs {
+            if let InputConfig::Kafka { group_id, .. } = input {
+                if !group_ids.insert(group_id) {
+                    return Err(ConfigError::Invalid(format!("kafka inputs must not share group_id {}, the kafka input does not join consumer groups", group_id)));
+                }
+            }
+        }
+        if self.sinks.is_empty() {
+            return Err(ConfigError::Invalid("sinks must name at least one sink".to_string()));
+        }
+        for sink in &self.sinks {
+            sink.validate()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl InputConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        match self {
+            InputConfig::Http => Ok(()),
+            InputConfig::Syslog { address, .. } => match address.parse::<SocketAddr>() {
+                Ok(_) => Ok(()),
+                Err(_) => Err(ConfigError::Invalid(format!("syslog input address {} must be an ip and port, i.e. 0.0.0.0:514", address))),
+            },
+            InputConfig::File { paths, checkpoint, .. } => {
+                if paths.is_empty() || paths.iter().any(String::is_empty) || checkpoint.as_ref().is_some_and(|checkpoint| checkpoint.is_empty()) {
+                    return Err(ConfigError::Invalid("file input requires at least one path, and paths and the checkpoint must not be empty".to_string()));
+                }
+                Ok(())
+            }
+            InputConfig::Kafka { brokers, topics, group_id, .. } => {
+                if brokers.is_empty() || topics.is_empty() || topics.iter().any(String::is_empty) || group_id.is_empty() {
+                    return Err(ConfigError::Invalid("kafka input requires brokers, topics and a group_id".to_string()));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl SinkConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        match self {
+            SinkConfig::Stdout => Ok(()),
+            SinkConfig::File { path, max_size, max_files } => {
+                if path.is_empty() || *max_size == 0 || *max_files == 0 {
+                    return Err(ConfigError::Invalid("file sink requires a path, and a max_size and max_files greater than 0".to_string()));
+                }
+                Ok(())
+            }
+            SinkConfig::Webhook { url } if url.starts_with("http://") || url.starts_with("https://") => Ok(()),
+            SinkConfig::Webhook { url } => Err(ConfigError::Invalid(format!("webhook sink url {} must start with http:// or https://", url))),
+            SinkConfig::Kafka { brokers, topic } => {
+                if brokers.is_empty() || topic.is_empty() {
+                    return Err(ConfigError::Invalid("kafka sink requires brokers and a topic".to_string()));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn split_list(list: &str) -> Vec<String> {
+    list.split(',').map(str::trim).filter(|item| !item.is_empty()).map(str::to_string).collect()
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            rules: RulesConfig::default(),
+            field_mappings: FieldMappingsConfig::default(),
+            server: ServerConfig::default(),
+            routing: RoutingConfig::default(),
+            inputs: vec![InputConfig::Http],
+            sinks: vec![SinkConfig::Stdout],
+        }
+    }
+}
+
+impl Default for RulesConfig {
+    fn default() -> Self {
+        Self {
+            dirs: vec!["config/rules/rules".to_string()],
+        }
+    }
+}
+
+impl Default for FieldMappingsConfig {
+    fn default() -> Self {
+        Self {
+            profiles: Vec::new(),
+            files: vec!["config/field_mappings.yml".to_string()],
+        }
+    }
+}
+
+impl Default for RoutingConfig {
+    fn default() -> Self {
+        Self {
+            fields: LogsourceFields {
+                category: "logsource.category".to_string(),
+                product: "logsource.product".to_string(),
+                service: "logsource.service".to_string(),
+            },
+            headers: LogsourceFields {
+                category: "X-Logsource-Category".to_string(),
+                product: "X-Logsource-Product".to_string(),
+                service: "X-Logsource-Service".to_string(),
+            },
+        }
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            host: "127.0.0.1".to_string(),
+            port: 8080,
+            max_payload_size: 262_144,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::*;
+
+    fn config(yml: &str) -> AppConfig {
+        serde_yaml::from_str(yml).unwrap()
+    }
+
+    #[test]
+    fn deserialize_config() {
+        let config = config(r#"
+            rules:
+              dirs: [rules/windows, rules/linux]
+            server:
+              port: 9200
+            inputs:
+              - type: http
+              - type: syslog
+                protocol: udp
+                address: 0.0.0.0:514
+              - type: file
+                paths: [/var/log/auth.log]
+                format: syslog
+                logsource:
+                  product: linux
+                  service: auth
+            sinks:
+              - type: webhook
+                url: https://example.com/alerts
+        "#);
+
+        assert_eq!(config.rules.dirs, vec!["rules/windows".to_string(), "rules/linux".to_string()]);
+        assert_eq!(config.field_mappings, FieldMappingsConfig::default());
+        assert_eq!(config.server, ServerConfig { port: 9200, ..Default::default() });
+        assert_eq!(config.inputs[1], InputConfig::Syslog { protocol: SyslogProtocol::Udp, address: "0.0.0.0:514".to_string(), logsource: Logsource::default() });
+        assert_eq!(config.inputs[2], InputConfig::File {
+            paths: vec!["/var/log/auth.log".to_string()],
+            checkpoint: None,
+            format: LineFormat::Syslog,
+            logsource: Logsource { product: "linux".to_string(), service: "auth".to_string(), ..Default::default() },
+        });
+        assert_eq!(config.routing, RoutingConfig::default());
+        assert_eq!(config.sinks, vec![SinkConfig::Webhook { url: "https://example.com/alerts".to_string() }]);
+
+        assert!(serde_yaml::from_str::<AppConfig>("server:\n  prot: 9200\n").is_err());
+        assert!(serde_yaml::from_str::<AppConfig>("inputs:\n  - type: carrier_pigeon\n").is_err());
+    }
+
+    #[test]
+    fn env_overrides() {
+        let env = HashMap::from([
+            ("LOG_ANALYZER_RULES_DIRS", "rules/a, rules/b,"),
+            ("LOG_ANALYZER_FIELD_MAPPINGS_PROFILES", "ecs,zeek"),
+            ("LOG_ANALYZER_SERVER_HOST", "0.0.0.0"),
+            ("LOG_ANALYZER_SERVER_PORT", "9000"),
+        ]);
+        let mut config = AppConfig::default();
+        config.apply_env_overrides(|name| env.get(name).map(|value| value.to_string())).unwrap();
+
+        assert_eq!(config.rules.dirs, vec!["rules/a".to_string(), "rules/b".to_string()]);
+        assert_eq!(config.field_mappings.profiles, vec!["ecs".to_string(), "zeek".to_string()]);
+        assert_eq!(config.server.host, "0.0.0.0");
+        assert_eq!(config.server.port, 9000);
+
+        let result = config.apply_env_overrides(|name| (name == "LOG_ANALYZER_SERVER_PORT").then(|| "http".to_string()));
+        assert!(matches!(result, Err(ConfigError::InvalidEnv { name, .. }) if name == "LOG_ANALYZER_SERVER_PORT"));
+    }
+
+    #[test]
+    fn validate_config() {
+        // paths are relative to the crate when testing
+        let mut valid = AppConfig::default();
+        valid.rules.dirs = vec!["../../config/rules/rules".to_string()];
+        valid.field_mappings.files = vec!["../../config/field_mappings.yml".to_string()];
+        assert_eq!(valid.validate(), Ok(()));
+
+        let mut config = valid.clone();
+        config.rules.dirs = vec!["does_not_exist".to_string()];
+        assert_eq!(config.validate(), Err(ConfigError::Invalid("rules directory does_not_exist does not exist".to_string())));
+
+        let mut config = valid.clone();
+        config.field_mappings.profiles = vec!["ecs".to_string(), "splunk".to_string()];
+        assert_eq!(config.validate(), Err(ConfigError::Invalid("unknown field mapping profile splunk, expected one of ecs, sysmon, zeek, cloudtrail".to_string())));
+
+        let mut config = valid.clone();
+        config.inputs.push(InputConfig::Syslog { protocol: SyslogProtocol::Tcp, address: "localhost".to_string(), logsource: Logsource::default() });
+        assert!(config.validate().is_err());
+
+        let mut config = valid.clone();
+        let kafka = InputConfig::Kafka { brokers: vec!["localhost:9092".to_string()], topics: vec!["logs".to_string()], group_id: "log-analyzer".to_string(), logsource: Logsource::default() };
+        config.inputs = vec![kafka.clone()];
+        assert_eq!(config.validate(), Ok(()));
+        config.inputs.push(kafka);
+        assert_eq!(config.validate(), Err(ConfigError::Invalid("kafka inputs must not share group_id log-analyzer, the kafka input does not join consumer groups".to_string())));
+
+        let mut config = valid.clone();
+        config.sinks = vec![SinkConfig::Webhook { url: "example.com".to_string() }];
+        assert!(config.validate().is_err());
+        config.sinks = vec![SinkConfig::Webhook { url: "https://example.com/alerts".to_string() }];
+        assert_eq!(config.validate(), Ok(()));
+
+        let mut config = valid;
+        config.server.max_payload_size = 0;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn load_config() {
+        assert!(matches!(AppConfig::load(Path::new("does_not_exist.yml")), Err(ConfigError::Io { .. })));
+
+        // the bundled config holds the defaults, its paths are relative to the root of the repository
+        let file = File::open("../../config/log_analyzer.yml").unwrap();
+        assert_eq!(serde_yaml::from_reader::<File, AppConfig>(file).unwrap(), AppConfig::default());
+    }
+}
@@ -0,0 +1,528 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::time::{Duration, SystemTime};
+use log::error;
+use serde_json::Value;
+use sigma_log_parser::log_event::LogEvent;
+use sigma_rule_parser::detection_parsers::condition::aggregation_parser::parse_timeframe;
+use sigma_rule_parser::rule_error::RuleError;
+use sigma_rule_parser::sigma_file::load_report::LoadReport;
+use sigma_rule_parser::structs::correlation::{Correlation, CorrelationType};
+use sigma_rule_parser::structs::sigma_rule::SigmaRule;
+
+/// The Correlator evaluates Sigma correlation rules over the rules that matched a log event.
+///
+/// Every time a rule matches, the Correlator is told which rule matched which event. Correlations that reference the rule record the event
+///     in the group of its group-by fields, and events that are older than the timespan of the correlation are evicted.
+/// A correlation that matches resets its group, so the same events do not match again.
+/// Correlations may reference other correlations, in which case a matching correlation is processed like any other matching rule.
+#[derive(Debug, Default)]
+pub struct Correlator {
+    correlations: Vec<CorrelationState>,
+}
+
+/// A correlation rule that matched, and the values of the group-by fields it matched for.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CorrelationMatch {
+    pub rule: SigmaRule,
+    pub group: BTreeMap<String, String>,
+    pub event_count: usize,
+}
+
+#[derive(Debug)]
+struct CorrelationState {
+    rule: SigmaRule,
+    correlation: Correlation,
+    timespan: Duration,
+    groups: HashMap<Vec<String>, VecDeque<CorrelatedEvent>>,
+}
+
+#[derive(Debug)]
+struct CorrelatedEvent {
+    timestamp: SystemTime,
+    rule_index: usize,
+    value: Option<String>,
+}
+
+impl Correlator {
+    /// Adds every correlation rule of the rule set, and reports each as loaded. Correlation rules that are invalid are skipped,
+    ///     and the reason is kept in the LoadReport.
+    pub fn new(rules: &[SigmaRule], report: &mut LoadReport) -> Correlator {
+        let mut correlator = Correlator::default();
+
+        for rule in rules.iter().filter(|rule| rule.is_correlation()) {
+            match correlator.add(rule, rules) {
+                Ok(()) => report.loaded(rule),
+                Err(err) => {
+                    error!("Could not build correlation rule {} - {}", rule.id, err);
+                    report.failed_to_build(rule, &err);
+                }
+            }
+        }
+
+        correlator
+    }
+
+    /// Adds a single correlation rule. Every rule it references must be part of the rule set, by name or by id.
+    pub fn add(&mut self, rule: &SigmaRule, rules: &[SigmaRule]) -> Result<(), RuleError> {
+        let invalid = |message: &str| RuleError::InvalidCorrelation(message.to_string());
+        let correlation = rule.correlation.clone().ok_or_else(|| invalid("rule is not a correlation rule"))?;
+        let timespan = parse_timeframe(&correlation.timespan).map_err(|_| RuleError::InvalidTimeframe(correlation.timespan.clone()))?;
+
+        for reference in &correlation.rules {
+            if !rules.iter().any(|rule| rule.is_referenced_by(reference)) {
+                return Err(invalid(&format!("referenced rule '{}' does not exist", reference)));
+            }
+        }
+
+        let condition = correlation.condition.clone().unwrap_or_default();
+        match correlation.correlation_type {
+            CorrelationType::EventCount | CorrelationType::ValueCount if condition.is_empty() => return Err(invalid("a condition is required")),
+            CorrelationType::ValueCount if condition.field.is_none() => return Err(invalid("value_count requires a condition field")),
+            _ => {}
+        }
+
+        self.correlations.push(CorrelationState {
+            rule: rule.clone(),
+            correlation,
+            timespan,
+            groups: HashMap::new(),
+        });
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.correlations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.correlations.is_empty()
+    }
+
+    /// Returns true when a correlation references the rule, so the events the rule matches must be processed in the order of time.
+    pub fn references(&self, rule: &SigmaRule) -> bool {
+        self.referencing(rule).next().is_some()
+    }
+
+    /// Sigma rules that are referenced by correlations only alert on their own when one of the correlations sets `generate: true`.
+    /// Rules that are not referenced by any correlation always alert.
+    pub fn generates_alerts(&self, rule: &SigmaRule) -> bool {
+        let mut generate = self.referencing(rule).map(|state| state.correlation.generate).peekable();
+        generate.peek().is_none() || generate.any(|generate| generate)
+    }
+
+    fn referencing<'a>(&'a self, rule: &'a SigmaRule) -> impl Iterator<Item = &'a CorrelationState> {
+        self.correlations.iter().filter(|state| state.correlation.rules.iter().any(|reference| rule.is_referenced_by(reference)))
+    }
+
+    /// Records that a rule matched an event, and returns the correlations that matched because of it.
+    pub fn process(&mut self, rule: &SigmaRule, event: &LogEvent, timestamp: SystemTime) -> Vec<CorrelationMatch> {
+        self.process_chain(rule, event, timestamp, 0)
+    }
+
+    fn process_chain(&mut self, rule: &SigmaRule, event: &LogEvent, timestamp: SystemTime, depth: usize) -> Vec<CorrelationMatch> {
+        // a chain can not be longer than the number of correlations, unless correlations reference each other
+        if depth > self.correlations.len() {
+            return Vec::new();
+        }
+
+        let mut matches = Vec::new();
+        for state in self.correlations.iter_mut() {
+            if let Some(correlation_match) = state.process(rule, event, timestamp) {
+                matches.push(correlation_match);
+            }
+        }
+
+        let mut chained_matches = Vec::new();
+        for correlation_match in &matches {
+            chained_matches.extend(self.process_chain(&correlation_match.rule, event, timestamp, depth + 1));
+        }
+
+        [matches, chained_matches].concat()
+    }
+
+    /// Evicts the events that fell out of the timespan of every correlation, and removes the groups that are empty.
+    pub fn expire(&mut self, now: SystemTime) {
+        for state in self.correlations.iter_mut() {
+            let timespan = state.timespan;
+            state.groups.retain(|_, events| {
+                evict(events, timespan, now);
+                !events.is_empty()
+            });
+        }
+    }
+}
+
+impl CorrelationState {
+    fn process(&mut self, rule: &SigmaRule, event: &LogEvent, timestamp: SystemTime) -> Option<CorrelationMatch> {
+        let rule_index = self.correlation.rules.iter().position(|reference| rule.is_referenced_by(reference))?;
+
+        let group = self
+            .correlation
+            .group_by
+            .iter()
+            .map(|field| field_value(event, &self.field_name(field, rule)))
+            .collect::<Vec<String>>();
+        let value = match self.correlation.condition.as_ref().and_then(|condition| condition.field.as_ref()) {
+            Some(field) => event.get(&self.field_name(field, rule)).filter(|value| !value.is_null()).map(field_value_string),
+            None => None,
+        };
+
+        let events = self.groups.entry(group.clone()).or_default();
+        events.push_back(CorrelatedEvent { timestamp, rule_index, value });
+        evict(events, self.timespan, timestamp);
+
+        if !is_match(&self.correlation, events) {
+            return None;
+        }
+
+        let event_count = events.len();
+        self.groups.remove(&group);
+
+        Some(CorrelationMatch {
+            rule: self.rule.clone(),
+            group: self.correlation.group_by.iter().cloned().zip(group).collect(),
+            event_count,
+        })
+    }
+
+    /// Aliases allow the referenced rules to use different field names for the same value.
+    fn field_name(&self, field: &str, rule: &SigmaRule) -> String {
+        self.correlation
+            .aliases
+            .get(field)
+            .and_then(|alias| alias.iter().find(|(reference, _)| rule.is_referenced_by(reference)))
+            .map(|(_, field)| field.clone())
+            .unwrap_or_else(|| field.to_string())
+    }
+}
+
+fn is_match(correlation: &Correlation, events: &VecDeque<CorrelatedEvent>) -> bool {
+    let condition = correlation.condition.clone().unwrap_or_default();
+
+    match correlation.correlation_type {
+        CorrelationType::EventCount => condition.matches(events.len() as f64),
+        CorrelationType::ValueCount => {
+            let values = events.iter().filter_map(|event| event.value.as_ref()).collect::<HashSet<&String>>();
+            condition.matches(values.len() as f64)
+        }
+        CorrelationType::Temporal => {
+            let rules = events.iter().map(|event| event.rule_index).collect::<HashSet<usize>>();
+            match condition.is_empty() {
+                true => rules.len() == correlation.rules.len(),
+                false => condition.matches(rules.len() as f64),
+            }
+        }
+        CorrelationType::TemporalOrdered => {
+            // the referenced rules must have matched in the order they are listed
+            let mut next_rule = 0;
+            for event in events {
+                if event.rule_index == next_rule {
+                    next_rule += 1;
+                }
+            }
+            next_rule == correlation.rules.len()
+        }
+    }
+}
+
+fn evict(events: &mut VecDeque<CorrelatedEvent>, timespan: Duration, now: SystemTime) {
+    if let Some(oldest) = now.checked_sub(timespan) {
+        events.retain(|event| event.timestamp >= oldest);
+    }
+}
+
+fn field_value(event: &LogEvent, field: &str) -> String {
+    match event.get(field) {
+        Some(value) => field_value_string(value),
+        None => Value::Null.to_string(),
+    }
+}
+
+fn field_value_string(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    fn rules(yml: &str) -> Vec<SigmaRule> {
+        serde_yaml::Deserializer::from_str(yml).map(|document| serde::Deserialize::deserialize(document).unwrap()).collect()
+    }
+
+    fn at(seconds: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    const BASE_RULES: &str = r#"
+title: Failed logon
+id: 4f9c1a8e-26a4-4d3e-9d2c-1a5f0c9b7e21
+name: failed_logon
+detection:
+    selection:
+        EventID: 4625
+    condition: selection
+---
+title: Successful logon
+id: 5a1d0f8e-7b8c-4a3e-8d2c-2b6f1d0c8f32
+name: successful_logon
+detection:
+    selection:
+        EventID: 4624
+    condition: selection
+---
+title: New service
+id: 9d2b7c1e-3f4a-4b5c-8d6e-7f8a9b0c1d2e
+detection:
+    selection:
+        EventID: 7045
+    condition: selection
+"#;
+
+    fn correlator(correlation_yml: &str) -> (Correlator, Vec<SigmaRule>) {
+        let rules = rules(&format!("{}\n---\n{}", correlation_yml, BASE_RULES));
+        let correlator = Correlator::new(&rules, &mut LoadReport::default());
+        (correlator, rules)
+    }
+
+    #[test]
+    fn event_count() {
+        let (mut correlator, rules) = correlator(r#"
+title: Many failed logons
+id: 0e95725d-7320-415d-80f7-004da920fc11
+correlation:
+    type: event_count
+    rules:
+        - failed_logon
+    group-by:
+        - User
+    timespan: 1m
+    condition:
+        gte: 3
+"#);
+        assert_eq!(correlator.len(), 1);
+        let failed_logon = &rules[1];
+        let event = LogEvent::from(json!({"EventID": 4625, "User": "admin"}));
+
+        assert!(correlator.process(failed_logon, &event, at(0)).is_empty());
+        assert!(correlator.process(failed_logon, &event, at(10)).is_empty());
+        assert!(correlator.process(failed_logon, &LogEvent::from(json!({"EventID": 4625, "User": "guest"})), at(20)).is_empty());
+
+        let matches = correlator.process(failed_logon, &event, at(30));
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].rule.title, "Many failed logons");
+        assert_eq!(matches[0].group.get("User"), Some(&"admin".to_string()));
+        assert_eq!(matches[0].event_count, 3);
+
+        // the group was reset, and the earlier events fall out of the timespan
+        assert!(correlator.process(failed_logon, &event, at(40)).is_empty());
+        assert!(correlator.process(failed_logon, &event, at(100)).is_empty());
+        assert!(correlator.process(failed_logon, &event, at(110)).is_empty());
+
+        // rules that are not referenced are ignored
+        assert!(correlator.process(&rules[2], &event, at(111)).is_empty());
+    }
+
+    #[test]
+    fn value_count() {
+        let (mut correlator, rules) = correlator(r#"
+title: Password spraying
+id: 1a2b3c4d-1a2b-4c3d-8e9f-0a1b2c3d4e5f
+correlation:
+    type: value_count
+    rules:
+        - failed_logon
+    group-by:
+        - SourceIp
+    timespan: 5m
+    condition:
+        gt: 2
+        field: User
+"#);
+        let failed_logon = &rules[1];
+        for user in ["admin", "admin", "guest"] {
+            let event = LogEvent::from(json!({"SourceIp": "10.0.0.1", "User": user}));
+            assert!(correlator.process(failed_logon, &event, at(0)).is_empty());
+        }
+
+        let matches = correlator.process(failed_logon, &LogEvent::from(json!({"SourceIp": "10.0.0.1", "User": "root"})), at(1));
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].event_count, 4);
+    }
+
+    #[test]
+    fn temporal_with_aliases() {
+        let (mut correlator, rules) = correlator(r#"
+title: Logon followed by a new service
+id: 2b3c4d5e-2b3c-4d5e-9f0a-1b2c3d4e5f6a
+correlation:
+    type: temporal
+    rules:
+        - successful_logon
+        - 9d2b7c1e-3f4a-4b5c-8d6e-7f8a9b0c1d2e
+    group-by:
+        - host
+    aliases:
+        host:
+            successful_logon: WorkstationName
+            9d2b7c1e-3f4a-4b5c-8d6e-7f8a9b0c1d2e: ComputerName
+    timespan: 10m
+"#);
+        let (successful_logon, new_service) = (&rules[2], &rules[3]);
+
+        assert!(correlator.process(new_service, &LogEvent::from(json!({"ComputerName": "ws1"})), at(0)).is_empty());
+        assert!(correlator.process(successful_logon, &LogEvent::from(json!({"WorkstationName": "ws2"})), at(10)).is_empty());
+
+        let matches = correlator.process(successful_logon, &LogEvent::from(json!({"WorkstationName": "ws1"})), at(20));
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].group.get("host"), Some(&"ws1".to_string()));
+    }
+
+    #[test]
+    fn temporal_ordered() {
+        let (mut correlator, rules) = correlator(r#"
+title: Brute force followed by a successful logon
+id: 3c4d5e6f-3c4d-4e5f-8a0b-2c3d4e5f6a7b
+correlation:
+    type: temporal_ordered
+    rules:
+        - failed_logon
+        - successful_logon
+    group-by:
+        - User
+    timespan: 10m
+"#);
+        let (failed_logon, successful_logon) = (&rules[1], &rules[2]);
+        let event = LogEvent::from(json!({"User": "admin"}));
+
+        assert!(correlator.process(successful_logon, &event, at(0)).is_empty());
+        assert!(correlator.process(failed_logon, &event, at(10)).is_empty());
+        assert_eq!(correlator.process(successful_logon, &event, at(20)).len(), 1);
+    }
+
+    #[test]
+    fn chained_correlations() {
+        let (mut correlator, rules) = correlator(r#"
+title: Many failed logons
+id: 0e95725d-7320-415d-80f7-004da920fc11
+name: many_failed_logons
+correlation:
+    type: event_count
+    rules:
+        - failed_logon
+    group-by:
+        - User
+    timespan: 1m
+    condition:
+        gte: 2
+---
+title: Brute force followed by a successful logon
+id: 3c4d5e6f-3c4d-4e5f-8a0b-2c3d4e5f6a7b
+correlation:
+    type: temporal_ordered
+    rules:
+        - many_failed_logons
+        - successful_logon
+    group-by:
+        - User
+    timespan: 10m
+"#);
+        assert_eq!(correlator.len(), 2);
+        let (failed_logon, successful_logon) = (&rules[2], &rules[3]);
+        let event = LogEvent::from(json!({"User": "admin"}));
+
+        assert!(correlator.process(failed_logon, &event, at(0)).is_empty());
+        assert_eq!(correlator.process(failed_logon, &event, at(1)).len(), 1);
+
+        let matches = correlator.process(successful_logon, &event, at(2));
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].rule.title, "Brute force followed by a successful logon");
+    }
+
+    #[test]
+    fn invalid_correlations_are_skipped() {
+        let rules = rules(&format!("{}\n---\n{}", r#"
+title: References a rule that does not exist
+id: 4d5e6f7a-4d5e-4f6a-9b0c-3d4e5f6a7b8c
+correlation:
+    type: event_count
+    rules:
+        - does_not_exist
+    timespan: 1m
+    condition:
+        gte: 2
+---
+title: Value count without a field
+id: 5e6f7a8b-5e6f-4a7b-8c0d-4e5f6a7b8c9d
+correlation:
+    type: value_count
+    rules:
+        - failed_logon
+    timespan: 1m
+    condition:
+        gte: 2
+---
+title: Invalid timespan
+id: 6f7a8b9c-6f7a-4b8c-9d0e-5f6a7b8c9d0e
+correlation:
+    type: temporal
+    rules:
+        - failed_logon
+    timespan: one minute
+"#, BASE_RULES));
+        let mut report = LoadReport::default();
+        let correlator = Correlator::new(&rules, &mut report);
+        assert!(correlator.is_empty());
+        assert_eq!(report.summary().failed_to_build, 3);
+        assert_eq!(report.entries[0].reason.as_deref(), Some("invalid correlation: referenced rule 'does_not_exist' does not exist"));
+        assert_eq!(report.entries[2].reason.as_deref(), Some("invalid timeframe 'one minute'"));
+    }
+
+    #[test]
+    fn referenced_rules_generate_alerts() {
+        let correlation = |generate: bool| format!(r#"
+title: Many failed logons
+id: 0e95725d-7320-415d-80f7-004da920fc11
+correlation:
+    type: event_count
+    rules:
+        - failed_logon
+    timespan: 1m
+    condition:
+        gte: 2
+    generate: {generate}
+"#);
+        let (suppressed, rules) = correlator(&correlation(false));
+        assert!(suppressed.references(&rules[1]) && !suppressed.generates_alerts(&rules[1]));
+        assert!(!suppressed.references(&rules[2]) && suppressed.generates_alerts(&rules[2]));
+
+        let (generated, rules) = correlator(&correlation(true));
+        assert!(generated.generates_alerts(&rules[1]));
+    }
+
+    #[test]
+    fn expire_removes_stale_groups() {
+        let (mut correlator, rules) = correlator(r#"
+title: Many failed logons
+id: 0e95725d-7320-415d-80f7-004da920fc11
+correlation:
+    type: event_count
+    rules:
+        - failed_logon
+    group-by:
+        - User
+    timespan: 1m
+    condition:
+        gte: 10
+"#);
+        correlator.process(&rules[1], &LogEvent::from(json!({"User": "admin"})), at(0));
+        assert_eq!(correlator.correlations[0].groups.len(), 1);
+
+        correlator.expire(at(120));
+        assert!(correlator.correlations[0].groups.is_empty());
+    }
+}
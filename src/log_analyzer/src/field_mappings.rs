@@ -1,65 +1,234 @@
-use serde::Deserialize;
-use serde_json::Value;
-use std::collections::HashMap;
+pub mod profiles;
+
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::path::Path;
+use anyhow::{anyhow, Error};
+use serde::Deserialize;
+use sigma_rule_parser::structs::sigma_rule::Logsource;
 
+/// Translates the name of a field in a Sigma rule (target_field) to the name of the field in the log events (source_field).
+/// The source field may be a dotted path into nested objects, i.e. TargetImage -> target.img
+#[derive(Clone, Debug, PartialEq)]
 pub struct FieldMapping {
-    target_field: String,
-    source_field: String,
+    pub target_field: String,
+    pub source_field: String,
+}
+
+/// The field mappings of every configured file, keyed by the logsource they apply to.
+/// The mappings of a rule are the mappings of every logsource that matches the logsource of the rule,
+///     where the mappings of a more specific logsource replace the mappings of a less specific one.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FieldMappings {
+    logsources: Vec<LogsourceFieldMappings>,
+}
+
+/// The field mappings of a logsource. The empty fields of the logsource match any value, so an empty logsource applies to every rule.
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogsourceFieldMappings {
+    pub logsource: Logsource,
+    pub fields: BTreeMap<String, String>,
 }
 
-pub fn parse_field_mappings() -> Vec<FieldMapping> {
-    let mapping_path = Path::new("../../../test/assets/valid_fieldmapping.json");
-    println!("Path: {:?}", mapping_path);
+/// A field mapping file is either a list of logsources and their fields, or a single mapping of fields that applies to every logsource:
+///     - logsource: { product: linux }
+///       fields: { TargetImage: target.img }
+/// or
+///     { "TargetImage": "target.img" }
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FieldMappingFile {
+    Logsources(Vec<LogsourceFieldMappings>),
+    Fields(BTreeMap<String, String>),
+}
 
-    let data = fs::read_to_string(mapping_path).expect("Unable to read file");
-    // println!("File data: {:?}", data);
+impl FieldMappings {
+    /// Reads the bundled profiles and then every field mapping file in order, so the files replace the mappings of a profile.
+    /// JSON is read as YAML, as YAML is a superset of JSON.
+    pub fn load(profiles: &[String], files: &[String]) -> Result<FieldMappings, Error> {
+        let mut field_mappings = FieldMappings::default();
+        for profile in profiles {
+            field_mappings.logsources.extend(parse_profile(profile)?);
+        }
+        for file in files {
+            field_mappings.logsources.extend(parse_field_mappings(Path::new(file))?);
+        }
+        Ok(field_mappings)
+    }
 
-    let json: HashMap<String, String> = serde_json::from_str(&data).expect("Invalid JSON format");
-    println!("json: {:?}", json);
+    pub fn new(logsources: Vec<LogsourceFieldMappings>) -> FieldMappings {
+        FieldMappings { logsources }
+    }
 
-    let mut field_mappings: Vec<FieldMapping> = vec![];
-    for (target, source) in json {
-        field_mappings.push(FieldMapping {
-            target_field: target,
-            source_field: source,
-        })
+    pub fn is_empty(&self) -> bool {
+        self.logsources.iter().all(|logsource| logsource.fields.is_empty())
     }
 
-    field_mappings
+    /// Returns the field mappings that apply to a rule with the logsource, from the Sigma field name to the field of the log event.
+    pub fn for_logsource(&self, logsource: &Logsource) -> HashMap<String, String> {
+        let mut matching = self.logsources
+            .iter()
+            .filter(|mappings| mappings.applies_to(logsource))
+            .collect::<Vec<&LogsourceFieldMappings>>();
+        // the sort is stable, so mappings of the same specificity are applied in the order they were read
+        matching.sort_by_key(|mappings| mappings.specificity());
+
+        matching
+            .into_iter()
+            .flat_map(|mappings| mappings.fields.clone())
+            .collect()
+    }
+}
+
+impl LogsourceFieldMappings {
+    fn applies_to(&self, logsource: &Logsource) -> bool {
+        [
+            (&self.logsource.category, &logsource.category),
+            (&self.logsource.product, &logsource.product),
+            (&self.logsource.service, &logsource.service),
+        ]
+            .iter()
+            .all(|(mapping, rule)| mapping.is_empty() || mapping.eq_ignore_ascii_case(rule))
+    }
+
+    fn specificity(&self) -> usize {
+        [&self.logsource.category, &self.logsource.product, &self.logsource.service].iter().filter(|value| !value.is_empty()).count()
+    }
+
+    pub fn field_mappings(&self) -> Vec<FieldMapping> {
+        self.fields
+            .iter()
+            .map(|(target_field, source_field)| FieldMapping { target_field: target_field.clone(), source_field: source_field.clone() })
+            .collect()
+    }
+}
+
+/// Reads a single field mapping file. An empty file has no mappings.
+pub fn parse_field_mappings(path: &Path) -> Result<Vec<LogsourceFieldMappings>, Error> {
+    let data = fs::read_to_string(path).map_err(|err| anyhow!("Could not read field mappings {} - {}", path.display(), err))?;
+    parse_field_mapping_data(&data).map_err(|err| anyhow!("Invalid field mappings {} - {}", path.display(), err))
+}
+
+/// Reads a bundled profile, see profiles::profile_names for the available profiles.
+pub fn parse_profile(name: &str) -> Result<Vec<LogsourceFieldMappings>, Error> {
+    let data = profiles::bundled_profile(name)
+        .ok_or_else(|| anyhow!("Unknown field mapping profile {}, expected one of {}", name, profiles::profile_names().join(", ")))?;
+    parse_field_mapping_data(data).map_err(|err| anyhow!("Invalid field mapping profile {} - {}", name, err))
+}
+
+fn parse_field_mapping_data(data: &str) -> Result<Vec<LogsourceFieldMappings>, serde_yaml::Error> {
+    if data.lines().all(|line| line.trim().is_empty() || line.trim_start().starts_with('#')) {
+        return Ok(Vec::new());
+    }
+
+    match serde_yaml::from_str(data)? {
+        FieldMappingFile::Logsources(logsources) => Ok(logsources),
+        FieldMappingFile::Fields(fields) => Ok(vec![LogsourceFieldMappings { logsource: Logsource::default(), fields }]),
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::field_mappings::{parse_field_mappings, FieldMapping};
+    use super::*;
+
+    fn logsource(category: &str, product: &str, service: &str) -> Logsource {
+        Logsource { category: category.to_string(), product: product.to_string(), service: service.to_string(), ..Default::default() }
+    }
 
     #[test]
     fn parse_field_mappings_file() {
-        let actual_mapping = parse_field_mappings();
+        let actual_mapping = parse_field_mappings(Path::new("test/assets/valid_fieldmapping.json")).unwrap();
         let expected_mapping = vec![
             FieldMapping {
-                target_field: "target-field".to_string(),
-                source_field: "json-source-field".to_string(),
+                target_field: "EventCode".to_string(),
+                source_field: "event.code".to_string(),
             },
             FieldMapping {
-                target_field: "TargetImage".to_string(),
-                source_field: "target.img".to_string(),
+                target_field: "EventMsg".to_string(),
+                source_field: "event.msg".to_string(),
             },
             FieldMapping {
                 target_field: "SourceImage".to_string(),
                 source_field: "source.img".to_string(),
             },
             FieldMapping {
-                target_field: "EventCode".to_string(),
-                source_field: "event.code".to_string(),
+                target_field: "TargetImage".to_string(),
+                source_field: "target.img".to_string(),
             },
             FieldMapping {
-                target_field: "EventMsg".to_string(),
-                source_field: "event.msg".to_string(),
+                target_field: "target-field".to_string(),
+                source_field: "json-source-field".to_string(),
             },
         ];
 
-        assert_eq!(actual_mapping.len(), expected_mapping.len());
+        assert_eq!(actual_mapping.len(), 1);
+        assert_eq!(actual_mapping[0].logsource, Logsource::default());
+        assert_eq!(actual_mapping[0].field_mappings(), expected_mapping);
+    }
+
+    #[test]
+    fn parse_invalid_field_mappings_file() {
+        assert!(parse_field_mappings(Path::new("test/assets/does_not_exist.json")).is_err());
+        assert!(FieldMappings::load(&[], &["../../config/field_mappings.yml".to_string()]).is_ok());
+    }
+
+    #[test]
+    fn field_mappings_by_logsource() {
+        let field_mappings: Vec<LogsourceFieldMappings> = serde_yaml::from_str(r#"
+            - fields:
+                Image: process.executable
+                User: user.name
+            - logsource:
+                product: linux
+              fields:
+                Image: exe
+            - logsource:
+                product: linux
+                category: process_creation
+              fields:
+                Image: process.exe
+            - logsource:
+                product: windows
+              fields:
+                User: winlog.user.name
+        "#).unwrap();
+        let field_mappings = FieldMappings::new(field_mappings);
+
+        let linux_process = field_mappings.for_logsource(&logsource("process_creation", "linux", ""));
+        assert_eq!(linux_process.get("Image"), Some(&"process.exe".to_string()));
+        assert_eq!(linux_process.get("User"), Some(&"user.name".to_string()));
+
+        let linux_auditd = field_mappings.for_logsource(&logsource("", "linux", "auditd"));
+        assert_eq!(linux_auditd.get("Image"), Some(&"exe".to_string()));
+
+        let windows = field_mappings.for_logsource(&logsource("process_creation", "Windows", ""));
+        assert_eq!(windows.get("Image"), Some(&"process.executable".to_string()));
+        assert_eq!(windows.get("User"), Some(&"winlog.user.name".to_string()));
+    }
+
+    #[test]
+    fn bundled_profiles() {
+        for name in profiles::profile_names() {
+            assert!(!parse_profile(name).unwrap().is_empty(), "profile {}", name);
+        }
+        assert!(parse_profile("splunk").is_err());
+
+        let field_mappings = FieldMappings::load(&["ECS".to_string(), "cloudtrail".to_string()], &[]).unwrap();
+        let windows_process = field_mappings.for_logsource(&logsource("process_creation", "windows", ""));
+        assert_eq!(windows_process.get("Image"), Some(&"process.executable".to_string()));
+        assert_eq!(windows_process.get("EventID"), Some(&"event.code".to_string()));
+
+        // the profile that is read last replaces the mappings of the same logsource
+        let cloudtrail = field_mappings.for_logsource(&logsource("", "aws", "cloudtrail"));
+        assert_eq!(cloudtrail.get("eventName"), Some(&"detail.eventName".to_string()));
+
+        let sysmon = FieldMappings::load(&["sysmon".to_string()], &[]).unwrap();
+        let windows_registry = sysmon.for_logsource(&logsource("registry_set", "windows", ""));
+        assert_eq!(windows_registry.get("TargetObject"), Some(&"Event.EventData.TargetObject".to_string()));
+        assert!(sysmon.for_logsource(&logsource("process_creation", "linux", "")).is_empty());
+
+        let zeek = FieldMappings::load(&["zeek".to_string()], &[]).unwrap();
+        assert_eq!(zeek.for_logsource(&logsource("proxy", "", "")).get("c-uri"), Some(&"uri".to_string()));
     }
 }
@@ -0,0 +1,20 @@
+/// The field mapping profiles that are bundled with the log analyzer, by name.
+/// Each profile maps the field names of the Sigma rules to the fields of a common log schema, keyed by the logsource of the rules.
+const PROFILES: [(&str, &str); 4] = [
+    ("ecs", include_str!("profiles/ecs.yml")),
+    ("sysmon", include_str!("profiles/sysmon.yml")),
+    ("zeek", include_str!("profiles/zeek.yml")),
+    ("cloudtrail", include_str!("profiles/cloudtrail.yml")),
+];
+
+/// Returns the field mapping file of a bundled profile. The name is not case sensitive.
+pub fn bundled_profile(name: &str) -> Option<&'static str> {
+    PROFILES
+        .iter()
+        .find(|(profile, _)| profile.eq_ignore_ascii_case(name))
+        .map(|(_, data)| *data)
+}
+
+pub fn profile_names() -> Vec<&'static str> {
+    PROFILES.iter().map(|(profile, _)| *profile).collect()
+}
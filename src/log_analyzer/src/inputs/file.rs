@@ -0,0 +1,399 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, Metadata};
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use anyhow::{anyhow, Error};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sigma_log_parser::log_event::LogEvent;
+use sigma_rule_parser::structs::sigma_rule::Logsource;
+use crate::config::LineFormat;
+use crate::pipeline::Pipeline;
+use crate::sinks::FailedAlerts;
+
+/// How often the files are checked for new lines and rotation.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A longer line is skipped, so a file without newlines does not grow the memory without bound.
+const MAX_LINE_SIZE: usize = 1_048_576;
+
+/// Tails files and evaluates every line in the pipeline. A file is only detected as truncated when it is shorter than the offset that was read.
+/// A file that is renamed (logrotate's default) is read to its end before the new file at the path is read from its start,
+///     and a file that is truncated (logrotate's copytruncate) is read again from its start.
+/// The offsets of the lines that were evaluated are written to the checkpoint file after every poll, once their alerts were delivered,
+///     so a restarted tailer continues with the first line that was not evaluated yet.
+pub struct FileTailer {
+    files: Vec<TailedFile>,
+    checkpoint: Option<PathBuf>,
+    format: LineFormat,
+    logsource: Logsource,
+    pipeline: Arc<Pipeline>,
+    /// The alerts of the lines that were not delivered, so the checkpoint is not written
+    failed: FailedAlerts,
+}
+
+/// A file is identified by its device and inode, which do not change when the file is renamed.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+struct FileIdentity {
+    device: u64,
+    inode: u64,
+}
+
+/// The identity of a file and the offset of the first line that was not evaluated yet.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+struct FilePosition {
+    #[serde(flatten)]
+    identity: FileIdentity,
+    offset: u64,
+}
+
+struct TailedFile {
+    path: PathBuf,
+    reader: Option<BufReader<File>>,
+    identity: Option<FileIdentity>,
+    offset: u64,
+    /// The bytes of a line that is not complete yet, which are read but not included in the offset
+    partial: Vec<u8>,
+}
+
+impl FileTailer {
+    /// Reads the offsets of the checkpoint file. A checkpoint file that does not exist yet has no offsets.
+    pub fn new(paths: &[String], checkpoint: Option<&str>, format: LineFormat, logsource: Logsource, pipeline: Arc<Pipeline>) -> Result<FileTailer, Error> {
+        let positions = match checkpoint {
+            Some(checkpoint) => read_checkpoint(checkpoint)?,
+            None => BTreeMap::new(),
+        };
+
+        let files = paths
+            .iter()
+            .map(|path| TailedFile::new(PathBuf::from(path), positions.get(path).copied()))
+            .collect();
+
+        Ok(FileTailer { files, checkpoint: checkpoint.map(PathBuf::from), format, logsource, pipeline, failed: FailedAlerts::default() })
+    }
+
+    /// Polls the files until reading one of them, delivering the alerts of a checkpointed file or writing the checkpoint fails.
+    pub fn run(mut self) -> Result<(), Error> {
+        info!("Tailing {}", self.files.iter().map(|file| file.path.display().to_string()).collect::<Vec<String>>().join(", "));
+        loop {
+            self.poll()?;
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Evaluates the lines that were added to the files since the last poll, and returns the number of lines.
+    pub fn poll(&mut self) -> Result<usize, Error> {
+        let mut lines = 0;
+        for file in self.files.iter_mut() {
+            let (format, logsource, pipeline, failed) = (self.format, &self.logsource, &self.pipeline, &self.failed);
+            lines += file.poll(&mut |line| evaluate_line(line, format, logsource, pipeline, failed))
+                .map_err(|err| anyhow!("Could not read {} - {}", file.path.display(), err))?;
+        }
+
+        let flushed = self.pipeline.flush(&self.failed);
+        if let Some(checkpoint) = &self.checkpoint {
+            // the lines are evaluated again by a restarted tailer
+            flushed.map_err(|err| anyhow!("Not writing checkpoint {} - {}", checkpoint.display(), err))?;
+            let positions = self.files.iter().filter_map(|file| file.position().map(|position| (file.path.display().to_string(), position))).collect();
+            write_checkpoint(checkpoint, &positions).map_err(|err| anyhow!("Could not write checkpoint {} - {}", checkpoint.display(), err))?;
+        } else if let Err(err) = flushed {
+            warn!("{}", err);
+        }
+
+        Ok(lines)
+    }
+}
+
+impl TailedFile {
+    fn new(path: PathBuf, position: Option<FilePosition>) -> TailedFile {
+        TailedFile {
+            path,
+            reader: None,
+            identity: position.map(|position| position.identity),
+            offset: position.map_or(0, |position| position.offset),
+            partial: Vec::new(),
+        }
+    }
+
+    fn position(&self) -> Option<FilePosition> {
+        self.identity.map(|identity| FilePosition { identity, offset: self.offset })
+    }
+
+    fn poll<F: FnMut(&[u8])>(&mut self, evaluate: &mut F) -> io::Result<usize> {
+        let mut lines = 0;
+        // a rotated file is read to its end, and then the new file at the path is read in the same poll
+        loop {
+            if self.reader.is_none() && !self.open()? {
+                return Ok(lines);
+            }
+            lines += self.read_lines(evaluate)?;
+
+            let metadata = match fs::metadata(&self.path) {
+                Ok(metadata) => metadata,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(lines),
+                Err(err) => return Err(err),
+            };
+            if Some(identity(&metadata)) != self.identity {
+                info!("{} was rotated", self.path.display());
+                if !self.partial.is_empty() {
+                    // the last line of the rotated file will not be completed by the writer, which writes to the new file
+                    evaluate(&std::mem::take(&mut self.partial));
+                    lines += 1;
+                }
+                self.reader = None;
+                self.identity = None;
+                self.offset = 0;
+                continue;
+            }
+            if metadata.len() < self.offset + self.partial.len() as u64 {
+                info!("{} was truncated", self.path.display());
+                self.offset = 0;
+                self.partial.clear();
+                if let Some(reader) = self.reader.as_mut() {
+                    reader.seek(SeekFrom::Start(0))?;
+                }
+                continue;
+            }
+            return Ok(lines);
+        }
+    }
+
+    /// Opens the file at the path, and continues at the checkpoint when it is the file of the checkpoint.
+    /// Returns false when there is no file at the path yet.
+    fn open(&mut self) -> io::Result<bool> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(err) => return Err(err),
+        };
+        let metadata = file.metadata()?;
+
+        if self.identity != Some(identity(&metadata)) || metadata.len() < self.offset {
+            self.offset = 0;
+        }
+        self.identity = Some(identity(&metadata));
+        self.partial.clear();
+
+        let mut reader = BufReader::new(file);
+        reader.seek(SeekFrom::Start(self.offset))?;
+        self.reader = Some(reader);
+        Ok(true)
+    }
+
+    fn read_lines<F: FnMut(&[u8])>(&mut self, evaluate: &mut F) -> io::Result<usize> {
+        let reader = match self.reader.as_mut() {
+            Some(reader) => reader,
+            None => return Ok(0),
+        };
+
+        let mut lines = 0;
+        loop {
+            let read = reader.read_until(b'\n', &mut self.partial)?;
+            if read == 0 {
+                return Ok(lines);
+            }
+            if self.partial.ends_with(b"\n") {
+                self.offset += self.partial.len() as u64;
+                evaluate(&self.partial);
+                self.partial.clear();
+                lines += 1;
+            } else if self.partial.len() > MAX_LINE_SIZE {
+                warn!("Skipping line at offset {} of {}, which is longer than {} bytes", self.offset, self.path.display(), MAX_LINE_SIZE);
+                self.offset += self.partial.len() as u64;
+                self.partial.clear();
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn identity(metadata: &Metadata) -> FileIdentity {
+    use std::os::unix::fs::MetadataExt;
+    FileIdentity { device: metadata.dev(), inode: metadata.ino() }
+}
+
+/// Without inodes a rename can not be told apart from a new file, so only truncation is detected.
+#[cfg(not(unix))]
+fn identity(_metadata: &Metadata) -> FileIdentity {
+    FileIdentity { device: 0, inode: 0 }
+}
+
+fn evaluate_line(line: &[u8], format: LineFormat, logsource: &Logsource, pipeline: &Pipeline, failed: &FailedAlerts) {
+    let line = String::from_utf8_lossy(line);
+    let line = line.trim_end_matches(['\r', '\n']);
+    if line.trim().is_empty() {
+        return;
+    }
+
+    match parse_line(line, format) {
+        Ok(event) => {
+            pipeline.evaluate_counting(&event, logsource, Some(failed));
+        }
+        Err(err) => warn!("Skipping line - {}", err),
+    }
+}
+
+pub fn parse_line(line: &str, format: LineFormat) -> Result<LogEvent, Error> {
+    match format {
+        LineFormat::Json => LogEvent::from_json(line),
+        LineFormat::Auto if line.trim_start().starts_with('{') => LogEvent::from_json(line),
+        LineFormat::Syslog if line.starts_with('<') => LogEvent::from_syslog(line),
+        // syslog daemons write the lines to files without the priority, so the default priority (user.notice) is assumed
+        LineFormat::Syslog => LogEvent::from_syslog(&format!("<13>{}", line)),
+        LineFormat::Auto | LineFormat::Text => {
+            let mut fields = Map::new();
+            fields.insert("message".to_string(), Value::String(line.to_string()));
+            Ok(LogEvent::new(fields))
+        }
+    }
+}
+
+fn read_checkpoint(checkpoint: &str) -> Result<BTreeMap<String, FilePosition>, Error> {
+    match fs::read_to_string(checkpoint) {
+        Ok(data) => serde_json::from_str(&data).map_err(|err| anyhow!("Invalid checkpoint {} - {}", checkpoint, err)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(err) => Err(anyhow!("Could not read checkpoint {} - {}", checkpoint, err)),
+    }
+}
+
+/// The checkpoint is written to a temporary file that replaces the checkpoint, so a crash never leaves half a checkpoint.
+fn write_checkpoint(checkpoint: &Path, positions: &BTreeMap<String, FilePosition>) -> io::Result<()> {
+    let mut temporary = checkpoint.as_os_str().to_owned();
+    temporary.push(".tmp");
+    fs::write(&temporary, serde_json::to_vec_pretty(positions)?)?;
+    fs::rename(&temporary, checkpoint)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use sigma_rule_parser::sigma_file::load_report::LoadReport;
+    use sigma_rule_parser::structs::sigma_rule::SigmaRule;
+    use crate::config::RoutingConfig;
+    use crate::rule_set::RuleSet;
+    use crate::sinks::tests::FailingSink;
+    use super::*;
+
+    fn pipeline() -> Arc<Pipeline> {
+        Arc::new(Pipeline::new(rule_set(), RoutingConfig::default()))
+    }
+
+    fn rule_set() -> RuleSet {
+        let rule: SigmaRule = serde_yaml::from_str(r#"
+            title: Whoami
+            id: whoami
+            detection:
+              selection:
+                - CommandLine: whoami
+                - message|contains: whoami
+              condition: selection
+        "#).unwrap();
+        RuleSet::from_rules(vec![rule], LoadReport::default())
+    }
+
+    fn append(path: &PathBuf, data: &str) {
+        fs::OpenOptions::new().create(true).append(true).open(path).unwrap().write_all(data.as_bytes()).unwrap();
+    }
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("log_analyzer_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn parse_lines() {
+        assert_eq!(parse_line(r#"{"CommandLine": "whoami"}"#, LineFormat::Auto).unwrap().get_str("CommandLine"), Some("whoami"));
+        assert_eq!(parse_line("plain text", LineFormat::Auto).unwrap().get_str("message"), Some("plain text"));
+        assert!(parse_line("plain text", LineFormat::Json).is_err());
+
+        let event = parse_line("Oct 11 22:14:15 host sshd[42]: Accepted publickey for root", LineFormat::Syslog).unwrap();
+        assert_eq!(event.get_str("app_name"), Some("sshd"));
+        assert_eq!(event.get_str("message"), Some("Accepted publickey for root"));
+    }
+
+    #[test]
+    fn tail_rotated_and_truncated_files() {
+        let dir = test_dir("tail");
+        let path = dir.join("app.log");
+        let pipeline = pipeline();
+        let mut tailer = FileTailer::new(&[path.display().to_string()], None, LineFormat::Auto, Logsource::default(), pipeline.clone()).unwrap();
+
+        assert_eq!(tailer.poll().unwrap(), 0);
+        append(&path, "{\"CommandLine\": \"whoami\"}\nid\nwho");
+        assert_eq!(tailer.poll().unwrap(), 2);
+        append(&path, "ami\n");
+        assert_eq!(tailer.poll().unwrap(), 1);
+
+        // logrotate renames the file, and the writer writes the last line to the renamed file before it opens a new file
+        fs::rename(&path, dir.join("app.log.1")).unwrap();
+        append(&dir.join("app.log.1"), "rotated\nlast line");
+        append(&path, "new file\n");
+        assert_eq!(tailer.poll().unwrap(), 3);
+
+        // copytruncate copies the file and truncates it
+        fs::write(&path, "").unwrap();
+        append(&path, "cut\n");
+        assert_eq!(tailer.poll().unwrap(), 1);
+        assert_eq!(pipeline.events(), 7);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn continue_at_checkpoint() {
+        let dir = test_dir("checkpoint");
+        let path = dir.join("app.log");
+        let paths = [path.display().to_string()];
+        let checkpoint = dir.join("checkpoint.json").display().to_string();
+
+        append(&path, "one\ntwo\nthr");
+        let mut tailer = FileTailer::new(&paths, Some(&checkpoint), LineFormat::Text, Logsource::default(), pipeline()).unwrap();
+        assert_eq!(tailer.poll().unwrap(), 2);
+        drop(tailer);
+
+        // the restarted tailer reads the line that was not complete, and none of the lines that were evaluated
+        append(&path, "ee\nfour\n");
+        let mut tailer = FileTailer::new(&paths, Some(&checkpoint), LineFormat::Text, Logsource::default(), pipeline()).unwrap();
+        assert_eq!(tailer.poll().unwrap(), 2);
+
+        // a file that replaced the file of the checkpoint is read from its start
+        drop(tailer);
+        fs::remove_file(&path).unwrap();
+        append(&path, "five\n");
+        let mut tailer = FileTailer::new(&paths, Some(&checkpoint), LineFormat::Text, Logsource::default(), pipeline()).unwrap();
+        assert_eq!(tailer.poll().unwrap(), 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn keep_checkpoint_until_alerts_are_delivered() {
+        let dir = test_dir("undelivered");
+        let path = dir.join("app.log");
+        let paths = [path.display().to_string()];
+        let checkpoint = dir.join("checkpoint.json").display().to_string();
+        let failing = Arc::new(Pipeline::new(rule_set(), RoutingConfig::default()).with_sinks(vec![Box::new(FailingSink)]));
+
+        append(&path, "ls\n");
+        let mut tailer = FileTailer::new(&paths, Some(&checkpoint), LineFormat::Text, Logsource::default(), pipeline()).unwrap();
+        assert_eq!(tailer.poll().unwrap(), 1);
+        drop(tailer);
+
+        append(&path, "whoami\n");
+        let mut tailer = FileTailer::new(&paths, Some(&checkpoint), LineFormat::Text, Logsource::default(), failing).unwrap();
+        assert!(tailer.poll().is_err());
+        drop(tailer);
+
+        // the line of the alert that was not delivered is evaluated again
+        let mut tailer = FileTailer::new(&paths, Some(&checkpoint), LineFormat::Text, Logsource::default(), pipeline()).unwrap();
+        assert_eq!(tailer.poll().unwrap(), 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
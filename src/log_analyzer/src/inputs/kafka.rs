@@ -0,0 +1,329 @@
+use std::collections::BTreeMap;
+use std::slice;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Error};
+use log::{info, warn};
+use sigma_log_parser::log_event::LogEvent;
+use sigma_rule_parser::structs::sigma_rule::Logsource;
+use crate::kafka::{check_error, Connection, Record, TopicPartition, EARLIEST, NOT_LEADER_OR_FOLLOWER, OFFSET_OUT_OF_RANGE, UNKNOWN_TOPIC_OR_PARTITION};
+use crate::pipeline::Pipeline;
+use crate::sinks::FailedAlerts;
+
+/// How long a consumer that failed waits before it connects again.
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long a fetch waits for records when a partition has none.
+const FETCH_WAIT: Duration = Duration::from_millis(500);
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often the metadata is read again, so the consumer follows leaders that moved and partitions that were added, as Kafka clients do by default.
+const METADATA_REFRESH: Duration = Duration::from_secs(300);
+
+/// Consumes every partition of the topics and evaluates every record as a JSON object in the pipeline.
+/// The consumer does not join the consumer group of the group_id (there is no JoinGroup, SyncGroup or Heartbeat), the group only keeps its offsets.
+///     So the partitions are not shared: consumers with the same group_id would each evaluate every record and overwrite each other's offsets,
+///     which is why every input, and every instance of the log analyzer, needs a group_id of its own.
+/// The leaders of the partitions are read from the metadata when the consumer connects, and again when a leader moves or every METADATA_REFRESH.
+/// The offsets are committed after the alerts of the records were delivered to the sinks, so a record is evaluated again after a restart
+///     unless its alerts were delivered (at least once). A partition without a committed offset is consumed from its first record.
+pub struct KafkaConsumer {
+    brokers: Vec<String>,
+    topics: Vec<String>,
+    group_id: String,
+    logsource: Logsource,
+    pipeline: Arc<Pipeline>,
+    /// The alerts of the records that were not delivered, so the offsets are not committed
+    failed: FailedAlerts,
+    session: Option<Session>,
+}
+
+/// The connections to the brokers and the offsets of the partitions, which are dropped when a request fails.
+struct Session {
+    coordinator: Connection,
+    /// The connection to every leader, and the partitions that it leads
+    leaders: Vec<Leader>,
+    /// The offset of the next record of every partition
+    positions: BTreeMap<TopicPartition, i64>,
+    committed: BTreeMap<TopicPartition, i64>,
+    /// When the metadata was read
+    connected: Instant,
+}
+
+struct Leader {
+    address: String,
+    connection: Connection,
+    partitions: Vec<TopicPartition>,
+}
+
+impl KafkaConsumer {
+    pub fn new(brokers: &[String], topics: &[String], group_id: &str, logsource: Logsource, pipeline: Arc<Pipeline>) -> KafkaConsumer {
+        KafkaConsumer {
+            brokers: brokers.to_vec(),
+            topics: topics.to_vec(),
+            group_id: group_id.to_string(),
+            logsource,
+            pipeline,
+            failed: FailedAlerts::default(),
+            session: None,
+        }
+    }
+
+    /// Polls the topics, and connects again when the brokers fail.
+    pub fn run(mut self) -> Result<(), Error> {
+        info!("Consuming {} with the offsets of group {}, which this consumer does not share with other consumers", self.topics.join(", "), self.group_id);
+        loop {
+            if let Err(err) = self.poll() {
+                warn!("Kafka input failed, retrying in {}s - {}", RETRY_INTERVAL.as_secs(), err);
+                self.session = None;
+                thread::sleep(RETRY_INTERVAL);
+            }
+        }
+    }
+
+    /// Evaluates the records that were fetched from every leader, and commits their offsets once their alerts were delivered.
+    /// Returns the number of records that were evaluated.
+    pub fn poll(&mut self) -> Result<usize, Error> {
+        // the offsets were committed by the previous poll, so the new session continues at them
+        if self.session.as_ref().is_some_and(|session| session.connected.elapsed() >= METADATA_REFRESH) {
+            self.session = None;
+        }
+        if self.session.is_none() {
+            self.session = Some(self.connect()?);
+        }
+        let session = self.session.as_mut().unwrap();
+
+        let mut records = 0;
+        let mut moved = None;
+        for leader in session.leaders.iter_mut() {
+            let offsets = leader.partitions.iter().map(|topic_partition| (topic_partition.clone(), session.positions[topic_partition])).collect();
+            let fetched = leader.connection.fetch(&offsets, FETCH_WAIT).map_err(|err| anyhow!("Could not fetch from {} - {}", leader.address, err))?;
+
+            for partition in fetched {
+                let (topic, number) = &partition.topic_partition;
+                if partition.error_code == OFFSET_OUT_OF_RANGE {
+                    // the records were deleted by the retention of the topic, or the topic was created again
+                    let earliest = leader.connection.list_offsets(slice::from_ref(&partition.topic_partition), EARLIEST)
+                        .map_err(|err| anyhow!("Could not list offsets of {}-{} - {}", topic, number, err))?;
+                    warn!("Offset {} of {}-{} is out of range, continuing at the first record", session.positions[&partition.topic_partition], topic, number);
+                    session.positions.extend(earliest);
+                    continue;
+                }
+                if partition.error_code == NOT_LEADER_OR_FOLLOWER || partition.error_code == UNKNOWN_TOPIC_OR_PARTITION {
+                    // the partition is fetched from its new leader once the records of the other partitions are committed
+                    moved = Some((leader.address.clone(), partition.topic_partition.clone()));
+                    continue;
+                }
+                check_error(partition.error_code).map_err(|err| anyhow!("Could not fetch {}-{} - {}", topic, number, err))?;
+
+                let position = session.positions.get_mut(&partition.topic_partition).unwrap();
+                // a fetch returns the whole batch of the offset, of which the records before the offset were evaluated already
+                for record in &partition.records.records {
+                    if record.offset < *position {
+                        continue;
+                    }
+                    evaluate_record(record, &partition.topic_partition, &self.logsource, &self.pipeline, &self.failed);
+                    *position = record.offset + 1;
+                    records += 1;
+                }
+                // past the control batches (transaction markers), which have no records to evaluate
+                if let Some(next_offset) = partition.records.next_offset {
+                    *position = (*position).max(next_offset);
+                }
+            }
+        }
+
+        self.pipeline.flush(&self.failed).map_err(|err| anyhow!("Not committing offsets - {}", err))?;
+        let changed: BTreeMap<TopicPartition, i64> = session.positions.iter()
+            .filter(|(topic_partition, offset)| session.committed.get(*topic_partition) != Some(offset))
+            .map(|(topic_partition, offset)| (topic_partition.clone(), *offset))
+            .collect();
+        if !changed.is_empty() {
+            session.coordinator.offset_commit(&self.group_id, &changed).map_err(|err| anyhow!("Could not commit offsets of group {} - {}", self.group_id, err))?;
+            session.committed.extend(changed);
+        }
+        if let Some((address, (topic, number))) = moved {
+            info!("{} does not lead {}-{} anymore, reading the metadata again", address, topic, number);
+            self.session = None;
+        }
+        Ok(records)
+    }
+
+    /// Connects to the leaders of the partitions and the coordinator of the group, and reads the offsets that the group committed.
+    fn connect(&self) -> Result<Session, Error> {
+        let mut connection = Connection::connect(&self.brokers, TIMEOUT).map_err(|err| anyhow!("Could not connect to {} - {}", self.brokers.join(", "), err))?;
+        let metadata = connection.metadata(&self.topics).map_err(|err| anyhow!("Could not read the metadata of {} - {}", self.topics.join(", "), err))?;
+
+        let mut partitions_by_leader: BTreeMap<i32, Vec<TopicPartition>> = BTreeMap::new();
+        for (topic_partition, leader) in &metadata.leaders {
+            partitions_by_leader.entry(*leader).or_default().push(topic_partition.clone());
+        }
+        let mut leaders = Vec::new();
+        for (node_id, partitions) in partitions_by_leader {
+            let address = metadata.brokers.get(&node_id)
+                .ok_or_else(|| anyhow!("{}-{} has no leader", partitions[0].0, partitions[0].1))?;
+            let connection = Connection::connect(slice::from_ref(address), TIMEOUT).map_err(|err| anyhow!("Could not connect to {} - {}", address, err))?;
+            leaders.push(Leader { address: address.clone(), connection, partitions });
+        }
+
+        let coordinator = connection.find_coordinator(&self.group_id).map_err(|err| anyhow!("Could not find the coordinator of group {} - {}", self.group_id, err))?;
+        let mut coordinator = Connection::connect(slice::from_ref(&coordinator), TIMEOUT).map_err(|err| anyhow!("Could not connect to {} - {}", coordinator, err))?;
+        let partitions = metadata.leaders.keys().cloned().collect::<Vec<TopicPartition>>();
+        let committed = coordinator.offset_fetch(&self.group_id, &partitions).map_err(|err| anyhow!("Could not fetch offsets of group {} - {}", self.group_id, err))?;
+
+        let mut positions = committed.clone();
+        for leader in leaders.iter_mut() {
+            let uncommitted = leader.partitions.iter().filter(|topic_partition| !committed.contains_key(*topic_partition)).cloned().collect::<Vec<TopicPartition>>();
+            if !uncommitted.is_empty() {
+                positions.extend(leader.connection.list_offsets(&uncommitted, EARLIEST).map_err(|err| anyhow!("Could not list offsets at {} - {}", leader.address, err))?);
+            }
+        }
+        if let Some(topic_partition) = partitions.iter().find(|topic_partition| !positions.contains_key(*topic_partition)) {
+            return Err(anyhow!("{}-{} has no offset", topic_partition.0, topic_partition.1));
+        }
+
+        info!("Consuming {} partitions of {} from {}", partitions.len(), self.topics.join(", "), leaders.iter().map(|leader| leader.address.as_str()).collect::<Vec<&str>>().join(", "));
+        Ok(Session { coordinator, leaders, positions, committed, connected: Instant::now() })
+    }
+}
+
+fn evaluate_record(record: &Record, (topic, partition): &TopicPartition, logsource: &Logsource, pipeline: &Pipeline, failed: &FailedAlerts) {
+    let value = match &record.value {
+        Some(value) => String::from_utf8_lossy(value),
+        None => {
+            warn!("Skipping record {} of {}-{} without a value", record.offset, topic, partition);
+            return;
+        }
+    };
+    match LogEvent::from_json(&value) {
+        Ok(event) => {
+            pipeline.evaluate_counting(&event, logsource, Some(failed));
+        }
+        Err(err) => warn!("Skipping record {} of {}-{} - {}", record.offset, topic, partition, err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sigma_rule_parser::sigma_file::load_report::LoadReport;
+    use sigma_rule_parser::structs::sigma_rule::SigmaRule;
+    use crate::alert::Alert;
+    use crate::config::RoutingConfig;
+    use crate::kafka::mock::MockBroker;
+    use crate::kafka::{CONTROL, GZIP};
+    use crate::rule_set::RuleSet;
+    use crate::sinks::kafka::KafkaSink;
+    use super::*;
+
+    /// A pipeline that produces its alerts to a topic of the broker.
+    fn pipeline(broker: &MockBroker, alerts_topic: &str) -> Arc<Pipeline> {
+        let rule: SigmaRule = serde_yaml::from_str(r#"
+            title: Whoami
+            id: whoami
+            detection:
+              selection:
+                CommandLine: whoami
+              condition: selection
+        "#).unwrap();
+        let sink = KafkaSink::new(slice::from_ref(&broker.address), alerts_topic);
+        Arc::new(Pipeline::new(RuleSet::from_rules(vec![rule], LoadReport::default()), RoutingConfig::default()).with_sinks(vec![Box::new(sink)]))
+    }
+
+    fn start_consumer(broker: &MockBroker, pipeline: Arc<Pipeline>) -> KafkaConsumer {
+        KafkaConsumer::new(slice::from_ref(&broker.address), &["logs".to_string()], "log-analyzer", Logsource::default(), pipeline)
+    }
+
+    #[test]
+    fn consume_and_commit_after_alerts_are_delivered() {
+        let broker = MockBroker::start();
+        broker.create_topic("logs", 2);
+        broker.create_topic("alerts", 1);
+        broker.append("logs", 0, r#"{"CommandLine": "whoami"}"#);
+        broker.append("logs", 0, "not json");
+        broker.append("logs", 1, r#"{"CommandLine": "ls"}"#);
+
+        let pipeline = pipeline(&broker, "alerts");
+        let mut consumer = start_consumer(&broker, pipeline.clone());
+        assert_eq!(consumer.poll().unwrap(), 3);
+        assert_eq!(pipeline.events(), 2);
+        assert_eq!((broker.committed("log-analyzer", "logs", 0), broker.committed("log-analyzer", "logs", 1)), (Some(2), Some(1)));
+
+        let alerts = broker.records("alerts", 0);
+        assert_eq!(alerts.len(), 1);
+        let alert: Alert = serde_json::from_slice(alerts[0].value.as_ref().unwrap()).unwrap();
+        assert_eq!((alert.id.as_str(), alert.event.get_str("CommandLine")), ("whoami", Some("whoami")));
+
+        // a restarted consumer continues at the committed offsets
+        broker.append("logs", 1, r#"{"CommandLine": "whoami"}"#);
+        let mut consumer = start_consumer(&broker, pipeline.clone());
+        assert_eq!(consumer.poll().unwrap(), 1);
+        assert_eq!(consumer.poll().unwrap(), 0);
+        assert_eq!(broker.committed("log-analyzer", "logs", 1), Some(2));
+        assert_eq!(broker.records("alerts", 0).len(), 2);
+    }
+
+    #[test]
+    fn consume_compressed_and_control_batches() {
+        let broker = MockBroker::start();
+        broker.create_topic("logs", 1);
+        broker.create_topic("alerts", 1);
+        broker.append_batch("logs", 0, &[r#"{"CommandLine": "whoami"}"#, r#"{"CommandLine": "ls"}"#], GZIP);
+        broker.append_batch("logs", 0, &["commit marker"], CONTROL);
+
+        let mut consumer = start_consumer(&broker, pipeline(&broker, "alerts"));
+        assert_eq!(consumer.poll().unwrap(), 2);
+        // the offset after the control batch is committed, so the consumer does not fetch it again
+        assert_eq!(broker.committed("log-analyzer", "logs", 0), Some(3));
+        assert_eq!(consumer.poll().unwrap(), 0);
+        assert_eq!(broker.records("alerts", 0).len(), 1);
+
+        broker.append_batch("logs", 0, &[r#"{"CommandLine": "whoami"}"#], 2);
+        let err = consumer.poll().unwrap_err().to_string();
+        assert!(err.contains("compressed with snappy are not supported"), "{}", err);
+        assert_eq!(broker.committed("log-analyzer", "logs", 0), Some(3));
+    }
+
+    #[test]
+    fn consume_from_new_leader() {
+        let (broker, leader) = (MockBroker::start(), MockBroker::start());
+        broker.create_topic("logs", 1);
+        broker.append("logs", 0, r#"{"CommandLine": "ls"}"#);
+        for broker in [&broker, &leader] {
+            broker.create_topic("alerts", 1);
+        }
+
+        let mut consumer = start_consumer(&broker, pipeline(&broker, "alerts"));
+        assert_eq!(consumer.poll().unwrap(), 1);
+
+        // the new leader has the records of the partition, and the offsets stay with the coordinator
+        leader.create_topic("logs", 1);
+        leader.append("logs", 0, r#"{"CommandLine": "ls"}"#);
+        leader.append("logs", 0, r#"{"CommandLine": "whoami"}"#);
+        broker.move_leader(&leader);
+        assert_eq!(consumer.poll().unwrap(), 0);
+        assert!(consumer.session.is_none());
+        assert_eq!(consumer.poll().unwrap(), 1);
+        assert_eq!(broker.committed("log-analyzer", "logs", 0), Some(2));
+        assert_eq!(leader.records("alerts", 0).len(), 1);
+    }
+
+    #[test]
+    fn keep_offsets_when_alerts_are_not_delivered() {
+        let broker = MockBroker::start();
+        broker.create_topic("logs", 1);
+        broker.append("logs", 0, r#"{"CommandLine": "ls"}"#);
+        broker.append("logs", 0, r#"{"CommandLine": "whoami"}"#);
+
+        let mut consumer = start_consumer(&broker, pipeline(&broker, "missing"));
+        assert!(consumer.poll().unwrap_err().to_string().contains("Not committing offsets"));
+        assert_eq!(broker.committed("log-analyzer", "logs", 0), None);
+
+        broker.create_topic("missing", 1);
+        let mut consumer = start_consumer(&broker, pipeline(&broker, "missing"));
+        assert_eq!(consumer.poll().unwrap(), 2);
+        assert_eq!(broker.committed("log-analyzer", "logs", 0), Some(2));
+        assert_eq!(broker.records("missing", 0).len(), 1);
+    }
+}
@@ -0,0 +1,218 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use log::{info, warn};
+use sigma_log_parser::log_event::LogEvent;
+use sigma_rule_parser::structs::sigma_rule::Logsource;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader};
+use tokio::net::{TcpListener, UdpSocket};
+use crate::config::SyslogProtocol;
+use crate::pipeline::Pipeline;
+
+/// The largest syslog message that is read. RFC 5425 requires receivers to accept at least 2048 bytes, and larger UDP datagrams are truncated.
+const MAX_MESSAGE_SIZE: usize = 65_536;
+
+/// Receives syslog messages (RFC 3164 and RFC 5424) and evaluates them in the pipeline, until the socket fails.
+pub async fn listen(protocol: SyslogProtocol, address: SocketAddr, logsource: Logsource, pipeline: Arc<Pipeline>) -> io::Result<()> {
+    match protocol {
+        SyslogProtocol::Udp => listen_udp(UdpSocket::bind(address).await?, logsource, pipeline).await,
+        SyslogProtocol::Tcp => listen_tcp(TcpListener::bind(address).await?, logsource, pipeline).await,
+    }
+}
+
+/// Every datagram is a single message.
+pub async fn listen_udp(socket: UdpSocket, logsource: Logsource, pipeline: Arc<Pipeline>) -> io::Result<()> {
+    info!("Receiving syslog on udp {}", socket.local_addr()?);
+    let mut buffer = vec![0; MAX_MESSAGE_SIZE];
+    loop {
+        let (size, sender) = socket.recv_from(&mut buffer).await?;
+        evaluate_message(&buffer[..size], &sender, &logsource, &pipeline);
+    }
+}
+
+/// Every connection is read on its own task, so a slow sender does not hold up the others.
+pub async fn listen_tcp(listener: TcpListener, logsource: Logsource, pipeline: Arc<Pipeline>) -> io::Result<()> {
+    info!("Receiving syslog on tcp {}", listener.local_addr()?);
+    loop {
+        let (stream, sender) = listener.accept().await?;
+        let logsource = logsource.clone();
+        let pipeline = pipeline.clone();
+        actix_web::rt::spawn(async move {
+            if let Err(err) = read_stream(BufReader::new(stream), &sender, &logsource, &pipeline).await {
+                warn!("Closed syslog connection of {} - {}", sender, err);
+            }
+        });
+    }
+}
+
+/// Reads the messages of a TCP stream, which are either prefixed by their length (octet counting, RFC 6587)
+///     or terminated by a newline (non-transparent framing). Each message is framed on its own, as senders do not mix both.
+/// A message that starts with a digit is octet counted when the digits are followed by a space, and is terminated by a newline otherwise.
+async fn read_stream<R: AsyncRead + Unpin>(mut stream: BufReader<R>, sender: &SocketAddr, logsource: &Logsource, pipeline: &Pipeline) -> io::Result<()> {
+    let mut message = Vec::new();
+    loop {
+        let first = match stream.fill_buf().await?.first() {
+            Some(first) => *first,
+            None => return Ok(()),
+        };
+
+        message.clear();
+        if first.is_ascii_digit() {
+            // the count of the largest message has 5 digits, so a prefix without a space in its first 10 bytes is not a count
+            (&mut stream).take(10).read_until(b' ', &mut message).await?;
+            if let Some(length) = octet_count(&message) {
+                if length > MAX_MESSAGE_SIZE {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, format!("octet count {} exceeds {}", length, MAX_MESSAGE_SIZE)));
+                }
+                message.resize(length, 0);
+                stream.read_exact(&mut message).await?;
+                evaluate_message(&message, sender, logsource, pipeline);
+                continue;
+            }
+            // the prefix is the start of messages terminated by a newline, of which the first ones may have ended within the prefix
+            while let Some(end) = message.iter().position(|byte| *byte == b'\n') {
+                evaluate_message(&message[..=end], sender, logsource, pipeline);
+                message.drain(..=end);
+            }
+            if message.is_empty() {
+                continue;
+            }
+        }
+        // the limit keeps a sender that never sends a newline from growing the message without bound
+        (&mut stream).take((MAX_MESSAGE_SIZE - message.len()) as u64).read_until(b'\n', &mut message).await?;
+
+        evaluate_message(&message, sender, logsource, pipeline);
+    }
+}
+
+/// The length of an octet counted message, of a prefix of digits that is followed by a space.
+fn octet_count(prefix: &[u8]) -> Option<usize> {
+    let digits = prefix.strip_suffix(b" ")?;
+    if !digits.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    std::str::from_utf8(digits).ok()?.parse().ok()
+}
+
+fn evaluate_message(message: &[u8], sender: &SocketAddr, logsource: &Logsource, pipeline: &Pipeline) {
+    let message = String::from_utf8_lossy(message);
+    if message.trim().is_empty() {
+        return;
+    }
+
+    match LogEvent::from_syslog(&message) {
+        Ok(event) => {
+            pipeline.evaluate(&event, logsource);
+        }
+        Err(err) => warn!("Skipping syslog message of {} - {}", sender, err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use sigma_rule_parser::sigma_file::load_report::LoadReport;
+    use sigma_rule_parser::structs::sigma_rule::SigmaRule;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpStream;
+    use crate::config::RoutingConfig;
+    use crate::rule_set::RuleSet;
+    use super::*;
+
+    /// A rule with a count aggregation, so the test sees that the received messages were parsed.
+    fn pipeline() -> Arc<Pipeline> {
+        let rule: SigmaRule = serde_yaml::from_str(r#"
+            title: Failed su
+            id: failed_su
+            logsource:
+              product: linux
+            detection:
+              selection:
+                app_name: su
+                message|contains: failed
+                sd.origin.ip: 10.0.0.1
+              timeframe: 1h
+              condition: selection | count() > 3
+        "#).unwrap();
+        Arc::new(Pipeline::new(RuleSet::from_rules(vec![rule], LoadReport::default()), RoutingConfig::default()))
+    }
+
+    fn matches(pipeline: &Pipeline) -> bool {
+        let event = LogEvent::from_syslog(r#"<34>1 - - su - - [origin ip="10.0.0.1"] failed"#).unwrap();
+        !pipeline.evaluate(&event, &Logsource::default()).is_empty()
+    }
+
+    async fn wait_for<F: Fn() -> bool>(condition: F) -> bool {
+        for _ in 0..100 {
+            if condition() {
+                return true;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        false
+    }
+
+    #[actix_web::test]
+    async fn receive_udp_messages() {
+        let pipeline = pipeline();
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = socket.local_addr().unwrap();
+        actix_web::rt::spawn(listen_udp(socket, Logsource::default(), pipeline.clone()));
+
+        let sender = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        sender.send_to(br#"<34>1 2003-10-11T22:14:15.003Z host su - ID47 [origin ip="10.0.0.1"] 'su root' failed"#, address).await.unwrap();
+        sender.send_to(b"not syslog", address).await.unwrap();
+
+        // the fourth matching event within the hour matches the aggregation, so the syslog messages were parsed, and the invalid one was skipped
+        assert!(wait_for(|| pipeline.events() == 1).await);
+        assert!(!matches(&pipeline));
+        sender.send_to(br#"<34>1 - host su - - [origin ip="10.0.0.1"] failed"#, address).await.unwrap();
+        assert!(wait_for(|| pipeline.events() == 3).await);
+        assert!(matches(&pipeline));
+    }
+
+    #[actix_web::test]
+    async fn receive_tcp_messages() {
+        let pipeline = pipeline();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        actix_web::rt::spawn(listen_tcp(listener, Logsource::default(), pipeline.clone()));
+
+        let message = r#"<34>1 2003-10-11T22:14:15.003Z host su - ID47 [origin ip="10.0.0.1"] 'su root' failed"#;
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream.write_all(format!("{} {}", message.len(), message).as_bytes()).await.unwrap();
+        stream.write_all(format!("{}\n", message).as_bytes()).await.unwrap();
+        stream.write_all(format!("{} {}", message.len(), message).as_bytes()).await.unwrap();
+        stream.shutdown().await.unwrap();
+
+        assert!(wait_for(|| pipeline.events() == 3).await);
+        assert!(matches(&pipeline));
+    }
+
+    #[actix_web::test]
+    async fn frame_messages_that_start_with_digits_by_newlines() {
+        let pipeline = pipeline();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        actix_web::rt::spawn(listen_tcp(listener, Logsource::default(), pipeline.clone()));
+
+        // lines without a priority are skipped, the connection is kept for the octet counted message after them
+        let message = "Oct 11 22:14:15 host su: 'su root' failed";
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream.write_all(format!("1234567890123 {}\n", message).as_bytes()).await.unwrap();
+        stream.write_all(b"1\n2\n").await.unwrap();
+        stream.write_all(format!("2003-10-11T22:14:15Z{}\n", message).as_bytes()).await.unwrap();
+        stream.write_all(format!("{} <34>{}", message.len() + 4, message).as_bytes()).await.unwrap();
+        stream.shutdown().await.unwrap();
+
+        assert!(wait_for(|| pipeline.events() == 1).await);
+    }
+
+    #[test]
+    fn read_octet_counts() {
+        assert_eq!(octet_count(b"42 "), Some(42));
+        assert_eq!(octet_count(b"42"), None);
+        assert_eq!(octet_count(b"+42 "), None);
+        assert_eq!(octet_count(b"4\n2 "), None);
+    }
+}
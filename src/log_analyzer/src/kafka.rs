@@ -0,0 +1,761 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+// A client of the Kafka protocol (https://kafka.apache.org/protocol), as far as the log analyzer needs it.
+// Records are written in the v2 record batch format, which every broker since Kafka 0.11 (and Redpanda) reads,
+//     with request versions that Kafka 4 still accepts.
+// The established client, rdkafka, wraps librdkafka, a C library that is compiled with the log analyzer or linked from the system.
+//     The sink needs a single Produce request and the input a consumer that commits offsets, which are a few requests of the protocol
+//     that the tests run against a broker in memory (see mock), so the log analyzer stays a Rust build without that library.
+//     What it does not support is left to a client such as rdkafka: TLS and SASL, consumer group membership, and compression other than gzip.
+
+#[cfg(test)]
+pub(crate) mod mock;
+
+const CLIENT_ID: &str = "log-analyzer";
+// the api keys and the versions of the requests
+const PRODUCE: (i16, i16) = (0, 3);
+const FETCH: (i16, i16) = (1, 4);
+const LIST_OFFSETS: (i16, i16) = (2, 1);
+const METADATA: (i16, i16) = (3, 4);
+const OFFSET_COMMIT: (i16, i16) = (8, 2);
+const OFFSET_FETCH: (i16, i16) = (9, 1);
+const FIND_COORDINATOR: (i16, i16) = (10, 1);
+/// The timestamp of ListOffsets that asks for the first offset of a partition.
+pub const EARLIEST: i64 = -2;
+/// The error code of a fetch from an offset that the partition does not have (anymore).
+pub const OFFSET_OUT_OF_RANGE: i16 = 1;
+/// The error code of a request for a partition that the broker does not know (anymore), i.e. after it was moved to another broker.
+pub const UNKNOWN_TOPIC_OR_PARTITION: i16 = 3;
+/// The error code of a request to a broker that does not lead the partition (anymore).
+pub const NOT_LEADER_OR_FOLLOWER: i16 = 6;
+/// Responses are read into memory, so a broker can not make the client allocate without bound.
+const MAX_RESPONSE_SIZE: usize = 64 * 1024 * 1024;
+/// The attributes of a record batch: the compression codec in the lowest 3 bits, and whether it is a control batch (a transaction marker).
+pub const COMPRESSION_CODEC: i16 = 0x07;
+pub const GZIP: i16 = 1;
+pub const CONTROL: i16 = 0x20;
+
+pub type TopicPartition = (String, i32);
+
+/// The error code of a response that is not 0, which is the source of the io::Error that check_error returns.
+#[derive(Debug)]
+pub struct KafkaError {
+    pub error_code: i16,
+}
+
+/// The brokers of a cluster by their node id, and the node id of the leader of every partition of the topics.
+#[derive(Debug, Default, PartialEq)]
+pub struct Metadata {
+    pub brokers: BTreeMap<i32, String>,
+    pub leaders: BTreeMap<TopicPartition, i32>,
+}
+
+/// The records of a partition that a fetch returned, unless the error code is not 0.
+#[derive(Debug, PartialEq)]
+pub struct FetchedPartition {
+    pub topic_partition: TopicPartition,
+    pub error_code: i16,
+    pub records: RecordSet,
+}
+
+/// The records of the complete batches of a record set, and the offset after the last of these batches,
+///     which is past the last record when the batches end with a control batch.
+#[derive(Debug, Default, PartialEq)]
+pub struct RecordSet {
+    pub records: Vec<Record>,
+    pub next_offset: Option<i64>,
+}
+
+/// A record of a topic partition. The timestamp is in milliseconds since the epoch.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    pub offset: i64,
+    pub timestamp: i64,
+    pub key: Option<Vec<u8>>,
+    pub value: Option<Vec<u8>>,
+}
+
+/// A connection to a broker, which sends one request at a time.
+#[derive(Debug)]
+pub struct Connection {
+    stream: TcpStream,
+    correlation_id: i32,
+}
+
+impl Record {
+    pub fn new(key: Option<Vec<u8>>, value: Vec<u8>) -> Record {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_millis() as i64).unwrap_or_default();
+        Record { offset: 0, timestamp, key, value: Some(value) }
+    }
+}
+
+impl Connection {
+    /// Connects to the first of the brokers that accepts the connection. A broker that can not be resolved is skipped as one that refuses it.
+    pub fn connect(brokers: &[String], timeout: Duration) -> io::Result<Connection> {
+        let mut last_error = io::Error::new(io::ErrorKind::InvalidInput, "no brokers");
+        for broker in brokers {
+            let addresses = match broker.to_socket_addrs() {
+                Ok(addresses) => addresses,
+                Err(err) => {
+                    last_error = io::Error::new(err.kind(), format!("{} - {}", broker, err));
+                    continue;
+                }
+            };
+            for address in addresses {
+                match TcpStream::connect_timeout(&address, timeout) {
+                    Ok(stream) => {
+                        stream.set_read_timeout(Some(timeout))?;
+                        stream.set_write_timeout(Some(timeout))?;
+                        stream.set_nodelay(true)?;
+                        return Ok(Connection { stream, correlation_id: 0 });
+                    }
+                    Err(err) => last_error = err,
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Writes the records to a partition of a topic, and returns the offset of the first record.
+    /// The request waits for the leader of the partition to write the records (acks = 1).
+    pub fn produce(&mut self, topic: &str, partition: i32, records: &[Record]) -> io::Result<i64> {
+        let mut body = Vec::new();
+        put_nullable_string(&mut body, None);
+        put_i16(&mut body, 1);
+        put_i32(&mut body, 10_000);
+        put_i32(&mut body, 1);
+        put_string(&mut body, topic);
+        put_i32(&mut body, 1);
+        put_i32(&mut body, partition);
+        put_bytes(&mut body, &encode_record_batch(records));
+
+        let response = self.request(PRODUCE, &body)?;
+        // the response has the single topic and partition of the request
+        let mut response = Decoder::new(&response);
+        let topics = response.i32()?;
+        response.string()?;
+        if topics != 1 || response.i32()? != 1 {
+            return Err(invalid_data("the produce response is not the response of a single partition"));
+        }
+        let (_, error_code, base_offset) = (response.i32()?, response.i16()?, response.i64()?);
+        check_error(error_code)?;
+        Ok(base_offset)
+    }
+
+    /// The brokers of the cluster, and the leaders of the partitions of the topics.
+    pub fn metadata(&mut self, topics: &[String]) -> io::Result<Metadata> {
+        let mut body = Vec::new();
+        put_i32(&mut body, topics.len() as i32);
+        for topic in topics {
+            put_string(&mut body, topic);
+        }
+        body.push(0);
+
+        let response = self.request(METADATA, &body)?;
+        let mut response = Decoder::new(&response);
+        let mut metadata = Metadata::default();
+        response.i32()?;
+        for _ in 0..response.i32()? {
+            let (node_id, host, port) = (response.i32()?, response.string()?, response.i32()?);
+            response.nullable_string()?;
+            metadata.brokers.insert(node_id, format!("{}:{}", host, port));
+        }
+        response.nullable_string()?;
+        response.i32()?;
+        for _ in 0..response.i32()? {
+            let (error_code, topic) = (response.i16()?, response.string()?);
+            check_error(error_code).map_err(|err| io::Error::new(err.kind(), format!("{} of topic {}", err, topic)))?;
+            response.i8()?;
+            for _ in 0..response.i32()? {
+                let (_, partition, leader) = (response.i16()?, response.i32()?, response.i32()?);
+                response.array(Decoder::i32)?;
+                response.array(Decoder::i32)?;
+                metadata.leaders.insert((topic.clone(), partition), leader);
+            }
+        }
+        Ok(metadata)
+    }
+
+    /// The address of the broker that keeps the offsets of a consumer group.
+    pub fn find_coordinator(&mut self, group_id: &str) -> io::Result<String> {
+        let mut body = Vec::new();
+        put_string(&mut body, group_id);
+        body.push(0);
+
+        let response = self.request(FIND_COORDINATOR, &body)?;
+        let mut response = Decoder::new(&response);
+        response.i32()?;
+        let error_code = response.i16()?;
+        response.nullable_string()?;
+        check_error(error_code)?;
+        let (_, host, port) = (response.i32()?, response.string()?, response.i32()?);
+        Ok(format!("{}:{}", host, port))
+    }
+
+    /// The offsets that a consumer group committed for the partitions. Partitions without an offset are left out.
+    pub fn offset_fetch(&mut self, group_id: &str, partitions: &[TopicPartition]) -> io::Result<BTreeMap<TopicPartition, i64>> {
+        let mut body = Vec::new();
+        put_string(&mut body, group_id);
+        put_topics(&mut body, partitions.iter().map(|topic_partition| (topic_partition, ())), |_, _| {});
+
+        let response = self.request(OFFSET_FETCH, &body)?;
+        let mut offsets = BTreeMap::new();
+        read_topics(&mut Decoder::new(&response), |topic_partition, response| {
+            let offset = response.i64()?;
+            response.nullable_string()?;
+            check_error(response.i16()?)?;
+            if offset >= 0 {
+                offsets.insert(topic_partition, offset);
+            }
+            Ok(())
+        })?;
+        Ok(offsets)
+    }
+
+    /// Commits the offsets of a consumer group, as a consumer that is not a member of the group.
+    pub fn offset_commit(&mut self, group_id: &str, offsets: &BTreeMap<TopicPartition, i64>) -> io::Result<()> {
+        let mut body = Vec::new();
+        put_string(&mut body, group_id);
+        put_i32(&mut body, -1);
+        put_string(&mut body, "");
+        put_i64(&mut body, -1);
+        put_topics(&mut body, offsets.iter(), |body, offset| {
+            put_i64(body, *offset);
+            put_nullable_string(body, None);
+        });
+
+        let response = self.request(OFFSET_COMMIT, &body)?;
+        read_topics(&mut Decoder::new(&response), |_, response| check_error(response.i16()?))
+    }
+
+    /// The offsets of the partitions at a timestamp, i.e. EARLIEST.
+    pub fn list_offsets(&mut self, partitions: &[TopicPartition], timestamp: i64) -> io::Result<BTreeMap<TopicPartition, i64>> {
+        let mut body = Vec::new();
+        put_i32(&mut body, -1);
+        put_topics(&mut body, partitions.iter().map(|topic_partition| (topic_partition, ())), |body, _| put_i64(body, timestamp));
+
+        let response = self.request(LIST_OFFSETS, &body)?;
+        let mut offsets = BTreeMap::new();
+        read_topics(&mut Decoder::new(&response), |topic_partition, response| {
+            check_error(response.i16()?)?;
+            response.i64()?;
+            offsets.insert(topic_partition, response.i64()?);
+            Ok(())
+        })?;
+        Ok(offsets)
+    }
+
+    /// Fetches the records of the partitions from their offsets, and waits up to the max wait for records when there are none.
+    /// The records of a batch before the offset may be returned as well.
+    pub fn fetch(&mut self, offsets: &BTreeMap<TopicPartition, i64>, max_wait: Duration) -> io::Result<Vec<FetchedPartition>> {
+        let mut body = Vec::new();
+        put_i32(&mut body, -1);
+        put_i32(&mut body, max_wait.as_millis() as i32);
+        put_i32(&mut body, 1);
+        put_i32(&mut body, 50 * 1024 * 1024);
+        body.push(0);
+        put_topics(&mut body, offsets.iter(), |body, offset| {
+            put_i64(body, *offset);
+            put_i32(body, 1024 * 1024);
+        });
+
+        let response = self.request(FETCH, &body)?;
+        let mut response = Decoder::new(&response);
+        response.i32()?;
+        let mut fetched = Vec::new();
+        read_topics(&mut response, |topic_partition, response| {
+            let error_code = response.i16()?;
+            response.take(8 + 8)?;
+            response.array(|aborted| aborted.take(16).map(|_| ()))?;
+            let records = match response.bytes()? {
+                Some(bytes) if error_code == 0 => decode_record_batches(bytes)?,
+                _ => RecordSet::default(),
+            };
+            fetched.push(FetchedPartition { topic_partition, error_code, records });
+            Ok(())
+        })?;
+        Ok(fetched)
+    }
+
+    /// Sends a request and reads its response, of which the body is returned.
+    pub fn request(&mut self, (api_key, api_version): (i16, i16), body: &[u8]) -> io::Result<Vec<u8>> {
+        self.correlation_id = self.correlation_id.wrapping_add(1);
+        let mut request = Vec::with_capacity(body.len() + 32);
+        put_i16(&mut request, api_key);
+        put_i16(&mut request, api_version);
+        put_i32(&mut request, self.correlation_id);
+        put_string(&mut request, CLIENT_ID);
+        request.extend_from_slice(body);
+
+        write_frame(&mut self.stream, &request)?;
+        let response = read_frame(&mut self.stream)?;
+        let mut decoder = Decoder::new(&response);
+        if decoder.i32()? != self.correlation_id {
+            return Err(invalid_data("the response is not the response of the request"));
+        }
+        Ok(response[4..].to_vec())
+    }
+}
+
+/// Writes the partitions grouped by their topic, with the values that the put function writes for every partition.
+fn put_topics<'a, T, I, F>(buffer: &mut Vec<u8>, partitions: I, mut put: F)
+where
+    T: 'a,
+    I: IntoIterator<Item = (&'a TopicPartition, T)>,
+    F: FnMut(&mut Vec<u8>, T),
+{
+    let mut topics: BTreeMap<&str, Vec<(i32, T)>> = BTreeMap::new();
+    for ((topic, partition), value) in partitions {
+        topics.entry(topic).or_default().push((*partition, value));
+    }
+
+    put_i32(buffer, topics.len() as i32);
+    for (topic, partitions) in topics {
+        put_string(buffer, topic);
+        put_i32(buffer, partitions.len() as i32);
+        for (partition, value) in partitions {
+            put_i32(buffer, partition);
+            put(buffer, value);
+        }
+    }
+}
+
+/// Reads the partitions of a response grouped by their topic, where the read function reads the values of every partition.
+fn read_topics<'a, F>(decoder: &mut Decoder<'a>, mut read: F) -> io::Result<()>
+where
+    F: FnMut(TopicPartition, &mut Decoder<'a>) -> io::Result<()>,
+{
+    for _ in 0..decoder.i32()? {
+        let topic = decoder.string()?;
+        for _ in 0..decoder.i32()? {
+            let partition = decoder.i32()?;
+            read((topic.clone(), partition), decoder)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes a request or response with its size in front of it.
+pub fn write_frame<W: Write>(writer: &mut W, frame: &[u8]) -> io::Result<()> {
+    writer.write_all(&(frame.len() as i32).to_be_bytes())?;
+    writer.write_all(frame)?;
+    writer.flush()
+}
+
+pub fn read_frame<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut size = [0; 4];
+    reader.read_exact(&mut size)?;
+    let size = usize::try_from(i32::from_be_bytes(size)).map_err(|_| invalid_data("negative frame size"))?;
+    if size > MAX_RESPONSE_SIZE {
+        return Err(invalid_data("frame too large"));
+    }
+    let mut frame = vec![0; size];
+    reader.read_exact(&mut frame)?;
+    Ok(frame)
+}
+
+/// Fails with the name of a Kafka error code, of which 0 is no error. The code is kept as a KafkaError, see error_code.
+pub fn check_error(error_code: i16) -> io::Result<()> {
+    match error_code {
+        0 => Ok(()),
+        _ => Err(io::Error::other(KafkaError { error_code })),
+    }
+}
+
+/// The Kafka error code of an error that check_error returned.
+pub fn error_code(err: &io::Error) -> Option<i16> {
+    err.get_ref()?.downcast_ref::<KafkaError>().map(|err| err.error_code)
+}
+
+impl Display for KafkaError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self.error_code {
+            1 => "OFFSET_OUT_OF_RANGE",
+            2 => "CORRUPT_MESSAGE",
+            3 => "UNKNOWN_TOPIC_OR_PARTITION",
+            5 => "LEADER_NOT_AVAILABLE",
+            6 => "NOT_LEADER_OR_FOLLOWER",
+            7 => "REQUEST_TIMED_OUT",
+            10 => "MESSAGE_TOO_LARGE",
+            14 => "COORDINATOR_LOAD_IN_PROGRESS",
+            15 => "COORDINATOR_NOT_AVAILABLE",
+            16 => "NOT_COORDINATOR",
+            22 => "ILLEGAL_GENERATION",
+            25 => "UNKNOWN_MEMBER_ID",
+            29 => "TOPIC_AUTHORIZATION_FAILED",
+            30 => "GROUP_AUTHORIZATION_FAILED",
+            35 => "UNSUPPORTED_VERSION",
+            error_code => return write!(f, "Kafka error code {}", error_code),
+        };
+        write!(f, "Kafka error {}", name)
+    }
+}
+
+impl Error for KafkaError {}
+
+/// Encodes the records as a record batch (magic 2). The offset of the first record is the base offset of the batch, which a producer leaves at 0,
+///     and the records that follow it have the offsets after it.
+pub fn encode_record_batch(records: &[Record]) -> Vec<u8> {
+    encode_batch(records, 0)
+}
+
+/// Encodes the records as a record batch with the attributes, of which only the gzip codec is compressed.
+pub fn encode_batch(records: &[Record], attributes: i16) -> Vec<u8> {
+    let base_offset = records.first().map(|record| record.offset).unwrap_or_default();
+    let first_timestamp = records.first().map(|record| record.timestamp).unwrap_or_default();
+    let max_timestamp = records.iter().map(|record| record.timestamp).max().unwrap_or_default();
+
+    // everything after the crc, which the crc is computed over
+    let mut batch = Vec::new();
+    put_i16(&mut batch, attributes);
+    put_i32(&mut batch, records.len() as i32 - 1);
+    put_i64(&mut batch, first_timestamp);
+    put_i64(&mut batch, max_timestamp);
+    put_i64(&mut batch, -1);
+    put_i16(&mut batch, -1);
+    put_i32(&mut batch, -1);
+    put_i32(&mut batch, records.len() as i32);
+    let mut encoded_records = Vec::new();
+    for (offset_delta, record) in records.iter().enumerate() {
+        let mut encoded = Vec::new();
+        encoded.push(0);
+        put_varint(&mut encoded, record.timestamp - first_timestamp);
+        put_varint(&mut encoded, offset_delta as i64);
+        for bytes in [&record.key, &record.value] {
+            match bytes {
+                Some(bytes) => {
+                    put_varint(&mut encoded, bytes.len() as i64);
+                    encoded.extend_from_slice(bytes);
+                }
+                None => put_varint(&mut encoded, -1),
+            }
+        }
+        put_varint(&mut encoded, 0);
+
+        put_varint(&mut encoded_records, encoded.len() as i64);
+        encoded_records.extend_from_slice(&encoded);
+    }
+    if attributes & COMPRESSION_CODEC == GZIP {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        // writing to a vector does not fail
+        let _ = encoder.write_all(&encoded_records);
+        encoded_records = encoder.finish().unwrap_or_default();
+    }
+    batch.extend_from_slice(&encoded_records);
+
+    let mut encoded = Vec::with_capacity(batch.len() + 21);
+    put_i64(&mut encoded, base_offset);
+    put_i32(&mut encoded, batch.len() as i32 + 9);
+    put_i32(&mut encoded, -1);
+    encoded.push(2);
+    encoded.extend_from_slice(&crc32c(&batch).to_be_bytes());
+    encoded.extend_from_slice(&batch);
+    encoded
+}
+
+/// Decodes the record batches of a record set, which a broker may end with a partial batch.
+/// Gzip compressed batches are decompressed, the records of control batches are left out, and a batch of another codec
+///     or of a message format before magic 2 (Kafka 0.11) is an error, so a consumer never passes over records that it did not read.
+pub fn decode_record_batches(bytes: &[u8]) -> io::Result<RecordSet> {
+    let mut record_set = RecordSet::default();
+    let mut decoder = Decoder::new(bytes);
+
+    while decoder.remaining() >= 12 {
+        let base_offset = decoder.i64()?;
+        let length = usize::try_from(decoder.i32()?).map_err(|_| invalid_data("negative batch length"))?;
+        if decoder.remaining() < length {
+            break;
+        }
+        let mut batch = Decoder::new(decoder.take(length)?);
+        batch.i32()?;
+        let magic = batch.i8()?;
+        if magic != 2 {
+            return Err(invalid_data(&format!("record batches of magic {} are not supported", magic)));
+        }
+        let crc = batch.i32()? as u32;
+        if crc32c(batch.rest()) != crc {
+            return Err(invalid_data("the crc of a record batch does not match"));
+        }
+        let attributes = batch.i16()?;
+        let last_offset_delta = batch.i32()?;
+        let first_timestamp = batch.i64()?;
+        batch.take(8 + 8 + 2 + 4)?;
+        let count = batch.i32()?;
+        record_set.next_offset = Some(base_offset + i64::from(last_offset_delta) + 1);
+        if attributes & CONTROL != 0 {
+            continue;
+        }
+
+        let decompressed;
+        let mut batch = match attributes & COMPRESSION_CODEC {
+            0 => batch,
+            GZIP => {
+                decompressed = gunzip(batch.rest())?;
+                Decoder::new(&decompressed)
+            }
+            codec => {
+                let name = match codec {
+                    2 => "snappy",
+                    3 => "lz4",
+                    4 => "zstd",
+                    _ => "an unknown codec",
+                };
+                return Err(invalid_data(&format!("record batches compressed with {} are not supported, only gzip", name)));
+            }
+        };
+        for _ in 0..count {
+            let length = usize::try_from(batch.varint()?).map_err(|_| invalid_data("negative record length"))?;
+            let mut record = Decoder::new(batch.take(length)?);
+            record.i8()?;
+            let timestamp = first_timestamp + record.varint()?;
+            let offset = base_offset + record.varint()?;
+            let key = record.varint_bytes()?;
+            let value = record.varint_bytes()?;
+            record_set.records.push(Record { offset, timestamp, key, value });
+        }
+    }
+
+    Ok(record_set)
+}
+
+/// Decompresses the records of a gzip batch, which may not be larger than a response.
+fn gunzip(bytes: &[u8]) -> io::Result<Vec<u8>> {
+    let mut decompressed = Vec::new();
+    MultiGzDecoder::new(bytes).take(MAX_RESPONSE_SIZE as u64 + 1).read_to_end(&mut decompressed)?;
+    if decompressed.len() > MAX_RESPONSE_SIZE {
+        return Err(invalid_data("decompressed record batch too large"));
+    }
+    Ok(decompressed)
+}
+
+/// A reader of the big endian values of requests and responses.
+pub struct Decoder<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(bytes: &'a [u8]) -> Decoder<'a> {
+        Decoder { bytes, position: 0 }
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.bytes.len() - self.position
+    }
+
+    pub fn rest(&self) -> &'a [u8] {
+        &self.bytes[self.position..]
+    }
+
+    pub fn take(&mut self, length: usize) -> io::Result<&'a [u8]> {
+        if self.remaining() < length {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the message ended early"));
+        }
+        self.position += length;
+        Ok(&self.bytes[self.position - length..self.position])
+    }
+
+    /// An array with an int32 length, where -1 is null, of which every element is read by the read function.
+    pub fn array<T, F: FnMut(&mut Decoder<'a>) -> io::Result<T>>(&mut self, mut read: F) -> io::Result<Vec<T>> {
+        let length = self.i32()?;
+        (0..length.max(0)).map(|_| read(self)).collect()
+    }
+
+    pub fn i8(&mut self) -> io::Result<i8> {
+        Ok(self.take(1)?[0] as i8)
+    }
+
+    pub fn i16(&mut self) -> io::Result<i16> {
+        Ok(i16::from_be_bytes(self.take(2)?.try_into().unwrap_or_default()))
+    }
+
+    pub fn i32(&mut self) -> io::Result<i32> {
+        Ok(i32::from_be_bytes(self.take(4)?.try_into().unwrap_or_default()))
+    }
+
+    pub fn i64(&mut self) -> io::Result<i64> {
+        Ok(i64::from_be_bytes(self.take(8)?.try_into().unwrap_or_default()))
+    }
+
+    /// A string with an int16 length, where -1 is null.
+    pub fn nullable_string(&mut self) -> io::Result<Option<String>> {
+        let length = self.i16()?;
+        if length < 0 {
+            return Ok(None);
+        }
+        let bytes = self.take(length as usize)?;
+        Ok(Some(String::from_utf8_lossy(bytes).into_owned()))
+    }
+
+    pub fn string(&mut self) -> io::Result<String> {
+        Ok(self.nullable_string()?.unwrap_or_default())
+    }
+
+    /// Bytes with an int32 length, where -1 is null.
+    pub fn bytes(&mut self) -> io::Result<Option<&'a [u8]>> {
+        let length = self.i32()?;
+        if length < 0 {
+            return Ok(None);
+        }
+        Ok(Some(self.take(length as usize)?))
+    }
+
+    /// A zigzag encoded variable length integer.
+    pub fn varint(&mut self) -> io::Result<i64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok((value >> 1) as i64 ^ -((value & 1) as i64));
+            }
+        }
+        Err(invalid_data("varint too long"))
+    }
+
+    fn varint_bytes(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let length = self.varint()?;
+        if length < 0 {
+            return Ok(None);
+        }
+        Ok(Some(self.take(length as usize)?.to_vec()))
+    }
+}
+
+pub fn put_i16(buffer: &mut Vec<u8>, value: i16) {
+    buffer.extend_from_slice(&value.to_be_bytes());
+}
+
+pub fn put_i32(buffer: &mut Vec<u8>, value: i32) {
+    buffer.extend_from_slice(&value.to_be_bytes());
+}
+
+pub fn put_i64(buffer: &mut Vec<u8>, value: i64) {
+    buffer.extend_from_slice(&value.to_be_bytes());
+}
+
+pub fn put_string(buffer: &mut Vec<u8>, value: &str) {
+    put_nullable_string(buffer, Some(value));
+}
+
+pub fn put_nullable_string(buffer: &mut Vec<u8>, value: Option<&str>) {
+    match value {
+        Some(value) => {
+            put_i16(buffer, value.len() as i16);
+            buffer.extend_from_slice(value.as_bytes());
+        }
+        None => put_i16(buffer, -1),
+    }
+}
+
+pub fn put_bytes(buffer: &mut Vec<u8>, value: &[u8]) {
+    put_i32(buffer, value.len() as i32);
+    buffer.extend_from_slice(value);
+}
+
+pub fn put_varint(buffer: &mut Vec<u8>, value: i64) {
+    let mut value = ((value << 1) ^ (value >> 63)) as u64;
+    while value >= 0x80 {
+        buffer.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+/// The CRC-32C (Castagnoli) checksum, which record batches are checked with.
+fn crc32c(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0x82f6_3b78 & (!(crc & 1)).wrapping_add(1));
+        }
+    }
+    !crc
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connect_to_the_brokers_that_resolve() {
+        let broker = mock::MockBroker::start();
+        let brokers = vec!["unresolvable.invalid:9092".to_string(), broker.address.clone()];
+        assert!(Connection::connect(&brokers, Duration::from_secs(5)).is_ok());
+
+        let err = Connection::connect(&brokers[..1], Duration::from_secs(5)).unwrap_err();
+        assert!(err.to_string().starts_with("unresolvable.invalid:9092 - "), "{}", err);
+    }
+
+    #[test]
+    fn crc32c_checksums() {
+        assert_eq!(crc32c(b""), 0);
+        assert_eq!(crc32c(b"123456789"), 0xe306_9283);
+    }
+
+    #[test]
+    fn varints() {
+        for value in [0, 1, -1, 63, -64, 64, 300, i64::MAX, i64::MIN] {
+            let mut buffer = Vec::new();
+            put_varint(&mut buffer, value);
+            assert_eq!(Decoder::new(&buffer).varint().unwrap(), value);
+        }
+
+        let mut buffer = Vec::new();
+        put_varint(&mut buffer, -1);
+        put_varint(&mut buffer, 150);
+        assert_eq!(buffer, vec![0x01, 0xac, 0x02]);
+    }
+
+    #[test]
+    fn encode_and_decode_record_batches() {
+        let records = vec![
+            Record { offset: 0, timestamp: 1_000, key: Some(b"key".to_vec()), value: Some(b"first".to_vec()) },
+            Record { offset: 1, timestamp: 1_500, key: None, value: Some(b"second".to_vec()) },
+        ];
+        let mut batches = encode_record_batch(&records);
+        assert_eq!(decode_record_batches(&batches).unwrap(), RecordSet { records: records.clone(), next_offset: Some(2) });
+
+        // a broker places the batch at its offset, and may end the record set with a partial batch
+        batches[..8].copy_from_slice(&40i64.to_be_bytes());
+        batches.extend_from_slice(&encode_record_batch(&records)[..30]);
+        let decoded = decode_record_batches(&batches).unwrap();
+        assert_eq!(decoded.records.iter().map(|record| record.offset).collect::<Vec<i64>>(), vec![40, 41]);
+        assert_eq!(decoded.next_offset, Some(42));
+
+        let last = batches.len() - 31;
+        batches[last] ^= 1;
+        assert!(decode_record_batches(&batches).is_err());
+    }
+
+    #[test]
+    fn decode_compressed_and_control_batches() {
+        let records = vec![
+            Record { offset: 0, timestamp: 1_000, key: None, value: Some(b"first".to_vec()) },
+            Record { offset: 1, timestamp: 1_000, key: None, value: Some(b"second".to_vec()) },
+        ];
+        let mut batches = encode_batch(&records, GZIP);
+        let mut control = encode_batch(&records[..1], CONTROL);
+        control[..8].copy_from_slice(&2i64.to_be_bytes());
+        batches.extend_from_slice(&control);
+        assert_eq!(decode_record_batches(&batches).unwrap(), RecordSet { records: records.clone(), next_offset: Some(3) });
+
+        let mut batch = encode_batch(&records, 2);
+        assert_eq!(decode_record_batches(&batch).unwrap_err().to_string(), "record batches compressed with snappy are not supported, only gzip");
+        batch[16] = 1;
+        assert_eq!(decode_record_batches(&batch).unwrap_err().to_string(), "record batches of magic 1 are not supported");
+    }
+}
@@ -0,0 +1,280 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use super::*;
+
+/// A single node Kafka broker in memory, which answers the requests of the log analyzer in the versions it sends them.
+/// Its partitions may be moved to another MockBroker, after which it names that broker as their leader (node 1).
+pub struct MockBroker {
+    pub address: String,
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    /// The batches of every partition of every topic
+    topics: BTreeMap<String, Vec<Vec<Batch>>>,
+    /// The committed offsets by group, topic and partition
+    offsets: BTreeMap<(String, TopicPartition), i64>,
+    /// The address of the broker that leads the partitions, when it is not this broker
+    leader: Option<String>,
+}
+
+/// Records with consecutive offsets, which are fetched with the attributes of the batch.
+#[derive(Clone)]
+struct Batch {
+    attributes: i16,
+    records: Vec<Record>,
+}
+
+impl MockBroker {
+    pub fn start() -> MockBroker {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let state = Arc::new(Mutex::new(State::default()));
+
+        let (broker_address, broker_state) = (address.clone(), state.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let (address, state) = (broker_address.clone(), broker_state.clone());
+                thread::spawn(move || serve(stream, &address, &state));
+            }
+        });
+        MockBroker { address, state }
+    }
+
+    pub fn create_topic(&self, topic: &str, partitions: usize) {
+        self.state.lock().unwrap().topics.insert(topic.to_string(), vec![Vec::new(); partitions]);
+    }
+
+    pub fn append(&self, topic: &str, partition: usize, value: &str) {
+        self.append_batch(topic, partition, &[value], 0);
+    }
+
+    /// Appends the values as a single batch with the attributes, i.e. GZIP or CONTROL.
+    pub fn append_batch(&self, topic: &str, partition: usize, values: &[&str], attributes: i16) {
+        let mut state = self.state.lock().unwrap();
+        let log = &mut state.topics.get_mut(topic).unwrap()[partition];
+        let records = values.iter().map(|value| Record::new(None, value.as_bytes().to_vec())).collect();
+        append(log, attributes, records);
+    }
+
+    /// The records of a partition, without the records of control batches.
+    pub fn records(&self, topic: &str, partition: usize) -> Vec<Record> {
+        self.state.lock().unwrap().topics[topic][partition]
+            .iter()
+            .filter(|batch| batch.attributes & CONTROL == 0)
+            .flat_map(|batch| batch.records.clone())
+            .collect()
+    }
+
+    /// Makes another broker the leader of every partition, so this broker fails the requests for them with NOT_LEADER_OR_FOLLOWER.
+    pub fn move_leader(&self, leader: &MockBroker) {
+        self.state.lock().unwrap().leader = Some(leader.address.clone());
+    }
+
+    pub fn committed(&self, group_id: &str, topic: &str, partition: i32) -> Option<i64> {
+        self.state.lock().unwrap().offsets.get(&(group_id.to_string(), (topic.to_string(), partition))).copied()
+    }
+}
+
+/// The offset after the last record of a partition.
+fn end_offset(log: &[Batch]) -> i64 {
+    log.last().and_then(|batch| batch.records.last()).map(|record| record.offset + 1).unwrap_or_default()
+}
+
+/// Appends the records at the end of the partition, and returns the offset of the first one.
+fn append(log: &mut Vec<Batch>, attributes: i16, records: Vec<Record>) -> i64 {
+    let base_offset = end_offset(log);
+    let records = records.into_iter().enumerate().map(|(delta, record)| Record { offset: base_offset + delta as i64, ..record }).collect();
+    log.push(Batch { attributes, records });
+    base_offset
+}
+
+fn serve(mut stream: TcpStream, address: &str, state: &Mutex<State>) {
+    while let Ok(request) = read_frame(&mut stream) {
+        let response = handle(&request, address, state).expect("invalid request");
+        if write_frame(&mut stream, &response).is_err() {
+            return;
+        }
+    }
+}
+
+fn handle(request: &[u8], address: &str, state: &Mutex<State>) -> io::Result<Vec<u8>> {
+    let mut request = Decoder::new(request);
+    let api = (request.i16()?, request.i16()?);
+    let correlation_id = request.i32()?;
+    request.nullable_string()?;
+
+    let mut response = Vec::new();
+    put_i32(&mut response, correlation_id);
+    if api == FETCH {
+        // a fetch without records waits, as a broker waits up to the max wait for records
+        let body = request.rest();
+        let (max_wait, ready, mut fetched) = fetch(&mut Decoder::new(body), state)?;
+        if !ready {
+            thread::sleep(max_wait.min(Duration::from_millis(20)));
+            fetched = fetch(&mut Decoder::new(body), state)?.2;
+        }
+        response.extend(fetched);
+        return Ok(response);
+    }
+
+    let mut state = state.lock().unwrap();
+    match api {
+        PRODUCE => {
+            request.nullable_string()?;
+            request.take(2 + 4)?;
+            let mut partitions = Vec::new();
+            read_topics(&mut request, |(topic, partition), request| {
+                let records = decode_record_batches(request.bytes()?.unwrap_or_default())?.records;
+                let moved = state.leader.is_some();
+                let result = match state.topics.get_mut(&topic).and_then(|partitions| partitions.get_mut(partition as usize)) {
+                    Some(_) if moved => (NOT_LEADER_OR_FOLLOWER, -1),
+                    Some(log) => (0, append(log, 0, records)),
+                    None => (UNKNOWN_TOPIC_OR_PARTITION, -1),
+                };
+                partitions.push(((topic, partition), result));
+                Ok(())
+            })?;
+            put_topics(&mut response, partitions.iter().map(|(topic_partition, result)| (topic_partition, result)), |response, (error_code, base_offset)| {
+                put_i16(response, *error_code);
+                put_i64(response, *base_offset);
+                put_i64(response, -1);
+            });
+            put_i32(&mut response, 0);
+        }
+        LIST_OFFSETS => {
+            request.i32()?;
+            let mut partitions = Vec::new();
+            read_topics(&mut request, |topic_partition, request| {
+                let timestamp = request.i64()?;
+                let end_offset = end_offset(&state.topics[&topic_partition.0][topic_partition.1 as usize]);
+                partitions.push((topic_partition, if timestamp == EARLIEST { 0 } else { end_offset }));
+                Ok(())
+            })?;
+            put_topics(&mut response, partitions.iter().map(|(topic_partition, offset)| (topic_partition, offset)), |response, offset| {
+                put_i16(response, 0);
+                put_i64(response, -1);
+                put_i64(response, *offset);
+            });
+        }
+        METADATA => {
+            let topics = request.array(Decoder::string)?;
+            let brokers = [Some(address), state.leader.as_deref()].into_iter().flatten().collect::<Vec<&str>>();
+            put_i32(&mut response, 0);
+            put_i32(&mut response, brokers.len() as i32);
+            for (node_id, address) in brokers.iter().enumerate() {
+                let (host, port) = address.rsplit_once(':').unwrap();
+                put_i32(&mut response, node_id as i32);
+                put_string(&mut response, host);
+                put_i32(&mut response, port.parse().unwrap());
+                put_nullable_string(&mut response, None);
+            }
+            put_nullable_string(&mut response, None);
+            put_i32(&mut response, 0);
+            put_i32(&mut response, topics.len() as i32);
+            for topic in &topics {
+                let partitions = state.topics.get(topic).map(Vec::len);
+                put_i16(&mut response, if partitions.is_some() { 0 } else { UNKNOWN_TOPIC_OR_PARTITION });
+                put_string(&mut response, topic);
+                response.push(0);
+                put_i32(&mut response, partitions.unwrap_or_default() as i32);
+                for partition in 0..partitions.unwrap_or_default() {
+                    put_i16(&mut response, 0);
+                    put_i32(&mut response, partition as i32);
+                    put_i32(&mut response, brokers.len() as i32 - 1);
+                    put_i32(&mut response, 1);
+                    put_i32(&mut response, 0);
+                    put_i32(&mut response, 1);
+                    put_i32(&mut response, 0);
+                }
+            }
+        }
+        OFFSET_COMMIT => {
+            let group_id = request.string()?;
+            request.i32()?;
+            request.string()?;
+            request.i64()?;
+            let mut partitions = Vec::new();
+            read_topics(&mut request, |topic_partition, request| {
+                let offset = request.i64()?;
+                request.nullable_string()?;
+                state.offsets.insert((group_id.clone(), topic_partition.clone()), offset);
+                partitions.push(topic_partition);
+                Ok(())
+            })?;
+            put_topics(&mut response, partitions.iter().map(|topic_partition| (topic_partition, ())), |response, _| put_i16(response, 0));
+        }
+        OFFSET_FETCH => {
+            let group_id = request.string()?;
+            let mut partitions = Vec::new();
+            for _ in 0..request.i32()? {
+                let topic = request.string()?;
+                for partition in request.array(Decoder::i32)? {
+                    let offset = state.offsets.get(&(group_id.clone(), (topic.clone(), partition))).copied().unwrap_or(-1);
+                    partitions.push(((topic.clone(), partition), offset));
+                }
+            }
+            put_topics(&mut response, partitions.iter().map(|(topic_partition, offset)| (topic_partition, offset)), |response, offset| {
+                put_i64(response, *offset);
+                put_nullable_string(response, None);
+                put_i16(response, 0);
+            });
+        }
+        FIND_COORDINATOR => {
+            let (host, port) = address.rsplit_once(':').unwrap();
+            put_i32(&mut response, 0);
+            put_i16(&mut response, 0);
+            put_nullable_string(&mut response, None);
+            put_i32(&mut response, 0);
+            put_string(&mut response, host);
+            put_i32(&mut response, port.parse().unwrap());
+        }
+        api => return Err(io::Error::new(io::ErrorKind::Unsupported, format!("api {:?}", api))),
+    }
+    Ok(response)
+}
+
+/// Reads a fetch request and returns the max wait, whether a partition has records or an error, and the response.
+fn fetch(request: &mut Decoder, state: &Mutex<State>) -> io::Result<(Duration, bool, Vec<u8>)> {
+    let state = state.lock().unwrap();
+    let moved = state.leader.is_some();
+    request.i32()?;
+    let max_wait = Duration::from_millis(request.i32()? as u64);
+    request.take(4 + 4 + 1)?;
+
+    let mut partitions = Vec::new();
+    let mut ready = false;
+    read_topics(request, |topic_partition, request| {
+        let offset = request.i64()?;
+        request.i32()?;
+        // the batches that end after the offset, as a broker returns the whole batch of an offset
+        let (error_code, batches) = match state.topics.get(&topic_partition.0).and_then(|partitions| partitions.get(topic_partition.1 as usize)) {
+            Some(_) if moved => (NOT_LEADER_OR_FOLLOWER, Vec::new()),
+            Some(log) if offset >= 0 && offset <= end_offset(log) => {
+                let batches = log.iter().filter(|batch| batch.records.last().is_some_and(|record| record.offset >= offset));
+                (0, batches.flat_map(|batch| encode_batch(&batch.records, batch.attributes)).collect())
+            }
+            Some(_) => (OFFSET_OUT_OF_RANGE, Vec::new()),
+            None => (UNKNOWN_TOPIC_OR_PARTITION, Vec::new()),
+        };
+        ready |= error_code != 0 || !batches.is_empty();
+        partitions.push((topic_partition, (error_code, batches)));
+        Ok(())
+    })?;
+
+    let mut response = Vec::new();
+    put_i32(&mut response, 0);
+    put_topics(&mut response, partitions.iter().map(|(topic_partition, result)| (topic_partition, result)), |response, (error_code, batches)| {
+        put_i16(response, *error_code);
+        put_i64(response, -1);
+        put_i64(response, -1);
+        put_i32(response, 0);
+        put_bytes(response, batches);
+    });
+    Ok((max_wait, ready, response))
+}
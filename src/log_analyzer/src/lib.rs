@@ -0,0 +1,13 @@
+pub mod alert;
+pub mod cli;
+pub mod config;
+pub mod correlation;
+pub mod field_mappings;
+pub mod inputs;
+pub mod kafka;
+pub mod pipeline;
+pub mod prefilter;
+pub mod routing;
+pub mod rule_set;
+pub mod server;
+pub mod sinks;
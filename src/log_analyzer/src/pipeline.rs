@@ -0,0 +1,121 @@
+use std::io;
+use std::sync::{Mutex, PoisonError};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+use log::info;
+use sigma_log_parser::log_event::LogEvent;
+use sigma_rule_parser::structs::sigma_rule::Logsource;
+use crate::config::RoutingConfig;
+use crate::routing::event_logsource;
+use crate::rule_set::{RuleMatch, RuleSet};
+use crate::sinks::{Dispatcher, FailedAlerts, Sink};
+
+/// The detection pipeline that every input sends its events to.
+/// The inputs share the rules, so aggregations and correlations see the events of every input.
+/// The alerts of the rules that match are sent to the sinks.
+#[derive(Debug)]
+pub struct Pipeline {
+    rule_set: Mutex<RuleSet>,
+    routing: RoutingConfig,
+    events: AtomicU64,
+    dispatcher: Option<Dispatcher>,
+}
+
+impl Pipeline {
+    pub fn new(rule_set: RuleSet, routing: RoutingConfig) -> Pipeline {
+        Pipeline { rule_set: Mutex::new(rule_set), routing, events: AtomicU64::new(0), dispatcher: None }
+    }
+
+    pub fn with_sinks(mut self, sinks: Vec<Box<dyn Sink>>) -> Pipeline {
+        self.dispatcher = Some(Dispatcher::start(sinks));
+        self
+    }
+
+    pub fn routing(&self) -> &RoutingConfig {
+        &self.routing
+    }
+
+    /// The number of events that were evaluated.
+    pub fn events(&self) -> u64 {
+        self.events.load(Ordering::Relaxed)
+    }
+
+    /// Waits until the alerts of the events that were evaluated were delivered to the sinks,
+    ///     and fails when an alert that was counted in failed (see evaluate_counting) could not be delivered.
+    pub fn flush(&self, failed: &FailedAlerts) -> io::Result<()> {
+        match self.dispatcher.as_ref() {
+            Some(dispatcher) => dispatcher.flush(failed),
+            None => Ok(()),
+        }
+    }
+
+    /// Evicts the events of aggregations and correlations that fell out of their window, see RuleSet::expire.
+    pub fn expire(&self, now: SystemTime) {
+        self.rule_set.lock().unwrap_or_else(PoisonError::into_inner).expire(now);
+    }
+
+    /// Evaluates an event against the rules of its logsource, and returns the rules that matched it.
+    /// The received logsource is the logsource of the input, which the fields of the event may replace.
+    pub fn evaluate(&self, event: &LogEvent, received: &Logsource) -> Vec<RuleMatch> {
+        self.evaluate_counting(event, received, None)
+    }
+
+    /// Evaluates an event like evaluate, and counts the alerts that the sinks fail to deliver in failed, which the input flushes.
+    pub fn evaluate_counting(&self, event: &LogEvent, received: &Logsource, failed: Option<&FailedAlerts>) -> Vec<RuleMatch> {
+        let logsource = event_logsource(&self.routing.fields, event, received);
+        // a panic while evaluating an event does not leave the rules in a state that other events can not be evaluated in
+        let mut rule_set = self.rule_set.lock().unwrap_or_else(PoisonError::into_inner);
+        let alerts = rule_set.alerts(event, &logsource, SystemTime::now());
+        drop(rule_set);
+        self.events.fetch_add(1, Ordering::Relaxed);
+
+        let mut rule_matches = Vec::with_capacity(alerts.len());
+        for alert in alerts {
+            info!("Event matched [{}] {} ({})", alert.level, alert.title, alert.id);
+            rule_matches.push(RuleMatch::from(&alert));
+            if let Some(dispatcher) = self.dispatcher.as_ref() {
+                dispatcher.dispatch(alert, failed);
+            }
+        }
+        rule_matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use serde_json::json;
+    use sigma_rule_parser::sigma_file::load_report::LoadReport;
+    use sigma_rule_parser::structs::sigma_rule::SigmaRule;
+    use crate::sinks::tests::MemorySink;
+    use super::*;
+
+    #[test]
+    fn send_alerts_to_sinks() {
+        let rule: SigmaRule = serde_yaml::from_str(r#"
+            title: Whoami
+            id: whoami
+            tags:
+              - attack.discovery
+            detection:
+              selection:
+                Image|endswith: '\whoami.exe'
+                CommandLine|contains: whoami
+              condition: selection
+        "#).unwrap();
+        let alerts = Arc::new(Mutex::new(Vec::new()));
+        let pipeline = Pipeline::new(RuleSet::from_rules(vec![rule], LoadReport::default()), RoutingConfig::default())
+            .with_sinks(vec![Box::new(MemorySink(alerts.clone()))]);
+
+        let event = LogEvent::from(json!({"Image": "C:\\whoami.exe", "CommandLine": "whoami /all", "User": "admin"}));
+        assert_eq!(pipeline.evaluate(&event, &Logsource::default()).len(), 1);
+        assert!(pipeline.evaluate(&LogEvent::from(json!({"Image": "cmd.exe"})), &Logsource::default()).is_empty());
+        drop(pipeline);
+
+        let alerts = alerts.lock().unwrap();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].tags, vec!["attack.discovery"]);
+        assert_eq!(serde_json::to_value(&alerts[0].matched_fields).unwrap(), json!({"CommandLine": "whoami /all", "Image": "C:\\whoami.exe"}));
+        assert_eq!(alerts[0].event, event);
+    }
+}
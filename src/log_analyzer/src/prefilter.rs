@@ -0,0 +1,202 @@
+use std::collections::BTreeMap;
+use aho_corasick::AhoCorasick;
+use serde_json::Value;
+use sigma_log_parser::log_event::LogEvent;
+use crate::rule_set::CompiledRule;
+
+/// An index of the literals of every rule, so a single pass over an event finds the rules that may match it.
+/// A rule may only match an event that contains one of its literals (see CompiledDetection::literals), so the other rules are not evaluated.
+/// The needles of every field share one Aho-Corasick automaton, as do the keywords, which are searched for in every value of the event.
+/// Rules that may match without a literal are candidates for every event.
+#[derive(Debug, Default)]
+pub struct Prefilter {
+    rules: usize,
+    /// The rules that are evaluated for every event
+    unfiltered: Vec<usize>,
+    fields: Vec<(String, LiteralIndex)>,
+    keywords: Option<LiteralIndex>,
+}
+
+#[derive(Debug)]
+struct LiteralIndex {
+    automaton: AhoCorasick,
+    /// The rules of every needle of the automaton, by the id of its pattern
+    rules: Vec<Vec<usize>>,
+}
+
+impl Prefilter {
+    /// The literals of a field are indexed by the name of the field and by the name it is mapped to, as the Detection reads either of them.
+    pub fn new(compiled_rules: &[CompiledRule]) -> Prefilter {
+        let mut unfiltered = Vec::new();
+        let mut fields: BTreeMap<String, BTreeMap<String, Vec<usize>>> = BTreeMap::new();
+        let mut keywords: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+
+        for (position, compiled_rule) in compiled_rules.iter().enumerate() {
+            let literals = match compiled_rule.matcher.literals() {
+                Some(literals) => literals,
+                None => {
+                    unfiltered.push(position);
+                    continue;
+                }
+            };
+
+            for literal in literals {
+                let field = match literal.field {
+                    Some(field) => field,
+                    None => {
+                        keywords.entry(literal.needle).or_default().push(position);
+                        continue;
+                    }
+                };
+                if let Some(mapped_field) = compiled_rule.field_mappings.get(&field) {
+                    fields.entry(mapped_field.clone()).or_default().entry(literal.needle.clone()).or_default().push(position);
+                }
+                fields.entry(field).or_default().entry(literal.needle).or_default().push(position);
+            }
+        }
+
+        Prefilter {
+            rules: compiled_rules.len(),
+            unfiltered,
+            fields: fields.into_iter().map(|(field, needles)| (field, LiteralIndex::new(needles))).collect(),
+            keywords: (!keywords.is_empty()).then(|| LiteralIndex::new(keywords)),
+        }
+    }
+
+    /// The number of rules that are only evaluated for the events that contain one of their literals.
+    pub fn filtered(&self) -> usize {
+        self.rules - self.unfiltered.len()
+    }
+
+    /// Whether every rule may match the event, by the position of the rule.
+    pub fn candidates(&self, event: &LogEvent) -> Vec<bool> {
+        let mut candidates = vec![false; self.rules];
+        for &position in &self.unfiltered {
+            candidates[position] = true;
+        }
+
+        for (field, index) in &self.fields {
+            if let Some(value) = event.get(field) {
+                index.search(value, false, &mut candidates);
+            }
+        }
+        if let Some(index) = self.keywords.as_ref() {
+            for value in event.fields().values() {
+                index.search(value, true, &mut candidates);
+            }
+        }
+
+        candidates
+    }
+}
+
+impl LiteralIndex {
+    fn new(needles: BTreeMap<String, Vec<usize>>) -> LiteralIndex {
+        let (needles, rules): (Vec<String>, Vec<Vec<usize>>) = needles.into_iter().unzip();
+        LiteralIndex { automaton: AhoCorasick::new(needles), rules }
+    }
+
+    /// Marks the rules of the needles that a value contains, as the Detection compares it: in lowercase, and every item of a list.
+    /// The values of objects are only compared by keywords.
+    fn search(&self, value: &Value, objects: bool, candidates: &mut [bool]) {
+        let mut mark = |value: &str| {
+            for found in self.automaton.find_overlapping_iter(value) {
+                for &position in &self.rules[found.pattern()] {
+                    candidates[position] = true;
+                }
+            }
+        };
+
+        match value {
+            Value::String(value) => mark(&value.to_lowercase()),
+            Value::Number(value) => mark(&value.to_string()),
+            Value::Bool(value) if !objects => mark(&value.to_string()),
+            Value::Array(values) => values.iter().for_each(|value| self.search(value, objects, candidates)),
+            Value::Object(values) if objects => values.values().for_each(|value| self.search(value, objects, candidates)),
+            Value::Bool(_) | Value::Object(_) | Value::Null => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use sigma_rule_parser::sigma_file::load_report::LoadReport;
+    use sigma_rule_parser::structs::sigma_rule::SigmaRule;
+    use crate::field_mappings::{FieldMappings, LogsourceFieldMappings};
+    use crate::rule_set::RuleSet;
+    use super::*;
+
+    fn rule_set() -> RuleSet {
+        let rules: Vec<SigmaRule> = serde_yaml::from_str(r#"
+            - id: whoami
+              detection:
+                selection:
+                  EventID: 1
+                  Image|endswith: '\whoami.exe'
+                condition: selection
+            - id: mimikatz
+              detection:
+                keywords:
+                  - 'sekurlsa::'
+                  - 'MIMIKATZ'
+                condition: keywords
+            - id: high_port
+              detection:
+                selection:
+                  DestinationPort|gt: 1024
+                condition: selection
+            - id: not_system
+              detection:
+                filter:
+                  User: SYSTEM
+                condition: not filter
+        "#).unwrap();
+        let field_mappings: Vec<LogsourceFieldMappings> = serde_yaml::from_str(r#"
+            - logsource: {}
+              fields:
+                Image: process.executable
+        "#).unwrap();
+        RuleSet::from_rules(rules, LoadReport::default()).with_field_mappings(&FieldMappings::new(field_mappings))
+    }
+
+    #[test]
+    fn find_candidate_rules() {
+        let rule_set = rule_set();
+        let prefilter = rule_set.prefilter();
+        assert_eq!(prefilter.filtered(), 2);
+
+        assert_eq!(prefilter.candidates(&LogEvent::from(json!({"Image": "C:\\Windows\\WHOAMI.EXE"}))), vec![true, false, true, true]);
+        assert_eq!(prefilter.candidates(&LogEvent::from(json!({"process": {"executable": ["C:\\Windows\\whoami.exe"]}}))), vec![true, false, true, true]);
+        assert_eq!(prefilter.candidates(&LogEvent::from(json!({"data": {"message": "Mimikatz"}}))), vec![false, true, true, true]);
+        assert_eq!(prefilter.candidates(&LogEvent::from(json!({"Image": "C:\\Windows\\cmd.exe", "EventID": 1}))), vec![false, false, true, true]);
+    }
+
+    /// Every rule of the bundled rules that matches an event is a candidate of the event, so prefiltering does not change the matches.
+    #[test]
+    fn keep_the_matches_of_the_bundled_rules() {
+        let rule_set = RuleSet::load("../../config/rules/rules").unwrap();
+        assert!(rule_set.prefilter().filtered() * 10 > rule_set.len() * 9);
+
+        let events = [
+            json!({"EventID": 1, "Image": "C:\\Windows\\System32\\whoami.exe", "CommandLine": "whoami /priv", "ParentImage": "C:\\Windows\\System32\\cmd.exe"}),
+            json!({"EventID": 1, "Image": "C:\\Windows\\System32\\rundll32.exe", "CommandLine": "rundll32.exe C:\\Windows\\System32\\comsvcs.dll, MiniDump 624 lsass.dmp full"}),
+            json!({"EventID": 4625, "LogonType": 3, "TargetUserName": "administrator", "IpAddress": "10.0.0.1"}),
+            json!({"eventSource": "iam.amazonaws.com", "eventName": "CreateAccessKey", "userIdentity": {"type": "Root"}}),
+            json!({"cs-uri-query": "/index.php?id=1' UNION SELECT password FROM users--", "c-useragent": ""}),
+            json!({"message": "Invoke-Mimikatz -Command sekurlsa::logonpasswords"}),
+        ];
+        let mut matches = 0;
+        for event in &events {
+            let event = LogEvent::from(event.clone());
+            let candidates = rule_set.prefilter().candidates(&event);
+            for (position, compiled_rule) in rule_set.rules().iter().enumerate() {
+                if compiled_rule.matcher.matches_with_field_mappings(&event, &compiled_rule.field_mappings) {
+                    assert!(candidates[position], "{} matches {:?}", compiled_rule.rule.id, event);
+                    matches += 1;
+                }
+            }
+        }
+        assert!(matches >= events.len());
+    }
+}
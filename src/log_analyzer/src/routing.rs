@@ -0,0 +1,165 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use sigma_log_parser::log_event::LogEvent;
+use sigma_rule_parser::structs::sigma_rule::Logsource;
+use crate::config::LogsourceFields;
+
+/// The category, product and service of a logsource in lowercase, where an empty value is not known.
+type LogsourceKey = (String, String, String);
+
+/// The logsources of events are sent by clients, so the routes of at most this many of them are remembered.
+const MAX_ROUTES: usize = 1024;
+
+/// An index from the logsource of the rules to the rules, so an event is only evaluated against the rules of its logsource.
+/// A rule applies to an event when every value of the rule logsource is empty, not known for the event, or equal to the value of the event.
+/// Events without a logsource are evaluated against every rule.
+#[derive(Debug, Default)]
+pub struct LogsourceIndex {
+    /// The rules of every distinct logsource, by their position in the RuleSet
+    logsources: Vec<(LogsourceKey, Vec<usize>)>,
+    /// The rules of every logsource an event was tagged with, found on first use, up to MAX_ROUTES logsources
+    routes: HashMap<LogsourceKey, Vec<usize>>,
+}
+
+impl LogsourceIndex {
+    pub fn new<'a, I: IntoIterator<Item = &'a Logsource>>(rule_logsources: I) -> LogsourceIndex {
+        let mut positions: HashMap<LogsourceKey, Vec<usize>> = HashMap::new();
+        for (position, logsource) in rule_logsources.into_iter().enumerate() {
+            positions.entry(logsource_key(logsource)).or_default().push(position);
+        }
+
+        let mut logsources = positions.into_iter().collect::<Vec<(LogsourceKey, Vec<usize>)>>();
+        logsources.sort();
+        LogsourceIndex { logsources, routes: HashMap::new() }
+    }
+
+    /// Returns the positions of the rules that apply to an event of the logsource, in the order of the rules.
+    /// Once MAX_ROUTES logsources are remembered, the rules of other logsources are found again for every event.
+    pub fn route(&mut self, logsource: &Logsource) -> Cow<'_, [usize]> {
+        let event = logsource_key(logsource);
+        if !self.routes.contains_key(&event) {
+            let positions = find_rules(&self.logsources, &event);
+            if self.routes.len() >= MAX_ROUTES {
+                return Cow::Owned(positions);
+            }
+            self.routes.insert(event.clone(), positions);
+        }
+        Cow::Borrowed(&self.routes[&event])
+    }
+
+    /// Returns the positions of the rules that apply to an event of the logsource, without remembering the route,
+    ///     so the index may be shared between threads.
+    pub fn rules_for(&self, logsource: &Logsource) -> Vec<usize> {
+        let event = logsource_key(logsource);
+        match self.routes.get(&event) {
+            Some(positions) => positions.clone(),
+            None => find_rules(&self.logsources, &event),
+        }
+    }
+
+    /// The number of distinct logsources of the rules.
+    pub fn len(&self) -> usize {
+        self.logsources.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.logsources.is_empty()
+    }
+}
+
+impl LogsourceFields {
+    /// Reads a logsource from named values, i.e. the fields of an event or the headers of a request. Empty names are not read.
+    pub fn read<F: Fn(&str) -> Option<String>>(&self, value: F) -> Logsource {
+        let read = |name: &str| if name.is_empty() { String::new() } else { value(name).unwrap_or_default() };
+        Logsource {
+            category: read(&self.category),
+            product: read(&self.product),
+            service: read(&self.service),
+            ..Default::default()
+        }
+    }
+}
+
+/// Tags an event with its logsource. The values read from the fields of the event replace the values of the logsource it was received with,
+///     which is taken from the headers of the request or the config of the input.
+pub fn event_logsource(fields: &LogsourceFields, event: &LogEvent, received: &Logsource) -> Logsource {
+    let tagged = fields.read(|name| event.get_str(name).map(str::to_string));
+    let choose = |tagged: String, received: &String| if tagged.is_empty() { received.clone() } else { tagged };
+    Logsource {
+        category: choose(tagged.category, &received.category),
+        product: choose(tagged.product, &received.product),
+        service: choose(tagged.service, &received.service),
+        ..Default::default()
+    }
+}
+
+fn logsource_key(logsource: &Logsource) -> LogsourceKey {
+    (logsource.category.to_lowercase(), logsource.product.to_lowercase(), logsource.service.to_lowercase())
+}
+
+fn find_rules(logsources: &[(LogsourceKey, Vec<usize>)], event: &LogsourceKey) -> Vec<usize> {
+    let mut positions = logsources
+        .iter()
+        .filter(|(rule, _)| applies_to(rule, event))
+        .flat_map(|(_, positions)| positions.iter().copied())
+        .collect::<Vec<usize>>();
+    positions.sort_unstable();
+    positions
+}
+
+fn applies_to(rule: &LogsourceKey, event: &LogsourceKey) -> bool {
+    [(&rule.0, &event.0), (&rule.1, &event.1), (&rule.2, &event.2)]
+        .iter()
+        .all(|(rule, event)| rule.is_empty() || event.is_empty() || rule == event)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    fn logsource(category: &str, product: &str, service: &str) -> Logsource {
+        Logsource { category: category.to_string(), product: product.to_string(), service: service.to_string(), ..Default::default() }
+    }
+
+    #[test]
+    fn route_events_by_logsource() {
+        let rules = [
+            logsource("process_creation", "windows", ""),
+            logsource("", "linux", "auditd"),
+            logsource("process_creation", "linux", ""),
+            logsource("", "", ""),
+            logsource("", "Windows", "security"),
+        ];
+        let mut index = LogsourceIndex::new(&rules);
+        assert_eq!(index.len(), 5);
+
+        assert_eq!(*index.route(&logsource("", "linux", "auditd")), [1, 2, 3]);
+        assert_eq!(*index.route(&logsource("process_creation", "windows", "sysmon")), [0, 3]);
+        assert_eq!(*index.route(&logsource("", "WINDOWS", "")), [0, 3, 4]);
+        assert_eq!(*index.route(&Logsource::default()), [0, 1, 2, 3, 4]);
+        assert_eq!(index.rules_for(&logsource("", "linux", "")), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn remember_a_limited_number_of_routes() {
+        let rules = [logsource("", "linux", ""), logsource("", "windows", "")];
+        let mut index = LogsourceIndex::new(&rules);
+
+        for service in 0..MAX_ROUTES + 10 {
+            assert_eq!(*index.route(&logsource("", "linux", &service.to_string())), [0]);
+        }
+        assert_eq!(index.routes.len(), MAX_ROUTES);
+        assert_eq!(*index.route(&logsource("", "windows", "security")), [1]);
+        assert_eq!(*index.route(&logsource("", "linux", "0")), [0]);
+    }
+
+    #[test]
+    fn tag_events_with_logsource() {
+        let fields = LogsourceFields { category: String::new(), product: "host.os.type".to_string(), service: "source".to_string() };
+        let event = LogEvent::from(json!({"host": {"os": {"type": "linux"}}, "source": 1}));
+
+        let received = logsource("process_creation", "windows", "sysmon");
+        assert_eq!(event_logsource(&fields, &event, &received), logsource("process_creation", "linux", "sysmon"));
+    }
+}
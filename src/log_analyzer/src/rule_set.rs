@@ -0,0 +1,552 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::time::SystemTime;
+use log::error;
+use serde::Serialize;
+use serde_json::Value;
+use sigma_log_parser::log_event::LogEvent;
+use sigma_rule_parser::detection_builder::{build, build_conditions};
+use sigma_rule_parser::detection_evaluator::aggregation_state::AggregationState;
+use sigma_rule_parser::detection_compiler::{compile, CompiledDetection};
+use sigma_rule_parser::rule_error::RuleError;
+use sigma_rule_parser::sigma_file::load_report::LoadReport;
+use sigma_rule_parser::sigma_file::sigma_rule::load_sigma_rules;
+use sigma_rule_parser::structs::detection::Detection;
+use sigma_rule_parser::structs::detection_logic::DetectionLogic;
+use sigma_rule_parser::structs::sigma_rule::{Logsource, SigmaRule};
+use crate::alert::Alert;
+use crate::correlation::Correlator;
+use crate::field_mappings::FieldMappings;
+use crate::prefilter::Prefilter;
+use crate::routing::LogsourceIndex;
+
+/// The Sigma rules of a rules directory, built into Detections that log events are evaluated against.
+/// Rules that could not be loaded or built are left out, and the reason of each is kept in the LoadReport.
+#[derive(Debug)]
+pub struct RuleSet {
+    rules: Vec<CompiledRule>,
+    index: LogsourceIndex,
+    prefilter: Prefilter,
+    correlator: Correlator,
+    report: LoadReport,
+}
+
+/// A Sigma rule with its Detection, the field mappings of its logsource, and the state of its aggregation when the condition has one.
+/// Events are matched by the compiled Detection, the Detection is kept to explain the rule.
+#[derive(Debug)]
+pub struct CompiledRule {
+    pub rule: SigmaRule,
+    pub detection: Detection,
+    pub matcher: CompiledDetection,
+    pub field_mappings: HashMap<String, String>,
+    /// The fields the Detection looks at, which are the matched fields of its alerts
+    pub fields: Vec<String>,
+    aggregation: Option<AggregationState>,
+    /// The Detection and the aggregation of every condition of a rule with a list of conditions, which the Detection joins with "or"
+    conditions: Vec<(CompiledDetection, Option<AggregationState>)>,
+    /// True when a correlation references the rule
+    correlated: bool,
+    /// False when the rule only feeds correlations that do not set `generate: true`
+    generate: bool,
+}
+
+/// A rule that matched a log event, either because its Detection (and aggregation) matched or because its correlation matched.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct RuleMatch {
+    pub id: String,
+    pub title: String,
+    pub level: String,
+}
+
+impl RuleSet {
+    pub fn load(rules_dir: &str) -> Result<RuleSet, RuleError> {
+        RuleSet::load_dirs(&[rules_dir.to_string()])
+    }
+
+    /// Loads the rules of every rules directory into a single RuleSet, so correlations may reference rules of another directory.
+    pub fn load_dirs(rules_dirs: &[String]) -> Result<RuleSet, RuleError> {
+        let mut sigma_rules = Vec::new();
+        let mut report = LoadReport::default();
+        for rules_dir in rules_dirs {
+            let (dir_rules, dir_report) = load_sigma_rules(rules_dir.to_string())?;
+            sigma_rules.extend(dir_rules);
+            report.entries.extend(dir_report.entries);
+        }
+
+        Ok(RuleSet::from_rules(sigma_rules, report))
+    }
+
+    /// Builds every rule that is not a correlation rule. Correlation rules are added to the Correlator instead.
+    /// Each rule is reported as loaded once it is built or added, or as failed to build with the reason.
+    pub fn from_rules(sigma_rules: Vec<SigmaRule>, mut report: LoadReport) -> RuleSet {
+        let correlator = Correlator::new(&sigma_rules, &mut report);
+
+        let mut rules = Vec::new();
+        for rule in sigma_rules.into_iter().filter(|rule| !rule.is_correlation()) {
+            let compiled_rule = build_conditions(&rule).and_then(|conditions| {
+                let detection = build(rule.clone())?;
+                let mut conditions = conditions
+                    .into_iter()
+                    .map(|(detection, aggregation)| Ok((compile(&detection)?, aggregation.map(AggregationState::new))))
+                    .collect::<Result<Vec<(CompiledDetection, Option<AggregationState>)>, RuleError>>()?;
+                // the Detection of a single condition is the Detection of the rule
+                let aggregation = match conditions.len() {
+                    1 => conditions.pop().and_then(|(_, aggregation)| aggregation),
+                    _ => None,
+                };
+                Ok((compile(&detection)?, detection, aggregation, conditions))
+            });
+            match compiled_rule {
+                Ok((matcher, detection, aggregation, conditions)) => {
+                    report.loaded(&rule);
+                    rules.push(CompiledRule {
+                        correlated: correlator.references(&rule),
+                        generate: correlator.generates_alerts(&rule),
+                        rule,
+                        fields: detection_fields(&detection),
+                        detection,
+                        matcher,
+                        field_mappings: HashMap::new(),
+                        aggregation,
+                        conditions,
+                    });
+                }
+                Err(err) => {
+                    error!("Could not build rule {} - {}", rule.id, err);
+                    report.failed_to_build(&rule, &err);
+                }
+            }
+        }
+
+        let index = LogsourceIndex::new(rules.iter().map(|compiled_rule| &compiled_rule.rule.logsource));
+        let prefilter = Prefilter::new(&rules);
+        RuleSet { rules, index, prefilter, correlator, report }
+    }
+
+    /// Assigns every rule the field mappings of its logsource.
+    pub fn with_field_mappings(mut self, field_mappings: &FieldMappings) -> RuleSet {
+        for compiled_rule in self.rules.iter_mut() {
+            compiled_rule.field_mappings = field_mappings.for_logsource(&compiled_rule.rule.logsource);
+        }
+        // the literals of the fields are indexed by their mapped names as well
+        self.prefilter = Prefilter::new(&self.rules);
+        self
+    }
+
+    pub fn rules(&self) -> &[CompiledRule] {
+        &self.rules
+    }
+
+    pub fn index(&self) -> &LogsourceIndex {
+        &self.index
+    }
+
+    pub fn prefilter(&self) -> &Prefilter {
+        &self.prefilter
+    }
+
+    pub fn correlator(&self) -> &Correlator {
+        &self.correlator
+    }
+
+    pub fn report(&self) -> &LoadReport {
+        &self.report
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Evaluates a log event against every rule, and returns the rules and correlations that matched it.
+    /// The timestamp is the time of the event, which places it in the window of aggregations and correlations.
+    pub fn evaluate(&mut self, event: &LogEvent, timestamp: SystemTime) -> Vec<RuleMatch> {
+        self.evaluate_with_logsource(event, &Logsource::default(), timestamp)
+    }
+
+    /// Evaluates a log event against the rules of its logsource, see LogsourceIndex.
+    pub fn evaluate_with_logsource(&mut self, event: &LogEvent, logsource: &Logsource, timestamp: SystemTime) -> Vec<RuleMatch> {
+        self.alerts(event, logsource, timestamp).iter().map(RuleMatch::from).collect()
+    }
+
+    /// Evaluates a log event against the rules of its logsource, and returns an Alert for every rule and correlation that matched it.
+    /// Only the rules that the Prefilter finds for the event are evaluated. Rules that only feed correlations are evaluated without an Alert.
+    pub fn alerts(&mut self, event: &LogEvent, logsource: &Logsource, timestamp: SystemTime) -> Vec<Alert> {
+        self.evaluate_rules(event, logsource, timestamp, false)
+    }
+
+    /// Evaluates a log event against the stateful rules of its logsource only, which are the rules that detect leaves out:
+    ///     rules with an aggregation and rules that correlations reference. Together with detect every rule alerts once,
+    ///     so events may be detected on many threads while the stateful rules are evaluated in the order of the events.
+    pub fn stateful_alerts(&mut self, event: &LogEvent, logsource: &Logsource, timestamp: SystemTime) -> Vec<Alert> {
+        self.evaluate_rules(event, logsource, timestamp, true)
+    }
+
+    pub fn has_stateful_rules(&self) -> bool {
+        self.rules.iter().any(CompiledRule::is_stateful)
+    }
+
+    fn evaluate_rules(&mut self, event: &LogEvent, logsource: &Logsource, timestamp: SystemTime, stateful_only: bool) -> Vec<Alert> {
+        let mut alerts = Vec::new();
+        let candidates = self.prefilter.candidates(event);
+
+        for &position in self.index.route(logsource).iter() {
+            let compiled_rule = &mut self.rules[position];
+            if (stateful_only && !compiled_rule.is_stateful()) || !candidates[position] || !compiled_rule.matches(event, timestamp) {
+                continue;
+            }
+
+            // detect alerts for the rules without an aggregation
+            let detected = stateful_only && !compiled_rule.has_aggregation();
+            if compiled_rule.generate && !detected {
+                alerts.push(Alert::new(&compiled_rule.rule, compiled_rule.matched_fields(event), event, timestamp));
+            }
+            for correlation_match in self.correlator.process(&compiled_rule.rule, event, timestamp) {
+                if !self.correlator.generates_alerts(&correlation_match.rule) {
+                    continue;
+                }
+                let group = correlation_match.group.into_iter().map(|(field, value)| (field, Value::String(value))).collect();
+                alerts.push(Alert::new(&correlation_match.rule, group, event, timestamp));
+            }
+        }
+
+        alerts
+    }
+
+    /// Evaluates a log event against the Detections of the rules of its logsource, and returns the rules that matched it.
+    /// The RuleSet is not changed, so events may be evaluated on many threads at once. Rules with an aggregation are not evaluated
+    ///     and correlations are not processed, as they need every event in the order of time. Rules that only feed correlations are left out too.
+    pub fn detect(&self, event: &LogEvent, logsource: &Logsource) -> Vec<RuleMatch> {
+        let candidates = self.prefilter.candidates(event);
+        self.index
+            .rules_for(logsource)
+            .into_iter()
+            .filter(|&position| candidates[position])
+            .map(|position| &self.rules[position])
+            .filter(|compiled_rule| compiled_rule.generate && !compiled_rule.has_aggregation())
+            .filter(|compiled_rule| compiled_rule.matcher.matches_with_field_mappings(event, &compiled_rule.field_mappings))
+            .map(|compiled_rule| RuleMatch::from(&compiled_rule.rule))
+            .collect()
+    }
+
+    /// Evicts the events of aggregations and correlations that fell out of their window.
+    pub fn expire(&mut self, now: SystemTime) {
+        for compiled_rule in self.rules.iter_mut() {
+            let conditions = compiled_rule.conditions.iter_mut().filter_map(|(_, aggregation)| aggregation.as_mut());
+            for aggregation in compiled_rule.aggregation.iter_mut().chain(conditions) {
+                aggregation.expire(now);
+            }
+        }
+        self.correlator.expire(now);
+    }
+}
+
+impl CompiledRule {
+    pub fn has_aggregation(&self) -> bool {
+        self.aggregation.is_some() || self.conditions.iter().any(|(_, aggregation)| aggregation.is_some())
+    }
+
+    /// A rule is stateful when it needs the events in the order of time, either for its aggregation or for the correlations that reference it.
+    pub fn is_stateful(&self) -> bool {
+        self.has_aggregation() || self.correlated
+    }
+
+    /// The values of the fields of the Detection that the event has, read through the field mappings as the Detection reads them.
+    pub fn matched_fields(&self, event: &LogEvent) -> BTreeMap<String, Value> {
+        self.fields
+            .iter()
+            .filter_map(|field| {
+                let value = self.field_mappings.get(field).and_then(|source_field| event.get(source_field)).or_else(|| event.get(field))?;
+                Some((field.clone(), value.clone()))
+            })
+            .collect()
+    }
+
+    /// A rule with an aggregation only matches once the aggregation of the events that matched its Detection exceeds the threshold.
+    /// A rule with a list of conditions matches when any condition does, where every condition that matches records the event in its aggregation.
+    fn matches(&mut self, event: &LogEvent, timestamp: SystemTime) -> bool {
+        if !self.matcher.matches_with_field_mappings(event, &self.field_mappings) {
+            return false;
+        }
+        if !self.conditions.is_empty() {
+            let field_mappings = &self.field_mappings;
+            return self.conditions.iter_mut().fold(false, |matched, (matcher, aggregation)| {
+                let condition_matches = matcher.matches_with_field_mappings(event, field_mappings)
+                    && aggregation.as_mut().is_none_or(|aggregation| aggregation.evaluate(event, timestamp));
+                matched || condition_matches
+            });
+        }
+
+        match self.aggregation.as_mut() {
+            Some(aggregation) => aggregation.evaluate(event, timestamp),
+            None => true,
+        }
+    }
+}
+
+/// The names of the fields of every search identifier of a Detection, in the order of their names.
+fn detection_fields(detection: &Detection) -> Vec<String> {
+    fn logic_fields(logic: &DetectionLogic, fields: &mut BTreeSet<String>) {
+        for (field, logic) in logic.and.iter().flatten() {
+            if !field.name.is_empty() {
+                fields.insert(field.name.clone());
+            }
+            logic_fields(logic, fields);
+        }
+        for logic in logic.or.iter().flatten() {
+            logic_fields(logic, fields);
+        }
+    }
+
+    fn collect(detection: &Detection, fields: &mut BTreeSet<String>) {
+        for condition in detection.conditions.iter().flatten() {
+            logic_fields(&condition.detection_logic, fields);
+            if let Some(nested_detection) = condition.nested_detections.as_ref() {
+                collect(nested_detection, fields);
+            }
+        }
+    }
+
+    let mut fields = BTreeSet::new();
+    collect(detection, &mut fields);
+    fields.into_iter().collect()
+}
+
+impl From<&SigmaRule> for RuleMatch {
+    fn from(rule: &SigmaRule) -> Self {
+        Self {
+            id: rule.id.clone(),
+            title: rule.title.clone(),
+            level: rule.level.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use serde_json::json;
+    use sigma_rule_parser::sigma_file::load_report::{LoadStatus, LoadSummary};
+    use crate::field_mappings::LogsourceFieldMappings;
+    use super::*;
+
+    fn rule(yml: &str) -> SigmaRule {
+        serde_yaml::from_str(yml).unwrap()
+    }
+
+    fn rule_set() -> RuleSet {
+        let rules = vec![
+            rule(r#"
+                title: Whoami
+                id: whoami
+                level: low
+                detection:
+                  selection:
+                    Image|endswith: '\whoami.exe'
+                  condition: selection
+            "#),
+            rule(r#"
+                title: Many failed logons
+                id: failed_logons
+                level: high
+                detection:
+                  selection:
+                    EventID: 4625
+                  timeframe: 1m
+                  condition: selection | count() by IpAddress > 1
+            "#),
+            rule(r#"
+                title: Unsupported
+                id: unsupported
+                detection:
+                  selection:
+                    EventID: 1
+                  condition: selection | near other
+            "#),
+        ];
+
+        RuleSet::from_rules(rules, LoadReport::default())
+    }
+
+    #[test]
+    fn skips_rules_that_do_not_build() {
+        let rule_set = rule_set();
+        assert_eq!(rule_set.len(), 2);
+        assert_eq!(rule_set.report().summary(), LoadSummary { loaded: 2, failed_to_build: 1, ..Default::default() });
+        assert_eq!(rule_set.report().entries[2].status, LoadStatus::FailedToBuild);
+
+        // values are compiled when the rule is built
+        let invalid = rule(r#"
+            title: Invalid regex
+            id: invalid_regex
+            detection:
+              selection:
+                CommandLine|re: '(unclosed'
+              condition: selection
+        "#);
+        let rule_set = RuleSet::from_rules(vec![invalid], LoadReport::default());
+        assert!(rule_set.is_empty());
+        assert!(rule_set.report().entries[0].reason.as_ref().unwrap().starts_with("invalid value '(unclosed'"));
+    }
+
+    #[test]
+    fn evaluate_detections_and_aggregations() {
+        let mut rule_set = rule_set();
+        let start = SystemTime::UNIX_EPOCH;
+
+        let matches = rule_set.evaluate(&LogEvent::from(json!({"Image": "C:\\Windows\\System32\\whoami.exe"})), start);
+        assert_eq!(matches, vec![RuleMatch { id: "whoami".to_string(), title: "Whoami".to_string(), level: "low".to_string() }]);
+
+        let failed_logon = LogEvent::from(json!({"EventID": 4625, "IpAddress": "10.0.0.1"}));
+        assert!(rule_set.evaluate(&failed_logon, start).is_empty());
+        assert_eq!(rule_set.evaluate(&failed_logon, start + Duration::from_secs(10))[0].id, "failed_logons");
+        assert!(rule_set.evaluate(&failed_logon, start + Duration::from_secs(200)).is_empty());
+
+        // without state, only the rules without an aggregation are evaluated
+        assert!(rule_set.detect(&failed_logon, &Logsource::default()).is_empty());
+        assert_eq!(rule_set.detect(&LogEvent::from(json!({"Image": "C:\\whoami.exe"})), &Logsource::default())[0].id, "whoami");
+    }
+
+    #[test]
+    fn evaluate_lists_of_conditions() {
+        let rules = vec![
+            rule(r#"
+                title: Failed Kerberos logons
+                id: kerberos
+                detection:
+                  selection:
+                    EventID: 4768
+                  timeframe: 1m
+                  condition:
+                    - selection | count() by IpAddress > 1
+            "#),
+            rule(r#"
+                title: Whoami or many logons
+                id: whoami_or_logons
+                detection:
+                  whoami:
+                    Image: whoami.exe
+                  logon:
+                    EventID: 4624
+                  condition:
+                    - whoami
+                    - logon | count() > 1
+            "#),
+        ];
+        let mut rule_set = RuleSet::from_rules(rules, LoadReport::default());
+        assert_eq!(rule_set.len(), 2);
+        let start = SystemTime::UNIX_EPOCH;
+
+        let kerberos = LogEvent::from(json!({"EventID": 4768, "IpAddress": "10.0.0.1"}));
+        assert!(rule_set.evaluate(&kerberos, start).is_empty());
+        assert_eq!(rule_set.evaluate(&kerberos, start + Duration::from_secs(1))[0].id, "kerberos");
+        assert!(rule_set.detect(&kerberos, &Logsource::default()).is_empty());
+
+        assert_eq!(rule_set.evaluate(&LogEvent::from(json!({"Image": "whoami.exe"})), start)[0].id, "whoami_or_logons");
+        let logon = LogEvent::from(json!({"EventID": 4624}));
+        assert!(rule_set.evaluate(&logon, start).is_empty());
+        assert_eq!(rule_set.evaluate(&logon, start)[0].id, "whoami_or_logons");
+    }
+
+    #[test]
+    fn evaluate_correlations() {
+        let correlated = |generate: bool| vec![
+            rule(r#"
+                title: Failed logon
+                id: failed_logon
+                detection:
+                  selection:
+                    EventID: 4625
+                  condition: selection
+            "#),
+            rule(&format!(r#"
+                title: Many failed logons
+                id: many_failed_logons
+                correlation:
+                  type: event_count
+                  rules:
+                    - failed_logon
+                  timespan: 1m
+                  condition:
+                    gte: 2
+                  generate: {generate}
+            "#)),
+        ];
+        let failed_logon = LogEvent::from(json!({"EventID": 4625}));
+        let start = SystemTime::UNIX_EPOCH;
+
+        // the failed logons only alert through the correlation
+        let mut rule_set = RuleSet::from_rules(correlated(false), LoadReport::default());
+        assert!(rule_set.evaluate(&failed_logon, start).is_empty());
+        assert_eq!(rule_set.evaluate(&failed_logon, start).iter().map(|rule_match| rule_match.id.as_str()).collect::<Vec<&str>>(), vec!["many_failed_logons"]);
+        assert!(rule_set.detect(&failed_logon, &Logsource::default()).is_empty());
+
+        let mut rule_set = RuleSet::from_rules(correlated(true), LoadReport::default());
+        assert_eq!(rule_set.evaluate(&failed_logon, start).len(), 1);
+        assert_eq!(rule_set.evaluate(&failed_logon, start).len(), 2);
+
+        // the stateful alerts leave out the alerts of detect
+        let mut rule_set = RuleSet::from_rules(correlated(true), LoadReport::default());
+        assert!(rule_set.has_stateful_rules());
+        assert_eq!(rule_set.detect(&failed_logon, &Logsource::default())[0].id, "failed_logon");
+        assert!(rule_set.stateful_alerts(&failed_logon, &Logsource::default(), start).is_empty());
+        assert_eq!(rule_set.stateful_alerts(&failed_logon, &Logsource::default(), start)[0].id, "many_failed_logons");
+    }
+
+    #[test]
+    fn evaluate_with_field_mappings() {
+        let field_mappings: Vec<LogsourceFieldMappings> = serde_yaml::from_str(r#"
+            - logsource:
+                product: linux
+              fields:
+                Image: process.executable
+        "#).unwrap();
+        let mut rule = rule(r#"
+            title: Whoami
+            id: whoami
+            logsource:
+              product: linux
+            detection:
+              selection:
+                Image|endswith: '/whoami'
+              condition: selection
+        "#);
+        let mut rule_set = RuleSet::from_rules(vec![rule.clone()], LoadReport::default()).with_field_mappings(&FieldMappings::new(field_mappings.clone()));
+
+        let event = LogEvent::from(json!({"process": {"executable": "/usr/bin/whoami"}}));
+        assert_eq!(rule_set.evaluate(&event, SystemTime::UNIX_EPOCH).len(), 1);
+
+        // the matched fields of the alert are named as in the rule
+        let alerts = rule_set.alerts(&event, &Logsource::default(), SystemTime::UNIX_EPOCH);
+        assert_eq!(alerts[0].matched_fields, BTreeMap::from([("Image".to_string(), json!("/usr/bin/whoami"))]));
+
+        rule.logsource.product = "windows".to_string();
+        let mut rule_set = RuleSet::from_rules(vec![rule], LoadReport::default()).with_field_mappings(&FieldMappings::new(field_mappings));
+        assert!(rule_set.evaluate(&event, SystemTime::UNIX_EPOCH).is_empty());
+    }
+
+    #[test]
+    fn evaluate_rules_of_event_logsource() {
+        let rule = |product: &str| rule(&format!(r#"
+            title: Whoami {product}
+            id: whoami_{product}
+            logsource:
+              product: {product}
+            detection:
+              selection:
+                CommandLine|contains: whoami
+              condition: selection
+        "#));
+        let mut rule_set = RuleSet::from_rules(vec![rule("windows"), rule("linux")], LoadReport::default());
+        assert_eq!(rule_set.index().len(), 2);
+
+        let event = LogEvent::from(json!({"CommandLine": "whoami"}));
+        assert_eq!(rule_set.evaluate(&event, SystemTime::UNIX_EPOCH).len(), 2);
+
+        let linux = Logsource { product: "linux".to_string(), service: "auditd".to_string(), ..Default::default() };
+        let matches = rule_set.evaluate_with_logsource(&event, &linux, SystemTime::UNIX_EPOCH);
+        assert_eq!(matches.iter().map(|rule_match| rule_match.id.as_str()).collect::<Vec<&str>>(), vec!["whoami_linux"]);
+    }
+}
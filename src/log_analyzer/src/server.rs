@@ -1,104 +1,180 @@
+mod bulk;
+mod elasticsearch;
+
+use std::sync::Arc;
 use actix_web::dev::Server;
-use actix_web::{web, get, App, HttpServer, Responder, HttpResponse, error, Error};
-use std::collections::HashMap;
-use actix_web::http::StatusCode;
-use serde_json::{json, Value};
-use serde::{Deserialize, Serialize};
-use sigma_rule_parser::structs::detection::Detection;
+use actix_web::{web, post, App, HttpRequest, HttpServer, HttpResponse, error, Error};
 use futures_util::StreamExt as _;
-
-// curl -X GET \
+use serde::Serialize;
+use serde_json::Value;
+use sigma_log_parser::log_event::LogEvent;
+use sigma_rule_parser::structs::sigma_rule::Logsource;
+use crate::config::{RoutingConfig, ServerConfig};
+use crate::pipeline::Pipeline;
+use crate::rule_set::RuleMatch;
+
+// curl -X POST \
 // -H "Content-Type: application/json" \
-// -d '{ "fname": "Angus", "lname": "Chuck"}' \
+// -H "X-Logsource-Product: windows" \
+// -d '{ "Image": "C:\\Windows\\System32\\whoami.exe", "CommandLine": "whoami /priv" }' \
 // http://localhost:8080/v1/log-ingress
 
+/// The rules that matched the events of a request.
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct IngressResponse {
+    pub events: usize,
+    pub matches: Vec<EventMatch>,
+}
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Log {
-
+/// A rule that matched an event, where the event is the position of the event in the request.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct EventMatch {
+    pub event: usize,
     #[serde(flatten)]
-    extra: HashMap<String, Value>,
+    pub rule: RuleMatch,
 }
 
-
-const MAX_SIZE: usize = 262_144;
-
-#[get("/log-ingress")]
-async fn handle_log(mut payload: web::Payload, mappings: web::Data<Vec<Detection>>) -> Result<HttpResponse, Error> {
-    // println!("Raw Json 'Value': {:?}", payload.);
-    println!("Mappings (req): {:?}", mappings);
-    // log['key'] is a borrow of data at that index
-    // json map can be indexed with string keys
-    // json array can be indexed with int keys
-
-    // If the type of the data is not right for the type with
-    // which it is being indexed, or if a map does not contain the
-    // key being indexed, or if the index into a vector is out of bounds,
-    // the returned element is Value::Null.
-
-    // String - we have the key and value
-    // Map - We have a nested set of key + values
-    // Array - We have a list of values
-
+/// Evaluates a JSON event, or a JSON array of events, against the rules of their logsource.
+/// The logsource of the events is read from the request headers, and from the fields of each event.
+#[post("/log-ingress")]
+async fn handle_log(
+    request: HttpRequest,
+    mut payload: web::Payload,
+    pipeline: web::Data<Pipeline>,
+    config: web::Data<ServerConfig>,
+) -> Result<HttpResponse, Error> {
     let mut body = web::BytesMut::new();
     while let Some(chunk) = payload.next().await {
         let chunk = chunk?;
         // limit max size of in-memory payload
-        if (body.len() + chunk.len()) > MAX_SIZE {
-            return Err(error::ErrorBadRequest("overflow"));
+        if (body.len() + chunk.len()) > config.max_payload_size {
+            return Err(error::ErrorPayloadTooLarge(format!("the body is larger than {} bytes", config.max_payload_size)));
         }
         body.extend_from_slice(&chunk);
     }
 
+    let events = parse_events(&body).map_err(error::ErrorBadRequest)?;
+    let received = header_logsource(&request, pipeline.routing());
 
-    Ok(HttpResponse::new(StatusCode::OK)) // <- send response
+    let mut response = IngressResponse { events: events.len(), matches: Vec::new() };
+    for (position, event) in events.iter().enumerate() {
+        for rule in pipeline.evaluate(event, &received) {
+            response.matches.push(EventMatch { event: position, rule });
+        }
+    }
 
+    Ok(HttpResponse::Ok().json(response))
 }
 
+/// A body is either a single JSON object, or an array of JSON objects.
+fn parse_events(body: &[u8]) -> Result<Vec<LogEvent>, String> {
+    let events = match serde_json::from_slice::<Value>(body).map_err(|err| format!("invalid JSON - {}", err))? {
+        Value::Array(events) => events,
+        event => vec![event],
+    };
 
+    events
+        .into_iter()
+        .enumerate()
+        .map(|(position, event)| match event {
+            Value::Object(fields) => Ok(LogEvent::new(fields)),
+            _ => Err(format!("event {} must be a JSON object", position)),
+        })
+        .collect()
+}
+
+fn header_logsource(request: &HttpRequest, routing: &RoutingConfig) -> Logsource {
+    routing.headers.read(|name| request.headers().get(name).and_then(|value| value.to_str().ok()).map(str::to_string))
+}
 
-pub fn create_server() -> Server {
-    let detections: Vec<Detection> = vec![];
-    let server = match HttpServer::new(move || {
+pub fn create_server(config: &ServerConfig, pipeline: Arc<Pipeline>) -> std::io::Result<Server> {
+    // every worker shares the pipeline, so aggregations and correlations see the events of every request
+    let pipeline = web::Data::from(pipeline);
+    let server_config = web::Data::new(config.clone());
+    let server = HttpServer::new(move || {
         App::new()
-            .app_data(web::Data::new(detections.clone()))
-            .service(handle_log)
+            .app_data(pipeline.clone())
+            .app_data(server_config.clone())
+            .service(web::scope("/v1").service(handle_log).service(bulk::handle_bulk))
+            .service(elasticsearch::handle_info)
+            .service(elasticsearch::handle_bulk)
+            .service(elasticsearch::handle_index_bulk)
     })
-        .bind(("127.0.0.1", 8080)) {
-        Ok(server) => server,
-        Err(_) => todo!()
-    };
+        .bind((config.host.as_str(), config.port))?;
 
-    server.run()
+    Ok(server.run())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use actix_web::{
-        http::{self, header::ContentType},
-        test
-    };
+    use actix_web::http::StatusCode;
+    use actix_web::test;
+    use serde_json::json;
+    use sigma_rule_parser::sigma_file::load_report::LoadReport;
+    use sigma_rule_parser::structs::sigma_rule::SigmaRule;
+    use crate::rule_set::RuleSet;
+
+    pub(super) fn pipeline() -> web::Data<Pipeline> {
+        let rule: SigmaRule = serde_yaml::from_str(r#"
+            title: Whoami
+            id: whoami
+            level: low
+            logsource:
+              product: windows
+            detection:
+              selection:
+                Image|endswith: '\whoami.exe'
+              condition: selection
+        "#).unwrap();
+        web::Data::new(Pipeline::new(RuleSet::from_rules(vec![rule], LoadReport::default()), RoutingConfig::default()))
+    }
+
+    macro_rules! app {
+        ($max_payload_size:expr) => {
+            test::init_service(
+                App::new()
+                    .app_data(pipeline())
+                    .app_data(web::Data::new(ServerConfig { max_payload_size: $max_payload_size, ..Default::default() }))
+                    .service(web::scope("/v1").service(handle_log))
+            ).await
+        };
+    }
 
     #[actix_web::test]
-    async fn test_index_ok() {
-        let detections = vec![0, 1];
-        let app = test::init_service(
-            App::new()
-                .app_data(detections.clone())
-                .service(handle_log)
-        ).await;
-
-        let req = test::TestRequest::get()
+    async fn ingress_returns_matches() {
+        let app = app!(1024);
+
+        let request = test::TestRequest::post()
             .uri("/v1/log-ingress")
+            .set_json(json!({"Image": "C:\\Windows\\System32\\whoami.exe"}))
             .to_request();
-        println!("Request: {:?}", req);
-
-        let resp = test::call_service(&app, req).await;
+        let response: Value = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(response, json!({"events": 1, "matches": [{"event": 0, "id": "whoami", "title": "Whoami", "level": "low"}]}));
 
-        println!("Response: {:?}", resp);
-        // assert_eq!(resp.status(), http::StatusCode::OK);
+        let request = test::TestRequest::post()
+            .uri("/v1/log-ingress")
+            .set_json(json!([{"Image": "cmd.exe"}, {"Image": "C:\\whoami.exe"}, {"Image": "C:\\whoami.exe", "logsource": {"product": "windows"}}]))
+            .insert_header(("X-Logsource-Product", "linux"))
+            .to_request();
+        let response: Value = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(response, json!({"events": 3, "matches": [{"event": 2, "id": "whoami", "title": "Whoami", "level": "low"}]}));
     }
 
+    #[actix_web::test]
+    async fn ingress_rejects_invalid_requests() {
+        let app = app!(64);
+
+        let request = test::TestRequest::get().uri("/v1/log-ingress").to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), StatusCode::NOT_FOUND);
 
+        let request = test::TestRequest::post().uri("/v1/log-ingress").set_payload("not json").to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), StatusCode::BAD_REQUEST);
+
+        let request = test::TestRequest::post().uri("/v1/log-ingress").set_json(json!([{"Image": "cmd.exe"}, 1])).to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), StatusCode::BAD_REQUEST);
+
+        let request = test::TestRequest::post().uri("/v1/log-ingress").set_payload("x".repeat(65)).to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
 }
@@ -0,0 +1,210 @@
+use actix_web::{web, post, HttpRequest, HttpResponse, Error};
+use futures_util::StreamExt as _;
+use serde::Serialize;
+use sigma_log_parser::log_event::LogEvent;
+use sigma_rule_parser::structs::sigma_rule::Logsource;
+use crate::config::ServerConfig;
+use crate::pipeline::Pipeline;
+use crate::rule_set::RuleMatch;
+use super::header_logsource;
+
+// curl -X POST \
+// -H "Content-Type: application/x-ndjson" \
+// --data-binary @events.ndjson \
+// http://localhost:8080/v1/log-ingress/bulk
+
+/// The number of line errors that are returned, the errors of later lines are only counted.
+const MAX_REPORTED_ERRORS: usize = 100;
+
+/// The rules that matched the lines of a bulk request, and the lines that could not be read.
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct BulkResponse {
+    /// The lines that are not empty
+    pub lines: usize,
+    pub events: usize,
+    pub matches: Vec<LineMatch>,
+    pub errors: ErrorSummary,
+}
+
+/// A rule that matched the event of a line, where the first line is 1.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct LineMatch {
+    pub line: usize,
+    #[serde(flatten)]
+    pub rule: RuleMatch,
+}
+
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct ErrorSummary {
+    pub count: usize,
+    /// The errors of the first lines that could not be read, at most MAX_REPORTED_ERRORS
+    pub lines: Vec<LineError>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct LineError {
+    pub line: usize,
+    pub error: String,
+}
+
+/// A line of the body, or the number of a line that is longer than the largest line accepted.
+#[derive(Debug, PartialEq)]
+pub(super) enum Line {
+    Complete(usize, Vec<u8>),
+    TooLong(usize),
+}
+
+/// Splits the chunks of a body into lines, and only keeps the line that is not complete yet.
+/// A line longer than the largest line accepted is dropped up to its end, so the memory of a request is bounded.
+pub(super) struct LineSplitter {
+    partial: Vec<u8>,
+    line: usize,
+    max_line_size: usize,
+    too_long: bool,
+}
+
+impl LineSplitter {
+    pub(super) fn new(max_line_size: usize) -> LineSplitter {
+        LineSplitter { partial: Vec::new(), line: 0, max_line_size, too_long: false }
+    }
+
+    pub(super) fn push(&mut self, chunk: &[u8]) -> Vec<Line> {
+        let mut lines = Vec::new();
+        let mut rest = chunk;
+
+        while let Some(end) = rest.iter().position(|byte| *byte == b'\n') {
+            self.append(&rest[..end]);
+            lines.push(self.complete());
+            rest = &rest[end + 1..];
+        }
+        self.append(rest);
+
+        lines
+    }
+
+    /// Returns the last line of a body that does not end with a newline.
+    pub(super) fn finish(&mut self) -> Option<Line> {
+        (!self.partial.is_empty() || self.too_long).then(|| self.complete())
+    }
+
+    fn append(&mut self, bytes: &[u8]) {
+        if self.too_long || self.partial.len() + bytes.len() > self.max_line_size {
+            self.too_long = true;
+            self.partial.clear();
+        } else {
+            self.partial.extend_from_slice(bytes);
+        }
+    }
+
+    fn complete(&mut self) -> Line {
+        self.line += 1;
+        if std::mem::take(&mut self.too_long) {
+            return Line::TooLong(self.line);
+        }
+        Line::Complete(self.line, std::mem::take(&mut self.partial))
+    }
+}
+
+/// Evaluates newline delimited JSON against the rules of the logsource of each event, while the body is read.
+/// The body is read one chunk at a time, and the next chunk is only read once the lines of the chunk are evaluated,
+///     so a client that sends faster than the events are evaluated is slowed down instead of the body being buffered.
+/// Lines that are not a JSON object are counted as errors, and do not fail the request. A line may be as long as server.max_payload_size.
+#[post("/log-ingress/bulk")]
+async fn handle_bulk(
+    request: HttpRequest,
+    mut payload: web::Payload,
+    pipeline: web::Data<Pipeline>,
+    config: web::Data<ServerConfig>,
+) -> Result<HttpResponse, Error> {
+    let received = header_logsource(&request, pipeline.routing());
+    let mut splitter = LineSplitter::new(config.max_payload_size);
+    let mut response = BulkResponse::default();
+
+    while let Some(chunk) = payload.next().await {
+        for line in splitter.push(&chunk?) {
+            evaluate_line(&pipeline, line, &received, &mut response);
+        }
+    }
+    if let Some(line) = splitter.finish() {
+        evaluate_line(&pipeline, line, &received, &mut response);
+    }
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+fn evaluate_line(pipeline: &Pipeline, line: Line, received: &Logsource, response: &mut BulkResponse) {
+    let (number, event) = match line {
+        Line::Complete(number, bytes) => {
+            let text = String::from_utf8_lossy(&bytes);
+            if text.trim().is_empty() {
+                return;
+            }
+            (number, LogEvent::from_json(&text).map_err(|err| err.to_string()))
+        }
+        Line::TooLong(number) => (number, Err("the line is longer than server.max_payload_size".to_string())),
+    };
+    response.lines += 1;
+
+    match event {
+        Ok(event) => {
+            response.events += 1;
+            for rule in pipeline.evaluate(&event, received) {
+                response.matches.push(LineMatch { line: number, rule });
+            }
+        }
+        Err(error) => {
+            response.errors.count += 1;
+            if response.errors.lines.len() < MAX_REPORTED_ERRORS {
+                response.errors.lines.push(LineError { line: number, error });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::App;
+    use actix_web::test::{call_and_read_body_json, init_service, TestRequest};
+    use serde_json::{json, Value};
+    use super::*;
+    use super::super::tests::pipeline;
+
+    #[test]
+    fn split_lines() {
+        let mut splitter = LineSplitter::new(8);
+        assert_eq!(splitter.push(b"{\"a\":1}\n{\"b\""), vec![Line::Complete(1, b"{\"a\":1}".to_vec())]);
+        assert_eq!(splitter.push(b":2}\n\n0123456789"), vec![Line::Complete(2, b"{\"b\":2}".to_vec()), Line::Complete(3, Vec::new())]);
+        assert_eq!(splitter.push(b"0123\n{}"), vec![Line::TooLong(4)]);
+        assert_eq!(splitter.finish(), Some(Line::Complete(5, b"{}".to_vec())));
+    }
+
+    #[actix_web::test]
+    async fn bulk_ingress_returns_matches_and_errors() {
+        let app = init_service(
+            App::new()
+                .app_data(pipeline())
+                .app_data(web::Data::new(ServerConfig { max_payload_size: 64, ..Default::default() }))
+                .service(web::scope("/v1").service(handle_bulk))
+        ).await;
+
+        let body = format!(
+            "{}\n\n{}\nnot json\n{}\n{}",
+            r#"{"Image": "C:\\whoami.exe"}"#,
+            r#"{"Image": "cmd.exe"}"#,
+            "x".repeat(100),
+            r#"{"Image": "C:\\Windows\\whoami.exe"}"#,
+        );
+        let request = TestRequest::post().uri("/v1/log-ingress/bulk").set_payload(body).to_request();
+        let response: Value = call_and_read_body_json(&app, request).await;
+
+        assert_eq!(response["lines"], 5);
+        assert_eq!(response["events"], 3);
+        assert_eq!(response["matches"], json!([
+            {"line": 1, "id": "whoami", "title": "Whoami", "level": "low"},
+            {"line": 6, "id": "whoami", "title": "Whoami", "level": "low"},
+        ]));
+        assert_eq!(response["errors"]["count"], 2);
+        assert_eq!(response["errors"]["lines"][0]["line"], 4);
+        assert_eq!(response["errors"]["lines"][1], json!({"line": 5, "error": "the line is longer than server.max_payload_size"}));
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use actix_web::{web, get, post, HttpRequest, HttpResponse, HttpResponseBuilder, Error};
+use actix_web::http::StatusCode;
+use futures_util::StreamExt as _;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use sigma_log_parser::log_event::LogEvent;
+use sigma_rule_parser::structs::sigma_rule::Logsource;
+use crate::config::ServerConfig;
+use crate::pipeline::Pipeline;
+use super::bulk::{Line, LineSplitter};
+use super::header_logsource;
+
+// Filebeat, Logstash and other Elasticsearch clients only need the URL of the log analyzer, i.e. for Filebeat:
+//     output.elasticsearch.hosts: ["http://localhost:8080"]
+//     setup.template.enabled: false
+//     setup.ilm.enabled: false
+
+/// The Elasticsearch version that is reported to clients, which check it before they send documents.
+const ELASTICSEARCH_VERSION: &str = "8.11.0";
+
+/// The documents are evaluated and not stored, so every document is the first version of the document.
+static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+/// An action line of a bulk request. Index, create and update actions are followed by a source line, delete actions are not.
+#[derive(Debug, PartialEq)]
+struct BulkAction {
+    name: String,
+    index: String,
+    id: String,
+}
+
+/// The result of an action, in the item format of the Elasticsearch bulk API.
+#[derive(Serialize, Debug, PartialEq)]
+struct BulkItem {
+    #[serde(rename = "_index")]
+    index: String,
+    #[serde(rename = "_id")]
+    id: String,
+    #[serde(rename = "_version", skip_serializing_if = "Option::is_none")]
+    version: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<&'static str>,
+    status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<Value>,
+}
+
+#[derive(Serialize, Debug, Default)]
+struct BulkResponse {
+    took: u128,
+    errors: bool,
+    items: Vec<BTreeMap<String, BulkItem>>,
+}
+
+/// The cluster info that clients request to check the version of Elasticsearch.
+#[get("/")]
+async fn handle_info() -> HttpResponse {
+    elasticsearch_response(StatusCode::OK).json(json!({
+        "name": "log-analyzer",
+        "cluster_name": "log-analyzer",
+        "version": {
+            "number": ELASTICSEARCH_VERSION,
+            "build_flavor": "default",
+            "minimum_wire_compatibility_version": "7.17.0",
+            "minimum_index_compatibility_version": "7.0.0",
+        },
+        "tagline": "You Know, for Search",
+    }))
+}
+
+#[post("/_bulk")]
+async fn handle_bulk(
+    request: HttpRequest,
+    payload: web::Payload,
+    pipeline: web::Data<Pipeline>,
+    config: web::Data<ServerConfig>,
+) -> Result<HttpResponse, Error> {
+    bulk(request, payload, None, pipeline, config).await
+}
+
+#[post("/{index}/_bulk")]
+async fn handle_index_bulk(
+    request: HttpRequest,
+    payload: web::Payload,
+    index: web::Path<String>,
+    pipeline: web::Data<Pipeline>,
+    config: web::Data<ServerConfig>,
+) -> Result<HttpResponse, Error> {
+    bulk(request, payload, Some(index.into_inner()), pipeline, config).await
+}
+
+/// Evaluates the source documents of a bulk request as log events, while the body is read.
+/// A source document that is not a JSON object fails its item, an invalid action line fails the request as it does in Elasticsearch.
+async fn bulk(
+    request: HttpRequest,
+    mut payload: web::Payload,
+    default_index: Option<String>,
+    pipeline: web::Data<Pipeline>,
+    config: web::Data<ServerConfig>,
+) -> Result<HttpResponse, Error> {
+    let start = Instant::now();
+    let received = header_logsource(&request, pipeline.routing());
+    let mut splitter = LineSplitter::new(config.max_payload_size);
+    let mut pending: Option<BulkAction> = None;
+    let mut response = BulkResponse::default();
+
+    let mut lines = Vec::new();
+    let mut finished = false;
+    while !finished {
+        match payload.next().await {
+            Some(chunk) => lines.extend(splitter.push(&chunk?)),
+            None => {
+                lines.extend(splitter.finish());
+                finished = true;
+            }
+        }
+
+        for line in lines.drain(..) {
+            let (number, bytes) = match line {
+                Line::Complete(number, bytes) => (number, bytes),
+                Line::TooLong(number) => return Ok(illegal_argument(&format!("line {} is longer than server.max_payload_size", number))),
+            };
+            if bytes.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+
+            match pending.take() {
+                None => match parse_action(&bytes, default_index.as_deref()) {
+                    Ok(action) if action.name == "delete" => response.push(&action, deleted()),
+                    Ok(action) => pending = Some(action),
+                    Err(reason) => return Ok(illegal_argument(&format!("Malformed action/metadata line [{}], {}", number, reason))),
+                },
+                Some(action) => {
+                    let item = evaluate_source(&pipeline, &action, &bytes, &received);
+                    response.push(&action, item);
+                }
+            }
+        }
+    }
+
+    if pending.is_some() {
+        return Ok(illegal_argument("The bulk request must be terminated by a newline [\\n]"));
+    }
+
+    response.took = start.elapsed().as_millis();
+    Ok(elasticsearch_response(StatusCode::OK).json(response))
+}
+
+fn parse_action(bytes: &[u8], default_index: Option<&str>) -> Result<BulkAction, String> {
+    let action = match serde_json::from_slice::<Value>(bytes) {
+        Ok(Value::Object(action)) if action.len() == 1 => action,
+        Ok(_) => return Err("expected an object with a single action".to_string()),
+        Err(err) => return Err(err.to_string()),
+    };
+    let (name, metadata) = action.into_iter().next().unwrap_or_default();
+    if !["index", "create", "update", "delete"].contains(&name.as_str()) {
+        return Err(format!("expected one of [create, delete, index, update] but found [{}]", name));
+    }
+
+    let metadata = metadata.as_object().cloned().unwrap_or_default();
+    let index = metadata.get("_index").and_then(Value::as_str).or(default_index).ok_or("the action requires an _index")?.to_string();
+    let id = match metadata.get("_id") {
+        Some(Value::String(id)) => id.clone(),
+        Some(Value::Number(id)) => id.to_string(),
+        _ if name == "update" || name == "delete" => return Err(format!("the {} action requires an _id", name)),
+        _ => generate_id(),
+    };
+
+    Ok(BulkAction { name, index, id })
+}
+
+/// Evaluates the source of an action. The source of an update action is the partial document in its doc field.
+fn evaluate_source(pipeline: &Pipeline, action: &BulkAction, bytes: &[u8], received: &Logsource) -> BulkItem {
+    let source = match serde_json::from_slice::<Value>(bytes) {
+        Ok(Value::Object(source)) if action.name == "update" => source.get("doc").and_then(Value::as_object).cloned().unwrap_or_else(Map::new),
+        Ok(Value::Object(source)) => source,
+        Ok(_) => return failed("the document must be a JSON object"),
+        Err(err) => return failed(&err.to_string()),
+    };
+
+    pipeline.evaluate(&LogEvent::new(source), received);
+
+    match action.name.as_str() {
+        "update" => BulkItem { version: Some(1), result: Some("updated"), status: 200, ..BulkItem::default() },
+        _ => BulkItem { version: Some(1), result: Some("created"), status: 201, ..BulkItem::default() },
+    }
+}
+
+impl BulkResponse {
+    fn push(&mut self, action: &BulkAction, mut item: BulkItem) {
+        self.errors |= item.error.is_some();
+        item.index = action.index.clone();
+        item.id = action.id.clone();
+        self.items.push(BTreeMap::from([(action.name.clone(), item)]));
+    }
+}
+
+impl Default for BulkItem {
+    fn default() -> Self {
+        Self { index: String::new(), id: String::new(), version: None, result: None, status: 200, error: None }
+    }
+}
+
+fn deleted() -> BulkItem {
+    BulkItem { version: Some(1), result: Some("deleted"), ..BulkItem::default() }
+}
+
+fn failed(reason: &str) -> BulkItem {
+    BulkItem { status: 400, error: Some(json!({"type": "document_parsing_exception", "reason": reason})), ..BulkItem::default() }
+}
+
+fn illegal_argument(reason: &str) -> HttpResponse {
+    let error = json!({"type": "illegal_argument_exception", "reason": reason});
+    elasticsearch_response(StatusCode::BAD_REQUEST).json(json!({"error": {"root_cause": [error.clone()], "type": error["type"], "reason": reason}, "status": 400}))
+}
+
+/// Elasticsearch clients since 7.14 refuse responses without the product header.
+fn elasticsearch_response(status: StatusCode) -> HttpResponseBuilder {
+    let mut response = HttpResponse::build(status);
+    response.insert_header(("X-Elastic-Product", "Elasticsearch"));
+    response
+}
+
+fn generate_id() -> String {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_nanos()).unwrap_or_default();
+    format!("{:x}{:04x}", nanos, SEQUENCE.fetch_add(1, Ordering::Relaxed) & 0xffff)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::App;
+    use actix_web::test::{call_and_read_body_json, call_service, init_service, TestRequest};
+    use super::*;
+    use super::super::tests::pipeline;
+
+    #[test]
+    fn parse_action_lines() {
+        assert_eq!(
+            parse_action(br#"{"index": {"_index": "logs", "_id": 1}}"#, None),
+            Ok(BulkAction { name: "index".to_string(), index: "logs".to_string(), id: "1".to_string() })
+        );
+        assert_eq!(parse_action(br#"{"create": {}}"#, Some("default")).unwrap().index, "default");
+        assert!(parse_action(br#"{"create": {}}"#, None).is_err());
+        assert!(parse_action(br#"{"update": {"_index": "logs"}}"#, None).is_err());
+        assert!(parse_action(br#"{"upsert": {"_index": "logs"}}"#, None).is_err());
+        assert!(parse_action(br#"{"index": {}, "create": {}}"#, Some("logs")).is_err());
+    }
+
+    #[actix_web::test]
+    async fn bulk_api() {
+        let app = init_service(
+            App::new()
+                .app_data(pipeline())
+                .app_data(web::Data::new(ServerConfig::default()))
+                .service(handle_info)
+                .service(handle_bulk)
+                .service(handle_index_bulk)
+        ).await;
+
+        let response = call_service(&app, TestRequest::get().uri("/").to_request()).await;
+        assert_eq!(response.headers().get("X-Elastic-Product").unwrap(), "Elasticsearch");
+
+        let body = [
+            r#"{"index": {"_index": "winlogbeat", "_id": "1"}}"#,
+            r#"{"Image": "C:\\whoami.exe"}"#,
+            r#"{"create": {}}"#,
+            r#"[1]"#,
+            r#"{"delete": {"_id": "2"}}"#,
+            r#"{"update": {"_id": "3"}}"#,
+            r#"{"doc": {"Image": "cmd.exe"}}"#,
+        ].join("\n") + "\n";
+        let request = TestRequest::post().uri("/logs/_bulk").set_payload(body).to_request();
+        let response: Value = call_and_read_body_json(&app, request).await;
+
+        assert_eq!(response["errors"], true);
+        assert_eq!(response["items"][0], json!({"index": {"_index": "winlogbeat", "_id": "1", "_version": 1, "result": "created", "status": 201}}));
+        assert_eq!(response["items"][1]["create"]["_index"], "logs");
+        assert_eq!(response["items"][1]["create"]["status"], 400);
+        assert_eq!(response["items"][2], json!({"delete": {"_index": "logs", "_id": "2", "_version": 1, "result": "deleted", "status": 200}}));
+        assert_eq!(response["items"][3]["update"]["result"], "updated");
+
+        let request = TestRequest::post().uri("/_bulk").set_payload("{\"index\": {\"_index\": \"logs\"}}\n").to_request();
+        let response = call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let request = TestRequest::post().uri("/_bulk").set_payload("{\"index\": {}}\n{}\n").to_request();
+        let response = call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
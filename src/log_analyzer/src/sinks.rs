@@ -0,0 +1,224 @@
+pub mod file;
+pub mod kafka;
+pub mod stdout;
+pub mod webhook;
+
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Sender, SyncSender};
+use std::thread::{self, JoinHandle};
+use log::{error, warn};
+use crate::alert::Alert;
+use crate::config::SinkConfig;
+use self::file::FileSink;
+use self::kafka::KafkaSink;
+use self::stdout::StdoutSink;
+use self::webhook::WebhookSink;
+
+/// The number of alerts that wait for the sinks, before dispatching an alert waits for the sinks.
+const QUEUE_SIZE: usize = 10_000;
+
+/// A destination of alerts. Sinks are called on the thread of the Dispatcher, so they may block while they deliver an alert.
+pub trait Sink: Send {
+    /// The name of the sink in the log, i.e. `webhook http://localhost:9000/alerts`
+    fn name(&self) -> String;
+
+    fn send(&mut self, alert: &Alert) -> io::Result<()>;
+}
+
+/// Creates the sink of a config. Sinks that connect to a server connect when they send their first alert.
+pub fn create(config: &SinkConfig) -> io::Result<Box<dyn Sink>> {
+    Ok(match config {
+        SinkConfig::Stdout => Box::new(StdoutSink::new(io::stdout())),
+        SinkConfig::File { path, max_size, max_files } => Box::new(FileSink::new(path, *max_size, *max_files)?),
+        SinkConfig::Webhook { url } => Box::new(WebhookSink::new(url)?),
+        SinkConfig::Kafka { brokers, topic } => Box::new(KafkaSink::new(brokers, topic)),
+    })
+}
+
+/// Delivers alerts to every sink on a thread of its own, so evaluating events does not wait for a slow sink until QUEUE_SIZE alerts are queued.
+/// An alert that a sink fails to deliver is logged and not retried. The alerts that are queued are delivered when the Dispatcher is dropped.
+#[derive(Debug)]
+pub struct Dispatcher {
+    sender: Option<SyncSender<Message>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// The number of alerts of an input that could not be delivered since its last flush,
+///     so the flush of an input fails for its own alerts only.
+#[derive(Clone, Debug, Default)]
+pub struct FailedAlerts(Arc<AtomicUsize>);
+
+#[derive(Debug)]
+enum Message {
+    Alert(Box<Alert>, Option<FailedAlerts>),
+    /// Answered once the alerts before it were delivered
+    Flush(Sender<()>),
+}
+
+impl Dispatcher {
+    pub fn start(mut sinks: Vec<Box<dyn Sink>>) -> Dispatcher {
+        let (sender, receiver) = mpsc::sync_channel::<Message>(QUEUE_SIZE);
+        let thread = thread::spawn(move || {
+            for message in receiver {
+                match message {
+                    Message::Alert(alert, failed) => {
+                        for sink in sinks.iter_mut() {
+                            if let Err(err) = sink.send(&alert) {
+                                error!("Could not send alert {} to {} - {}", alert.id, sink.name(), err);
+                                if let Some(failed) = failed.as_ref() {
+                                    failed.0.fetch_add(1, Ordering::Relaxed);
+                                }
+                            }
+                        }
+                    }
+                    Message::Flush(reply) => {
+                        let _ = reply.send(());
+                    }
+                }
+            }
+        });
+
+        Dispatcher { sender: Some(sender), thread: Some(thread) }
+    }
+
+    /// Queues an alert for the sinks, and waits when the queue is full. The alerts that are not delivered are counted in failed, if any.
+    pub fn dispatch(&self, alert: Alert, failed: Option<&FailedAlerts>) {
+        let sent = self.sender.as_ref().map(|sender| sender.send(Message::Alert(Box::new(alert), failed.cloned())));
+        if !matches!(sent, Some(Ok(()))) {
+            warn!("Dropping an alert, the sinks stopped");
+            if let Some(failed) = failed {
+                failed.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Waits until the alerts that were dispatched before were delivered, and fails when any of the alerts counted in failed
+    ///     could not be delivered since its last flush.
+    pub fn flush(&self, failed: &FailedAlerts) -> io::Result<()> {
+        let (reply, delivered) = mpsc::channel();
+        let sent = self.sender.as_ref().map(|sender| sender.send(Message::Flush(reply)));
+        if !matches!((sent, delivered.recv()), (Some(Ok(())), Ok(()))) {
+            return Err(io::Error::other("the sinks stopped"));
+        }
+        match failed.0.swap(0, Ordering::Relaxed) {
+            0 => Ok(()),
+            failed => Err(io::Error::other(format!("{} alerts could not be delivered to the sinks", failed))),
+        }
+    }
+}
+
+impl Drop for Dispatcher {
+    fn drop(&mut self) {
+        // the thread stops once the queue is empty and the sender is gone
+        drop(self.sender.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::SystemTime;
+    use serde_json::json;
+    use sigma_log_parser::log_event::LogEvent;
+    use sigma_rule_parser::structs::sigma_rule::SigmaRule;
+    use super::*;
+
+    /// A sink that keeps the alerts it was sent.
+    pub(crate) struct MemorySink(pub Arc<Mutex<Vec<Alert>>>);
+
+    impl Sink for MemorySink {
+        fn name(&self) -> String {
+            "memory".to_string()
+        }
+
+        fn send(&mut self, alert: &Alert) -> io::Result<()> {
+            self.0.lock().unwrap().push(alert.clone());
+            Ok(())
+        }
+    }
+
+    pub(crate) struct FailingSink;
+
+    impl Sink for FailingSink {
+        fn name(&self) -> String {
+            "failing".to_string()
+        }
+
+        fn send(&mut self, _: &Alert) -> io::Result<()> {
+            Err(io::Error::other("unavailable"))
+        }
+    }
+
+    pub(crate) fn alert(id: &str) -> Alert {
+        let rule: SigmaRule = serde_yaml::from_str(&format!("title: Test\nid: {}\nlevel: high\ndetection:\n  condition: selection\n", id)).unwrap();
+        Alert::new(&rule, BTreeMap::new(), &LogEvent::from(json!({"message": "test"})), SystemTime::UNIX_EPOCH)
+    }
+
+    /// A sink that waits for a message before it delivers an alert.
+    struct BlockedSink(mpsc::Receiver<()>, Arc<Mutex<Vec<Alert>>>);
+
+    impl Sink for BlockedSink {
+        fn name(&self) -> String {
+            "blocked".to_string()
+        }
+
+        fn send(&mut self, alert: &Alert) -> io::Result<()> {
+            self.0.recv().map_err(io::Error::other)?;
+            self.1.lock().unwrap().push(alert.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn dispatch_alerts_to_every_sink() {
+        let alerts = Arc::new(Mutex::new(Vec::new()));
+        let dispatcher = Dispatcher::start(vec![Box::new(FailingSink), Box::new(MemorySink(alerts.clone()))]);
+        let (failed, other_input) = (FailedAlerts::default(), FailedAlerts::default());
+        dispatcher.dispatch(alert("first"), Some(&failed));
+        // the failures of an input do not fail the flush of another input, nor does a flush of another input clear them
+        assert!(dispatcher.flush(&other_input).is_ok());
+        assert!(dispatcher.flush(&failed).is_err());
+        assert_eq!(alerts.lock().unwrap().len(), 1);
+        assert!(dispatcher.flush(&failed).is_ok());
+        dispatcher.dispatch(alert("second"), None);
+        assert!(dispatcher.flush(&failed).is_ok());
+        drop(dispatcher);
+
+        let ids = alerts.lock().unwrap().iter().map(|alert| alert.id.clone()).collect::<Vec<String>>();
+        assert_eq!(ids, vec!["first", "second"]);
+    }
+
+    #[test]
+    fn wait_for_the_sinks_when_the_queue_is_full() {
+        let alerts = Arc::new(Mutex::new(Vec::new()));
+        let (release, blocked) = mpsc::channel();
+        let dispatcher = Arc::new(Dispatcher::start(vec![Box::new(BlockedSink(blocked, alerts.clone()))]));
+
+        let sender = dispatcher.clone();
+        let dispatching = thread::spawn(move || {
+            for _ in 0..QUEUE_SIZE + 2 {
+                sender.dispatch(alert("queued"), None);
+            }
+        });
+        for _ in 0..QUEUE_SIZE + 2 {
+            release.send(()).unwrap();
+        }
+        dispatching.join().unwrap();
+        assert!(dispatcher.flush(&FailedAlerts::default()).is_ok());
+        assert_eq!(alerts.lock().unwrap().len(), QUEUE_SIZE + 2);
+    }
+
+    #[test]
+    fn create_sinks() {
+        assert_eq!(create(&SinkConfig::Stdout).unwrap().name(), "stdout");
+        assert_eq!(create(&SinkConfig::Kafka { brokers: vec!["localhost:9092".to_string()], topic: "alerts".to_string() }).unwrap().name(), "kafka alerts");
+        assert_eq!(create(&SinkConfig::Webhook { url: "https://example.com/alerts".to_string() }).unwrap().name(), "webhook https://example.com/alerts");
+        assert!(create(&SinkConfig::Webhook { url: "example.com/alerts".to_string() }).is_err());
+    }
+}
@@ -0,0 +1,108 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use crate::alert::Alert;
+use super::Sink;
+
+/// Appends every alert as a line of JSON to a file. A file that would grow past the max size is rotated first:
+///     `alerts.json` is renamed to `alerts.json.1`, `alerts.json.1` to `alerts.json.2` and so on, and the oldest of the max files is removed.
+#[derive(Debug)]
+pub struct FileSink {
+    path: PathBuf,
+    max_size: u64,
+    max_files: usize,
+    file: File,
+    size: u64,
+}
+
+impl FileSink {
+    pub fn new(path: &str, max_size: u64, max_files: usize) -> io::Result<FileSink> {
+        let path = PathBuf::from(path);
+        let file = open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(FileSink { path, max_size, max_files, file, size })
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let rotated = |number: usize| PathBuf::from(format!("{}.{}", self.path.display(), number));
+        ignore_not_found(fs::remove_file(rotated(self.max_files)))?;
+        for number in (1..self.max_files).rev() {
+            ignore_not_found(fs::rename(rotated(number), rotated(number + 1)))?;
+        }
+        fs::rename(&self.path, rotated(1))?;
+
+        self.file = open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Sink for FileSink {
+    fn name(&self) -> String {
+        format!("file {}", self.path.display())
+    }
+
+    fn send(&mut self, alert: &Alert) -> io::Result<()> {
+        let mut line = serde_json::to_vec(alert)?;
+        line.push(b'\n');
+
+        // an alert larger than the max size is written to a file of its own
+        if self.size > 0 && self.size + line.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(&line)?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+}
+
+fn open(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+fn ignore_not_found(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::tests::alert;
+
+    #[test]
+    fn rotate_files() {
+        let dir = std::env::temp_dir().join(format!("log_analyzer_file_sink_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("alerts.json");
+        let line_size = serde_json::to_vec(&alert("alert-0")).unwrap().len() as u64 + 1;
+
+        // two alerts fit in a file, and two rotated files are kept
+        let mut sink = FileSink::new(&path.display().to_string(), line_size * 2, 2).unwrap();
+        for number in 0..7 {
+            sink.send(&alert(&format!("alert-{}", number))).unwrap();
+        }
+
+        let ids = |name: &str| {
+            fs::read_to_string(dir.join(name)).unwrap().lines()
+                .map(|line| serde_json::from_str::<Alert>(line).unwrap().id)
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(ids("alerts.json"), vec!["alert-6"]);
+        assert_eq!(ids("alerts.json.1"), vec!["alert-4", "alert-5"]);
+        assert_eq!(ids("alerts.json.2"), vec!["alert-2", "alert-3"]);
+        assert!(!dir.join("alerts.json.3").exists());
+
+        // the size of an existing file counts towards the max size
+        drop(sink);
+        let mut sink = FileSink::new(&path.display().to_string(), line_size * 2, 2).unwrap();
+        sink.send(&alert("alert-7")).unwrap();
+        sink.send(&alert("alert-8")).unwrap();
+        assert_eq!(ids("alerts.json"), vec!["alert-8"]);
+        assert_eq!(ids("alerts.json.1"), vec!["alert-6", "alert-7"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,101 @@
+use std::io;
+use std::slice;
+use std::time::Duration;
+use crate::alert::Alert;
+use crate::kafka::{error_code, Connection, Record, NOT_LEADER_OR_FOLLOWER};
+use super::Sink;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Writes every alert as a JSON record to partition 0 of a Kafka topic, keyed by the id of the rule, with the client of crate::kafka.
+/// The metadata is read from the first broker that accepts the connection, and the records are written to the leader of the partition.
+///     When the leader moves, the metadata is read again. The connection is not encrypted.
+#[derive(Debug)]
+pub struct KafkaSink {
+    brokers: Vec<String>,
+    topic: String,
+    connection: Option<Connection>,
+}
+
+impl KafkaSink {
+    pub fn new(brokers: &[String], topic: &str) -> KafkaSink {
+        KafkaSink { brokers: brokers.to_vec(), topic: topic.to_string(), connection: None }
+    }
+
+    /// Connects to the leader of partition 0, as the metadata of the first broker that accepts the connection names it.
+    fn connect(&self) -> io::Result<Connection> {
+        let metadata = Connection::connect(&self.brokers, TIMEOUT)?.metadata(slice::from_ref(&self.topic))?;
+        let leader = metadata.leaders.get(&(self.topic.clone(), 0)).and_then(|leader| metadata.brokers.get(leader))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{}-0 has no leader", self.topic)))?;
+        Connection::connect(slice::from_ref(leader), TIMEOUT)
+    }
+}
+
+impl Sink for KafkaSink {
+    fn name(&self) -> String {
+        format!("kafka {}", self.topic)
+    }
+
+    fn send(&mut self, alert: &Alert) -> io::Result<()> {
+        let record = Record::new(Some(alert.id.as_bytes().to_vec()), serde_json::to_vec(alert)?);
+        let mut connection = match self.connection.take() {
+            Some(connection) => connection,
+            None => self.connect()?,
+        };
+
+        // a connection that failed is not used again, the next alert connects anew
+        let mut result = connection.produce(&self.topic, 0, slice::from_ref(&record));
+        if result.as_ref().is_err_and(|err| error_code(err) == Some(NOT_LEADER_OR_FOLLOWER)) {
+            connection = self.connect()?;
+            result = connection.produce(&self.topic, 0, &[record]);
+        }
+        if result.is_ok() {
+            self.connection = Some(connection);
+        }
+        result.map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::kafka::mock::MockBroker;
+    use super::*;
+    use super::super::tests::alert;
+
+    #[test]
+    fn produce_alerts() {
+        let broker = MockBroker::start();
+        broker.create_topic("alerts", 1);
+
+        let mut sink = KafkaSink::new(&["127.0.0.1:1".to_string(), broker.address.clone()], "alerts");
+        sink.send(&alert("first")).unwrap();
+        sink.send(&alert("second")).unwrap();
+
+        let records = broker.records("alerts", 0);
+        assert_eq!(records.iter().map(|record| record.offset).collect::<Vec<i64>>(), vec![0, 1]);
+        assert_eq!(records[0].key.as_deref(), Some(b"first".as_slice()));
+        assert_eq!(serde_json::from_slice::<Alert>(records[1].value.as_ref().unwrap()).unwrap(), alert("second"));
+
+        let mut sink = KafkaSink::new(std::slice::from_ref(&broker.address), "missing");
+        let err = sink.send(&alert("first")).unwrap_err();
+        assert!(err.to_string().contains("UNKNOWN_TOPIC_OR_PARTITION"));
+        assert!(sink.connection.is_none());
+    }
+
+    #[test]
+    fn produce_alerts_to_new_leader() {
+        let (broker, leader) = (MockBroker::start(), MockBroker::start());
+        broker.create_topic("alerts", 1);
+        leader.create_topic("alerts", 1);
+
+        let mut sink = KafkaSink::new(slice::from_ref(&broker.address), "alerts");
+        sink.send(&alert("first")).unwrap();
+        broker.move_leader(&leader);
+        sink.send(&alert("second")).unwrap();
+        sink.send(&alert("third")).unwrap();
+
+        assert_eq!(broker.records("alerts", 0).len(), 1);
+        let records = leader.records("alerts", 0);
+        assert_eq!(records.iter().map(|record| record.key.as_deref()).collect::<Vec<_>>(), vec![Some(b"second".as_slice()), Some(b"third".as_slice())]);
+    }
+}
@@ -0,0 +1,45 @@
+use std::io::{self, Write};
+use crate::alert::Alert;
+use super::Sink;
+
+/// Writes every alert as a line of JSON.
+pub struct StdoutSink<W> {
+    output: W,
+}
+
+impl<W: Write + Send> StdoutSink<W> {
+    pub fn new(output: W) -> StdoutSink<W> {
+        StdoutSink { output }
+    }
+}
+
+impl<W: Write + Send> Sink for StdoutSink<W> {
+    fn name(&self) -> String {
+        "stdout".to_string()
+    }
+
+    fn send(&mut self, alert: &Alert) -> io::Result<()> {
+        let mut line = serde_json::to_vec(alert)?;
+        line.push(b'\n');
+        self.output.write_all(&line)?;
+        self.output.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+    use super::*;
+    use super::super::tests::alert;
+
+    #[test]
+    fn write_json_lines() {
+        let mut sink = StdoutSink::new(Vec::new());
+        sink.send(&alert("first")).unwrap();
+        sink.send(&alert("second")).unwrap();
+
+        let output = String::from_utf8(sink.output).unwrap();
+        let ids = output.lines().map(|line| serde_json::from_str::<Value>(line).unwrap()["id"].clone()).collect::<Vec<Value>>();
+        assert_eq!(ids, vec!["first", "second"]);
+    }
+}
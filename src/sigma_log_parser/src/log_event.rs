@@ -0,0 +1,197 @@
+use anyhow::{anyhow, Error};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use crate::log_parsers::key_value::parse_key_value;
+use crate::log_parsers::syslog::parse_syslog;
+
+/// The LogEvent struct is the normalized representation of a single log, regardless of the format it arrived in.
+/// Fields are stored as a JSON object so that nested logs (i.e. ECS or CloudTrail) keep their structure,
+///     while flat formats (key=value, syslog) are stored as top level fields.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct LogEvent {
+    fields: Map<String, Value>,
+}
+
+impl LogEvent {
+    pub fn init() -> LogEvent {
+        LogEvent {
+            fields: Map::new(),
+        }
+    }
+
+    pub fn new(fields: Map<String, Value>) -> Self {
+        Self { fields }
+    }
+
+    /// Parses a single JSON document. The document must be a JSON object.
+    pub fn from_json(log: &str) -> Result<LogEvent, Error> {
+        match serde_json::from_str::<Value>(log)? {
+            Value::Object(fields) => Ok(LogEvent::new(fields)),
+            _ => Err(anyhow!("JSON log must be an object")),
+        }
+    }
+
+    /// Parses a line of key=value pairs, i.e. `user=admin action="logon failed" port=22`
+    pub fn from_key_value(log: &str) -> Result<LogEvent, Error> {
+        Ok(LogEvent::new(parse_key_value(log)?))
+    }
+
+    /// Parses an RFC 3164 (BSD) or RFC 5424 syslog message.
+    pub fn from_syslog(log: &str) -> Result<LogEvent, Error> {
+        Ok(LogEvent::new(parse_syslog(log)?))
+    }
+
+    /// Returns the value of a field.
+    /// An exact match of the field name is preferred, which allows flattened keys such as "event.code".
+    /// Otherwise the field name is treated as a dotted path into nested objects, where array elements may be selected by index.
+    pub fn get(&self, field: &str) -> Option<&Value> {
+        if let Some(value) = self.fields.get(field) {
+            return Some(value);
+        }
+
+        let mut path = field.split('.');
+        let mut value = self.fields.get(path.next()?)?;
+        for key in path {
+            value = match value {
+                Value::Object(object) => object.get(key)?,
+                Value::Array(array) => array.get(key.parse::<usize>().ok()?)?,
+                _ => return None,
+            };
+        }
+
+        Some(value)
+    }
+
+    pub fn get_str(&self, field: &str) -> Option<&str> {
+        self.get(field)?.as_str()
+    }
+
+    /// Numbers that were stored as strings (key=value and syslog logs) are parsed as well.
+    pub fn get_i64(&self, field: &str) -> Option<i64> {
+        match self.get(field)? {
+            Value::Number(number) => number.as_i64(),
+            Value::String(number) => number.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn get_u64(&self, field: &str) -> Option<u64> {
+        match self.get(field)? {
+            Value::Number(number) => number.as_u64(),
+            Value::String(number) => number.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn get_f64(&self, field: &str) -> Option<f64> {
+        match self.get(field)? {
+            Value::Number(number) => number.as_f64(),
+            Value::String(number) => number.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn get_bool(&self, field: &str) -> Option<bool> {
+        match self.get(field)? {
+            Value::Bool(boolean) => Some(*boolean),
+            Value::String(boolean) => boolean.trim().to_lowercase().parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn contains(&self, field: &str) -> bool {
+        self.get(field).is_some()
+    }
+
+    pub fn insert(&mut self, field: String, value: Value) -> Option<Value> {
+        self.fields.insert(field, value)
+    }
+
+    pub fn fields(&self) -> &Map<String, Value> {
+        &self.fields
+    }
+
+    pub fn into_value(self) -> Value {
+        Value::Object(self.fields)
+    }
+}
+
+/// JSON objects are used as the fields of the LogEvent.
+/// Any other JSON value (i.e. a plain string) is stored in the "message" field.
+impl From<Value> for LogEvent {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Object(fields) => LogEvent::new(fields),
+            value => {
+                let mut fields = Map::new();
+                fields.insert("message".to_string(), value);
+                LogEvent::new(fields)
+            }
+        }
+    }
+}
+
+impl From<Map<String, Value>> for LogEvent {
+    fn from(fields: Map<String, Value>) -> Self {
+        LogEvent::new(fields)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    #[test]
+    fn from_json_object() {
+        let event = LogEvent::from_json(r#"{"EventID": 4625, "User": "admin"}"#).unwrap();
+        assert_eq!(event.get_i64("EventID"), Some(4625));
+        assert_eq!(event.get_str("User"), Some("admin"));
+    }
+
+    #[test]
+    fn from_json_rejects_non_objects() {
+        assert!(LogEvent::from_json(r#"["not", "an", "object"]"#).is_err());
+        assert!(LogEvent::from_json("not json").is_err());
+    }
+
+    #[test]
+    fn dotted_path_lookup() {
+        let event = LogEvent::from(json!({
+            "process": {"executable": "C:\\Windows\\System32\\cmd.exe", "pid": 4},
+            "event.code": "4688",
+            "related": {"ip": ["10.0.0.1", "10.0.0.2"]}
+        }));
+
+        assert_eq!(event.get_str("process.executable"), Some("C:\\Windows\\System32\\cmd.exe"));
+        assert_eq!(event.get_u64("process.pid"), Some(4));
+        assert_eq!(event.get_str("event.code"), Some("4688"));
+        assert_eq!(event.get_str("related.ip.1"), Some("10.0.0.2"));
+        assert_eq!(event.get("process.missing"), None);
+        assert_eq!(event.get("process.pid.nested"), None);
+    }
+
+    #[test]
+    fn typed_field_access() {
+        let event = LogEvent::from(json!({"port": "22", "ratio": 0.5, "success": "False", "admin": true}));
+
+        assert_eq!(event.get_i64("port"), Some(22));
+        assert_eq!(event.get_f64("ratio"), Some(0.5));
+        assert_eq!(event.get_bool("success"), Some(false));
+        assert_eq!(event.get_bool("admin"), Some(true));
+        assert_eq!(event.get_i64("admin"), None);
+    }
+
+    #[test]
+    fn non_object_value_is_stored_as_message() {
+        let event = LogEvent::from(json!("plain text log"));
+        assert_eq!(event.get_str("message"), Some("plain text log"));
+    }
+
+    #[test]
+    fn serializes_as_the_original_object() {
+        let event = LogEvent::from(json!({"a": {"b": 1}}));
+        assert_eq!(serde_json::to_string(&event).unwrap(), r#"{"a":{"b":1}}"#);
+    }
+}
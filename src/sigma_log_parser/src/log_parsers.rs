@@ -0,0 +1,2 @@
+pub mod key_value;
+pub mod syslog;
@@ -0,0 +1,85 @@
+use anyhow::{anyhow, Error};
+use nom::branch::alt;
+use nom::bytes::complete::{take_till, take_while1};
+use nom::character::complete::char;
+use nom::sequence::{delimited, separated_pair};
+use nom::IResult;
+use serde_json::{Map, Value};
+
+/// Parses a line of key=value pairs into fields.
+/// Values may be bare words, or wrapped in double or single quotes when they contain whitespace.
+/// Words that are not a key=value pair are skipped.
+pub fn parse_key_value(log: &str) -> Result<Map<String, Value>, Error> {
+    let mut fields = Map::new();
+    let mut remaining = log.trim_start();
+
+    while !remaining.is_empty() {
+        remaining = match key_value(remaining) {
+            Ok((remaining, (key, value))) => {
+                fields.insert(key.to_string(), Value::String(value.to_string()));
+                remaining
+            }
+            Err(_) => skip_word(remaining),
+        }
+        .trim_start();
+    }
+
+    if fields.is_empty() {
+        return Err(anyhow!("Log does not contain any key=value pairs"));
+    }
+
+    Ok(fields)
+}
+
+fn key_value(input: &str) -> IResult<&str, (&str, &str)> {
+    separated_pair(key, char('='), value)(input)
+}
+
+fn key(input: &str) -> IResult<&str, &str> {
+    take_while1(|ch: char| !ch.is_whitespace() && ch != '=' && ch != '"' && ch != '\'')(input)
+}
+
+fn value(input: &str) -> IResult<&str, &str> {
+    alt((
+        delimited(char('"'), take_till(|ch| ch == '"'), char('"')),
+        delimited(char('\''), take_till(|ch| ch == '\''), char('\'')),
+        take_till(char::is_whitespace),
+    ))(input)
+}
+
+fn skip_word(input: &str) -> &str {
+    match input.find(char::is_whitespace) {
+        Some(index) => &input[index..],
+        None => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_value_pairs() {
+        let fields = parse_key_value(r#"user=admin action="logon failed" src='10.0.0.1' port=22"#).unwrap();
+
+        assert_eq!(fields.get("user"), Some(&Value::String("admin".to_string())));
+        assert_eq!(fields.get("action"), Some(&Value::String("logon failed".to_string())));
+        assert_eq!(fields.get("src"), Some(&Value::String("10.0.0.1".to_string())));
+        assert_eq!(fields.get("port"), Some(&Value::String("22".to_string())));
+    }
+
+    #[test]
+    fn empty_values_and_stray_words() {
+        let fields = parse_key_value("date=2022-11-29 devname empty= type=\"traffic\"").unwrap();
+
+        assert_eq!(fields.len(), 3);
+        assert_eq!(fields.get("empty"), Some(&Value::String("".to_string())));
+        assert_eq!(fields.get("type"), Some(&Value::String("traffic".to_string())));
+    }
+
+    #[test]
+    fn no_key_value_pairs() {
+        assert!(parse_key_value("just a plain message").is_err());
+        assert!(parse_key_value("").is_err());
+    }
+}
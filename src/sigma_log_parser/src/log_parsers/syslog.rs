@@ -0,0 +1,339 @@
+use anyhow::anyhow;
+use nom::bytes::complete::{tag, take_till1, take_while1, take_while_m_n};
+use nom::character::complete::{char, digit1, space1};
+use nom::combinator::{map_res, opt, recognize, verify};
+use nom::error::{Error, ErrorKind};
+use nom::sequence::{delimited, terminated, tuple};
+use nom::IResult;
+use serde_json::{Map, Value};
+
+/// The Syslog message header as defined by RFC 3164 (BSD syslog) and RFC 5424.
+/// RFC 5424 messages are identified by the version number that follows the priority, i.e. `<165>1 ...`
+#[derive(Clone, Debug, PartialEq)]
+pub struct SyslogMessage<'a> {
+    pub facility: u8,
+    pub severity: u8,
+    pub version: Option<u8>,
+    pub timestamp: Option<&'a str>,
+    pub hostname: Option<&'a str>,
+    pub app_name: Option<&'a str>,
+    pub proc_id: Option<&'a str>,
+    pub msg_id: Option<&'a str>,
+    pub structured_data: Option<&'a str>,
+    pub message: &'a str,
+}
+
+/// Parses a syslog message into fields.
+/// Header values that are missing, or set to the RFC 5424 NILVALUE ("-"), are not added to the fields.
+/// The parameters of the structured data are added by SD-ID, i.e. sd.exampleSDID@32473.iut
+pub fn parse_syslog(log: &str) -> Result<Map<String, Value>, anyhow::Error> {
+    let syslog_message = match syslog(log.trim_end_matches(['\r', '\n'])) {
+        Ok((_, syslog_message)) => syslog_message,
+        Err(_) => return Err(anyhow!("Log is not a valid syslog message")),
+    };
+
+    let mut fields = Map::new();
+    fields.insert("facility".to_string(), Value::from(syslog_message.facility));
+    fields.insert("severity".to_string(), Value::from(syslog_message.severity));
+
+    let header = [
+        ("timestamp", syslog_message.timestamp),
+        ("hostname", syslog_message.hostname),
+        ("app_name", syslog_message.app_name),
+        ("proc_id", syslog_message.proc_id),
+        ("msg_id", syslog_message.msg_id),
+        ("structured_data", syslog_message.structured_data),
+    ];
+    for (field, value) in header {
+        if let Some(value) = value.filter(|value| *value != "-") {
+            fields.insert(field.to_string(), Value::String(value.to_string()));
+        }
+    }
+    if let Some(structured_data) = syslog_message.structured_data.filter(|value| *value != "-") {
+        fields.insert("sd".to_string(), Value::Object(structured_data_params(structured_data)));
+    }
+    if let Some(version) = syslog_message.version {
+        fields.insert("version".to_string(), Value::from(version));
+    }
+    fields.insert("message".to_string(), Value::String(syslog_message.message.to_string()));
+
+    Ok(fields)
+}
+
+pub fn syslog(input: &str) -> IResult<&str, SyslogMessage<'_>> {
+    let (remaining, priority) = priority(input)?;
+    let (facility, severity) = ((priority >> 3) as u8, (priority & 7) as u8);
+
+    match terminated(version, space1)(remaining) {
+        Ok((remaining, version)) => rfc5424(remaining, facility, severity, version),
+        Err(_) => rfc3164(remaining, facility, severity),
+    }
+}
+
+/// The priority is the facility (0-23) times 8 plus the severity (0-7), so it is at most 191.
+fn priority(input: &str) -> IResult<&str, u16> {
+    let number = map_res(take_while_m_n(1, 3, |ch: char| ch.is_ascii_digit()), str::parse);
+    delimited(char('<'), verify(number, |priority: &u16| *priority <= 191), char('>'))(input)
+}
+
+fn version(input: &str) -> IResult<&str, u8> {
+    map_res(take_while_m_n(1, 2, |ch: char| ch.is_ascii_digit()), str::parse)(input)
+}
+
+/// <PRI>VERSION TIMESTAMP HOSTNAME APP-NAME PROCID MSGID STRUCTURED-DATA [MSG]
+fn rfc5424(input: &str, facility: u8, severity: u8, version: u8) -> IResult<&str, SyslogMessage<'_>> {
+    let (remaining, (timestamp, _, hostname, _, app_name, _, proc_id, _, msg_id, _, structured_data)) = tuple((
+        header_value,
+        space1,
+        header_value,
+        space1,
+        header_value,
+        space1,
+        header_value,
+        space1,
+        header_value,
+        space1,
+        structured_data,
+    ))(input)?;
+    let message = remaining.strip_prefix(' ').unwrap_or(remaining);
+    let message = message.strip_prefix('\u{feff}').unwrap_or(message);
+
+    Ok((
+        "",
+        SyslogMessage {
+            facility,
+            severity,
+            version: Some(version),
+            timestamp: Some(timestamp),
+            hostname: Some(hostname),
+            app_name: Some(app_name),
+            proc_id: Some(proc_id),
+            msg_id: Some(msg_id),
+            structured_data: Some(structured_data),
+            message,
+        },
+    ))
+}
+
+/// <PRI>Mmm dd hh:mm:ss HOSTNAME TAG[PID]: MSG
+/// The header and tag are optional, as many devices do not send them.
+fn rfc3164(input: &str, facility: u8, severity: u8) -> IResult<&str, SyslogMessage<'_>> {
+    let (remaining, timestamp) = opt(terminated(bsd_timestamp, space1))(input)?;
+    let (remaining, hostname) = match timestamp {
+        Some(_) => opt(terminated(header_value, space1))(remaining)?,
+        None => (remaining, None),
+    };
+    let (remaining, tag) = opt(bsd_tag)(remaining)?;
+    let (app_name, proc_id) = match tag {
+        Some((app_name, proc_id)) => (Some(app_name), proc_id),
+        None => (None, None),
+    };
+
+    Ok((
+        "",
+        SyslogMessage {
+            facility,
+            severity,
+            version: None,
+            timestamp,
+            hostname,
+            app_name,
+            proc_id,
+            msg_id: None,
+            structured_data: None,
+            message: remaining.trim_start(),
+        },
+    ))
+}
+
+fn bsd_timestamp(input: &str) -> IResult<&str, &str> {
+    recognize(tuple((
+        take_while_m_n(3, 3, |ch: char| ch.is_ascii_alphabetic()),
+        space1,
+        digit1,
+        space1,
+        digit1,
+        char(':'),
+        digit1,
+        char(':'),
+        digit1,
+    )))(input)
+}
+
+/// TAG[PID]: or TAG:
+fn bsd_tag(input: &str) -> IResult<&str, (&str, Option<&str>)> {
+    let (remaining, app_name) = take_while1(|ch: char| ch.is_alphanumeric() || "-_./".contains(ch))(input)?;
+    let (remaining, proc_id) = opt(delimited(char('['), take_till1(|ch| ch == ']'), char(']')))(remaining)?;
+    let (remaining, _) = tag(":")(remaining)?;
+
+    Ok((remaining, (app_name, proc_id)))
+}
+
+fn header_value(input: &str) -> IResult<&str, &str> {
+    take_till1(|ch: char| ch == ' ')(input)
+}
+
+/// STRUCTURED-DATA is either the NILVALUE or one or more [SD-ID PARAM="VALUE" ...] elements.
+/// Escaped characters (\", \], \\) within parameter values are skipped over.
+fn structured_data(input: &str) -> IResult<&str, &str> {
+    if let Some(remaining) = input.strip_prefix('-') {
+        return Ok((remaining, "-"));
+    }
+
+    let mut index = 0;
+    while input[index..].starts_with('[') {
+        let mut escaped = false;
+        let mut in_value = false;
+        let element_end = input[index..].char_indices().skip(1).find(|(_, ch)| {
+            let end = !escaped && !in_value && *ch == ']';
+            if escaped {
+                escaped = false;
+            } else if *ch == '\\' {
+                escaped = true;
+            } else if *ch == '"' {
+                in_value = !in_value;
+            }
+            end
+        });
+
+        match element_end {
+            Some((end, _)) => index += end + 1,
+            None => break,
+        }
+    }
+
+    if index == 0 {
+        return Err(nom::Err::Error(Error::new(input, ErrorKind::Tag)));
+    }
+
+    Ok((&input[index..], &input[..index]))
+}
+
+/// Splits [SD-ID PARAM="VALUE" ...] elements into their parameters by SD-ID, and unescapes the values.
+/// An SD-ID may occur more than once, where the parameters of a later element replace those of an earlier one.
+pub fn structured_data_params(structured_data: &str) -> Map<String, Value> {
+    let mut elements = Map::new();
+    let mut chars = structured_data.chars().peekable();
+
+    while chars.next() == Some('[') {
+        let mut id = String::new();
+        while let Some(ch) = chars.next_if(|ch| *ch != ' ' && *ch != ']') {
+            id.push(ch);
+        }
+        let mut params = Map::new();
+
+        loop {
+            while chars.next_if_eq(&' ').is_some() {}
+            if chars.next_if_eq(&']').is_some() || chars.peek().is_none() {
+                break;
+            }
+
+            let name = chars.by_ref().take_while(|ch| *ch != '=').collect::<String>();
+            if chars.next() != Some('"') {
+                break;
+            }
+            let mut value = String::new();
+            while let Some(ch) = chars.next() {
+                match ch {
+                    '\\' => value.extend(chars.next()),
+                    '"' => break,
+                    _ => value.push(ch),
+                }
+            }
+            params.insert(name, Value::String(value));
+        }
+
+        match elements.get_mut(&id) {
+            Some(Value::Object(existing)) => existing.extend(params),
+            _ => {
+                elements.insert(id, Value::Object(params));
+            }
+        }
+    }
+
+    elements
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rfc5424_message() {
+        let fields = parse_syslog(r#"<165>1 2003-10-11T22:14:15.003Z mymachine.example.com evntslog - ID47 [exampleSDID@32473 iut="3" eventSource="Application"] An application event log entry..."#).unwrap();
+
+        assert_eq!(fields.get("facility"), Some(&Value::from(20)));
+        assert_eq!(fields.get("severity"), Some(&Value::from(5)));
+        assert_eq!(fields.get("version"), Some(&Value::from(1)));
+        assert_eq!(fields.get("timestamp"), Some(&Value::from("2003-10-11T22:14:15.003Z")));
+        assert_eq!(fields.get("hostname"), Some(&Value::from("mymachine.example.com")));
+        assert_eq!(fields.get("app_name"), Some(&Value::from("evntslog")));
+        assert_eq!(fields.get("proc_id"), None);
+        assert_eq!(fields.get("msg_id"), Some(&Value::from("ID47")));
+        assert_eq!(fields.get("structured_data"), Some(&Value::from(r#"[exampleSDID@32473 iut="3" eventSource="Application"]"#)));
+        assert_eq!(fields.get("message"), Some(&Value::from("An application event log entry...")));
+        assert_eq!(fields["sd"]["exampleSDID@32473"]["eventSource"], Value::from("Application"));
+    }
+
+    #[test]
+    fn rfc5424_structured_data_params() {
+        let params = structured_data_params(r#"[exampleSDID@32473 iut="3" eventSource="Application" eventID="1011"][examplePriority@32473 class="high"][meta path="C:\\Temp\]\"x\"" empty=""]"#);
+
+        assert_eq!(Value::Object(params), serde_json::json!({
+            "exampleSDID@32473": {"iut": "3", "eventSource": "Application", "eventID": "1011"},
+            "examplePriority@32473": {"class": "high"},
+            "meta": {"path": "C:\\Temp]\"x\"", "empty": ""},
+        }));
+        assert_eq!(Value::Object(structured_data_params(r#"[timeQuality][origin ip="10.0.0.1"]"#)), serde_json::json!({"timeQuality": {}, "origin": {"ip": "10.0.0.1"}}));
+    }
+
+    #[test]
+    fn rfc5424_message_without_structured_data() {
+        let fields = parse_syslog("<34>1 2003-10-11T22:14:15.003Z mymachine.example.com su - ID47 - 'su root' failed for lonvick on /dev/pts/8").unwrap();
+
+        assert_eq!(fields.get("facility"), Some(&Value::from(4)));
+        assert_eq!(fields.get("severity"), Some(&Value::from(2)));
+        assert_eq!(fields.get("structured_data"), None);
+        assert_eq!(fields.get("message"), Some(&Value::from("'su root' failed for lonvick on /dev/pts/8")));
+    }
+
+    #[test]
+    fn rfc3164_message() {
+        let fields = parse_syslog("<34>Oct 11 22:14:15 mymachine su[1234]: 'su root' failed for lonvick on /dev/pts/8\n").unwrap();
+
+        assert_eq!(fields.get("timestamp"), Some(&Value::from("Oct 11 22:14:15")));
+        assert_eq!(fields.get("hostname"), Some(&Value::from("mymachine")));
+        assert_eq!(fields.get("app_name"), Some(&Value::from("su")));
+        assert_eq!(fields.get("proc_id"), Some(&Value::from("1234")));
+        assert_eq!(fields.get("version"), None);
+        assert_eq!(fields.get("message"), Some(&Value::from("'su root' failed for lonvick on /dev/pts/8")));
+    }
+
+    #[test]
+    fn rfc3164_message_without_tag() {
+        let fields = parse_syslog("<13>Feb  5 17:32:18 10.0.0.99 Use the BFG!").unwrap();
+
+        assert_eq!(fields.get("timestamp"), Some(&Value::from("Feb  5 17:32:18")));
+        assert_eq!(fields.get("hostname"), Some(&Value::from("10.0.0.99")));
+        assert_eq!(fields.get("app_name"), None);
+        assert_eq!(fields.get("message"), Some(&Value::from("Use the BFG!")));
+    }
+
+    #[test]
+    fn rfc3164_message_without_header() {
+        let fields = parse_syslog("<13>sshd: Accepted publickey for root").unwrap();
+
+        assert_eq!(fields.get("timestamp"), None);
+        assert_eq!(fields.get("app_name"), Some(&Value::from("sshd")));
+        assert_eq!(fields.get("message"), Some(&Value::from("Accepted publickey for root")));
+    }
+
+    #[test]
+    fn invalid_syslog_message() {
+        assert!(parse_syslog("no priority here").is_err());
+        assert!(parse_syslog("<999999>1 - - - - - -").is_err());
+        assert!(parse_syslog("<999>1 2003-10-11T22:14:15.003Z host app - - - message").is_err());
+        assert!(parse_syslog("<192>Oct 11 22:14:15 host su: message").is_err());
+        assert_eq!(parse_syslog("<191>Oct 11 22:14:15 host su: message").unwrap().get("facility"), Some(&Value::from(23)));
+    }
+}
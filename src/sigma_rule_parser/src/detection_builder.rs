@@ -1,46 +1,100 @@
-use std::fmt::Error;
-use log::{info, warn};
+use std::collections::BTreeMap;
+use crate::detection_parsers::condition::aggregation_parser::{parse_aggregation, parse_timeframe};
 use crate::detection_parsers::condition::condition_parser::parse_detection_condition;
 use crate::detection_parsers::logic::logic_parser::parse_detection_logic;
-use crate::sigma_file::sigma_rule::read_condition;
+use crate::rule_error::RuleError;
+use crate::sigma_file::sigma_rule::read_conditions;
+use crate::structs::aggregation::Aggregation;
 use crate::structs::detection::Detection;
-use crate::structs::sigma_rule::SigmaRule;
+use crate::structs::sigma_rule::{SigmaRule, YmlTypes};
 
 
-// Result<Detection, Error>
-pub fn build(rule: SigmaRule) -> Result<Detection, Error> {
+/// Builds the Detection for a single Sigma rule.
+/// The condition field is parsed into the Detection tree first, then each search identifier in the tree is assigned its DetectionLogic.
+/// A list of conditions is joined as "(c1) or (c2)" without their aggregations, which build_conditions builds for each condition.
+pub fn build(rule: SigmaRule) -> Result<Detection, RuleError> {
+    let conditions = read_conditions(rule.detection.get("condition").ok_or(RuleError::MissingCondition)?)?;
+    let condition = match conditions.as_slice() {
+        [condition] => condition.to_string(),
+        conditions => {
+            let mut joined = Vec::new();
+            for condition in conditions {
+                let (detection, aggregation) = split_aggregation(condition);
+                if let Some(aggregation) = aggregation {
+                    parse_aggregation(aggregation)?;
+                }
+                joined.push(format!("({})", detection));
+            }
+            joined.join(" or ")
+        }
+    };
 
-    let rule_id = rule.id;
-    let mut raw_detection = rule.detection;
+    build_detection(&rule.detection, &condition)
+}
 
-    let condition = raw_detection.get("condition").unwrap();
-    let condition = read_condition(condition).to_string();
+/// Builds the Detection and the Aggregation of every condition of a Sigma rule, of which a list of conditions has many.
+/// The rule matches an event when any of its conditions does.
+pub fn build_conditions(rule: &SigmaRule) -> Result<Vec<(Detection, Option<Aggregation>)>, RuleError> {
+    let conditions = read_conditions(rule.detection.get("condition").ok_or(RuleError::MissingCondition)?)?;
+    conditions
+        .into_iter()
+        .map(|condition| Ok((build_detection(&rule.detection, condition)?, build_aggregation(rule, condition)?)))
+        .collect()
+}
+
+/// Parses a condition, of which the aggregation is left out, and assigns the DetectionLogic of its search identifiers.
+fn build_detection(raw_detection: &BTreeMap<String, YmlTypes>, condition: &str) -> Result<Detection, RuleError> {
+    let mut raw_detection = raw_detection.clone();
     raw_detection.remove("condition");
+    // the timeframe belongs to the aggregation of the condition, see build_aggregation
+    raw_detection.remove("timeframe");
 
-    /// search identifiers are used to validate whether or not a condition contains existing search id's
+    // search identifiers are used to validate whether or not a condition contains existing search id's
     let search_identifiers = raw_detection.clone().into_keys().collect::<Vec<String>>();
-    let mut detection = parse_detection_condition(condition.as_str(), search_identifiers)?;
-    println!("PARSE! {:?}", detection.clone());
-
+    let mut detection = parse_detection_condition(condition, search_identifiers)?;
 
-    // MUST NOW ITERATE THROUGH A DETECTION, FIND ALL SEARCH IDENTIFIERS, THEN SAVE THE LOGIC TO THE DETECTIONCONDITION
-    let test_detection_logic_result = parse_detection_logic(&mut detection, raw_detection.clone());
-    println!("it works - {:?}", test_detection_logic_result);
+    parse_detection_logic(&mut detection, &raw_detection)?;
     Ok(detection)
 }
 
+/// Builds the Aggregation of a condition, the part that follows the pipe: "selection | count() by src_ip > 10"
+/// Returns None when the condition does not aggregate. The timeframe of the detection field is the window that events are aggregated in.
+fn build_aggregation(rule: &SigmaRule, condition: &str) -> Result<Option<Aggregation>, RuleError> {
+    let mut aggregation = match split_aggregation(condition).1 {
+        Some(aggregation) => parse_aggregation(aggregation)?,
+        None => return Ok(None),
+    };
+
+    if let Some(YmlTypes::String(timeframe)) = rule.detection.get("timeframe") {
+        aggregation.timeframe = Some(parse_timeframe(timeframe)?);
+    }
+
+    Ok(Some(aggregation))
+}
+
+/// Splits a condition at its pipe, into the detection and the aggregation that starts with the pipe.
+fn split_aggregation(condition: &str) -> (&str, Option<&str>) {
+    match condition.find('|') {
+        Some(index) => (condition[..index].trim(), Some(&condition[index..])),
+        None => (condition.trim(), None),
+    }
+}
 
 /// These tests are real scenarios of conditions that have been written in Sigma rules.
 #[cfg(test)]
 mod tests {
-    use crate::detection_builder::build;
+    use std::time::Duration;
+    use crate::detection_builder::{build, build_conditions};
+    use crate::rule_error::RuleError;
+    use crate::structs::aggregation::AggregationFunction;
+    use crate::structs::sigma_rule::SigmaRule;
     use crate::sigma_file::sigma_rule::process_sigma_rules;
 
     #[test]
     fn lazy_logic_parser_test() {
         let sigma_rules = process_sigma_rules("src/sigma_file/test/assets/detection_logic/parse_detection_logic.yml".to_string()).unwrap();
         for rule in sigma_rules {
-            build(rule);
+            assert!(build(rule).is_ok());
         }
     }
 
@@ -48,7 +102,130 @@ mod tests {
     fn parse_rule() {
         let sigma_rules = process_sigma_rules("src/sigma_file/test/assets/mimikatz.yml".to_string()).unwrap();
         for rule in sigma_rules {
-            build(rule);
+            assert!(build(rule).is_ok());
         }
     }
+
+    #[test]
+    fn build_rule_with_aggregation() {
+        let rule: SigmaRule = serde_yaml::from_str(r#"
+            detection:
+              selection:
+                event.outcome: failure
+              timeframe: 10m
+              condition: selection | count(user.name) by source.ip > 5
+        "#).unwrap();
+
+        assert!(build(rule.clone()).is_ok());
+
+        let aggregation = build_conditions(&rule).unwrap().remove(0).1.unwrap();
+        assert_eq!(aggregation.function, AggregationFunction::Count);
+        assert_eq!(aggregation.field, Some("user.name".to_string()));
+        assert_eq!(aggregation.group_by, Some("source.ip".to_string()));
+        assert_eq!(aggregation.threshold, 5.0);
+        assert_eq!(aggregation.timeframe, Some(Duration::from_secs(600)));
+    }
+
+    #[test]
+    fn build_rule_without_aggregation() {
+        let sigma_rules = process_sigma_rules("src/sigma_file/test/assets/mimikatz.yml".to_string()).unwrap();
+        for rule in sigma_rules {
+            assert!(build_conditions(&rule).unwrap().iter().all(|(_, aggregation)| aggregation.is_none()));
+        }
+    }
+
+    #[test]
+    fn build_rule_with_unsupported_aggregation() {
+        let rule: SigmaRule = serde_yaml::from_str(r#"
+            detection:
+              selection1:
+                EventID: 1
+              selection2:
+                EventID: 2
+              condition: selection1 | near selection2
+        "#).unwrap();
+
+        let error = RuleError::UnsupportedAggregation("| near selection2".to_string());
+        assert_eq!(build_conditions(&rule), Err(error.clone()));
+        assert_eq!(build(rule), Err(error));
+    }
+
+    #[test]
+    fn build_rule_with_a_list_of_conditions() {
+        let rule: SigmaRule = serde_yaml::from_str(r#"
+            detection:
+              selection:
+                EventID: 4768
+              filter_computer:
+                TargetUserName|endswith: '$'
+              timeframe: 24h
+              condition:
+                - selection and not filter_computer | count(TargetUserName) by IpAddress > 10
+        "#).unwrap();
+        assert!(build(rule.clone()).unwrap().conditions.is_some());
+        let conditions = build_conditions(&rule).unwrap();
+        assert_eq!(conditions.len(), 1);
+        let aggregation = conditions[0].1.as_ref().unwrap();
+        assert_eq!((aggregation.field.as_deref(), aggregation.group_by.as_deref()), (Some("TargetUserName"), Some("IpAddress")));
+        assert_eq!(aggregation.timeframe, Some(Duration::from_secs(24 * 3600)));
+
+        let rule: SigmaRule = serde_yaml::from_str(r#"
+            detection:
+              selection1:
+                EventID: 1
+              selection2:
+                EventID: 2
+              condition:
+                - selection1
+                - selection2 | count() > 5
+        "#).unwrap();
+        let detection = build(rule.clone()).unwrap();
+        let joined = build(serde_yaml::from_str(r#"
+            detection:
+              selection1:
+                EventID: 1
+              selection2:
+                EventID: 2
+              condition: (selection1) or (selection2)
+        "#).unwrap()).unwrap();
+        assert_eq!(detection, joined);
+        let conditions = build_conditions(&rule).unwrap();
+        assert_eq!(conditions.iter().map(|(_, aggregation)| aggregation.is_some()).collect::<Vec<bool>>(), vec![false, true]);
+
+        let rule: SigmaRule = serde_yaml::from_str(r#"
+            detection:
+              selection:
+                EventID: 1
+              condition:
+                selection: true
+        "#).unwrap();
+        assert_eq!(build(rule.clone()), Err(RuleError::InvalidCondition("selection: true".to_string())));
+        assert_eq!(build_conditions(&rule), Err(RuleError::InvalidCondition("selection: true".to_string())));
+    }
+
+    #[test]
+    fn build_rule_reports_why_it_failed() {
+        let rule: SigmaRule = serde_yaml::from_str(r#"
+            detection:
+              selection:
+                EventID: 1
+        "#).unwrap();
+        assert_eq!(build(rule), Err(RuleError::MissingCondition));
+
+        let rule: SigmaRule = serde_yaml::from_str(r#"
+            detection:
+              selection:
+                EventID: 1
+              condition: selection and not filter
+        "#).unwrap();
+        assert_eq!(build(rule), Err(RuleError::UnknownSearchIdentifier("filter".to_string())));
+
+        let rule: SigmaRule = serde_yaml::from_str(r#"
+            detection:
+              selection:
+                CommandLine|expand: '%ProgramData%'
+              condition: selection
+        "#).unwrap();
+        assert_eq!(build(rule), Err(RuleError::UnsupportedModifier("expand".to_string())));
+    }
 }
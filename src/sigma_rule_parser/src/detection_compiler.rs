@@ -0,0 +1,415 @@
+use std::collections::HashMap;
+use serde_json::Value;
+use sigma_log_parser::log_event::LogEvent;
+use crate::detection_evaluator::field_value;
+use crate::rule_error::RuleError;
+use crate::structs::detection::Detection;
+use crate::structs::detection_condition::{DetectionCondition, Operator};
+use crate::structs::detection_field::{DetectionField, Modifier};
+use crate::structs::detection_logic::DetectionLogic;
+use self::field_matcher::{FieldMatcher, KeywordMatcher};
+
+pub mod field_matcher;
+
+/// A Detection that was compiled into typed matchers, which matches the same log events as detection_evaluator::matches.
+/// DetectionLogic keeps every value as a string, so interpreting it parses, transforms and lowercases the values again for every event.
+/// Compiling does that once per rule instead:
+///     - the values of a field are transformed by its modifiers and lowercased, and many values of `contains` share one Aho-Corasick automaton
+///     - regular expressions, CIDR ranges and the numbers of `lt`, `gt`, ... are parsed, and fail the rule when they are invalid
+#[derive(Clone, Debug)]
+pub struct CompiledDetection {
+    operator: Option<Operator>,
+    conditions: Vec<CompiledCondition>,
+}
+
+#[derive(Clone, Debug)]
+struct CompiledCondition {
+    operator: Option<Operator>,
+    is_negated: bool,
+    search: Search,
+}
+
+#[derive(Clone, Debug)]
+enum Search {
+    Nested(CompiledDetection),
+    Logic(LogicMatcher),
+}
+
+/// The second order logic of a search identifier, see detection_evaluator::logic_matches.
+#[derive(Clone, Debug)]
+enum LogicMatcher {
+    All(Vec<LogicMatcher>),
+    Any(Vec<LogicMatcher>),
+    Field(FieldMatcher),
+    Keywords(KeywordMatcher),
+    /// A null value of a field, which matches the field when it is missing or null
+    Missing(String),
+    /// Logic without a value, which never matches
+    Never,
+}
+
+/// A string that a log event contains when a Detection matches it, in lowercase. The literals of keywords are contained in any field.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Literal {
+    pub field: Option<String>,
+    pub needle: String,
+}
+
+/// Compiles the DetectionLogic of every search identifier of a Detection that was returned by detection_builder::build.
+pub fn compile(detection: &Detection) -> Result<CompiledDetection, RuleError> {
+    let conditions = detection.conditions.iter().flatten().map(compile_condition).collect::<Result<Vec<CompiledCondition>, RuleError>>()?;
+    Ok(CompiledDetection { operator: detection.operator.clone(), conditions })
+}
+
+fn compile_condition(condition: &DetectionCondition) -> Result<CompiledCondition, RuleError> {
+    let search = match condition.nested_detections.as_ref() {
+        Some(nested_detection) => Search::Nested(compile(nested_detection)?),
+        None => Search::Logic(compile_logic(&condition.detection_logic, None)?),
+    };
+
+    Ok(CompiledCondition { operator: condition.operator.clone(), is_negated: condition.is_negated.unwrap_or(false), search })
+}
+
+/// The values of a list are compiled into a single matcher of their field (or keywords), the other items of the list into matchers of their own.
+fn compile_logic(logic: &DetectionLogic, field: Option<&DetectionField>) -> Result<LogicMatcher, RuleError> {
+    if let Some(and) = logic.and.as_ref() {
+        let matchers = and.iter().map(|(field, logic)| compile_logic(logic, Some(field))).collect::<Result<Vec<LogicMatcher>, RuleError>>()?;
+        return Ok(LogicMatcher::All(matchers));
+    }
+
+    if let Some(or) = logic.or.as_ref() {
+        let values = or.iter().filter_map(plain_value).collect::<Vec<&str>>();
+        let mut matchers = or
+            .iter()
+            .filter(|logic| plain_value(logic).is_none())
+            .map(|logic| compile_logic(logic, field))
+            .collect::<Result<Vec<LogicMatcher>, RuleError>>()?;
+        if !values.is_empty() {
+            matchers.push(compile_values(&values, field)?);
+        }
+
+        return Ok(match field {
+            Some(field) if field.has_modifier(&Modifier::All) => LogicMatcher::All(matchers),
+            _ => LogicMatcher::Any(matchers),
+        });
+    }
+
+    match (plain_value(logic), field) {
+        (Some(value), _) => compile_values(&[value], field),
+        (None, Some(field)) => Ok(LogicMatcher::Missing(field.name.clone())),
+        (None, None) => Ok(LogicMatcher::Never),
+    }
+}
+
+fn compile_values(values: &[&str], field: Option<&DetectionField>) -> Result<LogicMatcher, RuleError> {
+    Ok(match field {
+        Some(field) => LogicMatcher::Field(FieldMatcher::new(field, values)?),
+        None => LogicMatcher::Keywords(KeywordMatcher::new(values)?),
+    })
+}
+
+/// Literals with longer needles match fewer events, and fewer literals match fewer events. No literals match no event at all.
+fn selectivity(literals: &[Literal]) -> (usize, std::cmp::Reverse<usize>) {
+    let shortest = literals.iter().map(|literal| literal.needle.chars().count()).min().unwrap_or(usize::MAX);
+    (shortest, std::cmp::Reverse(literals.len()))
+}
+
+fn plain_value(logic: &DetectionLogic) -> Option<&str> {
+    match logic {
+        DetectionLogic { and: None, or: None, value: Some(value) } => Some(value),
+        _ => None,
+    }
+}
+
+impl CompiledDetection {
+    /// Literals of which a log event contains at least one when the Detection matches it, so an event without any of them can be skipped.
+    /// Returns None when the Detection may match without a literal, i.e. it negates a search identifier or only compares numbers.
+    /// Of the search identifiers that must all match, the literals of the one with the longest needles are returned.
+    pub fn literals(&self) -> Option<Vec<Literal>> {
+        let (first, rest) = match self.conditions.split_first() {
+            Some(conditions) => conditions,
+            None => return Some(Vec::new()),
+        };
+
+        let mut literals = first.literals();
+        for condition in rest {
+            literals = match (condition.operator.as_ref().or(self.operator.as_ref()), literals, condition.literals()) {
+                (Some(Operator::Or), Some(literals), Some(condition_literals)) => Some([literals, condition_literals].concat()),
+                (Some(Operator::Or), _, _) => None,
+                (_, Some(literals), Some(condition_literals)) => Some(std::cmp::max_by_key(literals, condition_literals, |literals| selectivity(literals))),
+                (_, literals, condition_literals) => literals.or(condition_literals),
+            };
+        }
+
+        literals.map(|mut literals| {
+            literals.sort();
+            literals.dedup();
+            literals
+        })
+    }
+
+    pub fn matches(&self, event: &LogEvent) -> bool {
+        self.matches_with_field_mappings(event, &HashMap::new())
+    }
+
+    /// The field names of the Sigma rule are translated to the fields of the log event first, see detection_evaluator::matches_with_field_mappings.
+    /// An empty Detection never matches.
+    pub fn matches_with_field_mappings(&self, event: &LogEvent, field_mappings: &HashMap<String, String>) -> bool {
+        let (first, rest) = match self.conditions.split_first() {
+            Some(conditions) => conditions,
+            None => return false,
+        };
+
+        let mut result = first.matches(event, field_mappings);
+        for condition in rest {
+            result = match condition.operator.as_ref().or(self.operator.as_ref()) {
+                Some(Operator::Or) => result || condition.matches(event, field_mappings),
+                _ => result && condition.matches(event, field_mappings),
+            };
+        }
+
+        result
+    }
+}
+
+impl CompiledCondition {
+    fn literals(&self) -> Option<Vec<Literal>> {
+        if self.is_negated {
+            return None;
+        }
+        match &self.search {
+            Search::Nested(nested_detection) => nested_detection.literals(),
+            Search::Logic(logic) => logic.literals(),
+        }
+    }
+
+    fn matches(&self, event: &LogEvent, field_mappings: &HashMap<String, String>) -> bool {
+        let result = match &self.search {
+            Search::Nested(nested_detection) => nested_detection.matches_with_field_mappings(event, field_mappings),
+            Search::Logic(logic) => logic.matches(event, field_mappings),
+        };
+
+        result != self.is_negated
+    }
+}
+
+impl LogicMatcher {
+    fn literals(&self) -> Option<Vec<Literal>> {
+        match self {
+            LogicMatcher::All(matchers) => matchers.iter().filter_map(LogicMatcher::literals).max_by_key(|literals| selectivity(literals)),
+            LogicMatcher::Any(matchers) => matchers.iter().map(LogicMatcher::literals).collect::<Option<Vec<Vec<Literal>>>>().map(|literals| literals.concat()),
+            LogicMatcher::Field(matcher) => Some(matcher.needles()?.into_iter().map(|needle| Literal { field: Some(matcher.field.clone()), needle }).collect()),
+            LogicMatcher::Keywords(matcher) => Some(matcher.needles()?.into_iter().map(|needle| Literal { field: None, needle }).collect()),
+            LogicMatcher::Missing(_) => None,
+            LogicMatcher::Never => Some(Vec::new()),
+        }
+    }
+
+    fn matches(&self, event: &LogEvent, field_mappings: &HashMap<String, String>) -> bool {
+        match self {
+            LogicMatcher::All(matchers) => matchers.iter().all(|matcher| matcher.matches(event, field_mappings)),
+            LogicMatcher::Any(matchers) => matchers.iter().any(|matcher| matcher.matches(event, field_mappings)),
+            LogicMatcher::Field(matcher) => matcher.matches(field_value(event, field_mappings, &matcher.field)),
+            LogicMatcher::Keywords(matcher) => event.fields().values().any(|event_value| matcher.matches(event_value)),
+            LogicMatcher::Missing(field) => matches!(field_value(event, field_mappings, field), None | Some(Value::Null)),
+            LogicMatcher::Never => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use crate::detection_builder::build;
+    use crate::detection_evaluator::matches;
+    use crate::structs::sigma_rule::SigmaRule;
+    use super::*;
+
+    fn build_detection(detection_yml: &str) -> Detection {
+        let rule: SigmaRule = serde_yaml::from_str(detection_yml).unwrap();
+        build(rule).unwrap()
+    }
+
+    /// The compiled Detection matches the same events as the interpreted Detection.
+    fn assert_matches(detection: &Detection, events: &[(Value, bool)]) {
+        let compiled = compile(detection).unwrap();
+        for (event, expected) in events {
+            let event = LogEvent::from(event.clone());
+            assert_eq!(compiled.matches(&event), *expected, "{:?}", event);
+            assert_eq!(matches(detection, &event), *expected, "{:?}", event);
+        }
+    }
+
+    #[test]
+    fn compile_conditions() {
+        let detection = build_detection(r#"
+            detection:
+              selection1:
+                EventID:
+                  - 1
+                  - 2
+              selection2:
+                - Image|endswith: '\rundll32.exe'
+                - CommandLine|contains|all:
+                    - 'javascript:'
+                    - 'RunHTMLApplication'
+              filter:
+                User: SYSTEM
+              condition: (selection1 or selection2) and not filter
+        "#);
+
+        assert_matches(&detection, &[
+            (json!({"EventID": 1, "User": "guest"}), true),
+            (json!({"EventID": 2, "User": "system"}), false),
+            (json!({"Image": "C:\\Windows\\RUNDLL32.exe"}), true),
+            (json!({"CommandLine": "rundll32 javascript:\"\\..\\mshtml,RunHTMLApplication\""}), true),
+            (json!({"CommandLine": "rundll32 javascript:alert(1)"}), false),
+            (json!({"EventID": 3}), false),
+        ]);
+    }
+
+    #[test]
+    fn compile_keywords_and_modifiers() {
+        let detection = build_detection(r#"
+            detection:
+              keywords:
+                - 'mimikatz'
+                - 'sekurlsa'
+              encoded:
+                CommandLine|base64offset|contains: '/bin/bash'
+              network:
+                DestinationIp|cidr: '10.0.0.0/8'
+                DestinationPort|gte: 1024
+              missing:
+                Image: null
+                User: admin
+              condition: keywords or encoded or network or missing
+        "#);
+
+        assert_matches(&detection, &[
+            (json!({"process": {"command_line": "SEKURLSA::logonpasswords"}}), true),
+            (json!({"CommandLine": "echo L2Jpbi9iYXNoIC1p | base64 -d | sh"}), true),
+            (json!({"DestinationIp": "10.20.30.40", "DestinationPort": 4444}), true),
+            (json!({"DestinationIp": "10.20.30.40", "DestinationPort": 443}), false),
+            (json!({"User": "admin"}), true),
+            (json!({"User": "admin", "Image": "cmd.exe"}), false),
+        ]);
+    }
+
+    #[test]
+    fn compile_null_and_empty_values() {
+        let detection = build_detection(r#"
+            detection:
+              selection:
+                CommandLine: null
+                ParentImage: ''
+              optional:
+                User:
+                  - null
+                  - SYSTEM
+              condition: selection or optional
+        "#);
+
+        assert_matches(&detection, &[
+            (json!({"ParentImage": ""}), true),
+            (json!({"ParentImage": "", "CommandLine": null}), true),
+            (json!({"ParentImage": "", "CommandLine": "whoami", "User": "guest"}), false),
+            (json!({"Image": "whoami.exe"}), true),
+            (json!({"User": "system", "CommandLine": "whoami"}), true),
+            (json!({"User": "guest", "ParentImage": "explorer.exe"}), false),
+        ]);
+        assert_matches(&build_detection(r#"
+            detection:
+              selection:
+                ParentImage: ''
+              condition: selection
+        "#), &[
+            (json!({"ParentImage": ""}), true),
+            (json!({"ParentImage": null}), false),
+            (json!({"Image": "whoami.exe"}), false),
+        ]);
+        assert_eq!(compile(&detection).unwrap().literals(), None);
+    }
+
+    #[test]
+    fn compile_field_mappings() {
+        let detection = compile(&build_detection(r#"
+            detection:
+              selection:
+                TargetImage|endswith: '\lsass.exe'
+              condition: selection
+        "#)).unwrap();
+        let field_mappings = HashMap::from([("TargetImage".to_string(), "target.img".to_string())]);
+
+        let event = LogEvent::from(json!({"target": {"img": "C:\\Windows\\System32\\lsass.exe"}}));
+        assert!(detection.matches_with_field_mappings(&event, &field_mappings));
+        assert!(!detection.matches(&event));
+    }
+
+    #[test]
+    fn literals_of_detections() {
+        let literals = |detection_yml: &str| {
+            compile(&build_detection(detection_yml)).unwrap().literals().map(|literals| {
+                literals.into_iter().map(|literal| format!("{}:{}", literal.field.unwrap_or_default(), literal.needle)).collect::<Vec<String>>()
+            })
+        };
+
+        // the search identifier with the longest needles is enough when both must match
+        assert_eq!(literals(r#"
+            detection:
+              selection:
+                EventID: 1
+                Image|endswith:
+                  - '\PsExec.exe'
+                  - '\PsExec64.exe'
+              filter:
+                User|contains: 'SYSTEM'
+              condition: selection and not filter
+        "#), Some(vec!["Image:\\psexec.exe".to_string(), "Image:\\psexec64.exe".to_string()]));
+
+        assert_eq!(literals(r#"
+            detection:
+              keywords:
+                - 'Mimikatz'
+              selection:
+                CommandLine|contains|all:
+                  - 'sekurlsa'
+                  - '::'
+              condition: keywords or selection
+        "#), Some(vec![":mimikatz".to_string(), "CommandLine:sekurlsa".to_string()]));
+
+        // a detection that matches without a literal can not be skipped
+        assert_eq!(literals(r#"
+            detection:
+              selection:
+                EventID: 1
+              network:
+                DestinationPort|gt: 1024
+              condition: selection or network
+        "#), None);
+        assert_eq!(literals(r#"
+            detection:
+              filter:
+                User: SYSTEM
+              condition: not filter
+        "#), None);
+        assert_eq!(literals(r#"
+            detection:
+              selection:
+                Image: ''
+              condition: selection
+        "#), None);
+    }
+
+    #[test]
+    fn fail_rules_with_invalid_values() {
+        let detection = build_detection(r#"
+            detection:
+              selection:
+                CommandLine|re: '(?<!\\)cmd'
+              condition: selection
+        "#);
+
+        assert!(matches!(compile(&detection), Err(RuleError::InvalidValue { .. })));
+    }
+}
@@ -0,0 +1,386 @@
+use std::net::IpAddr;
+use aho_corasick::AhoCorasick;
+use ipnet::IpNet;
+use regex::Regex;
+use serde_json::Value;
+use crate::detection_evaluator::value_modifiers::{case_sensitive, value_patterns, Comparison};
+use crate::detection_evaluator::value_pattern::ValuePattern;
+use crate::rule_error::RuleError;
+use crate::structs::detection_field::{DetectionField, Modifier};
+
+/// Lists with at least this many needles are searched with a single Aho-Corasick automaton, instead of one needle at a time.
+const AHO_CORASICK_MIN_NEEDLES: usize = 8;
+
+/// The values of a field of a search identifier, compiled for the comparison of the modifiers of the field.
+/// The values are transformed, parsed for wildcards and lowercased once, so matching an event only lowercases the value of the field.
+#[derive(Clone, Debug)]
+pub struct FieldMatcher {
+    pub field: String,
+    comparison: Comparison,
+    /// Every value must match (the all modifier), instead of any value
+    all: bool,
+    case_sensitive: bool,
+    values: Vec<ValueMatcher>,
+    /// Every needle of the values, when any of many values may be contained in the field
+    automaton: Option<Box<AhoCorasick>>,
+}
+
+#[derive(Clone, Debug)]
+enum ValueMatcher {
+    /// The transformed variants of a value, any of which may match
+    Strings(Vec<Needle>),
+    Regex(Regex),
+    Cidr(IpNet),
+    Number(f64),
+    Exists(bool),
+}
+
+/// A value without wildcards is compared as a string, a value with wildcards by the regular expression of its pattern.
+#[derive(Clone, Debug)]
+enum Needle {
+    Literal(String),
+    /// The longest literal of the pattern is kept for CompiledDetection::literals
+    Wildcards { regex: Regex, literal: Option<String> },
+}
+
+/// Keywords are values without a field, which are searched for in every value of the log event.
+#[derive(Clone, Debug)]
+pub struct KeywordMatcher {
+    keywords: Vec<Needle>,
+    automaton: Option<Box<AhoCorasick>>,
+}
+
+impl FieldMatcher {
+    /// Fails when a value can not be compared as the modifiers require, i.e. an invalid regular expression or a `gt` that is not a number.
+    pub fn new(field: &DetectionField, values: &[&str]) -> Result<FieldMatcher, RuleError> {
+        let comparison = Comparison::from_modifiers(&field.modifiers);
+        let case_sensitive = case_sensitive(&field.modifiers);
+        let values = values
+            .iter()
+            .map(|value| ValueMatcher::new(value, &comparison, &field.modifiers, case_sensitive))
+            .collect::<Result<Vec<ValueMatcher>, RuleError>>()?;
+
+        let all = field.has_modifier(&Modifier::All);
+        let automaton = match comparison {
+            Comparison::Contains if !all => automaton(values.iter().flat_map(|value| match value {
+                ValueMatcher::Strings(needles) => needles.as_slice(),
+                _ => &[],
+            })),
+            _ => None,
+        };
+
+        Ok(FieldMatcher { field: field.name.clone(), comparison, all, case_sensitive, values, automaton })
+    }
+
+    /// Compares the values to the value of the field in the log event. A field that holds a list matches when any of its items match.
+    /// A field that is missing or null only matches exists, null values of the Sigma rule are compiled by the Detection instead.
+    pub fn matches(&self, field_value: Option<&Value>) -> bool {
+        if self.comparison == Comparison::Exists {
+            let exists = !matches!(field_value, None | Some(Value::Null));
+            return self.all_or_any(|value| matches!(value, ValueMatcher::Exists(expected) if *expected == exists));
+        }
+
+        let lowercase = !self.case_sensitive
+            && matches!(self.comparison, Comparison::Equals | Comparison::Contains | Comparison::StartsWith | Comparison::EndsWith);
+        let mut field_values = Vec::new();
+        if let Some(field_value) = field_value {
+            collect_field_values(field_value, lowercase, &mut field_values);
+        }
+
+        if let Some(automaton) = self.automaton.as_ref() {
+            return field_values.iter().any(|field_value| automaton.is_match(field_value));
+        }
+        self.all_or_any(|value| field_values.iter().any(|field_value| self.value_matches(value, field_value)))
+    }
+
+    /// The needles in lowercase of which the field contains at least one when it matches, see CompiledDetection::literals.
+    /// Returns None when the field may match without a needle, i.e. a regular expression, a value of only wildcards
+    ///     or an empty value.
+    pub fn needles(&self) -> Option<Vec<String>> {
+        let needles = self.values.iter().map(|value| match value {
+            ValueMatcher::Strings(needles) => needles.iter().map(Needle::literal).collect::<Option<Vec<String>>>(),
+            _ => None,
+        });
+
+        if self.all {
+            // the field contains the needles of every value, so those of a single value are enough
+            needles.flatten().max_by_key(|needles| needles.iter().map(|needle| needle.chars().count()).min().unwrap_or(usize::MAX))
+        } else {
+            needles.collect::<Option<Vec<Vec<String>>>>().map(|needles| needles.concat())
+        }
+    }
+
+    fn all_or_any<F: FnMut(&ValueMatcher) -> bool>(&self, matches: F) -> bool {
+        if self.all {
+            self.values.iter().all(matches)
+        } else {
+            self.values.iter().any(matches)
+        }
+    }
+
+    fn value_matches(&self, value: &ValueMatcher, field_value: &str) -> bool {
+        match value {
+            ValueMatcher::Strings(needles) => needles.iter().any(|needle| match needle {
+                Needle::Literal(needle) => match self.comparison {
+                    Comparison::Contains => field_value.contains(needle.as_str()),
+                    Comparison::StartsWith => field_value.starts_with(needle.as_str()),
+                    Comparison::EndsWith => field_value.ends_with(needle.as_str()),
+                    _ => field_value == needle,
+                },
+                Needle::Wildcards { regex, .. } => regex.is_match(field_value),
+            }),
+            ValueMatcher::Regex(regex) => regex.is_match(field_value),
+            ValueMatcher::Cidr(network) => field_value.parse::<IpAddr>().is_ok_and(|address| network.contains(&address)),
+            ValueMatcher::Number(value) => field_value.trim().parse::<f64>().is_ok_and(|field_value| match self.comparison {
+                Comparison::Lt => field_value < *value,
+                Comparison::Lte => field_value <= *value,
+                Comparison::Gt => field_value > *value,
+                _ => field_value >= *value,
+            }),
+            ValueMatcher::Exists(_) => false,
+        }
+    }
+}
+
+impl ValueMatcher {
+    fn new(value: &str, comparison: &Comparison, modifiers: &[Modifier], case_sensitive: bool) -> Result<ValueMatcher, RuleError> {
+        let invalid = |message: String| RuleError::InvalidValue { value: value.to_string(), message };
+
+        Ok(match comparison {
+            Comparison::Exists => ValueMatcher::Exists(value.eq_ignore_ascii_case("true")),
+            Comparison::Re => ValueMatcher::Regex(Regex::new(value).map_err(|err| invalid(regex_error(err)))?),
+            Comparison::Cidr => ValueMatcher::Cidr(value.parse().map_err(|err: ipnet::AddrParseError| invalid(err.to_string()))?),
+            Comparison::Lt | Comparison::Lte | Comparison::Gt | Comparison::Gte => {
+                ValueMatcher::Number(value.trim().parse().map_err(|_| invalid("not a number".to_string()))?)
+            }
+            Comparison::Equals | Comparison::Contains | Comparison::StartsWith | Comparison::EndsWith => {
+                let needles = value_patterns(value, modifiers)
+                    .map_err(invalid)?
+                    .into_iter()
+                    .map(|pattern| {
+                        let pattern = if case_sensitive { pattern } else { pattern.to_lowercase() };
+                        Needle::new(&pattern, comparison).map_err(|err| invalid(regex_error(err)))
+                    })
+                    .collect::<Result<Vec<Needle>, RuleError>>()?;
+                ValueMatcher::Strings(needles)
+            }
+        })
+    }
+}
+
+impl Needle {
+    fn new(pattern: &ValuePattern, comparison: &Comparison) -> Result<Needle, regex::Error> {
+        Ok(match pattern.as_literal() {
+            Some(literal) => Needle::Literal(literal),
+            None => Needle::Wildcards {
+                regex: pattern.to_regex(comparison)?,
+                literal: pattern.longest_literal().map(str::to_string),
+            },
+        })
+    }
+
+    /// The text in lowercase that a value contains when the needle matches it, unless it is empty.
+    fn literal(&self) -> Option<String> {
+        match self {
+            Needle::Literal(literal) => Some(literal),
+            Needle::Wildcards { literal, .. } => literal.as_ref(),
+        }
+        .filter(|literal| !literal.is_empty())
+        .map(|literal| literal.to_lowercase())
+    }
+}
+
+impl KeywordMatcher {
+    /// Keywords are parsed for wildcards like the values of fields, and are always contained in a value of the log event.
+    pub fn new(keywords: &[&str]) -> Result<KeywordMatcher, RuleError> {
+        let keywords = keywords
+            .iter()
+            .map(|keyword| {
+                Needle::new(&ValuePattern::parse(keyword).to_lowercase(), &Comparison::Contains)
+                    .map_err(|err| RuleError::InvalidValue { value: keyword.to_string(), message: regex_error(err) })
+            })
+            .collect::<Result<Vec<Needle>, RuleError>>()?;
+        Ok(KeywordMatcher { automaton: automaton(keywords.iter()), keywords })
+    }
+
+    /// The keywords, unless a keyword without a literal (i.e. an empty keyword) matches every event.
+    pub fn needles(&self) -> Option<Vec<String>> {
+        self.keywords.iter().map(Needle::literal).collect()
+    }
+
+    /// Keywords are not bound to a field, so every value of the log event is searched for the keywords.
+    pub fn matches(&self, event_value: &Value) -> bool {
+        match event_value {
+            Value::String(event_value) => self.contained_in(&event_value.to_lowercase()),
+            Value::Number(event_value) => self.contained_in(&event_value.to_string()),
+            Value::Array(event_values) => event_values.iter().any(|event_value| self.matches(event_value)),
+            Value::Object(event_values) => event_values.values().any(|event_value| self.matches(event_value)),
+            Value::Bool(_) | Value::Null => false,
+        }
+    }
+
+    fn contained_in(&self, event_value: &str) -> bool {
+        match self.automaton.as_ref() {
+            Some(automaton) => automaton.is_match(event_value),
+            None => self.keywords.iter().any(|keyword| match keyword {
+                Needle::Literal(keyword) => event_value.contains(keyword.as_str()),
+                Needle::Wildcards { regex, .. } => regex.is_match(event_value),
+            }),
+        }
+    }
+}
+
+/// An automaton of the needles, unless there are few of them or any has wildcards.
+fn automaton<'a, I: Iterator<Item = &'a Needle>>(needles: I) -> Option<Box<AhoCorasick>> {
+    let needles = needles
+        .map(|needle| match needle {
+            Needle::Literal(literal) => Some(literal),
+            Needle::Wildcards { .. } => None,
+        })
+        .collect::<Option<Vec<&String>>>()?;
+    (needles.len() >= AHO_CORASICK_MIN_NEEDLES).then(|| Box::new(AhoCorasick::new(needles)))
+}
+
+/// The syntax errors of the regex crate point at the pattern on the lines before, the reason is the last line.
+fn regex_error(err: regex::Error) -> String {
+    let message = err.to_string();
+    message.lines().last().unwrap_or_default().trim_start_matches("error: ").to_string()
+}
+
+/// The values of a field as strings, where a list adds every item. Null is not a value and objects are never compared.
+fn collect_field_values(field_value: &Value, lowercase: bool, field_values: &mut Vec<String>) {
+    match field_value {
+        Value::Array(items) => items.iter().for_each(|item| collect_field_values(item, lowercase, field_values)),
+        Value::Null | Value::Object(_) => {}
+        Value::String(field_value) if lowercase => field_values.push(field_value.to_lowercase()),
+        Value::String(field_value) => field_values.push(field_value.clone()),
+        field_value => field_values.push(field_value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    fn field(field: &str) -> DetectionField {
+        let mut parts = field.split('|');
+        DetectionField::new(parts.next().unwrap().to_string(), parts.map(|modifier| modifier.parse().unwrap()).collect())
+    }
+
+    #[test]
+    fn match_lowercased_values() {
+        let matcher = FieldMatcher::new(&field("Image|endswith"), &["\\LSASS.exe", "\\whoami.exe"]).unwrap();
+        assert!(matcher.matches(Some(&json!("C:\\Windows\\System32\\lsass.EXE"))));
+        assert!(matcher.matches(Some(&json!(["cmd.exe", "C:\\Windows\\whoami.exe"]))));
+        assert!(!matcher.matches(Some(&json!("C:\\Windows\\cmd.exe"))));
+        assert!(!matcher.matches(None));
+
+        let matcher = FieldMatcher::new(&field("CommandLine|contains|all"), &["-nop", "-W HIDDEN"]).unwrap();
+        assert!(matcher.matches(Some(&json!("powershell -NoP -nop -w hidden"))));
+        assert!(!matcher.matches(Some(&json!("powershell -nop"))));
+        assert!(matcher.matches(Some(&json!(["powershell -nop", "-w hidden"]))));
+    }
+
+    #[test]
+    fn match_many_values_with_an_automaton() {
+        let values = ["mimikatz", "sekurlsa", "kerberos::", "lsadump", "privilege::debug", "token::elevate", "crypto::", "dpapi::"];
+        let matcher = FieldMatcher::new(&field("CommandLine|contains"), &values).unwrap();
+        assert!(matcher.automaton.is_some());
+        assert!(matcher.matches(Some(&json!("mimikatz.exe PRIVILEGE::Debug exit"))));
+        assert!(matcher.matches(Some(&json!(["whoami", "lsadump::sam"]))));
+        assert!(!matcher.matches(Some(&json!("whoami /all"))));
+        assert!(!matcher.matches(None));
+
+        assert!(FieldMatcher::new(&field("CommandLine|contains|all"), &values).unwrap().automaton.is_none());
+        assert!(FieldMatcher::new(&field("CommandLine|startswith"), &values).unwrap().automaton.is_none());
+
+        let matcher = KeywordMatcher::new(&values).unwrap();
+        assert!(matcher.automaton.is_some());
+        assert!(matcher.matches(&json!({"message": "Invoke-Mimikatz", "code": 1})));
+        assert!(!matcher.matches(&json!({"message": "nothing to see", "ok": true})));
+    }
+
+    #[test]
+    fn match_wildcards_and_cased_values() {
+        let matcher = FieldMatcher::new(&field("Image"), &["C:\\Windows\\\\*\\cmd.exe", "*\\net?.exe"]).unwrap();
+        assert!(matcher.matches(Some(&json!("c:\\windows\\SysWOW64\\CMD.exe"))));
+        assert!(matcher.matches(Some(&json!("D:\\tools\\net1.exe"))));
+        assert!(!matcher.matches(Some(&json!("D:\\tools\\net.exe"))));
+        assert_eq!(matcher.needles(), Some(vec!["c:\\windows\\".to_string(), ".exe".to_string()]));
+
+        let matcher = FieldMatcher::new(&field("CommandLine|contains"), &["what\\?", "\\*"]).unwrap();
+        assert!(matcher.matches(Some(&json!("so what?"))));
+        assert!(matcher.matches(Some(&json!("rm *"))));
+        assert!(!matcher.matches(Some(&json!("whats up"))));
+        assert!(FieldMatcher::new(&field("CommandLine|contains"), &["a*b", "*"]).unwrap().needles().is_none());
+
+        let matcher = FieldMatcher::new(&field("User|cased"), &["SYSTEM", "Admin*"]).unwrap();
+        assert!(matcher.matches(Some(&json!("SYSTEM"))));
+        assert!(matcher.matches(Some(&json!("Administrator"))));
+        assert!(!matcher.matches(Some(&json!("system"))));
+        assert!(!matcher.matches(Some(&json!("administrator"))));
+
+        let values = ["mimikatz", "sekurlsa", "kerberos::", "lsadump", "privilege::debug", "token::elevate", "crypto::", "dpapi*"];
+        let matcher = FieldMatcher::new(&field("CommandLine|contains"), &values).unwrap();
+        assert!(matcher.automaton.is_none());
+        assert!(matcher.matches(Some(&json!("dpapi::masterkey"))));
+
+        let matcher = KeywordMatcher::new(&["Invoke-*Katz", "sekurlsa::"]).unwrap();
+        assert!(matcher.matches(&json!({"message": "invoke-mimikatz"})));
+        assert!(!matcher.matches(&json!({"message": "invoke-command"})));
+        assert_eq!(matcher.needles(), Some(vec!["invoke-".to_string(), "sekurlsa::".to_string()]));
+        assert!(KeywordMatcher::new(&["*"]).unwrap().needles().is_none());
+    }
+
+    #[test]
+    fn match_typed_values() {
+        let matcher = FieldMatcher::new(&field("DestinationPort|gte"), &["1024"]).unwrap();
+        assert!(matcher.matches(Some(&json!(4444))));
+        assert!(matcher.matches(Some(&json!("1024"))));
+        assert!(!matcher.matches(Some(&json!(443))));
+
+        let matcher = FieldMatcher::new(&field("DestinationIp|cidr"), &["10.0.0.0/8", "fe80::/10"]).unwrap();
+        assert!(matcher.matches(Some(&json!("10.1.2.3"))));
+        assert!(matcher.matches(Some(&json!("fe80::1"))));
+        assert!(!matcher.matches(Some(&json!("192.168.1.1"))));
+
+        let matcher = FieldMatcher::new(&field("CommandLine|re"), &[r"-enc\s+[A-Za-z0-9+/=]+"]).unwrap();
+        assert!(matcher.matches(Some(&json!("powershell.exe -enc SQBFAFgA"))));
+        assert!(!matcher.matches(Some(&json!("POWERSHELL.EXE -ENC"))));
+
+        let matcher = FieldMatcher::new(&field("EventID"), &["4624", "4625"]).unwrap();
+        assert!(matcher.matches(Some(&json!(4625))));
+        assert!(!matcher.matches(Some(&json!(46250))));
+    }
+
+    #[test]
+    fn match_missing_and_existing_fields() {
+        let matcher = FieldMatcher::new(&field("Image"), &[""]).unwrap();
+        assert!(!matcher.matches(None));
+        assert!(!matcher.matches(Some(&Value::Null)));
+        assert!(matcher.matches(Some(&json!(""))));
+        assert!(matcher.matches(Some(&json!([null, ""]))));
+        assert!(!matcher.matches(Some(&json!("whoami.exe"))));
+        assert!(!FieldMatcher::new(&field("Image|contains"), &[""]).unwrap().matches(None));
+
+        let matcher = FieldMatcher::new(&field("Image|exists"), &["true"]).unwrap();
+        assert!(matcher.matches(Some(&json!("whoami.exe"))));
+        assert!(!matcher.matches(None));
+        assert!(FieldMatcher::new(&field("Image|exists"), &["false"]).unwrap().matches(Some(&Value::Null)));
+    }
+
+    #[test]
+    fn reject_values_that_do_not_compile() {
+        assert!(matches!(FieldMatcher::new(&field("CommandLine|re"), &["(unclosed"]), Err(RuleError::InvalidValue { .. })));
+        assert!(matches!(FieldMatcher::new(&field("DestinationIp|cidr"), &["10.0.0.0/33"]), Err(RuleError::InvalidValue { .. })));
+        assert_eq!(
+            FieldMatcher::new(&field("DestinationPort|gt"), &["high"]).unwrap_err().to_string(),
+            "invalid value 'high': not a number"
+        );
+        assert_eq!(
+            FieldMatcher::new(&field("CommandLine|base64"), &["who*"]).unwrap_err().to_string(),
+            "invalid value 'who*': wildcards can not be encoded"
+        );
+    }
+}
@@ -0,0 +1,347 @@
+use std::collections::HashMap;
+use serde_json::Value;
+use sigma_log_parser::log_event::LogEvent;
+use crate::structs::detection::Detection;
+use crate::structs::detection_condition::{DetectionCondition, Operator};
+use crate::structs::detection_field::{DetectionField, Modifier};
+use crate::structs::detection_logic::DetectionLogic;
+use self::value_modifiers::Comparison;
+use self::value_pattern::ValuePattern;
+
+pub mod aggregation_state;
+pub mod value_modifiers;
+pub mod value_pattern;
+
+/// Returns true when the log event satisfies the Detection that was returned by detection_builder::build.
+///
+/// The Detection is evaluated in two steps, mirroring how it was built:
+///     - The '''first order logic''' walks the DetectionConditions, applying operators, negation and nested detections (parentheses)
+///     - The '''second order logic''' compares the DetectionLogic of each search identifier to the fields of the log event
+///
+/// The values are parsed and transformed again for every event (only regular expressions are cached, see value_modifiers::cached_regex),
+///     so a Detection that is evaluated for many events is compiled once with detection_compiler::compile instead.
+pub fn matches(detection: &Detection, event: &LogEvent) -> bool {
+    matches_with_field_mappings(detection, event, &HashMap::new())
+}
+
+/// The same as matches, but the field names of the Sigma rule are translated to the fields of the log event first,
+///     i.e. {"TargetImage": "target.img"} compares the values of TargetImage to the value of target.img in the log event.
+/// A field that is not mapped, or whose mapped field is not in the log event, is looked up by its own name.
+pub fn matches_with_field_mappings(detection: &Detection, event: &LogEvent, field_mappings: &HashMap<String, String>) -> bool {
+    evaluate_detection(detection, &|condition: &DetectionCondition| {
+        logic_matches(&condition.detection_logic, event, field_mappings, None)
+    })
+}
+
+/// Walks the conditions of a Detection, calling search_identifier_matches for every condition that references a search identifier.
+/// Each condition carries the operator that joins it to the conditions before it, falling back to the operator of the Detection.
+/// An empty Detection never matches.
+pub fn evaluate_detection<F>(detection: &Detection, search_identifier_matches: &F) -> bool
+where
+    F: Fn(&DetectionCondition) -> bool,
+{
+    let conditions = match detection.conditions.as_ref() {
+        Some(conditions) if !conditions.is_empty() => conditions,
+        _ => return false,
+    };
+
+    let mut result = evaluate_condition(&conditions[0], search_identifier_matches);
+    for condition in &conditions[1..] {
+        result = match condition.operator.as_ref().or(detection.operator.as_ref()) {
+            Some(Operator::Or) => result || evaluate_condition(condition, search_identifier_matches),
+            _ => result && evaluate_condition(condition, search_identifier_matches),
+        };
+    }
+
+    result
+}
+
+fn evaluate_condition<F>(condition: &DetectionCondition, search_identifier_matches: &F) -> bool
+where
+    F: Fn(&DetectionCondition) -> bool,
+{
+    let result = match condition.nested_detections.as_ref() {
+        Some(nested_detection) => evaluate_detection(nested_detection, search_identifier_matches),
+        None => search_identifier_matches(condition),
+    };
+
+    result != condition.is_negated.unwrap_or(false)
+}
+
+/// Compares DetectionLogic to a log event.
+///     - "and" requires every field in the mapping to match
+///     - "or" requires any of the listed values to match, or all of them when the field has the "all" modifier
+///     - "value" is compared to the field it belongs to (applying its modifiers), or searched for in the whole event when it is a keyword (no field)
+///     - a null value matches a field that is missing or null
+fn logic_matches(logic: &DetectionLogic, event: &LogEvent, field_mappings: &HashMap<String, String>, field: Option<&DetectionField>) -> bool {
+    if let Some(and) = logic.and.as_ref() {
+        return and.iter().all(|(field, logic)| logic_matches(logic, event, field_mappings, Some(field)));
+    }
+
+    if let Some(or) = logic.or.as_ref() {
+        return match field {
+            Some(field) if field.has_modifier(&Modifier::All) => or.iter().all(|logic| logic_matches(logic, event, field_mappings, Some(field))),
+            _ => or.iter().any(|logic| logic_matches(logic, event, field_mappings, field)),
+        };
+    }
+
+    match (logic.value.as_ref(), field) {
+        (Some(value), Some(field)) => value_modifiers::field_matches(field_value(event, field_mappings, &field.name), &field.modifiers, value),
+        (Some(value), None) => event.fields().values().any(|event_value| keyword_matches(event_value, &ValuePattern::parse(value).to_lowercase())),
+        (None, Some(field)) => matches!(field_value(event, field_mappings, &field.name), None | Some(Value::Null)),
+        (None, None) => false,
+    }
+}
+
+pub(crate) fn field_value<'a>(event: &'a LogEvent, field_mappings: &HashMap<String, String>, field: &str) -> Option<&'a Value> {
+    field_mappings
+        .get(field)
+        .and_then(|source_field| event.get(source_field))
+        .or_else(|| event.get(field))
+}
+
+/// Keywords are not bound to a field, so every value of the log event is searched for the keyword.
+fn keyword_matches(event: &Value, keyword: &ValuePattern) -> bool {
+    match event {
+        Value::String(event_value) => keyword.matches(&event_value.to_lowercase(), &Comparison::Contains),
+        Value::Number(event_value) => keyword.matches(&event_value.to_string(), &Comparison::Contains),
+        Value::Array(event_values) => event_values.iter().any(|event_value| keyword_matches(event_value, keyword)),
+        Value::Object(event_values) => event_values.values().any(|event_value| keyword_matches(event_value, keyword)),
+        Value::Bool(_) | Value::Null => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use sigma_log_parser::log_event::LogEvent;
+    use crate::detection_builder::build;
+    use crate::detection_evaluator::{matches, matches_with_field_mappings};
+    use crate::structs::detection::Detection;
+    use crate::structs::sigma_rule::SigmaRule;
+
+    fn build_detection(detection_yml: &str) -> Detection {
+        let rule: SigmaRule = serde_yaml::from_str(detection_yml).unwrap();
+        build(rule).unwrap()
+    }
+
+    #[test]
+    fn match_mapping_and_sequence() {
+        let detection = build_detection(r#"
+            detection:
+              selection:
+                EventID:
+                  - 4624
+                  - 4625
+                LogonType: 3
+              condition: selection
+        "#);
+
+        assert!(matches(&detection, &LogEvent::from(json!({"EventID": 4625, "LogonType": 3}))));
+        assert!(!matches(&detection, &LogEvent::from(json!({"EventID": 4625, "LogonType": 10}))));
+        assert!(!matches(&detection, &LogEvent::from(json!({"EventID": 1, "LogonType": 3}))));
+        assert!(!matches(&detection, &LogEvent::from(json!({"EventID": 4625}))));
+    }
+
+    #[test]
+    fn match_list_of_mappings() {
+        let detection = build_detection(r#"
+            detection:
+              selection:
+                - Image: 'C:\Windows\System32\whoami.exe'
+                - OriginalFileName: whoami.exe
+              condition: selection
+        "#);
+
+        assert!(matches(&detection, &LogEvent::from(json!({"Image": "c:\\windows\\system32\\WHOAMI.exe"}))));
+        assert!(matches(&detection, &LogEvent::from(json!({"OriginalFileName": "whoami.exe"}))));
+        assert!(!matches(&detection, &LogEvent::from(json!({"Image": "C:\\Windows\\System32\\cmd.exe"}))));
+    }
+
+    #[test]
+    fn match_and_not() {
+        let detection = build_detection(r#"
+            detection:
+              selection:
+                EventID: 10
+              filter:
+                SourceUser: SYSTEM
+              condition: selection and not filter
+        "#);
+
+        assert!(matches(&detection, &LogEvent::from(json!({"EventID": 10, "SourceUser": "admin"}))));
+        assert!(!matches(&detection, &LogEvent::from(json!({"EventID": 10, "SourceUser": "system"}))));
+        assert!(!matches(&detection, &LogEvent::from(json!({"EventID": 11, "SourceUser": "admin"}))));
+    }
+
+    #[test]
+    fn match_nested_parens() {
+        let detection = build_detection(r#"
+            detection:
+              selection1:
+                EventID: 1
+              selection2:
+                EventID: 2
+              filter:
+                User: admin
+              condition: (selection1 or selection2) and not filter
+        "#);
+
+        assert!(matches(&detection, &LogEvent::from(json!({"EventID": 1, "User": "guest"}))));
+        assert!(matches(&detection, &LogEvent::from(json!({"EventID": 2}))));
+        assert!(!matches(&detection, &LogEvent::from(json!({"EventID": 2, "User": "admin"}))));
+        assert!(!matches(&detection, &LogEvent::from(json!({"EventID": 3}))));
+    }
+
+    #[test]
+    fn match_nested_fields() {
+        let detection = build_detection(r#"
+            detection:
+              selection:
+                process.name: sshd
+                event.outcome: failure
+              condition: selection
+        "#);
+
+        assert!(matches(&detection, &LogEvent::from(json!({"process": {"name": "sshd"}, "event": {"outcome": "failure"}}))));
+        assert!(matches(&detection, &LogEvent::from(json!({"process.name": "sshd", "event.outcome": "failure"}))));
+        assert!(!matches(&detection, &LogEvent::from(json!({"process": {"name": "sshd"}, "event": {"outcome": "success"}}))));
+    }
+
+    #[test]
+    fn match_key_value_and_syslog_events() {
+        let detection = build_detection(r#"
+            detection:
+              selection:
+                app_name: sshd
+                user: root
+              condition: selection
+        "#);
+
+        assert!(matches(&detection, &LogEvent::from_key_value("app_name=sshd user=root action=login").unwrap()));
+        assert!(!matches(&detection, &LogEvent::from_syslog("<38>Oct 11 22:14:15 host sshd[42]: Failed password for root").unwrap()));
+    }
+
+    #[test]
+    fn match_keywords() {
+        let detection = build_detection(r#"
+            detection:
+              keywords:
+                - 'mimikatz'
+                - 'sekurlsa'
+              condition: keywords
+        "#);
+
+        assert!(matches(&detection, &LogEvent::from(json!({"message": "Invoke-Mimikatz -DumpCreds"}))));
+        assert!(matches(&detection, &LogEvent::from(json!({"process": {"command_line": "sekurlsa::logonpasswords"}}))));
+        assert!(!matches(&detection, &LogEvent::from(json!({"message": "nothing to see"}))));
+    }
+
+    #[test]
+    fn match_null_value() {
+        let detection = build_detection(r#"
+            detection:
+              selection:
+                Image: null
+              condition: selection
+        "#);
+
+        assert!(matches(&detection, &LogEvent::from(json!({"CommandLine": "whoami"}))));
+        assert!(!matches(&detection, &LogEvent::from(json!({"Image": "whoami.exe"}))));
+    }
+
+    #[test]
+    fn match_value_modifiers() {
+        let detection = build_detection(r#"
+            detection:
+              selection:
+                Image|endswith: '\powershell.exe'
+                CommandLine|contains|all:
+                  - ' -nop '
+                  - ' -w hidden '
+              filter:
+                ParentImage|startswith: 'C:\Program Files\'
+              condition: selection and not filter
+        "#);
+
+        assert!(matches(&detection, &LogEvent::from(json!({
+            "Image": "C:\\Windows\\System32\\WindowsPowerShell\\v1.0\\PowerShell.exe",
+            "CommandLine": "powershell.exe -NoLogo -nop -w hidden -c whoami",
+            "ParentImage": "C:\\Windows\\explorer.exe"
+        }))));
+        assert!(!matches(&detection, &LogEvent::from(json!({
+            "Image": "C:\\Windows\\System32\\WindowsPowerShell\\v1.0\\powershell.exe",
+            "CommandLine": "powershell.exe -nop -c whoami"
+        }))));
+        assert!(!matches(&detection, &LogEvent::from(json!({
+            "Image": "C:\\Windows\\System32\\WindowsPowerShell\\v1.0\\powershell.exe",
+            "CommandLine": "powershell.exe -nop -w hidden -c whoami",
+            "ParentImage": "C:\\Program Files\\Agent\\agent.exe"
+        }))));
+    }
+
+    #[test]
+    fn match_encoded_and_network_modifiers() {
+        let detection = build_detection(r#"
+            detection:
+              encoded:
+                CommandLine|base64offset|contains: '/bin/bash'
+              network:
+                DestinationIp|cidr: '10.0.0.0/8'
+                DestinationPort|gte: 1024
+              condition: encoded or network
+        "#);
+
+        assert!(matches(&detection, &LogEvent::from(json!({"CommandLine": "echo L2Jpbi9iYXNoIC1p | base64 -d | sh"}))));
+        assert!(matches(&detection, &LogEvent::from(json!({"DestinationIp": "10.20.30.40", "DestinationPort": 4444}))));
+        assert!(!matches(&detection, &LogEvent::from(json!({"DestinationIp": "10.20.30.40", "DestinationPort": 443}))));
+    }
+
+    #[test]
+    fn match_field_mappings() {
+        let detection = build_detection(r#"
+            detection:
+              selection:
+                TargetImage|endswith: '\lsass.exe'
+                EventCode: 10
+              condition: selection
+        "#);
+        let field_mappings = std::collections::HashMap::from([
+            ("TargetImage".to_string(), "target.img".to_string()),
+            ("EventCode".to_string(), "event.code".to_string()),
+        ]);
+
+        let event = LogEvent::from(json!({"target": {"img": "C:\\Windows\\System32\\lsass.exe"}, "event": {"code": 10}}));
+        assert!(matches_with_field_mappings(&detection, &event, &field_mappings));
+        assert!(!matches(&detection, &event));
+
+        // fields are still found by their own name when the mapped field is not in the log event
+        let event = LogEvent::from(json!({"TargetImage": "C:\\Windows\\System32\\lsass.exe", "event.code": 10}));
+        assert!(matches_with_field_mappings(&detection, &event, &field_mappings));
+    }
+
+    #[test]
+    fn match_quantifiers() {
+        let detection = build_detection(r#"
+            detection:
+              selection_img:
+                Image|endswith: '\rundll32.exe'
+              selection_cli:
+                CommandLine|contains: 'javascript:'
+              filter_a:
+                User: SYSTEM
+              filter_b:
+                ParentImage|endswith: '\services.exe'
+              condition: 1 of selection_* and not all of filter_*
+        "#);
+
+        assert!(matches(&detection, &LogEvent::from(json!({"Image": "C:\\Windows\\rundll32.exe", "User": "SYSTEM"}))));
+        assert!(matches(&detection, &LogEvent::from(json!({"CommandLine": "mshta javascript:alert(1)"}))));
+        assert!(!matches(&detection, &LogEvent::from(json!({
+            "Image": "C:\\Windows\\rundll32.exe",
+            "User": "SYSTEM",
+            "ParentImage": "C:\\Windows\\services.exe"
+        }))));
+        assert!(!matches(&detection, &LogEvent::from(json!({"Image": "C:\\Windows\\cmd.exe"}))));
+    }
+}
@@ -0,0 +1,260 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, SystemTime};
+use serde_json::Value;
+use sigma_log_parser::log_event::LogEvent;
+use crate::structs::aggregation::{Aggregation, AggregationFunction};
+
+/// The in-memory state of a single Aggregation.
+///
+/// Events that matched the Detection of a rule are recorded per group (the value of the "by" field), and events that are older than the
+///     timeframe of the rule are evicted, so the aggregation is always calculated over a sliding window that ends at the latest event.
+/// A rule without a timeframe aggregates every event it has seen, of which only the running totals of each group are kept.
+#[derive(Clone, Debug)]
+pub struct AggregationState {
+    aggregation: Aggregation,
+    groups: HashMap<Option<String>, Group>,
+}
+
+#[derive(Clone, Debug)]
+enum Group {
+    /// The events within the timeframe
+    Window(VecDeque<AggregatedEvent>),
+    /// The totals of every event, when the rule has no timeframe
+    Totals(Totals),
+}
+
+#[derive(Clone, Debug)]
+struct AggregatedEvent {
+    timestamp: SystemTime,
+    value: Option<Value>,
+}
+
+/// What the aggregation functions are calculated from: the number of events, the distinct values for count(field),
+///     and the numeric values for min, max, avg and sum.
+#[derive(Clone, Debug, Default)]
+struct Totals {
+    events: usize,
+    distinct: HashSet<String>,
+    numbers: usize,
+    sum: f64,
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+impl AggregationState {
+    pub fn new(aggregation: Aggregation) -> Self {
+        Self {
+            aggregation,
+            groups: HashMap::new(),
+        }
+    }
+
+    pub fn aggregation(&self) -> &Aggregation {
+        &self.aggregation
+    }
+
+    /// Records an event that matched the Detection, and returns true when the aggregation of its group exceeds the threshold.
+    pub fn evaluate(&mut self, event: &LogEvent, timestamp: SystemTime) -> bool {
+        let group = self.aggregation.group_by.as_ref().map(|group_by| group_value(event.get(group_by)));
+        let value = self.aggregation.field.as_ref().and_then(|field| event.get(field)).filter(|value| !value.is_null()).cloned();
+
+        let aggregation = &self.aggregation;
+        let result = match self.groups.entry(group).or_insert_with(|| new_group(aggregation.timeframe)) {
+            Group::Window(events) => {
+                events.push_back(AggregatedEvent { timestamp, value });
+                evict(events, aggregation.timeframe, timestamp);
+                let mut totals = Totals::default();
+                for event in events.iter() {
+                    totals.add(aggregation, event.value.as_ref());
+                }
+                totals.aggregate(aggregation)
+            }
+            Group::Totals(totals) => {
+                totals.add(aggregation, value.as_ref());
+                totals.aggregate(aggregation)
+            }
+        };
+
+        result.is_some_and(|result| aggregation.operator.compare(result, aggregation.threshold))
+    }
+
+    /// Evicts the events of every group that fell out of the timeframe, and removes the groups that are empty.
+    /// Should be called periodically, as groups that stop receiving events are otherwise only evicted when they match again.
+    pub fn expire(&mut self, now: SystemTime) {
+        let timeframe = self.aggregation.timeframe;
+        self.groups.retain(|_, group| match group {
+            Group::Window(events) => {
+                evict(events, timeframe, now);
+                !events.is_empty()
+            }
+            Group::Totals(_) => true,
+        });
+    }
+
+    pub fn group_count(&self) -> usize {
+        self.groups.len()
+    }
+}
+
+fn new_group(timeframe: Option<Duration>) -> Group {
+    match timeframe {
+        Some(_) => Group::Window(VecDeque::new()),
+        None => Group::Totals(Totals::default()),
+    }
+}
+
+/// Groups are compared as strings, so that the numbers 22 and "22" fall into the same group.
+fn group_value(value: Option<&Value>) -> String {
+    match value {
+        Some(Value::String(value)) => value.clone(),
+        Some(value) => value.to_string(),
+        None => Value::Null.to_string(),
+    }
+}
+
+fn evict(events: &mut VecDeque<AggregatedEvent>, timeframe: Option<Duration>, now: SystemTime) {
+    let oldest = match timeframe.and_then(|timeframe| now.checked_sub(timeframe)) {
+        Some(oldest) => oldest,
+        None => return,
+    };
+
+    events.retain(|event| event.timestamp >= oldest);
+}
+
+impl Totals {
+    /// Adds an event with the value of the aggregated field, of which only what the function needs is kept.
+    fn add(&mut self, aggregation: &Aggregation, value: Option<&Value>) {
+        self.events += 1;
+        let value = match value {
+            Some(value) => value,
+            None => return,
+        };
+
+        if aggregation.function == AggregationFunction::Count {
+            self.distinct.insert(group_value(Some(value)));
+        } else if let Some(number) = numeric_value(value) {
+            self.numbers += 1;
+            self.sum += number;
+            self.min = Some(self.min.map_or(number, |min| min.min(number)));
+            self.max = Some(self.max.map_or(number, |max| max.max(number)));
+        }
+    }
+
+    /// count() counts the events of a group, count(field) counts the distinct values of the field.
+    /// min, max, avg and sum use the numeric values of the field, and return None when the group has no numeric values.
+    fn aggregate(&self, aggregation: &Aggregation) -> Option<f64> {
+        if aggregation.function == AggregationFunction::Count {
+            return match aggregation.field {
+                Some(_) => Some(self.distinct.len() as f64),
+                None => Some(self.events as f64),
+            };
+        }
+        if self.numbers == 0 {
+            return None;
+        }
+
+        match aggregation.function {
+            AggregationFunction::Min => self.min,
+            AggregationFunction::Max => self.max,
+            AggregationFunction::Sum => Some(self.sum),
+            _ => Some(self.sum / self.numbers as f64),
+        }
+    }
+}
+
+fn numeric_value(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(value) => value.as_f64(),
+        Value::String(value) => value.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::detection_parsers::condition::aggregation_parser::parse_aggregation;
+    use super::*;
+
+    fn state(aggregation: &str, timeframe: Option<u64>) -> AggregationState {
+        let mut aggregation = parse_aggregation(aggregation).unwrap();
+        aggregation.timeframe = timeframe.map(Duration::from_secs);
+        AggregationState::new(aggregation)
+    }
+
+    fn at(seconds: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn count_by_group_within_timeframe() {
+        let mut state = state("| count() by src_ip > 2", Some(60));
+
+        assert!(!state.evaluate(&LogEvent::from(json!({"src_ip": "10.0.0.1"})), at(0)));
+        assert!(!state.evaluate(&LogEvent::from(json!({"src_ip": "10.0.0.1"})), at(10)));
+        assert!(!state.evaluate(&LogEvent::from(json!({"src_ip": "10.0.0.2"})), at(20)));
+        assert!(state.evaluate(&LogEvent::from(json!({"src_ip": "10.0.0.1"})), at(30)));
+
+        // the first two events fell out of the window
+        assert!(!state.evaluate(&LogEvent::from(json!({"src_ip": "10.0.0.1"})), at(75)));
+    }
+
+    #[test]
+    fn count_distinct_values() {
+        let mut state = state("| count(user) by src_ip > 2", Some(300));
+
+        assert!(!state.evaluate(&LogEvent::from(json!({"src_ip": "10.0.0.1", "user": "admin"})), at(0)));
+        assert!(!state.evaluate(&LogEvent::from(json!({"src_ip": "10.0.0.1", "user": "admin"})), at(1)));
+        assert!(!state.evaluate(&LogEvent::from(json!({"src_ip": "10.0.0.1", "user": "root"})), at(2)));
+        assert!(!state.evaluate(&LogEvent::from(json!({"src_ip": "10.0.0.1"})), at(3)));
+        assert!(state.evaluate(&LogEvent::from(json!({"src_ip": "10.0.0.1", "user": "guest"})), at(4)));
+    }
+
+    #[test]
+    fn numeric_aggregations() {
+        let mut sum = state("| sum(bytes) by host > 100", None);
+        assert!(!sum.evaluate(&LogEvent::from(json!({"host": "a", "bytes": 60})), at(0)));
+        assert!(sum.evaluate(&LogEvent::from(json!({"host": "a", "bytes": "50"})), at(1)));
+        assert!(!sum.evaluate(&LogEvent::from(json!({"host": "b", "bytes": 50})), at(2)));
+
+        let mut avg = state("| avg(duration) < 1", None);
+        assert!(avg.evaluate(&LogEvent::from(json!({"duration": 0.5})), at(0)));
+        assert!(!avg.evaluate(&LogEvent::from(json!({"duration": 2.5})), at(1)));
+
+        let mut min = state("| min(port) <= 22", None);
+        assert!(!min.evaluate(&LogEvent::from(json!({"port": 443})), at(0)));
+        assert!(!min.evaluate(&LogEvent::from(json!({"other": 1})), at(1)));
+        assert!(min.evaluate(&LogEvent::from(json!({"port": 22})), at(2)));
+
+        let mut max = state("| max(port) > 1024", None);
+        assert!(max.evaluate(&LogEvent::from(json!({"port": 4444})), at(0)));
+    }
+
+    #[test]
+    fn expire_removes_stale_groups() {
+        let mut state = state("| count() by src_ip > 10", Some(60));
+        state.evaluate(&LogEvent::from(json!({"src_ip": "10.0.0.1"})), at(0));
+        state.evaluate(&LogEvent::from(json!({"src_ip": "10.0.0.2"})), at(50));
+        assert_eq!(state.group_count(), 2);
+
+        state.expire(at(100));
+        assert_eq!(state.group_count(), 1);
+    }
+
+    #[test]
+    fn keep_totals_without_timeframe() {
+        let mut state = state("| count(user) by src_ip > 1", None);
+        for seconds in 0..1000 {
+            state.evaluate(&LogEvent::from(json!({"src_ip": "10.0.0.1", "user": "admin"})), at(seconds));
+        }
+        assert!(state.evaluate(&LogEvent::from(json!({"src_ip": "10.0.0.1", "user": "root"})), at(1000)));
+        match &state.groups[&Some("10.0.0.1".to_string())] {
+            Group::Totals(totals) => assert_eq!((totals.events, totals.distinct.len()), (1001, 2)),
+            group => panic!("unexpected group {:?}", group),
+        }
+
+        // groups without a timeframe never expire
+        state.expire(at(100_000));
+        assert_eq!(state.group_count(), 1);
+    }
+}
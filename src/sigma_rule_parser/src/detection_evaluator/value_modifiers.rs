@@ -0,0 +1,326 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Mutex, OnceLock};
+use ipnet::IpNet;
+use regex::Regex;
+use serde_json::Value;
+use crate::detection_evaluator::value_pattern::ValuePattern;
+use crate::structs::detection_field::Modifier;
+
+/// How a value of the Sigma rule is compared to the value of a field, as determined by the modifiers of the field.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Comparison {
+    Equals,
+    Contains,
+    StartsWith,
+    EndsWith,
+    Re,
+    Cidr,
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+    Exists,
+}
+
+impl Comparison {
+    /// The last comparison modifier of the chain wins. base64offset can only be found within a string, so it implies contains.
+    pub fn from_modifiers(modifiers: &[Modifier]) -> Comparison {
+        modifiers.iter().fold(Comparison::Equals, |comparison, modifier| match modifier {
+            Modifier::Contains | Modifier::Base64Offset => Comparison::Contains,
+            Modifier::StartsWith => Comparison::StartsWith,
+            Modifier::EndsWith => Comparison::EndsWith,
+            Modifier::Re => Comparison::Re,
+            Modifier::Cidr => Comparison::Cidr,
+            Modifier::Lt => Comparison::Lt,
+            Modifier::Lte => Comparison::Lte,
+            Modifier::Gt => Comparison::Gt,
+            Modifier::Gte => Comparison::Gte,
+            Modifier::Exists => Comparison::Exists,
+            _ => comparison,
+        })
+    }
+}
+
+/// Compares a value of the Sigma rule to the value of a field in the Log, applying the modifiers of the field.
+/// A field that holds a list matches when any of its items match. A field that is missing or null only matches exists, see DetectionLogic for null values.
+pub fn field_matches(field_value: Option<&Value>, modifiers: &[Modifier], value: &str) -> bool {
+    let comparison = Comparison::from_modifiers(modifiers);
+
+    if comparison == Comparison::Exists {
+        let exists = !matches!(field_value, None | Some(Value::Null));
+        return exists == value.eq_ignore_ascii_case("true");
+    }
+
+    let field_value = match field_value {
+        None | Some(Value::Null) => return false,
+        Some(Value::Array(field_values)) => {
+            return field_values.iter().any(|field_value| field_matches(Some(field_value), modifiers, value))
+        }
+        Some(Value::Object(_)) => return false,
+        Some(Value::String(field_value)) => field_value.to_string(),
+        Some(field_value) => field_value.to_string(),
+    };
+
+    match comparison {
+        Comparison::Re => cached_regex(value).is_some_and(|regex| regex.is_match(&field_value)),
+        Comparison::Cidr => match (value.parse::<IpNet>(), field_value.parse::<IpAddr>()) {
+            (Ok(network), Ok(address)) => network.contains(&address),
+            _ => false,
+        },
+        Comparison::Lt | Comparison::Lte | Comparison::Gt | Comparison::Gte => {
+            match (field_value.trim().parse::<f64>(), value.trim().parse::<f64>()) {
+                (Ok(field_value), Ok(value)) => match comparison {
+                    Comparison::Lt => field_value < value,
+                    Comparison::Lte => field_value <= value,
+                    Comparison::Gt => field_value > value,
+                    _ => field_value >= value,
+                },
+                _ => false,
+            }
+        }
+        _ => {
+            let case_sensitive = case_sensitive(modifiers);
+            let field_value = if case_sensitive { field_value } else { field_value.to_lowercase() };
+
+            value_patterns(value, modifiers).unwrap_or_default().iter().any(|pattern| {
+                let pattern = if case_sensitive { pattern.clone() } else { pattern.to_lowercase() };
+                pattern.matches(&field_value, &comparison)
+            })
+        }
+    }
+}
+
+/// Sigma compares values case-insensitively, unless the cased modifier is used or the value is encoded with base64.
+pub fn case_sensitive(modifiers: &[Modifier]) -> bool {
+    modifiers.iter().any(|modifier| matches!(modifier, Modifier::Cased | Modifier::Base64 | Modifier::Base64Offset))
+}
+
+/// The patterns that a plain value of the Sigma rule is compared with, one for every variant of the transformation modifiers.
+/// The wildcards and escapes are read before the value is encoded, and a wildcard can not be encoded, so such a value is an error.
+pub fn value_patterns(value: &str, modifiers: &[Modifier]) -> Result<Vec<ValuePattern>, String> {
+    let encoded = modifiers.iter().any(|modifier| {
+        matches!(modifier, Modifier::Utf16le | Modifier::Utf16be | Modifier::Utf16 | Modifier::Wide | Modifier::Base64 | Modifier::Base64Offset)
+    });
+    if !encoded {
+        return Ok(transform_value(value, modifiers).iter().map(|value| ValuePattern::parse(value)).collect());
+    }
+
+    let value = ValuePattern::parse(value).as_literal().ok_or_else(|| "wildcards can not be encoded".to_string())?;
+    Ok(transform_value(&value, modifiers).iter().map(|value| ValuePattern::literal(value)).collect())
+}
+
+/// The regular expressions of the values of the Sigma rules, so that the interpreter compiles each of them once instead of for every event.
+/// Only values of the rules are compiled, never values of the log events, so the cache is as large as the rules.
+/// A value that is not a valid regular expression is cached as None, as it never matches.
+pub(crate) fn cached_regex(pattern: &str) -> Option<Regex> {
+    static REGEXES: OnceLock<Mutex<HashMap<String, Option<Regex>>>> = OnceLock::new();
+
+    let mut regexes = REGEXES.get_or_init(Default::default).lock().unwrap_or_else(|err| err.into_inner());
+    if let Some(regex) = regexes.get(pattern) {
+        return regex.clone();
+    }
+    let regex = Regex::new(pattern).ok();
+    regexes.insert(pattern.to_string(), regex.clone());
+    regex
+}
+
+/// Applies the transformation modifiers to a value of the Sigma rule, in the order they were written.
+/// Some modifiers (windash, base64offset) produce multiple variants of the value, any of which may match.
+pub fn transform_value(value: &str, modifiers: &[Modifier]) -> Vec<String> {
+    let mut values: Vec<Vec<u8>> = vec![value.as_bytes().to_vec()];
+
+    for modifier in modifiers {
+        values = match modifier {
+            Modifier::Windash => values.iter().flat_map(|value| windash(&String::from_utf8_lossy(value))).map(String::into_bytes).collect(),
+            Modifier::Utf16le | Modifier::Wide => values.iter().map(|value| utf16(value, u16::to_le_bytes)).collect(),
+            Modifier::Utf16be => values.iter().map(|value| utf16(value, u16::to_be_bytes)).collect(),
+            Modifier::Utf16 => values.iter().map(|value| [vec![0xff, 0xfe], utf16(value, u16::to_le_bytes)].concat()).collect(),
+            Modifier::Base64 => values.iter().map(|value| base64::encode(value).into_bytes()).collect(),
+            Modifier::Base64Offset => values.iter().flat_map(|value| base64_offset(value)).map(String::into_bytes).collect(),
+            _ => values,
+        };
+    }
+
+    values.iter().map(|value| String::from_utf8_lossy(value).to_string()).collect()
+}
+
+fn utf16(value: &[u8], to_bytes: fn(u16) -> [u8; 2]) -> Vec<u8> {
+    String::from_utf8_lossy(value).encode_utf16().flat_map(to_bytes).collect()
+}
+
+/// Windows command line flags may start with a dash or a slash, i.e. `-exec` or `/exec`.
+/// Returns the original value, and the value with every flag dash replaced by a slash or one of the unicode dashes.
+fn windash(value: &str) -> Vec<String> {
+    let mut values = vec![value.to_string()];
+
+    for dash in ['/', '\u{2013}', '\u{2014}', '\u{2015}'] {
+        let mut previous = ' ';
+        let replaced = value
+            .chars()
+            .map(|ch| {
+                let replaced = if ch == '-' && previous.is_whitespace() { dash } else { ch };
+                previous = ch;
+                replaced
+            })
+            .collect::<String>();
+
+        if !values.contains(&replaced) {
+            values.push(replaced);
+        }
+    }
+
+    values
+}
+
+/// The value may start at any of the three byte offsets of a base64 encoded string.
+/// Each variant removes the leading and trailing characters that depend on the surrounding data.
+fn base64_offset(value: &[u8]) -> Vec<String> {
+    let start_offsets = [0, 2, 3];
+    let end_offsets = [0, 3, 2];
+
+    (0..3)
+        .filter_map(|offset| {
+            let encoded = base64::encode([vec![b' '; offset], value.to_vec()].concat());
+            let end = encoded.len().checked_sub(end_offsets[(value.len() + offset) % 3])?;
+            encoded.get(start_offsets[offset]..end).map(str::to_string)
+        })
+        .filter(|encoded| !encoded.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    #[test]
+    fn string_comparisons_are_case_insensitive() {
+        let value = json!("C:\\Windows\\System32\\LSASS.exe");
+
+        assert!(field_matches(Some(&value), &[], "c:\\windows\\system32\\lsass.exe"));
+        assert!(field_matches(Some(&value), &[Modifier::Contains], "system32"));
+        assert!(field_matches(Some(&value), &[Modifier::StartsWith], "c:\\windows"));
+        assert!(field_matches(Some(&value), &[Modifier::EndsWith], "\\lsass.exe"));
+        assert!(!field_matches(Some(&value), &[Modifier::EndsWith], "\\lsass.dll"));
+    }
+
+    #[test]
+    fn wildcards_and_cased_values() {
+        let value = json!("C:\\Windows\\System32\\LSASS.exe");
+
+        assert!(field_matches(Some(&value), &[], "c:\\windows\\\\*\\lsass.exe"));
+        assert!(field_matches(Some(&value), &[Modifier::EndsWith], "\\lsas?.exe"));
+        assert!(!field_matches(Some(&value), &[], "c:\\windows\\\\*\\lsass"));
+        assert!(field_matches(Some(&value), &[Modifier::Cased, Modifier::EndsWith], "\\LSASS.exe"));
+        assert!(!field_matches(Some(&value), &[Modifier::Cased, Modifier::EndsWith], "\\lsass.exe"));
+        assert!(field_matches(Some(&value), &[Modifier::Cased], "C:\\\\*\\LSASS.*"));
+        assert!(field_matches(Some(&json!("what?")), &[], "What\\?"));
+        assert!(!field_matches(Some(&json!("whats")), &[], "What\\?"));
+    }
+
+    #[test]
+    fn encoded_values_are_unescaped() {
+        assert_eq!(value_patterns("a\\*b", &[Modifier::Base64]).unwrap(), vec![ValuePattern::literal("YSpi")]);
+        assert_eq!(value_patterns("a*b", &[Modifier::Base64]).unwrap_err(), "wildcards can not be encoded");
+        assert_eq!(value_patterns("a*b", &[Modifier::Windash]).unwrap(), vec![ValuePattern::parse("a*b")]);
+        assert!(!field_matches(Some(&json!("YSpi")), &[Modifier::Base64], "a*b"));
+    }
+
+    #[test]
+    fn numbers_and_lists() {
+        assert!(field_matches(Some(&json!(4625)), &[], "4625"));
+        assert!(field_matches(Some(&json!(["a.exe", "b.exe"])), &[Modifier::StartsWith], "b"));
+        assert!(!field_matches(Some(&json!({"nested": "b.exe"})), &[], "b.exe"));
+    }
+
+    #[test]
+    fn regular_expressions() {
+        let value = json!("powershell.exe -enc SQBFAFgA");
+
+        assert!(field_matches(Some(&value), &[Modifier::Re], r"-enc\s+[A-Za-z0-9+/=]+"));
+        assert!(!field_matches(Some(&value), &[Modifier::Re], "POWERSHELL"));
+        assert!(!field_matches(Some(&value), &[Modifier::Re], "(unclosed"));
+        // the second comparison reads the regex that the first one cached
+        assert!(!field_matches(Some(&value), &[Modifier::Re], "(unclosed"));
+
+        assert!(cached_regex(r"-enc\s+[A-Za-z0-9+/=]+").is_some());
+        assert!(cached_regex("(unclosed").is_none());
+    }
+
+    #[test]
+    fn cidr_ranges() {
+        assert!(field_matches(Some(&json!("10.1.2.3")), &[Modifier::Cidr], "10.0.0.0/8"));
+        assert!(!field_matches(Some(&json!("192.168.1.1")), &[Modifier::Cidr], "10.0.0.0/8"));
+        assert!(field_matches(Some(&json!("fe80::1")), &[Modifier::Cidr], "fe80::/10"));
+        assert!(!field_matches(Some(&json!("not an ip")), &[Modifier::Cidr], "10.0.0.0/8"));
+    }
+
+    #[test]
+    fn numeric_comparisons() {
+        assert!(field_matches(Some(&json!(5)), &[Modifier::Lt], "10"));
+        assert!(field_matches(Some(&json!("10")), &[Modifier::Lte], "10"));
+        assert!(field_matches(Some(&json!(10.5)), &[Modifier::Gt], "10"));
+        assert!(!field_matches(Some(&json!(9)), &[Modifier::Gte], "10"));
+        assert!(!field_matches(Some(&json!("abc")), &[Modifier::Gt], "10"));
+    }
+
+    #[test]
+    fn exists() {
+        assert!(field_matches(Some(&json!("value")), &[Modifier::Exists], "true"));
+        assert!(field_matches(None, &[Modifier::Exists], "false"));
+        assert!(field_matches(Some(&Value::Null), &[Modifier::Exists], "false"));
+        assert!(!field_matches(None, &[Modifier::Exists], "true"));
+    }
+
+    #[test]
+    fn missing_fields() {
+        assert!(!field_matches(None, &[], ""));
+        assert!(!field_matches(Some(&Value::Null), &[], ""));
+        assert!(field_matches(Some(&json!("")), &[], ""));
+        assert!(!field_matches(None, &[], "value"));
+        assert!(!field_matches(None, &[Modifier::Contains], ""));
+    }
+
+    #[test]
+    fn base64_values() {
+        assert_eq!(transform_value("/bin/bash", &[Modifier::Base64]), vec!["L2Jpbi9iYXNo".to_string()]);
+        assert_eq!(
+            transform_value("/bin/bash", &[Modifier::Base64Offset]),
+            vec!["L2Jpbi9iYXNo".to_string(), "9iaW4vYmFza".to_string(), "vYmluL2Jhc2".to_string()]
+        );
+        assert_eq!(
+            transform_value("/bin/sh", &[Modifier::Base64Offset]),
+            vec!["L2Jpbi9za".to_string(), "9iaW4vc2".to_string(), "vYmluL3No".to_string()]
+        );
+
+        let value = json!("echo ZWNobyAvYmluL2Jhc2ggLWM= | base64 -d");
+        assert!(field_matches(Some(&value), &[Modifier::Base64Offset, Modifier::Contains], "/bin/bash"));
+        assert!(!field_matches(Some(&json!("l2jpbi9iyxno")), &[Modifier::Base64Offset, Modifier::Contains], "/bin/bash"));
+    }
+
+    #[test]
+    fn utf16_base64_values() {
+        assert_eq!(transform_value("whoami", &[Modifier::Utf16le, Modifier::Base64]), vec!["dwBoAG8AYQBtAGkA".to_string()]);
+        assert_eq!(transform_value("whoami", &[Modifier::Wide, Modifier::Base64]), vec!["dwBoAG8AYQBtAGkA".to_string()]);
+        assert_eq!(transform_value("a", &[Modifier::Utf16be, Modifier::Base64]), vec!["AGE=".to_string()]);
+        assert_eq!(transform_value("a", &[Modifier::Utf16, Modifier::Base64]), vec!["//5hAA==".to_string()]);
+        assert_eq!(
+            transform_value("ping", &[Modifier::Utf16le, Modifier::Base64Offset]),
+            vec!["cABpAG4AZw".to_string(), "AAaQBuAGcA".to_string(), "wAGkAbgBnA".to_string()]
+        );
+
+        let value = json!("powershell.exe -EncodedCommand dwBoAG8AYQBtAGkA");
+        assert!(field_matches(Some(&value), &[Modifier::Utf16le, Modifier::Base64Offset, Modifier::Contains], "whoami"));
+    }
+
+    #[test]
+    fn windash_values() {
+        assert_eq!(
+            transform_value(" -exec bypass", &[Modifier::Windash]),
+            vec![" -exec bypass", " /exec bypass", " \u{2013}exec bypass", " \u{2014}exec bypass", " \u{2015}exec bypass"]
+        );
+        assert!(field_matches(Some(&json!("powershell.exe /ep bypass")), &[Modifier::Windash, Modifier::Contains], " -ep bypass"));
+        assert!(field_matches(Some(&json!("net-tool /x")), &[Modifier::Windash, Modifier::Contains], "net-tool -x"));
+    }
+}
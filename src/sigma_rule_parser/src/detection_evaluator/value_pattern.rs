@@ -0,0 +1,225 @@
+use regex::Regex;
+use crate::detection_evaluator::value_modifiers::{cached_regex, Comparison};
+
+/// A plain value of a Sigma rule, in which `*` matches any number of characters and `?` matches a single character.
+/// A backslash escapes a wildcard or another backslash (`\*`, `\?`, `\\`), any other backslash is kept as it is,
+///     so `C:\Windows\` needs no escaping while `C:\Windows\\*` is the folder followed by a wildcard.
+/// https://github.com/SigmaHQ/sigma-specification/blob/main/specification/sigma-rules-specification.md#escaping
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ValuePattern {
+    pub tokens: Vec<Token>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Token {
+    /// Unescaped text that is compared as it is
+    Literal(String),
+    /// `*`
+    AnyChars,
+    /// `?`
+    AnyChar,
+}
+
+impl ValuePattern {
+    pub fn parse(value: &str) -> ValuePattern {
+        let mut tokens = Vec::new();
+        let mut literal = String::new();
+        let mut chars = value.chars().peekable();
+
+        while let Some(ch) = chars.next() {
+            let token = match ch {
+                '\\' => {
+                    match chars.next_if(|next| matches!(next, '*' | '?' | '\\')) {
+                        Some(escaped) => literal.push(escaped),
+                        None => literal.push('\\'),
+                    }
+                    continue;
+                }
+                '*' => Token::AnyChars,
+                '?' => Token::AnyChar,
+                ch => {
+                    literal.push(ch);
+                    continue;
+                }
+            };
+
+            if !literal.is_empty() {
+                tokens.push(Token::Literal(std::mem::take(&mut literal)));
+            }
+            // consecutive `*` match the same as a single one
+            if !(token == Token::AnyChars && tokens.last() == Some(&Token::AnyChars)) {
+                tokens.push(token);
+            }
+        }
+        if !literal.is_empty() {
+            tokens.push(Token::Literal(literal));
+        }
+
+        ValuePattern { tokens }
+    }
+
+    /// A value without wildcards, i.e. a value that was encoded by the modifiers.
+    pub fn literal(value: &str) -> ValuePattern {
+        let tokens = if value.is_empty() { Vec::new() } else { vec![Token::Literal(value.to_string())] };
+        ValuePattern { tokens }
+    }
+
+    /// The unescaped value, or None when it has wildcards.
+    pub fn as_literal(&self) -> Option<String> {
+        self.tokens
+            .iter()
+            .map(|token| match token {
+                Token::Literal(literal) => Some(literal.as_str()),
+                Token::AnyChars | Token::AnyChar => None,
+            })
+            .collect()
+    }
+
+    /// The longest text that every match contains, or None when the pattern is only wildcards.
+    pub fn longest_literal(&self) -> Option<&str> {
+        self.tokens
+            .iter()
+            .filter_map(|token| match token {
+                Token::Literal(literal) => Some(literal.as_str()),
+                Token::AnyChars | Token::AnyChar => None,
+            })
+            .max_by_key(|literal| literal.chars().count())
+    }
+
+    pub fn to_lowercase(&self) -> ValuePattern {
+        let tokens = self
+            .tokens
+            .iter()
+            .map(|token| match token {
+                Token::Literal(literal) => Token::Literal(literal.to_lowercase()),
+                token => token.clone(),
+            })
+            .collect();
+        ValuePattern { tokens }
+    }
+
+    /// The regular expression of the pattern, anchored at the sides that the comparison requires. Wildcards match line breaks too.
+    pub fn to_regex(&self, comparison: &Comparison) -> Result<Regex, regex::Error> {
+        Regex::new(&self.regex_pattern(comparison))
+    }
+
+    fn regex_pattern(&self, comparison: &Comparison) -> String {
+        let mut regex = String::from("(?s)");
+        if !matches!(comparison, Comparison::Contains | Comparison::EndsWith) {
+            regex.push('^');
+        }
+        for token in &self.tokens {
+            match token {
+                Token::Literal(literal) => regex.push_str(&regex::escape(literal)),
+                Token::AnyChars => regex.push_str(".*"),
+                Token::AnyChar => regex.push('.'),
+            }
+        }
+        if !matches!(comparison, Comparison::Contains | Comparison::StartsWith) {
+            regex.push('$');
+        }
+
+        regex
+    }
+
+    /// Compares the pattern to a value with an equals, contains, startswith or endswith comparison.
+    /// Values without wildcards are compared as strings, the others by their regular expression, which is compiled once (see cached_regex).
+    pub fn matches(&self, value: &str, comparison: &Comparison) -> bool {
+        let literal = match self.as_literal() {
+            Some(literal) => literal,
+            None => return cached_regex(&self.regex_pattern(comparison)).is_some_and(|regex| regex.is_match(value)),
+        };
+
+        match comparison {
+            Comparison::Contains => value.contains(&literal),
+            Comparison::StartsWith => value.starts_with(&literal),
+            Comparison::EndsWith => value.ends_with(&literal),
+            _ => value == literal,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn literal(value: &str) -> Token {
+        Token::Literal(value.to_string())
+    }
+
+    #[test]
+    fn parse_wildcards() {
+        assert_eq!(ValuePattern::parse("").tokens, vec![]);
+        assert_eq!(ValuePattern::parse("whoami").tokens, vec![literal("whoami")]);
+        assert_eq!(ValuePattern::parse("*\\cmd.exe").tokens, vec![Token::AnyChars, literal("\\cmd.exe")]);
+        assert_eq!(ValuePattern::parse("net?.exe").tokens, vec![literal("net"), Token::AnyChar, literal(".exe")]);
+        assert_eq!(ValuePattern::parse("a**b").tokens, vec![literal("a"), Token::AnyChars, literal("b")]);
+        assert_eq!(ValuePattern::parse("a*?b").tokens, vec![literal("a"), Token::AnyChars, Token::AnyChar, literal("b")]);
+    }
+
+    /// The escaping examples of the Sigma specification.
+    #[test]
+    fn parse_escapes() {
+        // a backslash that does not precede a wildcard or a backslash is plain text
+        assert_eq!(ValuePattern::parse("C:\\Windows\\").tokens, vec![literal("C:\\Windows\\")]);
+        assert_eq!(ValuePattern::parse("\\").tokens, vec![literal("\\")]);
+        // escaped wildcards
+        assert_eq!(ValuePattern::parse("\\*").tokens, vec![literal("*")]);
+        assert_eq!(ValuePattern::parse("what\\?").tokens, vec![literal("what?")]);
+        // an escaped backslash before a wildcard
+        assert_eq!(ValuePattern::parse("C:\\\\*").tokens, vec![literal("C:\\"), Token::AnyChars]);
+        // an escaped backslash before an escaped wildcard
+        assert_eq!(ValuePattern::parse("\\\\\\*").tokens, vec![literal("\\*")]);
+        // an escaped backslash is a single backslash
+        assert_eq!(ValuePattern::parse("\\\\\\\\server").tokens, vec![literal("\\\\server")]);
+        assert_eq!(ValuePattern::parse("\\\\share").tokens, vec![literal("\\share")]);
+        // three backslashes are an escaped backslash and a plain one
+        assert_eq!(ValuePattern::parse("\\\\\\share").tokens, vec![literal("\\\\share")]);
+    }
+
+    #[test]
+    fn literals() {
+        assert_eq!(ValuePattern::parse("a\\*b").as_literal(), Some("a*b".to_string()));
+        assert_eq!(ValuePattern::parse("a*b").as_literal(), None);
+        assert_eq!(ValuePattern::parse("").as_literal(), Some(String::new()));
+        assert_eq!(ValuePattern::parse("*\\temp\\\\*.ps1").longest_literal(), Some("\\temp\\"));
+        assert_eq!(ValuePattern::parse("*?*").longest_literal(), None);
+        assert_eq!(ValuePattern::literal("*").tokens, vec![literal("*")]);
+    }
+
+    #[test]
+    fn match_wildcards() {
+        let pattern = ValuePattern::parse("c:\\windows\\\\*\\cmd.exe");
+        assert!(pattern.matches("c:\\windows\\system32\\cmd.exe", &Comparison::Equals));
+        assert!(pattern.matches("c:\\windows\\\\cmd.exe", &Comparison::Equals));
+        assert!(!pattern.matches("d:\\c:\\windows\\system32\\cmd.exe", &Comparison::Equals));
+        assert!(pattern.matches("d:\\c:\\windows\\system32\\cmd.exe", &Comparison::EndsWith));
+        assert!(!pattern.matches("c:\\windows\\system32\\cmd.exe /c", &Comparison::EndsWith));
+        assert!(pattern.matches("c:\\windows\\system32\\cmd.exe /c", &Comparison::StartsWith));
+        assert!(pattern.matches("run c:\\windows\\system32\\cmd.exe /c", &Comparison::Contains));
+
+        let pattern = ValuePattern::parse("net?.exe");
+        assert!(pattern.matches("net1.exe", &Comparison::Equals));
+        assert!(pattern.matches("netü.exe", &Comparison::Equals));
+        assert!(!pattern.matches("net.exe", &Comparison::Equals));
+        assert!(!pattern.matches("net12.exe", &Comparison::Equals));
+
+        assert!(ValuePattern::parse("*").matches("", &Comparison::Equals));
+        assert!(ValuePattern::parse("a*b").matches("a\nb", &Comparison::Equals));
+        // regular expression syntax in values is plain text
+        assert!(ValuePattern::parse("(a|b).+").matches("(a|b).+", &Comparison::Equals));
+        assert!(!ValuePattern::parse("(a|b).+").matches("aa", &Comparison::Equals));
+    }
+
+    #[test]
+    fn match_escaped_wildcards() {
+        assert!(ValuePattern::parse("what\\?").matches("what?", &Comparison::Equals));
+        assert!(!ValuePattern::parse("what\\?").matches("whats", &Comparison::Equals));
+        assert!(ValuePattern::parse("\\*").matches("*", &Comparison::Equals));
+        assert!(!ValuePattern::parse("\\*").matches("anything", &Comparison::Equals));
+        assert!(ValuePattern::parse("c:\\\\*").matches("c:\\windows", &Comparison::Equals));
+        assert!(!ValuePattern::parse("c:\\\\*").matches("c:windows", &Comparison::Equals));
+        assert!(ValuePattern::parse("\\\\\\*").matches("\\*", &Comparison::Equals));
+        assert!(!ValuePattern::parse("\\\\\\*").matches("\\anything", &Comparison::Equals));
+    }
+}
@@ -4,7 +4,9 @@ pub mod not_parser;
 pub mod and_parser;
 pub mod or_parser;
 pub mod search_id_parser;
+pub mod quantifier_parser;
 pub mod sub_parsers;
 pub mod parser_output;
 mod take_until_unbalanced;
 pub mod atomic_parsers;
+pub mod aggregation_parser;
@@ -0,0 +1,159 @@
+use std::time::Duration;
+use nom::branch::alt;
+use nom::bytes::complete::{tag, tag_no_case, take_while1};
+use nom::character::complete::{digit1, multispace0, multispace1, one_of};
+use nom::combinator::{all_consuming, map_res, opt, value};
+use nom::number::complete::double;
+use nom::sequence::{delimited, preceded, terminated, tuple};
+use nom::IResult;
+
+use crate::detection_parsers::condition::atomic_parsers::pipe;
+use crate::rule_error::RuleError;
+use crate::structs::aggregation::{Aggregation, AggregationFunction, ComparisonOperator};
+
+/// Parses the aggregation that follows the pipe of a condition: `| count(field) by group > 10`
+/// The field of count() is optional, the other functions (min, max, avg, sum) require a field.
+/// The "near" aggregation of Sigma is not supported and returns RuleError::UnsupportedAggregation.
+pub fn parse_aggregation(aggregation: &str) -> Result<Aggregation, RuleError> {
+    match all_consuming(terminated(aggregation_parser, multispace0))(aggregation) {
+        Ok((_, aggregation)) => Ok(aggregation),
+        Err(_) => Err(RuleError::UnsupportedAggregation(aggregation.trim().to_string())),
+    }
+}
+
+pub fn aggregation_parser(input: &str) -> IResult<&str, Aggregation> {
+    let (remaining, (function, field)) = preceded(pipe, preceded(multispace0, function))(input)?;
+    let (remaining, group_by) = opt(preceded(tuple((multispace1, tag_no_case("by"), multispace1)), field_name))(remaining)?;
+    let (remaining, (operator, threshold)) = tuple((preceded(multispace0, comparison_operator), preceded(multispace0, double)))(remaining)?;
+
+    if function != AggregationFunction::Count && field.is_none() {
+        return Err(nom::Err::Failure(nom::error::Error::new(input, nom::error::ErrorKind::Verify)));
+    }
+
+    let aggregation = Aggregation {
+        function,
+        field: field.map(str::to_string),
+        group_by: group_by.map(str::to_string),
+        operator,
+        threshold,
+        timeframe: None,
+    };
+
+    Ok((remaining, aggregation))
+}
+
+/// Parses the timeframe of a Sigma rule, i.e. 30s, 5m, 24h or 7d
+pub fn parse_timeframe(timeframe: &str) -> Result<Duration, RuleError> {
+    match all_consuming(timeframe_parser)(timeframe.trim()) {
+        Ok((_, (amount, unit))) => {
+            let seconds = match unit.to_ascii_lowercase() {
+                's' => 1,
+                'm' => 60,
+                'h' => 60 * 60,
+                _ => 24 * 60 * 60,
+            };
+            Ok(Duration::from_secs(amount * seconds))
+        }
+        Err(_) => Err(RuleError::InvalidTimeframe(timeframe.to_string())),
+    }
+}
+
+fn timeframe_parser(input: &str) -> IResult<&str, (u64, char)> {
+    tuple((map_res(digit1, str::parse::<u64>), one_of("smhdSMHD")))(input)
+}
+
+fn function(input: &str) -> IResult<&str, (AggregationFunction, Option<&str>)> {
+    let function_name = alt((
+        value(AggregationFunction::Count, tag_no_case("count")),
+        value(AggregationFunction::Min, tag_no_case("min")),
+        value(AggregationFunction::Max, tag_no_case("max")),
+        value(AggregationFunction::Avg, tag_no_case("avg")),
+        value(AggregationFunction::Sum, tag_no_case("sum")),
+    ));
+    let function_field = delimited(
+        tuple((multispace0, tag("("), multispace0)),
+        opt(field_name),
+        tuple((multispace0, tag(")"))),
+    );
+
+    tuple((function_name, function_field))(input)
+}
+
+fn field_name(input: &str) -> IResult<&str, &str> {
+    take_while1(|ch: char| ch.is_alphanumeric() || "_.-@".contains(ch))(input)
+}
+
+fn comparison_operator(input: &str) -> IResult<&str, ComparisonOperator> {
+    alt((
+        value(ComparisonOperator::Lte, tag("<=")),
+        value(ComparisonOperator::Gte, tag(">=")),
+        value(ComparisonOperator::Eq, tag("==")),
+        value(ComparisonOperator::Lt, tag("<")),
+        value(ComparisonOperator::Gt, tag(">")),
+        value(ComparisonOperator::Eq, tag("=")),
+    ))(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn count_by_field() {
+        let aggregation = parse_aggregation("| count() by src_ip > 50").unwrap();
+        assert_eq!(
+            aggregation,
+            Aggregation {
+                function: AggregationFunction::Count,
+                field: None,
+                group_by: Some("src_ip".to_string()),
+                operator: ComparisonOperator::Gt,
+                threshold: 50.0,
+                timeframe: None,
+            }
+        );
+    }
+
+    #[test]
+    fn count_distinct_field_by_field() {
+        let aggregation = parse_aggregation(" | count(TargetUserName) by WorkstationName > 3    ").unwrap();
+        assert_eq!(aggregation.field, Some("TargetUserName".to_string()));
+        assert_eq!(aggregation.group_by, Some("WorkstationName".to_string()));
+        assert_eq!(aggregation.threshold, 3.0);
+    }
+
+    #[test]
+    fn functions_and_operators() {
+        let aggregation = parse_aggregation("| SUM(message_size) by src_ip >= 300000").unwrap();
+        assert_eq!(aggregation.function, AggregationFunction::Sum);
+        assert_eq!(aggregation.operator, ComparisonOperator::Gte);
+
+        let aggregation = parse_aggregation("| avg(duration) < 0.5").unwrap();
+        assert_eq!(aggregation.function, AggregationFunction::Avg);
+        assert_eq!(aggregation.group_by, None);
+        assert_eq!(aggregation.operator, ComparisonOperator::Lt);
+        assert_eq!(aggregation.threshold, 0.5);
+
+        assert_eq!(parse_aggregation("| min(bytes) <= 10").unwrap().operator, ComparisonOperator::Lte);
+        assert_eq!(parse_aggregation("| max(bytes) = 10").unwrap().operator, ComparisonOperator::Eq);
+        assert_eq!(parse_aggregation("| count() == 10").unwrap().operator, ComparisonOperator::Eq);
+    }
+
+    #[test]
+    fn unsupported_aggregations() {
+        assert_eq!(parse_aggregation("| near selection2 "), Err(RuleError::UnsupportedAggregation("| near selection2".to_string())));
+        assert!(parse_aggregation("| sum() by src_ip > 10").is_err());
+        assert!(parse_aggregation("| count() by src_ip").is_err());
+        assert!(parse_aggregation("| count() > 10 and more").is_err());
+    }
+
+    #[test]
+    fn timeframes() {
+        assert_eq!(parse_timeframe("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_timeframe("10m"), Ok(Duration::from_secs(600)));
+        assert_eq!(parse_timeframe("24h"), Ok(Duration::from_secs(86_400)));
+        assert_eq!(parse_timeframe("7d"), Ok(Duration::from_secs(604_800)));
+        assert_eq!(parse_timeframe("1w"), Err(RuleError::InvalidTimeframe("1w".to_string())));
+        assert!(parse_timeframe("m").is_err());
+    }
+}
@@ -7,6 +7,7 @@ use nom::IResult;
 
 use crate::structs::detection_condition::{DetectionCondition, Operator};
 use crate::detection_parsers::condition::parser_output::ParserOutput;
+use crate::detection_parsers::condition::quantifier_parser::quantifier_parser;
 use crate::detection_parsers::condition::search_id_parser::search_identifiers_parser;
 use crate::structs::detection_metadata::{DetectionMetadata, ParserTypes};
 
@@ -18,26 +19,23 @@ pub fn and_parser(input: &str) -> IResult<&str, ParserOutput<DetectionCondition>
     let mut result_condition: String = String::from(result);
 
     let and_parser_result = downstream_and_parser(remaining.trim());
-    match and_parser_result {
-        Ok((_, parser_output)) => {
-            let downstream_parser_result = parser_output.metadata.parser_result.clone();
-            result_condition = format!("{}{}{}", result_condition, " ", downstream_parser_result);
-
-            let metadata = DetectionMetadata::new(
-                ParserTypes::And,
-                result_condition.clone(),
-                parser_output.metadata.search_identifiers.clone(),
-            );
-
-            condition = DetectionCondition::new(
-                metadata,
-                parser_output.is_negated.clone(),
-                Some(Operator::And),
-                parser_output.search_identifier.clone(),
-                parser_output.nested_detections.clone(),
-            );
-        }
-        Err(_) => {}
+    if let Ok((_, parser_output)) = and_parser_result {
+        let downstream_parser_result = parser_output.metadata.parser_result.clone();
+        result_condition = format!("{}{}{}", result_condition, " ", downstream_parser_result);
+
+        let metadata = DetectionMetadata::new(
+            ParserTypes::And,
+            result_condition.clone(),
+            parser_output.metadata.search_identifiers.clone(),
+        );
+
+        condition = DetectionCondition::new(
+            metadata,
+            parser_output.is_negated,
+            Some(Operator::And),
+            parser_output.search_identifier.clone(),
+            parser_output.nested_detections.clone(),
+        );
     }
 
     value(
@@ -53,7 +51,7 @@ pub fn and(input: &str) -> IResult<&str, &str> {
 }
 
 fn downstream_and_parser(input: &str) -> IResult<&str, ParserOutput<DetectionCondition>> {
-    alt((parens_parser, not_parser, search_identifiers_parser))(input)
+    alt((parens_parser, not_parser, quantifier_parser, search_identifiers_parser))(input)
 }
 
 
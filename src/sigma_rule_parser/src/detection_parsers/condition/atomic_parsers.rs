@@ -1,8 +1,7 @@
 use nom::bytes::complete::tag_no_case;
 use nom::IResult;
 
-
-/// This file is NOT in use, this is only the start for the remaining parsers to be supported.
+// Tags of the condition field that are shared between parsers, see quantifier_parser for "1 of" and "all of".
 
 pub fn one_of_them(input: &str) -> IResult<&str, &str> {
     tag_no_case("1 of them")(input.trim())
@@ -24,72 +23,59 @@ pub fn pipe(input: &str) -> IResult<&str, &str> {
     tag_no_case("|")(input.trim())
 }
 
-fn parser_str_builder(input: Option<Vec<String>>) -> String {
-    input.as_ref().unwrap().join(" ")
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
     use nom::error::ErrorKind::Tag;
     use nom::error::{Error, ParseError};
 
-    // Do this stuff eventually
-    ///////////////////////////////
-    ///////////////////////////////
-    ///////////////////////////////
-
-    // #[test]
-    // fn one_of_them_input() {
-    //     let parser_result = not(" not events ");
-    //     assert_eq!(parser_result, Ok((" events", "not")));
-    //
-    //     let parser_result = not(" and events ");
-    //     assert_eq!(
-    //         parser_result,
-    //         Err(nom::Err::Error(Error::from_error_kind("and events", Tag)))
-    //     );
-    // }
-    //
-    // #[test]
-    // fn all_of_them_input() {
-    //     let parser_result = not(" not events ");
-    //     assert_eq!(parser_result, Ok((" events", "not")));
-    //
-    //     let parser_result = not(" and events ");
-    //     assert_eq!(
-    //         parser_result,
-    //         Err(nom::Err::Error(Error::from_error_kind("and events", Tag)))
-    //     );
-    // }
-    //
-    // #[test]
-    // fn one_of_input() {
-    //     let parser_result = not(" not events ");
-    //     assert_eq!(parser_result, Ok((" events", "not")));
-    //
-    //     let parser_result = not(" and events ");
-    //     assert_eq!(
-    //         parser_result,
-    //         Err(nom::Err::Error(Error::from_error_kind("and events", Tag)))
-    //     );
-    // }
-    //
-    // #[test]
-    // fn all_of_input() {
-    //     let parser_result = not(" not events ");
-    //     assert_eq!(parser_result, Ok((" events", "not")));
-    //
-    //     let parser_result = not(" and events ");
-    //     assert_eq!(
-    //         parser_result,
-    //         Err(nom::Err::Error(Error::from_error_kind("and events", Tag)))
-    //     );
-    // }
-
-    ///////////////////////////////
-    ///////////////////////////////
-    ///////////////////////////////
+    #[test]
+    fn one_of_them_input() {
+        let parser_result = one_of_them(" 1 of them ");
+        assert_eq!(parser_result, Ok(("", "1 of them")));
+
+        let parser_result = one_of_them(" all of them ");
+        assert_eq!(
+            parser_result,
+            Err(nom::Err::Error(Error::from_error_kind("all of them", Tag)))
+        );
+    }
+
+    #[test]
+    fn all_of_them_input() {
+        let parser_result = all_of_them(" ALL OF THEM ");
+        assert_eq!(parser_result, Ok(("", "ALL OF THEM")));
+
+        let parser_result = all_of_them(" 1 of them ");
+        assert_eq!(
+            parser_result,
+            Err(nom::Err::Error(Error::from_error_kind("1 of them", Tag)))
+        );
+    }
+
+    #[test]
+    fn one_of_input() {
+        let parser_result = one_of(" 1 of selection* ");
+        assert_eq!(parser_result, Ok((" selection*", "1 of")));
+
+        let parser_result = one_of(" selection ");
+        assert_eq!(
+            parser_result,
+            Err(nom::Err::Error(Error::from_error_kind("selection", Tag)))
+        );
+    }
+
+    #[test]
+    fn all_of_input() {
+        let parser_result = all_of(" all of filter_* ");
+        assert_eq!(parser_result, Ok((" filter_*", "all of")));
+
+        let parser_result = all_of(" and filter ");
+        assert_eq!(
+            parser_result,
+            Err(nom::Err::Error(Error::from_error_kind("and filter", Tag)))
+        );
+    }
 
     #[test]
     fn pipe_input() {
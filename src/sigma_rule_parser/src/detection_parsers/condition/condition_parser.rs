@@ -1,137 +1,237 @@
-use std::fmt;
-use std::fmt::Error;
-use log::{error, info};
-use crate::detection_parsers::logic::logic_parser::parse_search_identifier;
+use crate::detection_parsers::condition::aggregation_parser::parse_aggregation;
+use crate::detection_parsers::condition::quantifier_parser::is_quantifier_placeholder;
 use crate::detection_parsers::condition::sub_parsers::parser;
+use crate::rule_error::RuleError;
 use crate::structs::detection::Detection;
-use crate::structs::detection_metadata::ParserTypes;
+use crate::structs::detection_condition::{DetectionCondition, Operator};
+use crate::structs::detection_metadata::{DetectionMetadata, ParserTypes};
 
 /// This function is responsible for handling each Sigma rule condition that is passed to it, returning a Detection.
 /// These Detections should be collected into a vec<> for further processing of the Detection Logic.
 ///
 /// At a high level, this method compares the search identifiers in the detection field to the search identifiers found in the condition field
 ///     of a Sigma rule.
-pub fn parse_detection_condition(condition: &str, search_identifiers: Vec<String>) -> Result<Detection, Error> {
+///
+/// An aggregation that follows a pipe ("selection | count() by src_ip > 10") is not part of the Detection, see aggregation_parser::parse_aggregation.
+/// The aggregation is still validated here, so a rule with an unsupported aggregation is not built without it.
+pub fn parse_detection_condition(condition: &str, search_identifiers: Vec<String>) -> Result<Detection, RuleError> {
     let mut detection = Detection::init(); // groups the conditions in the parentheses
-    let mut remaining_condition = condition;
+    let mut remaining_condition = match condition.find('|') {
+        // an aggregation applies to the whole condition, so a pipe can not be nested in parentheses
+        Some(index) if condition[..index].matches('(').count() != condition[..index].matches(')').count() => {
+            return Err(RuleError::InvalidCondition(condition.to_string()))
+        }
+        Some(index) => {
+            parse_aggregation(&condition[index..])?;
+            &condition[..index]
+        }
+        None => condition,
+    };
 
     let mut search_identifiers_result = Vec::new();
 
-    while !remaining_condition.is_empty() {
-        match parser(remaining_condition) {
-            Ok((remaining, parser_output)) => {
-                remaining_condition = remaining;
+    while !remaining_condition.trim().is_empty() {
+        let (remaining, parser_output) = parser(remaining_condition).map_err(|_| RuleError::InvalidCondition(condition.to_string()))?;
+        remaining_condition = remaining;
 
-                search_identifiers_result = parser_output.metadata.search_identifiers.clone();
+        search_identifiers_result.extend(parser_output.metadata.search_identifiers.clone());
 
-                match parser_output.metadata.parser_type.clone() {
-                    ParserTypes::Parens => {
-                        let condition = parser_output.result.clone();
-                        detection.conditions = Some(vec![parser_output.result.clone()]);
-                    }
-                    ParserTypes::OneOfThem => {
-                        println!("ONE_OF_THEM");
-                    }
-                    ParserTypes::AllOfThem => {
-                        println!("ALL_OF_THEM");
-                    }
-                    ParserTypes::OneOf => {
-                        println!("ONE_OF");
-                    }
-                    ParserTypes::AllOf => {
-                        println!("ALL_OF");
-                    }
-                    ParserTypes::Not => {
-                        let condition = parser_output.result.clone();
+        match parser_output.metadata.parser_type.clone() {
+            ParserTypes::Parens => {
+                detection.conditions = Some(vec![parser_output.result.clone()]);
+            }
+            ParserTypes::Not => {
+                let condition = parser_output.result.clone();
 
-                        let mut conditions = detection.conditions.unwrap_or(vec![]);
-                        conditions.push(condition.clone());
-                        detection.conditions = Some(conditions);
-                    }
-                    ParserTypes::And => {
-                        // TODO:
-                        // add check to see if detection.operator is None, OR, or AND.
-                        // When it is an operator that does not equal another operator, this must create a nested condition
+                let mut conditions = detection.conditions.unwrap_or(vec![]);
+                conditions.push(condition.clone());
+                detection.conditions = Some(conditions);
+            }
+            ParserTypes::And | ParserTypes::Or => {
+                // operators are collected in the order they were written, and grouped by precedence once the condition is parsed
+                detection.operator = parser_output.operator.clone();
+                let mut conditions = detection.conditions.ok_or(RuleError::InvalidCondition(condition.to_string()))?;
+                conditions.push(parser_output.result.clone());
+                detection.conditions = Some(conditions);
+            }
+            ParserTypes::SearchIdentifier | ParserTypes::OneOf | ParserTypes::AllOf | ParserTypes::OneOfThem | ParserTypes::AllOfThem => {
+                detection.conditions = Some(vec![parser_output.result]);
+            }
+            // the aggregation after a pipe was split off above, so any other parser output is not a valid condition
+            _ => return Err(RuleError::InvalidCondition(condition.to_string())),
+        }
+    }
 
-                        detection.operator = parser_output.operator.clone();
-                        let condition = parser_output.result.clone();
+    let mut detection = group_by_precedence(detection);
+    expand_quantifiers(&mut detection, &search_identifiers)?;
 
-                        let mut conditions = detection.conditions.unwrap();
-                        conditions.push(condition.clone());
-                        detection.conditions = Some(conditions);
-                    }
-                    ParserTypes::Or => {
-                        // TODO:
-                        // add check to see if detection.operator is None, OR, or AND.
-                        // When it is an operator that does not equal another operator, this must create a nested condition
-                        detection.operator = parser_output.operator.clone();
-                        let condition = parser_output.result.clone();
-
-                        let mut conditions = detection.conditions.unwrap();
-                        conditions.push(condition.clone());
-                        detection.conditions = Some(conditions);
-                    }
-                    ParserTypes::Pipe => {
-                        println!("PIPE SHOULD RETURN ERROR FOR NOW AND CONTINUE TO NEXT RULE, Correlations not yet supported");
-                    }
-                    ParserTypes::SearchIdentifier => {
-                        detection.conditions = Some(vec![parser_output.result]);
-                    }
-                    _ => {
-                        print!("I DONT KNOW YET, ERROR MAYBE???");
-                    }
-                }
+    validate_conditions(search_identifiers, search_identifiers_result)?;
+    Ok(detection)
+}
+
+/// Groups a chain of conditions by operator precedence: not > and > or.
+///
+/// The sub parsers return the conditions in the order they were written, each condition holding the operator that joins it to the condition before it.
+/// When both operators are used, i.e. "a and b or c and not d", every run of conditions joined by "and" is nested into its own Detection,
+///     and the root Detection joins these groups with "or": "(a and b) or (c and not d)".
+/// Negation is already bound to its condition by not_parser, and parentheses are already nested by parens_parser.
+pub fn group_by_precedence(detection: Detection) -> Detection {
+    let conditions = match detection.conditions {
+        Some(conditions) => conditions,
+        None => return detection,
+    };
+
+    let is_mixed = conditions.iter().skip(1).any(|condition| condition.operator == Some(Operator::Or))
+        && conditions.iter().skip(1).any(|condition| condition.operator != Some(Operator::Or));
+    if !is_mixed {
+        return Detection { operator: detection.operator, conditions: Some(conditions) };
+    }
+
+    let mut groups: Vec<Vec<DetectionCondition>> = Vec::new();
+    for condition in conditions {
+        match groups.last_mut() {
+            Some(group) if condition.operator != Some(Operator::Or) => group.push(condition),
+            _ => groups.push(vec![condition]),
+        }
+    }
+
+    let conditions = groups
+        .into_iter()
+        .enumerate()
+        .map(|(index, mut group)| {
+            let operator = if index == 0 { None } else { Some(Operator::Or) };
+            if group.len() == 1 {
+                return group.remove(0);
+            }
+
+            let parser_result = group.iter().map(|condition| condition.metadata.parser_result.clone()).collect::<Vec<String>>().join(" ");
+            let parser_result = match operator {
+                Some(_) => parser_result.split_once(' ').map(|(_, parser_result)| parser_result.to_string()).unwrap_or(parser_result),
+                None => parser_result,
+            };
+            let search_identifiers = group.iter().flat_map(|condition| condition.metadata.search_identifiers.clone()).collect();
+            group[0].operator = None;
+
+            let metadata = DetectionMetadata::new(ParserTypes::Parens, parser_result, search_identifiers);
+            let nested_detection = Detection { operator: Some(Operator::And), conditions: Some(group) };
+            DetectionCondition::new(metadata, None, operator, None, Some(nested_detection))
+        })
+        .collect();
+
+    Detection { operator: Some(Operator::Or), conditions: Some(conditions) }
+}
+
+/// Replaces the placeholders of quantifiers ("1 of selection_*", "all of them") with a condition for every matching search identifier.
+/// "them" matches all search identifiers, except the ones starting with an underscore as defined by the Sigma specification.
+/// A quantifier that does not match any search identifier of the Sigma rule returns RuleError::UnknownSearchIdentifier.
+fn expand_quantifiers(detection: &mut Detection, search_identifiers: &[String]) -> Result<(), RuleError> {
+    let conditions = match detection.conditions.take() {
+        Some(conditions) => conditions,
+        None => return Ok(()),
+    };
+
+    let mut expanded_conditions = Vec::new();
+    for mut condition in conditions {
+        if !is_quantifier_placeholder(&condition) {
+            if let Some(nested_detection) = condition.nested_detections.as_mut() {
+                expand_quantifiers(nested_detection, search_identifiers)?;
             }
-            Err(..) => {}
+            expanded_conditions.push(condition);
+            continue;
+        }
+
+        let pattern = condition.search_identifier.clone().unwrap_or_default();
+        let matching = search_identifiers
+            .iter()
+            .filter(|search_identifier| match condition.metadata.parser_type {
+                ParserTypes::OneOfThem | ParserTypes::AllOfThem => !search_identifier.starts_with('_'),
+                _ => search_identifier_matches(&pattern, search_identifier),
+            })
+            .collect::<Vec<&String>>();
+
+        if matching.is_empty() {
+            return Err(RuleError::UnknownSearchIdentifier(pattern));
+        }
+
+        for search_identifier in matching {
+            let metadata = DetectionMetadata::new(ParserTypes::SearchIdentifier, search_identifier.clone(), vec![search_identifier.clone()]);
+            expanded_conditions.push(DetectionCondition::new(metadata, None, None, Some(search_identifier.clone()), None));
         }
     }
 
-    match validate_conditions(search_identifiers, search_identifiers_result) {
-        true => Ok(detection),
-        false => Err(Error)
+    detection.conditions = Some(expanded_conditions);
+    Ok(())
+}
+
+/// Compares a search identifier to a pattern of a quantifier, where '*' matches any number of characters.
+fn search_identifier_matches(pattern: &str, search_identifier: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == search_identifier,
+        Some((prefix, remaining_pattern)) => {
+            let search_identifier = match search_identifier.strip_prefix(prefix) {
+                Some(search_identifier) => search_identifier,
+                None => return false,
+            };
+
+            (0..=search_identifier.len())
+                .filter(|index| search_identifier.is_char_boundary(*index))
+                .any(|index| search_identifier_matches(remaining_pattern, &search_identifier[index..]))
+        }
     }
 }
 
 /// This function compares all of the conditions that were found in the "condition: ..." field of the Sigma rule.
 /// If all conditions in the parsing of the condition are found, then that means the rule may have valid detection logic.
 /// If not all conditions in the parsing of the condition are NOT found, then the rule is invalid because the condition uses a rule that the file does not define.
-fn validate_conditions(search_identifiers: Vec<String>, search_identifiers_result: Vec<String>) -> bool {
-    let matching = search_identifiers_result.clone().iter().zip(&search_identifiers).filter(|&(a, b)| a == b).count();
-
-    return if search_identifiers_result.len() == matching {
-        true
-    } else {
-        false
+/// The first search identifier that is not defined is returned in the error.
+fn validate_conditions(search_identifiers: Vec<String>, search_identifiers_result: Vec<String>) -> Result<(), RuleError> {
+    match search_identifiers_result.into_iter().find(|search_identifier| !search_identifiers.contains(search_identifier)) {
+        Some(search_identifier) => Err(RuleError::UnknownSearchIdentifier(search_identifier)),
+        None => Ok(()),
     }
 }
 
 
 #[cfg(test)]
 mod tests {
-    use crate::detection_parsers::condition::condition_parser::{parse_detection_condition, validate_conditions};
+    use crate::detection_parsers::condition::condition_parser::{parse_detection_condition, search_identifier_matches, validate_conditions};
+    use crate::rule_error::RuleError;
     use crate::structs::detection::Detection;
     use crate::structs::detection_condition::{DetectionCondition, Operator};
     use crate::structs::detection_logic::DetectionLogic;
     use crate::structs::detection_metadata::{DetectionMetadata, ParserTypes};
+    use crate::detection_evaluator::evaluate_detection;
+    use proptest::prelude::*;
 
     #[test]
     fn parse_search_identifier_that_does_not_exist() {
         let search_identifiers: Vec<String> = vec!["selection".to_string(), "filter".to_string()];
-        let result = parse_detection_condition("keywords and not filter", search_identifiers);
-        println!("result = {:?}", result);
+        let result = parse_detection_condition("keywords and not filter", search_identifiers.clone());
+        assert_eq!(result, Err(RuleError::UnknownSearchIdentifier("keywords".to_string())));
+
+        let result = parse_detection_condition("selection and not (filter or keywords)", search_identifiers);
+        assert_eq!(result, Err(RuleError::UnknownSearchIdentifier("keywords".to_string())));
     }
 
-    // TODO: Remember to add support for condition typed like this
     #[test]
     fn test_parens_only() {
         let search_identifiers: Vec<String> = vec!["selection".to_string(), "filter".to_string()];
-        let result = parse_detection_condition("(selection or filter)", search_identifiers);
-        println!("{:?}", result);
+        let result = parse_detection_condition("(selection or filter)", search_identifiers).unwrap();
+
+        assert_eq!(result.operator, None);
+        let conditions = result.conditions.as_ref().unwrap();
+        assert_eq!(conditions.len(), 1);
+        assert_eq!(conditions[0].metadata.parser_type, ParserTypes::Parens);
+        assert_eq!(conditions[0].metadata.parser_result, "(selection or filter)");
+        assert_eq!(nested_search_identifiers(&result, 0), (Some(Operator::Or), vec!["selection".to_string(), "filter".to_string()]));
     }
 
     #[test]
     fn run_parse_for_nested_parens_condition() {
-        let search_identifiers: Vec<String> = vec!["filter_scmevent".to_string()];
+        let search_identifiers: Vec<String> = ["wmi_filter_to_consumer_binding", "consumer_keywords", "wmi_filter_registration", "filter_scmevent"]
+            .iter()
+            .map(|search_identifier| search_identifier.to_string())
+            .collect();
         let result = parse_detection_condition("( (wmi_filter_to_consumer_binding and consumer_keywords) or (wmi_filter_registration) ) and not filter_scmevent", search_identifiers);
         assert_eq!(result, Ok(Detection {
             operator: Some(Operator::And),
@@ -238,7 +338,7 @@ mod tests {
 
     #[test]
     fn run_parse_for_parens_condition() {
-        let search_identifiers: Vec<String> = vec!["selection1".to_string()];
+        let search_identifiers: Vec<String> = vec!["keywords".to_string(), "selection".to_string(), "filter".to_string(), "selection1".to_string()];
         let result = parse_detection_condition("not keywords or (selection and not filter) or selection1", search_identifiers);
         assert_eq!(
             result,
@@ -316,7 +416,7 @@ mod tests {
 
     #[test]
     fn run_parse_for_or_not() {
-        let search_identifiers: Vec<String> = vec!["filter".to_string()];
+        let search_identifiers: Vec<String> = vec!["selection".to_string(), "filter".to_string()];
         let result = parse_detection_condition("selection or not filter", search_identifiers);
 
         assert_eq!(
@@ -355,7 +455,7 @@ mod tests {
 
     #[test]
     fn run_parse_for_and_not() {
-        let search_identifiers: Vec<String> = vec!["filter".to_string()];
+        let search_identifiers: Vec<String> = vec!["selection".to_string(), "filter".to_string()];
         let result = parse_detection_condition("selection and not filter", search_identifiers);
         assert_eq!(
             result,
@@ -420,8 +520,8 @@ mod tests {
         let search_identifiers = vec!["selection".to_string(), "filter".to_string()];
         let search_identifiers_result = vec!["selection".to_string(), "filter".to_string()];
 
-        let is_valid = validate_conditions(search_identifiers, search_identifiers_result);
-        assert_eq!(is_valid, true);
+        let result = validate_conditions(search_identifiers, search_identifiers_result);
+        assert_eq!(result, Ok(()));
     }
 
     #[test]
@@ -429,7 +529,203 @@ mod tests {
         let search_identifiers = vec!["selection".to_string(), "filter".to_string()];
         let search_identifiers_result = vec!["keywords".to_string(), "filter".to_string()];
 
-        let is_valid = validate_conditions(search_identifiers, search_identifiers_result);
-        assert_eq!(is_valid, false);
+        let result = validate_conditions(search_identifiers, search_identifiers_result);
+        assert_eq!(result, Err(RuleError::UnknownSearchIdentifier("keywords".to_string())));
+    }
+
+    fn nested_search_identifiers(detection: &Detection, index: usize) -> (Option<Operator>, Vec<String>) {
+        let condition = &detection.conditions.as_ref().unwrap()[index];
+        let nested_detection = condition.nested_detections.as_ref().unwrap();
+        let search_identifiers = nested_detection.conditions.as_ref().unwrap().iter()
+            .map(|condition| condition.search_identifier.clone().unwrap())
+            .collect();
+
+        (nested_detection.operator.clone(), search_identifiers)
+    }
+
+    #[test]
+    fn run_parse_for_one_of_pattern() {
+        let search_identifiers: Vec<String> = vec!["filter".to_string(), "selection_cli".to_string(), "selection_img".to_string()];
+        let result = parse_detection_condition("1 of selection_* and not filter", search_identifiers).unwrap();
+
+        assert_eq!(result.operator, Some(Operator::And));
+        assert_eq!(
+            nested_search_identifiers(&result, 0),
+            (Some(Operator::Or), vec!["selection_cli".to_string(), "selection_img".to_string()])
+        );
+
+        let filter = &result.conditions.as_ref().unwrap()[1];
+        assert_eq!(filter.is_negated, Some(true));
+        assert_eq!(filter.search_identifier, Some("filter".to_string()));
+    }
+
+    #[test]
+    fn run_parse_for_all_of_them() {
+        let search_identifiers: Vec<String> = vec!["_internal".to_string(), "selection".to_string(), "keywords".to_string()];
+        let result = parse_detection_condition("all of them", search_identifiers).unwrap();
+
+        assert_eq!(result.conditions.as_ref().unwrap().len(), 1);
+        assert_eq!(
+            nested_search_identifiers(&result, 0),
+            (Some(Operator::And), vec!["selection".to_string(), "keywords".to_string()])
+        );
+    }
+
+    #[test]
+    fn run_parse_for_quantifiers_in_parens() {
+        let search_identifiers: Vec<String> = vec!["filter_a".to_string(), "filter_b".to_string(), "selection".to_string()];
+        let result = parse_detection_condition("selection and not (all of filter_*)", search_identifiers).unwrap();
+
+        let parens = &result.conditions.as_ref().unwrap()[1];
+        assert_eq!(parens.is_negated, Some(true));
+        assert_eq!(
+            nested_search_identifiers(parens.nested_detections.as_ref().unwrap(), 0),
+            (Some(Operator::And), vec!["filter_a".to_string(), "filter_b".to_string()])
+        );
+
+        let result = parse_detection_condition("selection or not 1 of them", vec!["selection".to_string(), "filter".to_string()]).unwrap();
+        let quantifier = &result.conditions.as_ref().unwrap()[1];
+        assert_eq!(quantifier.is_negated, Some(true));
+        assert_eq!(quantifier.operator, Some(Operator::Or));
+        assert_eq!(nested_search_identifiers(&result, 1).1, vec!["selection".to_string(), "filter".to_string()]);
+    }
+
+    #[test]
+    fn run_parse_for_quantifier_without_matches() {
+        let search_identifiers: Vec<String> = vec!["selection".to_string()];
+        assert_eq!(
+            parse_detection_condition("1 of filter_*", search_identifiers),
+            Err(RuleError::UnknownSearchIdentifier("filter_*".to_string()))
+        );
+    }
+
+    #[test]
+    fn search_identifier_patterns() {
+        assert!(search_identifier_matches("selection*", "selection"));
+        assert!(search_identifier_matches("selection*", "selection_img"));
+        assert!(search_identifier_matches("*_img", "selection_img"));
+        assert!(search_identifier_matches("sel*_*", "selection_img"));
+        assert!(search_identifier_matches("selection", "selection"));
+        assert!(!search_identifier_matches("selection", "selection_img"));
+        assert!(!search_identifier_matches("filter*", "selection_img"));
+    }
+
+    #[test]
+    fn run_parse_for_mixed_operators() {
+        let search_identifiers: Vec<String> = vec!["a".to_string(), "b".to_string(), "c".to_string(), "d".to_string()];
+        let result = parse_detection_condition("a and b or not c and d", search_identifiers).unwrap();
+
+        assert_eq!(result.operator, Some(Operator::Or));
+        let conditions = result.conditions.as_ref().unwrap();
+        assert_eq!(conditions.len(), 2);
+        assert_eq!(conditions[0].operator, None);
+        assert_eq!(conditions[0].metadata.parser_result, "a and b");
+        assert_eq!(conditions[1].operator, Some(Operator::Or));
+        assert_eq!(conditions[1].metadata.parser_result, "not c and d");
+        assert_eq!(nested_search_identifiers(&result, 0), (Some(Operator::And), vec!["a".to_string(), "b".to_string()]));
+        assert_eq!(nested_search_identifiers(&result, 1), (Some(Operator::And), vec!["c".to_string(), "d".to_string()]));
+        assert_eq!(conditions[1].nested_detections.as_ref().unwrap().conditions.as_ref().unwrap()[0].is_negated, Some(true));
+    }
+
+    #[test]
+    fn run_parse_for_mixed_operators_in_parens() {
+        let search_identifiers: Vec<String> = vec!["a".to_string(), "b".to_string(), "c".to_string(), "d".to_string()];
+        let result = parse_detection_condition("d and (a or b and c)", search_identifiers).unwrap();
+
+        assert_eq!(result.operator, Some(Operator::And));
+        let parens = result.conditions.as_ref().unwrap()[1].nested_detections.as_ref().unwrap();
+        assert_eq!(parens.operator, Some(Operator::Or));
+        assert_eq!(parens.conditions.as_ref().unwrap()[0].search_identifier, Some("a".to_string()));
+        assert_eq!(nested_search_identifiers(parens, 1), (Some(Operator::And), vec!["b".to_string(), "c".to_string()]));
+    }
+
+    #[test]
+    fn run_parse_for_invalid_condition() {
+        let search_identifiers: Vec<String> = vec!["selection".to_string()];
+        assert!(parse_detection_condition("selection and", search_identifiers.clone()).is_err());
+        assert!(parse_detection_condition("(selection", search_identifiers.clone()).is_err());
+
+        // "or" without a condition after it is not parsed as an empty condition
+        assert!(parse_detection_condition("selection or", search_identifiers.clone()).is_err());
+        assert!(parse_detection_condition("(selection or) and selection", search_identifiers.clone()).is_err());
+        assert_eq!(
+            parse_detection_condition("(selection | count() > 1)", search_identifiers).map(|_| ()),
+            Err(RuleError::InvalidCondition("(selection | count() > 1)".to_string()))
+        );
+    }
+
+    /// A reference boolean expression, used to prove that the parsed Detection evaluates the same way as the condition was written.
+    #[derive(Clone, Debug)]
+    enum Expression {
+        SearchIdentifier(usize),
+        Not(Box<Expression>),
+        And(Box<Expression>, Box<Expression>),
+        Or(Box<Expression>, Box<Expression>),
+        Parens(Box<Expression>),
+    }
+
+    impl Expression {
+        fn evaluate(&self, values: &[bool]) -> bool {
+            match self {
+                Expression::SearchIdentifier(index) => values[*index],
+                Expression::Not(expression) => !expression.evaluate(values),
+                Expression::And(left, right) => left.evaluate(values) && right.evaluate(values),
+                Expression::Or(left, right) => left.evaluate(values) || right.evaluate(values),
+                Expression::Parens(expression) => expression.evaluate(values),
+            }
+        }
+
+        /// Writes the expression as a Sigma condition, only adding the parentheses that precedence (not > and > or) requires.
+        fn condition(&self) -> String {
+            match self {
+                Expression::SearchIdentifier(index) => format!("sel{}", index),
+                Expression::Not(expression) => match expression.as_ref() {
+                    Expression::SearchIdentifier(_) | Expression::Parens(_) => format!("not {}", expression.condition()),
+                    _ => format!("not ({})", expression.condition()),
+                },
+                Expression::And(left, right) => format!("{} and {}", left.and_operand(), right.and_operand()),
+                Expression::Or(left, right) => format!("{} or {}", left.condition(), right.condition()),
+                Expression::Parens(expression) => format!("({})", expression.condition()),
+            }
+        }
+
+        fn and_operand(&self) -> String {
+            match self {
+                Expression::Or(_, _) => format!("({})", self.condition()),
+                _ => self.condition(),
+            }
+        }
+    }
+
+    fn expression_strategy() -> impl Strategy<Value = Expression> {
+        let leaf = (0..SEARCH_IDENTIFIERS).prop_map(Expression::SearchIdentifier);
+        leaf.prop_recursive(4, 24, 2, |inner| {
+            prop_oneof![
+                inner.clone().prop_map(|expression| Expression::Not(Box::new(expression))),
+                (inner.clone(), inner.clone()).prop_map(|(left, right)| Expression::And(Box::new(left), Box::new(right))),
+                (inner.clone(), inner.clone()).prop_map(|(left, right)| Expression::Or(Box::new(left), Box::new(right))),
+                inner.prop_map(|expression| Expression::Parens(Box::new(expression))),
+            ]
+        })
+    }
+
+    const SEARCH_IDENTIFIERS: usize = 5;
+
+    proptest! {
+        #[test]
+        fn parsed_condition_is_equivalent_to_reference_evaluation(
+            expression in expression_strategy(),
+            values in proptest::collection::vec(any::<bool>(), SEARCH_IDENTIFIERS),
+        ) {
+            let search_identifiers = (0..SEARCH_IDENTIFIERS).map(|index| format!("sel{}", index)).collect::<Vec<String>>();
+            let condition = expression.condition();
+            let detection = parse_detection_condition(&condition, search_identifiers).unwrap();
+
+            let result = evaluate_detection(&detection, &|condition: &DetectionCondition| {
+                let search_identifier = condition.search_identifier.as_ref().unwrap();
+                values[search_identifier["sel".len()..].parse::<usize>().unwrap()]
+            });
+            prop_assert_eq!(result, expression.evaluate(&values), "condition: {}", condition);
+        }
     }
 }
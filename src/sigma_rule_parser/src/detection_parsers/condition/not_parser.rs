@@ -6,6 +6,7 @@ use nom::IResult;
 
 use crate::structs::detection_condition::DetectionCondition;
 use crate::detection_parsers::condition::parser_output::ParserOutput;
+use crate::detection_parsers::condition::quantifier_parser::quantifier_parser;
 use crate::detection_parsers::condition::search_id_parser::search_identifiers_parser;
 use crate::structs::detection_metadata::{DetectionMetadata, ParserTypes};
 
@@ -16,26 +17,23 @@ pub fn not_parser(input: &str) -> IResult<&str, ParserOutput<DetectionCondition>
     let mut result_condition: String = String::from(result);
 
     let not_parser_result = downstream_not_parser(remaining);
-    match not_parser_result {
-        Ok((_, parser_output)) => {
-            let downstream_parser_result = parser_output.metadata.parser_result.clone();
-            result_condition = format!("{}{}{}", result_condition, " ", downstream_parser_result);
-
-            let metadata = DetectionMetadata::new(
-                ParserTypes::Not,
-                result_condition.clone(),
-                parser_output.metadata.search_identifiers.clone(),
-            );
+    if let Ok((_, parser_output)) = not_parser_result {
+        let downstream_parser_result = parser_output.metadata.parser_result.clone();
+        result_condition = format!("{}{}{}", result_condition, " ", downstream_parser_result);
+
+        let metadata = DetectionMetadata::new(
+            ParserTypes::Not,
+            result_condition.clone(),
+            parser_output.metadata.search_identifiers.clone(),
+        );
 
-            condition = DetectionCondition::new(
-                metadata,
-                Some(true),
-                None,
-                parser_output.search_identifier.clone(),
-                parser_output.nested_detections.clone(),
-            );
-        }
-        Err(_) => {}
+        condition = DetectionCondition::new(
+            metadata,
+            Some(true),
+            None,
+            parser_output.search_identifier.clone(),
+            parser_output.nested_detections.clone(),
+        );
     }
 
     value(
@@ -51,13 +49,11 @@ fn not(input: &str) -> IResult<&str, &str> {
 }
 
 fn downstream_not_parser(input: &str) -> IResult<&str, ParserOutput<DetectionCondition>> {
-    let result = alt((
+    alt((
         parens_parser,
-        // one of / all of combos
+        quantifier_parser,
         search_identifiers_parser,
-    ))(input);
-
-    result
+    ))(input)
 }
 
 #[cfg(test)]
@@ -7,41 +7,30 @@ use crate::structs::detection_condition::{DetectionCondition, Operator};
 use crate::detection_parsers::condition::not_parser::not_parser;
 use crate::detection_parsers::condition::parens_parser::parens_parser;
 use crate::detection_parsers::condition::parser_output::ParserOutput;
+use crate::detection_parsers::condition::quantifier_parser::quantifier_parser;
 use crate::detection_parsers::condition::search_id_parser::search_identifiers_parser;
-use crate::detection_parsers::condition::sub_parsers::parser;
 use crate::structs::detection_metadata::{DetectionMetadata, ParserTypes};
 
 pub fn or_parser(input: &str) -> IResult<&str, ParserOutput<DetectionCondition>> {
-    let mut condition = DetectionCondition::init();
     let (remaining, result) = or(input)?;
-    let mut result_condition: String = String::from(result);
-
-    let or_parser_result = downstream_or_parser(remaining.trim());
-    match or_parser_result {
-        Ok((_, parser_output)) => {
-            let downstream_parser_result = parser_output.metadata.parser_result.clone();
-            result_condition = format!("{}{}{}", result_condition, " ", downstream_parser_result);
-
-            // insert the current search id into the existing search id's, return to parser, add to vec, and validate once all parsing is done
-            let search_identifiers = parser_output.search_identifier.clone();
-
-            let metadata = DetectionMetadata::new(
-                ParserTypes::Or,
-                result_condition.clone(),
-                parser_output.metadata.search_identifiers.clone()
-            );
-
-
-            condition = DetectionCondition::new(
-                metadata,
-                parser_output.is_negated.clone(),
-                Some(Operator::Or),
-                parser_output.search_identifier.clone(),
-                parser_output.nested_detections.clone(),
-            );
-        }
-        Err(err) => println!("{:?}", err),
-    }
+
+    // the condition after "or" must parse, otherwise the condition is invalid
+    let (_, parser_output) = downstream_or_parser(remaining.trim())?;
+    let result_condition = format!("{}{}{}", result, " ", parser_output.metadata.parser_result);
+
+    let metadata = DetectionMetadata::new(
+        ParserTypes::Or,
+        result_condition.clone(),
+        parser_output.metadata.search_identifiers.clone()
+    );
+
+    let condition = DetectionCondition::new(
+        metadata,
+        parser_output.is_negated,
+        Some(Operator::Or),
+        parser_output.search_identifier.clone(),
+        parser_output.nested_detections.clone(),
+    );
 
     value(
         ParserOutput {
@@ -56,7 +45,7 @@ fn or(input: &str) -> IResult<&str, &str> {
 }
 
 pub fn downstream_or_parser(input: &str) -> IResult<&str, ParserOutput<DetectionCondition>> {
-    alt((parens_parser, not_parser, search_identifiers_parser))(input)
+    alt((parens_parser, not_parser, quantifier_parser, search_identifiers_parser))(input)
 }
 
 #[cfg(test)]
@@ -1,9 +1,11 @@
 use nom::bytes::complete::tag;
+use nom::error::{Error, ErrorKind, ParseError};
 use nom::sequence::delimited;
 use nom::IResult;
 
 use crate::structs::detection_condition::DetectionCondition;
 use crate::structs::detection::Detection;
+use crate::detection_parsers::condition::condition_parser::group_by_precedence;
 use crate::detection_parsers::condition::sub_parsers::parser;
 use crate::detection_parsers::condition::parser_output::ParserOutput;
 use crate::detection_parsers::condition::take_until_unbalanced::take_until_unbalanced;
@@ -11,77 +13,44 @@ use crate::structs::detection_metadata::{DetectionMetadata, ParserTypes};
 
 pub fn parens_parser(input: &str) -> IResult<&str, ParserOutput<DetectionCondition>> {
     let mut detection = Detection::init(); // groups the conditions in the parentheses
-    let mut condition = DetectionCondition::init(); // builds the conditions in the parentheses
 
     let (remaining, result) = parens(input)?;
-    let mut remaining_condition = remaining;
+    let remaining_condition = remaining;
     let mut resulting_condition = result;
 
     let mut search_identifiers = Vec::new();
 
-    while !resulting_condition.is_empty() {
-        match parser(resulting_condition) {
-            Ok((remaining, parser_output)) => {
-                resulting_condition = remaining;
+    while !resulting_condition.trim().is_empty() {
+        let (remaining, parser_output) = parser(resulting_condition).map_err(nom::Err::Error)?;
+        resulting_condition = remaining;
 
-                search_identifiers = [search_identifiers, parser_output.result.metadata.search_identifiers.clone()].concat();
+        search_identifiers = [search_identifiers, parser_output.result.metadata.search_identifiers.clone()].concat();
 
-                match parser_output.metadata.parser_type.clone() {
-                    ParserTypes::Parens => {
-                        condition = parser_output.result.clone();
-                        detection.conditions = Some(vec![parser_output.result.clone()]);
-                    }
-                    ParserTypes::OneOfThem => {
-                        println!("ONE_OF_THEM");
-                    }
-                    ParserTypes::AllOfThem => {
-                        println!("ALL_OF_THEM");
-                    }
-                    ParserTypes::OneOf => {
-                        println!("ONE_OF");
-                    }
-                    ParserTypes::AllOf => {
-                        println!("ALL_OF");
-                    }
-                    ParserTypes::Not => {
-                        condition = parser_output.result.clone();
-
-                        let mut conditions = detection.conditions.unwrap_or(vec![]);
-                        conditions.push(condition.clone());
-                        detection.conditions = Some(conditions);
-                    }
-                    ParserTypes::And => {
-                        detection.operator = parser_output.operator.clone();
-                        condition = parser_output.result.clone();
-
-                        let mut conditions = detection.conditions.unwrap();
-                        conditions.push(condition.clone());
-                        detection.conditions = Some(conditions);
-                    }
-                    ParserTypes::Or => {
-                        detection.operator = parser_output.operator.clone();
-                        condition = parser_output.result.clone();
-
-                        let mut conditions = detection.conditions.unwrap();
-                        conditions.push(condition.clone());
-                        detection.conditions = Some(conditions);
-                    }
-                    ParserTypes::Pipe => {
-                        println!("PIPE SHOULD RETURN ERROR FOR NOW AND CONTINUE TO NEXT RULE");
-                    }
-                    ParserTypes::SearchIdentifier => {
-                        detection.conditions = Some(vec![parser_output.result]);
-                    }
-                    _ => {
-                        print!("I DONT KNOW YET, ERROR MAYBE???");
-                    }
-                }
+        match parser_output.metadata.parser_type.clone() {
+            ParserTypes::Parens => {
+                detection.conditions = Some(vec![parser_output.result.clone()]);
+            }
+            ParserTypes::Not => {
+                let mut conditions = detection.conditions.unwrap_or(vec![]);
+                conditions.push(parser_output.result.clone());
+                detection.conditions = Some(conditions);
+            }
+            ParserTypes::And | ParserTypes::Or => {
+                detection.operator = parser_output.operator.clone();
+                let mut conditions = detection.conditions.unwrap_or(vec![]);
+                conditions.push(parser_output.result.clone());
+                detection.conditions = Some(conditions);
+            }
+            ParserTypes::SearchIdentifier | ParserTypes::OneOf | ParserTypes::AllOf | ParserTypes::OneOfThem | ParserTypes::AllOfThem => {
+                detection.conditions = Some(vec![parser_output.result]);
             }
-            Err(..) => {}
+            // an aggregation can not be nested in parentheses, so the condition is invalid instead of being parsed without it
+            _ => return Err(nom::Err::Failure(Error::from_error_kind(input, ErrorKind::Verify))),
         }
     }
 
-    let mut parser_result = format!("{}{}{}", "(", result.to_string(), ")");
+    let detection = group_by_precedence(detection);
+    let parser_result = format!("{}{}{}", "(", result, ")");
     let metadata = DetectionMetadata {
         parser_type: ParserTypes::Parens,
         parser_result,
@@ -357,8 +326,8 @@ mod tests {
         );
 
         let remaining_value = match parser_result {
-            Ok((returned, remaining)) => remaining,
-            Err(err) => "Error",
+            Ok((_returned, remaining)) => remaining,
+            Err(_err) => "Error",
         };
 
         // Test the nested parentheses
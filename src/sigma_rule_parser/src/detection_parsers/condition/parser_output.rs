@@ -30,9 +30,9 @@ impl<T> core::ops::Deref for ParserOutput<T> {
     }
 }
 
-impl<T: AsBytes> ParserOutput<T> {
+impl<T: AsBytes> AsBytes for ParserOutput<T> {
     fn as_bytes(&self) -> &[u8] {
-        &self.result.as_bytes()
+        self.result.as_bytes()
     }
 }
 
@@ -50,7 +50,7 @@ impl<T: Compare<B>, B: Into<ParserOutput<B>>> Compare<B> for ParserOutput<T> {
 
 // #[cfg(feature = "alloc")] ----- TODO - get answer to why is this used in nom_locate
 /// Builds and Returns type ParserInput
-impl<'a, T> ExtendInto for ParserOutput<T>
+impl<T> ExtendInto for ParserOutput<T>
 where
     T: ExtendInto,
 {
@@ -81,7 +81,7 @@ impl<T: FindToken<Token>, Token> FindToken<Token> for ParserOutput<T> {
     }
 }
 
-impl<'a, T> InputIter for ParserOutput<T>
+impl<T> InputIter for ParserOutput<T>
 where
     T: InputIter,
 {
@@ -195,12 +195,9 @@ where
     }
 }
 
-impl<T> Offset for ParserOutput<T> {
+impl<T: Offset> Offset for ParserOutput<T> {
     fn offset(&self, second: &Self) -> usize {
-        let fst = self.offset(self);
-        let snd = second.offset(second);
-
-        snd - fst
+        self.result.offset(&second.result)
     }
 }
 
@@ -211,7 +208,7 @@ impl<R: FromStr, T: ParseTo<R>> ParseTo<R> for ParserOutput<T> {
     }
 }
 
-impl<'a, T, R> Slice<R> for ParserOutput<T>
+impl<T, R> Slice<R> for ParserOutput<T>
 where
     T: Slice<R> + Offset + AsBytes + Slice<RangeTo<usize>>,
 {
@@ -0,0 +1,104 @@
+use nom::branch::alt;
+use nom::bytes::complete::take_till1;
+use nom::character::complete::space1;
+use nom::combinator::consumed;
+use nom::sequence::tuple;
+use nom::IResult;
+
+use crate::structs::detection::Detection;
+use crate::structs::detection_condition::{DetectionCondition, Operator};
+use crate::detection_parsers::condition::atomic_parsers::{all_of, one_of};
+use crate::detection_parsers::condition::parser_output::ParserOutput;
+use crate::structs::detection_metadata::{DetectionMetadata, ParserTypes};
+
+/// Parses the quantifiers of a condition: "1 of selection*", "all of filter_*", "1 of them" and "all of them".
+///
+/// The parser does not know which search identifiers the Sigma rule defines, so the quantifier is returned as a nested Detection
+///     holding a single placeholder condition, with the pattern as its search identifier.
+/// The placeholder is replaced by the matching search identifiers in condition_parser::expand_quantifiers.
+/// "1 of" joins the search identifiers with Or, "all of" joins them with And.
+pub fn quantifier_parser(input: &str) -> IResult<&str, ParserOutput<DetectionCondition>> {
+    let input = input.trim();
+    let (remaining, (parser_result, (quantifier, _, pattern))) =
+        consumed(tuple((alt((one_of, all_of)), space1, take_till1(|ch| ch == ' '))))(input)?;
+
+    let (parser_type, operator) = match (quantifier.to_lowercase().as_str(), pattern.to_lowercase().as_str()) {
+        ("1 of", "them") => (ParserTypes::OneOfThem, Operator::Or),
+        ("1 of", _) => (ParserTypes::OneOf, Operator::Or),
+        (_, "them") => (ParserTypes::AllOfThem, Operator::And),
+        (_, _) => (ParserTypes::AllOf, Operator::And),
+    };
+
+    let placeholder = DetectionCondition::new(
+        DetectionMetadata::new(parser_type.clone(), parser_result.to_string(), vec![pattern.to_string()]),
+        None,
+        None,
+        Some(pattern.to_string()),
+        None,
+    );
+
+    let detection = Detection {
+        operator: Some(operator),
+        conditions: Some(vec![placeholder]),
+    };
+
+    // the search identifiers of the quantifier are only known after expansion, so none are reported for validation
+    let condition = DetectionCondition::new(
+        DetectionMetadata::new(parser_type, parser_result.to_string(), vec![]),
+        None,
+        None,
+        None,
+        Some(detection),
+    );
+
+    Ok((remaining, ParserOutput { result: condition }))
+}
+
+/// A placeholder is a quantifier condition that has not been expanded into search identifiers yet.
+pub fn is_quantifier_placeholder(condition: &DetectionCondition) -> bool {
+    let is_quantifier = matches!(
+        condition.metadata.parser_type,
+        ParserTypes::OneOf | ParserTypes::AllOf | ParserTypes::OneOfThem | ParserTypes::AllOfThem
+    );
+
+    is_quantifier && condition.nested_detections.is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nom::error::ErrorKind::Tag;
+    use nom::error::{Error, ParseError};
+
+    #[test]
+    fn one_of_pattern_with_remaining() {
+        let (remaining, parser_output) = quantifier_parser(" 1 of selection_* and not filter").unwrap();
+        assert_eq!(remaining, " and not filter");
+        assert_eq!(parser_output.metadata.parser_type, ParserTypes::OneOf);
+        assert_eq!(parser_output.metadata.parser_result, "1 of selection_*");
+
+        let nested_detection = parser_output.nested_detections.clone().unwrap();
+        assert_eq!(nested_detection.operator, Some(Operator::Or));
+
+        let placeholder = &nested_detection.conditions.unwrap()[0];
+        assert!(is_quantifier_placeholder(placeholder));
+        assert_eq!(placeholder.search_identifier, Some("selection_*".to_string()));
+    }
+
+    #[test]
+    fn all_of_them() {
+        let (remaining, parser_output) = quantifier_parser("all of them").unwrap();
+        assert_eq!(remaining, "");
+        assert_eq!(parser_output.metadata.parser_type, ParserTypes::AllOfThem);
+        assert_eq!(parser_output.nested_detections.clone().unwrap().operator, Some(Operator::And));
+        assert!(!is_quantifier_placeholder(&parser_output.result));
+    }
+
+    #[test]
+    fn not_a_quantifier() {
+        assert_eq!(
+            quantifier_parser("selection and filter"),
+            Err(nom::Err::Error(Error::from_error_kind("selection and filter", Tag)))
+        );
+    }
+}
@@ -1,11 +1,9 @@
-use crate::detection_parsers::condition::and_parser::and_parser;
-use crate::detection_parsers::condition::or_parser::or_parser;
 use nom::branch::alt;
-use nom::bytes::complete::{tag_no_case, take_until, take_while};
+use nom::bytes::complete::{take_until, take_while};
 use nom::combinator::{rest, value};
 use nom::IResult;
 
-use crate::structs::detection_condition::{DetectionCondition, Operator};
+use crate::structs::detection_condition::DetectionCondition;
 use crate::detection_parsers::condition::parser_output::ParserOutput;
 use crate::structs::detection_metadata::{DetectionMetadata, ParserTypes};
 
@@ -13,8 +11,8 @@ pub fn search_identifiers_parser(input: &str) -> IResult<&str, ParserOutput<Dete
     let (_, result) = search_identifiers(input)?;
     let metadata = DetectionMetadata::new(
         ParserTypes::SearchIdentifier,
-        String::from(result.clone()),
-        vec![result.clone().to_string()],
+        String::from(result),
+        vec![result.to_string()],
     );
 
     let condition = DetectionCondition::new(
@@ -39,14 +37,7 @@ pub fn search_identifiers_parser(input: &str) -> IResult<&str, ParserOutput<Dete
 /// A successful response indicates that the condition is completed and ready to be stored in a Detection struct
 /// A failure indicates invalid input, or potentially a missed parsing use-case.
 fn search_identifiers(input: &str) -> IResult<&str, &str> {
-    let sid = alt((take_until(" "), rest))(input.trim());
-
-    let sid_result = match sid {
-        Ok(parsed_sid) => parsed_sid,
-        Err(e) => return Err(e),
-    };
-
-    Ok(sid_result)
+    alt((take_until(" "), rest))(input.trim())
 }
 
 #[cfg(test)]
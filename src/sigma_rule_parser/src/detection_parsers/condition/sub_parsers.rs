@@ -1,6 +1,6 @@
 use nom::branch::alt;
 use nom::error::Error;
-use nom::{Finish, IResult};
+use nom::Finish;
 
 use crate::detection_parsers::condition::parser_output::ParserOutput;
 
@@ -9,27 +9,28 @@ use crate::detection_parsers::condition::and_parser::and_parser;
 use crate::detection_parsers::condition::not_parser::not_parser;
 use crate::detection_parsers::condition::or_parser::or_parser;
 use crate::detection_parsers::condition::parens_parser::parens_parser;
+use crate::detection_parsers::condition::quantifier_parser::quantifier_parser;
 use crate::detection_parsers::condition::search_id_parser::search_identifiers_parser;
 
 /// Parser when parens is a match
 /// The below links contains a reference to the library that fixes this issue. Nom will support in v8.0
 /// https://stackoverflow.com/questions/70630556/parse-allowing-nested-parentheses-in-nom
 pub fn parser(input: &str) -> Result<(&str, ParserOutput<DetectionCondition>), Error<&str>> {
-    let result = alt((
+    alt((
         parens_parser,
         not_parser,
         and_parser,
         or_parser,
+        quantifier_parser,
         search_identifiers_parser,
     ))(input)
-    .finish();
-
-    result
+    .finish()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::structs::detection_metadata::ParserTypes;
 
     // #[test]
     // fn testa90() {
@@ -51,8 +52,9 @@ mod tests {
 
     #[test]
     fn test_parser() {
-        let test = parser("Selection");
-        println!("{:?}", test);
+        let (remaining, parser_output) = parser("Selection").unwrap();
+        assert_eq!(remaining, "");
+        assert_eq!(parser_output.metadata.parser_type, ParserTypes::SearchIdentifier);
     }
 
     #[test]
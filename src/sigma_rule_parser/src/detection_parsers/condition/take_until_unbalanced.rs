@@ -45,7 +45,7 @@ pub fn take_until_unbalanced(
             index += n;
             let mut it = i[index..].chars();
             match it.next().unwrap_or_default() {
-                c if c == '\\' => {
+                '\\' => {
                     // Skip the escape char `\`.
                     index += '\\'.len_utf8();
                     // Skip also the following char.
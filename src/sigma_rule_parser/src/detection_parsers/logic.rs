@@ -1 +1,2 @@
+pub mod field_parser;
 pub mod logic_parser;
\ No newline at end of file
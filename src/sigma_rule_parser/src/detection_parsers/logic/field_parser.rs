@@ -0,0 +1,55 @@
+use crate::rule_error::RuleError;
+use crate::structs::detection_field::{DetectionField, Modifier};
+
+/// Splits the key of a search identifier mapping into the name of the field and its modifiers.
+///     - "TargetFilename" -> DetectionField { name: "TargetFilename", modifiers: [] }
+///     - "CommandLine|contains|all" -> DetectionField { name: "CommandLine", modifiers: [Contains, All] }
+/// A modifier that is not supported returns RuleError::UnsupportedModifier, as the rule cannot be evaluated correctly without it.
+pub fn parse_detection_field(key: &str) -> Result<DetectionField, RuleError> {
+    let mut field = key.split('|');
+    let name = field.next().unwrap_or_default().to_string();
+
+    let modifiers = field
+        .map(|modifier| modifier.parse::<Modifier>())
+        .collect::<Result<Vec<Modifier>, RuleError>>()?;
+
+    Ok(DetectionField::new(name, modifiers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn field_without_modifiers() {
+        let result = parse_detection_field("TargetFilename");
+        assert_eq!(result, Ok(DetectionField::from("TargetFilename")));
+    }
+
+    #[test]
+    fn field_with_modifier_chain() {
+        let result = parse_detection_field("CommandLine|utf16le|base64offset|contains");
+        assert_eq!(
+            result,
+            Ok(DetectionField {
+                name: "CommandLine".to_string(),
+                modifiers: vec![Modifier::Utf16le, Modifier::Base64Offset, Modifier::Contains]
+            })
+        );
+
+        let result = parse_detection_field("CommandLine|contains|all");
+        assert_eq!(
+            result,
+            Ok(DetectionField {
+                name: "CommandLine".to_string(),
+                modifiers: vec![Modifier::Contains, Modifier::All]
+            })
+        );
+    }
+
+    #[test]
+    fn field_with_unsupported_modifier() {
+        let result = parse_detection_field("CommandLine|expand");
+        assert_eq!(result, Err(RuleError::UnsupportedModifier("expand".to_string())));
+    }
+}
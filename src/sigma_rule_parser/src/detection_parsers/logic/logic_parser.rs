@@ -1,7 +1,8 @@
 use std::collections::BTreeMap;
-use std::fmt::Error;
+use crate::detection_parsers::logic::field_parser::parse_detection_field;
+use crate::rule_error::RuleError;
 use crate::structs::detection::Detection;
-use crate::structs::detection_condition::{DetectionCondition, Operator};
+use crate::structs::detection_condition::DetectionCondition;
 use crate::structs::detection_logic::DetectionLogic;
 use crate::structs::sigma_rule::YmlTypes;
 
@@ -14,79 +15,52 @@ use crate::structs::sigma_rule::YmlTypes;
 /// The DetectionLogic field is what creates these so-called 'subsets of logic', a.k.a. '''second order logic'''
 ///     - The Search Identifier is a unique name that identifies the logic to perform on the data that is being compared to
 /// By assigning DetectionLogic within a DetectionCondition struct, the condition is able to govern the higher order relationships with the logic that must be calculated
-pub fn parse_detection_logic(parsed_detection: &mut Detection, sigma_detection: BTreeMap<String, YmlTypes>) -> Result<&Detection, Error> {
-
-    println!("0 - {:?}", parsed_detection.clone());
-    println!("1 - {:?}", sigma_detection.clone());
-
-    let mut detection_condition = DetectionCondition::init();
-
-    match parsed_detection.operator {
-        None => {
-            // check to see if nested_detection or search_id is not None
-            // TODO: Can I get rid of this for loop since there is no operator? AKA get rid of this soon.
-            for condition in parsed_detection.conditions.as_ref().unwrap() {
-                println!("Nice - {:?}", condition.clone());
-                if condition.nested_detections.is_some() {
-                    println!("ON THE WAY");
-                } else if condition.search_identifier.is_some() {
-
-                    detection_condition = condition.clone();
-
-                    // for loop of sigma_detection that has if statement and if true then assign logic to value outside of loop and break loop
-                    println!("parsed - {:?}", parsed_detection);
-                    println!("sigma - {:?}", sigma_detection);
-                    for (search_id, logic) in sigma_detection.clone() {
-                        if condition.search_identifier.as_ref().unwrap() == &search_id {
-                            println!("yay");
-                            let parsed_logic = parse_search_identifier(logic);
-                            println!("{:?}", parsed_logic);
-                            detection_condition.detection_logic = parsed_logic;
-                        } else {
-                            println!("nay");
-                        }
-                    }
-                } else {
-
-                }
-            }
+pub fn parse_detection_logic<'a>(parsed_detection: &'a mut Detection, sigma_detection: &BTreeMap<String, YmlTypes>) -> Result<&'a Detection, RuleError> {
+    if let Some(conditions) = parsed_detection.conditions.as_mut() {
+        for condition in conditions.iter_mut() {
+            parse_condition_logic(condition, sigma_detection)?;
         }
-        Some(_) => {
-            // figure out order of operations to parse through possible paths recursively
-        }
-    };
+    }
 
-    parsed_detection.conditions = Some(vec![detection_condition]);
     Ok(parsed_detection)
 }
 
-pub fn parse_search_identifier(logic: YmlTypes) -> DetectionLogic {
+/// Assigns the DetectionLogic of a single DetectionCondition.
+/// Nested detections (parentheses) are walked recursively so every search identifier in the tree receives its logic.
+/// A search identifier that is not defined in the detection field of the Sigma rule returns RuleError::UnknownSearchIdentifier.
+fn parse_condition_logic(condition: &mut DetectionCondition, sigma_detection: &BTreeMap<String, YmlTypes>) -> Result<(), RuleError> {
+    if let Some(nested_detection) = condition.nested_detections.as_mut() {
+        parse_detection_logic(nested_detection, sigma_detection)?;
+    } else if let Some(search_identifier) = condition.search_identifier.as_ref() {
+        match sigma_detection.get(search_identifier) {
+            Some(logic) => condition.detection_logic = parse_search_identifier(logic.clone())?,
+            None => return Err(RuleError::UnknownSearchIdentifier(search_identifier.clone())),
+        }
+    }
+
+    Ok(())
+}
+
+/// Parses the yml of a single search identifier into DetectionLogic.
+/// The keys of a mapping are split into the field name and its modifiers, see parse_detection_field.
+pub fn parse_search_identifier(logic: YmlTypes) -> Result<DetectionLogic, RuleError> {
     let mut detection_logic = DetectionLogic::init();
 
-    return match logic {
+    let detection_logic = match logic {
         YmlTypes::Mapping(search_id) => {
-            let logic = search_id;
-            let mut nested_detection_logic = DetectionLogic::init();
-
             let mut mapping_logic = BTreeMap::new();
 
-            for (field, detection_logic_yml) in logic.clone() {
-                let detection_field = field;
-                nested_detection_logic = parse_search_identifier(detection_logic_yml);
-
-                mapping_logic.insert(detection_field, nested_detection_logic);
+            for (field, detection_logic_yml) in search_id {
+                mapping_logic.insert(parse_detection_field(&field)?, parse_search_identifier(detection_logic_yml)?);
             }
             detection_logic.and = Some(mapping_logic);
             detection_logic
         },
         YmlTypes::Sequence(search_id) => {
-            let logic = search_id.to_vec();
             let mut sequence_logic: Vec<DetectionLogic> = Vec::new();
-            let mut nested_detection_logic = DetectionLogic::init();
 
-            for detection_logic_yml in logic {
-                nested_detection_logic = parse_search_identifier(detection_logic_yml);
-                sequence_logic.push(nested_detection_logic);
+            for detection_logic_yml in search_id {
+                sequence_logic.push(parse_search_identifier(detection_logic_yml)?);
             }
 
             detection_logic.or = Some(sequence_logic);
@@ -110,13 +84,10 @@ pub fn parse_search_identifier(logic: YmlTypes) -> DetectionLogic {
 
             nested_detection_logic
         },
-        _ => {
-            let mut nested_detection_logic = DetectionLogic::init();
-            nested_detection_logic.value = Some("".to_string());
-
-            nested_detection_logic
-        }
+        YmlTypes::Null => DetectionLogic::null(),
     };
+
+    Ok(detection_logic)
 }
 
 
@@ -125,8 +96,9 @@ mod tests {
     use std::collections::BTreeMap;
     use crate::detection_parsers::logic::logic_parser::parse_search_identifier;
     use crate::sigma_file::sigma_rule::process_sigma_rules;
+    use crate::structs::detection_field::DetectionField;
     use crate::structs::detection_logic::DetectionLogic;
-    use crate::structs::sigma_rule::{Logsource, SigmaRule, YmlTypes::Sequence, YmlTypes::Number, YmlTypes::Mapping};
+    use crate::structs::sigma_rule::YmlTypes;
 
 
     // TODO: Refactor this test to not use a file
@@ -135,11 +107,11 @@ mod tests {
         let rules = process_sigma_rules("src/sigma_file/test/assets/detection_logic/mapping.yml".to_string()).unwrap();
 
         for rule in rules {
-            for (search_identifier, detection) in rule.detection.clone() {
-                let result = parse_search_identifier(detection);
+            for (_search_identifier, detection) in rule.detection.clone() {
+                let result = parse_search_identifier(detection).unwrap();
 
                 let mut mapping = BTreeMap::new();
-                mapping.insert("EventID".to_string(), DetectionLogic {
+                mapping.insert(DetectionField::from("EventID"), DetectionLogic {
                     and: None,
                     or: Some(vec![
                         DetectionLogic {
@@ -156,7 +128,7 @@ mod tests {
                     value: None
                 });
 
-                mapping.insert("ComputerName".to_string(), DetectionLogic {
+                mapping.insert(DetectionField::from("ComputerName"), DetectionLogic {
                     and: None,
                     or: Some(vec![
                         DetectionLogic {
@@ -190,8 +162,8 @@ mod tests {
         let rules = process_sigma_rules("src/sigma_file/test/assets/detection_logic/sequence.yml".to_string()).unwrap();
 
         for rule in rules {
-            for (search_identifier, detection) in rule.detection {
-                let result = parse_search_identifier(detection);
+            for (_search_identifier, detection) in rule.detection {
+                let result = parse_search_identifier(detection).unwrap();
 
                 assert_eq!(result, DetectionLogic {
                     and: None,
@@ -213,4 +185,15 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_null_and_empty_values() {
+        let logic: YmlTypes = serde_yaml::from_str("{CommandLine: null, ParentImage: ''}").unwrap();
+        let result = parse_search_identifier(logic).unwrap();
+
+        let and = result.and.unwrap();
+        assert_eq!(and[&DetectionField::from("CommandLine")], DetectionLogic::null());
+        assert!(and[&DetectionField::from("CommandLine")].is_null());
+        assert_eq!(and[&DetectionField::from("ParentImage")], DetectionLogic { and: None, or: None, value: Some("".to_string()) });
+        assert!(!and[&DetectionField::from("ParentImage")].is_null());
+    }
 }
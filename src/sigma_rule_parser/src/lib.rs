@@ -1,4 +1,7 @@
 pub mod detection_builder;
+pub mod detection_compiler;
+pub mod detection_evaluator;
 pub mod structs;
 pub mod detection_parsers;
+pub mod rule_error;
 pub mod sigma_file;
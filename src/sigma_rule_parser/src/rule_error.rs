@@ -0,0 +1,89 @@
+use std::io::ErrorKind;
+use thiserror::Error;
+
+/// The reasons a Sigma rule can not be loaded or built.
+/// The details of io and yml errors are kept as values, so that the reason a rule was skipped can be compared and reported.
+#[derive(Clone, Debug, PartialEq, Error)]
+pub enum RuleError {
+    #[error("could not read {path}: {message}")]
+    Io { path: String, kind: ErrorKind, message: String },
+
+    #[error("invalid yml in {path} at line {line}, column {column}: {message}")]
+    Yaml { path: String, line: usize, column: usize, message: String },
+
+    #[error("rule is missing required field '{0}'")]
+    MissingField(String),
+
+    #[error("detection does not have a condition")]
+    MissingCondition,
+
+    #[error("invalid condition '{0}'")]
+    InvalidCondition(String),
+
+    #[error("search identifier '{0}' is not defined in the detection")]
+    UnknownSearchIdentifier(String),
+
+    #[error("unsupported value modifier '{0}'")]
+    UnsupportedModifier(String),
+
+    #[error("unsupported aggregation '{0}'")]
+    UnsupportedAggregation(String),
+
+    #[error("invalid timeframe '{0}'")]
+    InvalidTimeframe(String),
+
+    #[error("invalid correlation: {0}")]
+    InvalidCorrelation(String),
+
+    #[error("invalid value '{value}': {message}")]
+    InvalidValue { value: String, message: String },
+}
+
+impl RuleError {
+    pub fn io(path: &str, error: std::io::Error) -> RuleError {
+        RuleError::Io {
+            path: path.to_string(),
+            kind: error.kind(),
+            message: error.to_string(),
+        }
+    }
+
+    /// Lines and columns start at 1, as shown by editors. Errors without a location (i.e. an empty document) are reported at 0.
+    pub fn yaml(path: &str, error: serde_yaml::Error) -> RuleError {
+        let (line, column) = error.location().map_or((0, 0), |location| (location.line(), location.column()));
+
+        RuleError::Yaml {
+            path: path.to_string(),
+            line,
+            column,
+            message: error.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn yaml_error_location() {
+        let error = serde_yaml::from_str::<serde_yaml::Value>("title: test\ndetection: [unclosed\n").unwrap_err();
+        match RuleError::yaml("rule.yml", error) {
+            RuleError::Yaml { path, line, column, .. } => {
+                assert_eq!(path, "rule.yml");
+                assert_eq!(line, 3);
+                assert!(column >= 1);
+            }
+            error => panic!("unexpected error {:?}", error),
+        }
+    }
+
+    #[test]
+    fn error_messages() {
+        assert_eq!(
+            RuleError::UnknownSearchIdentifier("filter".to_string()).to_string(),
+            "search identifier 'filter' is not defined in the detection"
+        );
+        assert_eq!(RuleError::UnsupportedModifier("expand".to_string()).to_string(), "unsupported value modifier 'expand'");
+    }
+}
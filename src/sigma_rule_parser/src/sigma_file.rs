@@ -1,2 +1,3 @@
+pub mod load_report;
 pub mod sigma_rule;
 mod yml;
\ No newline at end of file
@@ -0,0 +1,192 @@
+use std::fmt::{Display, Formatter};
+use serde::Serialize;
+use crate::rule_error::RuleError;
+use crate::structs::sigma_rule::SigmaRule;
+
+/// The outcome of loading every Sigma rule file in a rules directory.
+/// Each rule that was read has an entry, and a file that could not be parsed has a single entry without a rule id.
+#[derive(Default, Serialize, PartialEq, Debug, Clone)]
+pub struct LoadReport {
+    pub entries: Vec<LoadReportEntry>,
+}
+
+#[derive(Serialize, PartialEq, Debug, Clone)]
+pub struct LoadReportEntry {
+    pub path: String,
+    pub id: Option<String>,
+    pub title: Option<String>,
+    pub status: LoadStatus,
+    pub reason: Option<String>,
+}
+
+#[derive(Serialize, PartialEq, Eq, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum LoadStatus {
+    Loaded,
+    SkippedInvalid,
+    FailedToParse,
+    FailedToBuild,
+}
+
+/// The number of entries of each LoadStatus.
+#[derive(Default, Serialize, PartialEq, Eq, Debug, Clone, Copy)]
+pub struct LoadSummary {
+    pub loaded: usize,
+    pub skipped_invalid: usize,
+    pub failed_to_parse: usize,
+    pub failed_to_build: usize,
+}
+
+impl LoadReport {
+    /// Rules are only loaded once they are built, so a rule is reported as loaded or as failed to build by whoever builds it.
+    pub fn loaded(&mut self, rule: &SigmaRule) {
+        self.entries.push(LoadReportEntry::new(&rule.path, Some(rule), LoadStatus::Loaded, None));
+    }
+
+    pub fn skipped_invalid(&mut self, path: &str, rule: &SigmaRule, error: &RuleError) {
+        self.entries.push(LoadReportEntry::new(path, Some(rule), LoadStatus::SkippedInvalid, Some(error)));
+    }
+
+    pub fn failed_to_parse(&mut self, path: &str, error: &RuleError) {
+        self.entries.push(LoadReportEntry::new(path, None, LoadStatus::FailedToParse, Some(error)));
+    }
+
+    pub fn failed_to_build(&mut self, rule: &SigmaRule, error: &RuleError) {
+        self.entries.push(LoadReportEntry::new(&rule.path, Some(rule), LoadStatus::FailedToBuild, Some(error)));
+    }
+
+    pub fn summary(&self) -> LoadSummary {
+        let mut summary = LoadSummary::default();
+        for entry in &self.entries {
+            match entry.status {
+                LoadStatus::Loaded => summary.loaded += 1,
+                LoadStatus::SkippedInvalid => summary.skipped_invalid += 1,
+                LoadStatus::FailedToParse => summary.failed_to_parse += 1,
+                LoadStatus::FailedToBuild => summary.failed_to_build += 1,
+            }
+        }
+        summary
+    }
+
+    /// The entries that were not loaded, with the reason of each.
+    pub fn failures(&self) -> impl Iterator<Item = &LoadReportEntry> {
+        self.entries.iter().filter(|entry| entry.status != LoadStatus::Loaded)
+    }
+
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        #[derive(Serialize)]
+        struct JsonReport<'a> {
+            summary: LoadSummary,
+            entries: &'a [LoadReportEntry],
+        }
+
+        serde_json::to_string_pretty(&JsonReport { summary: self.summary(), entries: &self.entries })
+    }
+}
+
+impl LoadReportEntry {
+    fn new(path: &str, rule: Option<&SigmaRule>, status: LoadStatus, error: Option<&RuleError>) -> Self {
+        Self {
+            path: path.to_string(),
+            id: rule.map(|rule| rule.id.clone()),
+            title: rule.map(|rule| rule.title.clone()),
+            status,
+            reason: error.map(RuleError::to_string),
+        }
+    }
+}
+
+impl Display for LoadStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let status = match self {
+            LoadStatus::Loaded => "loaded",
+            LoadStatus::SkippedInvalid => "skipped_invalid",
+            LoadStatus::FailedToParse => "failed_to_parse",
+            LoadStatus::FailedToBuild => "failed_to_build",
+        };
+        f.pad(status)
+    }
+}
+
+/// Prints the rules that were not loaded as a table, followed by the summary. Loaded rules are only counted, as there are thousands of them.
+impl Display for LoadReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let failures = self.failures().collect::<Vec<&LoadReportEntry>>();
+        if !failures.is_empty() {
+            let path_width = failures.iter().map(|entry| entry.path.len()).max().unwrap_or(0).max("FILE".len());
+            let id_width = failures.iter().map(|entry| entry.id.as_deref().unwrap_or("-").len()).max().unwrap_or(0).max("RULE".len());
+
+            writeln!(f, "{:<15}  {:<path_width$}  {:<id_width$}  REASON", "STATUS", "FILE", "RULE")?;
+            for entry in failures {
+                writeln!(
+                    f,
+                    "{:<15}  {:<path_width$}  {:<id_width$}  {}",
+                    entry.status,
+                    entry.path,
+                    entry.id.as_deref().unwrap_or("-"),
+                    entry.reason.as_deref().unwrap_or("-"),
+                )?;
+            }
+            writeln!(f)?;
+        }
+
+        let summary = self.summary();
+        write!(
+            f,
+            "loaded: {}, skipped_invalid: {}, failed_to_parse: {}, failed_to_build: {}",
+            summary.loaded, summary.skipped_invalid, summary.failed_to_parse, summary.failed_to_build
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(id: &str) -> SigmaRule {
+        SigmaRule { id: id.to_string(), title: format!("Rule {}", id), path: format!("rules/{}.yml", id), ..Default::default() }
+    }
+
+    fn report() -> LoadReport {
+        let mut report = LoadReport::default();
+        report.loaded(&rule("a"));
+        report.failed_to_build(&rule("b"), &RuleError::UnsupportedModifier("expand".to_string()));
+        report.skipped_invalid("rules/c.yml", &rule(""), &RuleError::MissingField("id".to_string()));
+        report.failed_to_parse("rules/d.yml", &RuleError::MissingCondition);
+        report
+    }
+
+    #[test]
+    fn summary_counts_each_status() {
+        let report = report();
+        assert_eq!(
+            report.summary(),
+            LoadSummary { loaded: 1, skipped_invalid: 1, failed_to_parse: 1, failed_to_build: 1 }
+        );
+
+        let failed_to_build = &report.entries[1];
+        assert_eq!(failed_to_build.status, LoadStatus::FailedToBuild);
+        assert_eq!(failed_to_build.path, "rules/b.yml");
+        assert_eq!(failed_to_build.reason, Some("unsupported value modifier 'expand'".to_string()));
+    }
+
+    #[test]
+    fn table_lists_failures_and_summary() {
+        let table = report().to_string();
+        let lines = table.lines().collect::<Vec<&str>>();
+
+        assert!(lines[0].starts_with("STATUS"));
+        assert_eq!(lines.len(), 6);
+        assert!(lines[1].starts_with("failed_to_build  rules/b.yml  b"));
+        assert!(lines[3].contains("rules/d.yml") && lines[3].contains("detection does not have a condition"));
+        assert_eq!(lines[5], "loaded: 1, skipped_invalid: 1, failed_to_parse: 1, failed_to_build: 1");
+    }
+
+    #[test]
+    fn json_report() {
+        let json: serde_json::Value = serde_json::from_str(&report().to_json().unwrap()).unwrap();
+        assert_eq!(json["summary"]["failed_to_parse"], 1);
+        assert_eq!(json["entries"][2]["status"], "skipped_invalid");
+        assert_eq!(json["entries"][3]["id"], serde_json::Value::Null);
+    }
+}
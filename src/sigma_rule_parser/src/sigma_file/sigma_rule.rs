@@ -1,93 +1,143 @@
-use anyhow::Error;
 use log::info;
 use std::fs::File;
 use std::io::BufReader;
 use walkdir::WalkDir;
+use crate::rule_error::RuleError;
+use crate::sigma_file::load_report::LoadReport;
 use crate::sigma_file::yml::is_yml;
-use crate::structs::detection_logic::DetectionLogic;
+use serde::Deserialize;
 use crate::structs::sigma_rule::{SigmaRule, YmlTypes};
 
 
-pub fn process_sigma_rules<'de>(rules_dir: String) -> Result<Vec<SigmaRule>, Error> {
+/// Reads every Sigma rule in the rules directory, or the single rule file when a file is given.
+/// Files and rules that can not be loaded are skipped, and the RuleError of each is logged.
+/// An error is only returned when the rules directory itself can not be read.
+pub fn process_sigma_rules(rules_dir: String) -> Result<Vec<SigmaRule>, RuleError> {
+    load_sigma_rules(rules_dir).map(|(sigma_rules, _)| sigma_rules)
+}
+
+/// The same as process_sigma_rules, but also returns the LoadReport with the reason each file or rule was skipped.
+/// The rules that were read are reported once they are built, and keep the path of their file for it.
+pub fn load_sigma_rules(rules_dir: String) -> Result<(Vec<SigmaRule>, LoadReport), RuleError> {
+    std::fs::metadata(&rules_dir).map_err(|error| RuleError::io(&rules_dir, error))?;
+
     let mut sigma_rules = Vec::new();
+    let mut report = LoadReport::default();
     for file in WalkDir::new(rules_dir)
         .into_iter()
         .filter_map(|file| file.ok())
     {
-        if file.metadata().unwrap().is_file() && is_yml(&file) {
+        if file.file_type().is_file() && is_yml(&file) {
             let file_path = &file.path().display().to_string();
-            let sigma_rule = read_rule_file(file_path);
 
-            match sigma_rule {
-                Ok(rule) => {
-                    if initial_rule_validation(&rule) {
-                        sigma_rules.push(rule)
-                    } else {
-                        info!("Rule is invalid. Please check required fields at https://github.com/SigmaHQ/sigma/wiki/Specification for {}.", file_path);
-                        continue;
-                    }
-                }
+            let rules = match read_rule_file(file_path) {
+                Ok(rules) => rules,
                 Err(error) => {
                     info!("Error loading rule {}. - {}", file_path, error);
+                    report.failed_to_parse(file_path, &error);
                     continue; // skip to the next rule
                 }
+            };
+
+            for mut rule in rules {
+                match initial_rule_validation(&rule) {
+                    Ok(()) => {
+                        rule.path = file_path.clone();
+                        sigma_rules.push(rule)
+                    }
+                    Err(error) => {
+                        info!("Rule is invalid. Please check required fields at https://github.com/SigmaHQ/sigma/wiki/Specification for {}. - {}", file_path, error);
+                        report.skipped_invalid(file_path, &rule, &error);
+                    }
+                }
             }
         }
     }
 
-    Ok(sigma_rules)
+    Ok((sigma_rules, report))
 }
 
 
-// TODO: Update all consumers of read_rule_file to propagate error and skip to the next Sigma rule file
-fn read_rule_file(file_path: &str) -> Result<SigmaRule, Error> {
-    let file = File::open(file_path.clone()).unwrap();
+/// Reads every Sigma rule in a yml file. A file may hold multiple yml documents separated by "---",
+///     i.e. a correlation rule followed by the rules it references.
+fn read_rule_file(file_path: &str) -> Result<Vec<SigmaRule>, RuleError> {
+    let file = File::open(file_path).map_err(|error| RuleError::io(file_path, error))?;
     let reader = BufReader::new(file);
-    let de_yml = serde_yaml::from_reader::<BufReader<File>, SigmaRule>(reader).unwrap();
-    // info!(" = {:?}", de_yml);
 
-    Ok(de_yml)
+    let mut sigma_rules = Vec::new();
+    for document in serde_yaml::Deserializer::from_reader(reader) {
+        sigma_rules.push(SigmaRule::deserialize(document).map_err(|error| RuleError::yaml(file_path, error))?);
+    }
+
+    Ok(sigma_rules)
 }
 
 // https://github.com/SigmaHQ/sigma/wiki/Specification#value-modifiers
-fn initial_rule_validation(rule: &SigmaRule) -> bool {
-    if rule.title == "" || rule.id == "" || rule.detection.is_empty() {
-        return false;
+// Correlation rules do not have a detection, they must reference at least one rule instead
+fn initial_rule_validation(rule: &SigmaRule) -> Result<(), RuleError> {
+    let has_detection = match rule.correlation.as_ref() {
+        Some(correlation) => !correlation.rules.is_empty(),
+        None => !rule.detection.is_empty(),
+    };
+
+    if rule.title.is_empty() {
+        return Err(RuleError::MissingField("title".to_string()));
+    }
+    if rule.id.is_empty() {
+        return Err(RuleError::MissingField("id".to_string()));
+    }
+    if !has_detection {
+        let field = if rule.is_correlation() { "correlation.rules" } else { "detection" };
+        return Err(RuleError::MissingField(field.to_string()));
     }
 
-    return true;
+    Ok(())
 }
 
-/// Conditions are returned by the yml processor as the Enum DetectionTypes.
-/// This method extracts the type that the value is stored in and stringifies the value.
-/// TODO: Turn this into a Result<&str, Error> response, handle error by continuing to next rule and outputting error message
-pub fn read_condition(condition: &YmlTypes) -> &str {
-    let condition_value = match condition {
-        YmlTypes::Boolean(condition) => stringify!(condition),
-        YmlTypes::Number(condition) => stringify!(condition),
-        YmlTypes::String(condition) => condition as &str,
-        YmlTypes::Sequence(_) => "",
-        YmlTypes::Mapping(_) => "",
-        _ => ""
-    };
+/// The conditions of a rule, which is either a single condition or a list of conditions of which any may match.
+/// A condition that is not a string, i.e. a mapping or a number, is a RuleError::InvalidCondition.
+pub fn read_conditions(condition: &YmlTypes) -> Result<Vec<&str>, RuleError> {
+    match condition {
+        YmlTypes::String(condition) => Ok(vec![condition]),
+        YmlTypes::Sequence(conditions) if !conditions.is_empty() => conditions
+            .iter()
+            .map(|condition| match condition {
+                YmlTypes::String(condition) => Ok(condition.as_str()),
+                condition => Err(invalid_condition(condition)),
+            })
+            .collect(),
+        YmlTypes::Null => Err(RuleError::MissingCondition),
+        condition => Err(invalid_condition(condition)),
+    }
+}
 
-    condition_value
+/// The condition of a rule as a single expression, where a list of conditions is joined as "(c1) or (c2)".
+pub fn read_condition(condition: &YmlTypes) -> Result<String, RuleError> {
+    let conditions = read_conditions(condition)?;
+    match conditions.as_slice() {
+        [condition] => Ok(condition.to_string()),
+        conditions => Ok(conditions.iter().map(|condition| format!("({})", condition)).collect::<Vec<String>>().join(" or ")),
+    }
+}
+
+fn invalid_condition(condition: &YmlTypes) -> RuleError {
+    let condition = serde_yaml::to_string(condition).unwrap_or_default();
+    RuleError::InvalidCondition(condition.trim().to_string())
 }
 
 
 #[cfg(test)]
 mod tests {
-    use std::env::current_dir;
-    use serde_yaml::{Mapping, Number, Sequence};
     use super::*;
+    use crate::sigma_file::load_report::LoadStatus;
 
 
     #[test]
-    fn read_rule_yml_file_and_validate_title() -> Result<(), Error> {
+    fn read_rule_yml_file_and_validate_title() -> Result<(), RuleError> {
         let rule = read_rule_file("src/sigma_file/test/assets/mimikatz.yml");
-        assert_eq!(rule.is_ok(), true, "yml returns as SigmaRule struct");
+        assert!(rule.is_ok(), "yml returns as SigmaRule struct");
         assert_eq!(
-            rule?.title, "Mimikatz through Windows Remote Management",
+            rule?[0].title, "Mimikatz through Windows Remote Management",
             "Validate title"
         );
         Ok(())
@@ -97,19 +147,19 @@ mod tests {
     // An invalid rule is one that is missing a required field, such as the title.
     // Read more about rule formatting here - https://github.com/SigmaHQ/sigma/wiki/Specification
     #[test]
-    fn read_rule_yml_file_handles_invalid_rule() -> Result<(), Error> {
+    fn read_rule_yml_file_handles_invalid_rule() -> Result<(), RuleError> {
         let rule =
             read_rule_file("src/sigma_file/test/assets/invalid_rules/invalid_title.yml");
-        assert_eq!(rule.is_ok(), true, "yml returns as SigmaRule struct");
-        assert_eq!(rule?.title, "", "Validate title is empty string");
+        assert!(rule.is_ok(), "yml returns as SigmaRule struct");
+        assert_eq!(rule?[0].title, "", "Validate title is empty string");
         Ok(())
     }
 
     #[test]
-    fn retrieve_all_sigma_yml_rules_in_dir() -> Result<(), Error> {
+    fn retrieve_all_sigma_yml_rules_in_dir() -> Result<(), RuleError> {
         let sigma_rules =
             process_sigma_rules("src/sigma_file/test/assets/do_not_modify_folder".to_string());
-        assert_eq!(sigma_rules.is_ok(), true, "Sigma Rule vec is ok");
+        assert!(sigma_rules.is_ok(), "Sigma Rule vec is ok");
         assert_eq!(
             sigma_rules?.len(),
             1,
@@ -119,49 +169,102 @@ mod tests {
     }
 
     #[test]
-    fn valid_rule_initial_validation() -> Result<(), Error> {
+    fn load_sigma_rules_reports_invalid_rules() -> Result<(), RuleError> {
+        let (sigma_rules, report) = load_sigma_rules("src/sigma_file/test/assets/invalid_rules".to_string())?;
+        assert!(sigma_rules.is_empty());
+        assert_eq!(report.summary().skipped_invalid, 3);
+        assert!(report.entries.iter().all(|entry| entry.status == LoadStatus::SkippedInvalid && entry.reason.is_some()));
+        Ok(())
+    }
+
+    #[test]
+    fn valid_rule_initial_validation() -> Result<(), RuleError> {
         let rule = read_rule_file("src/sigma_file/test/assets/mimikatz.yml");
-        assert_eq!(rule.is_ok(), true, "yml returns as SigmaRule struct");
+        assert!(rule.is_ok(), "yml returns as SigmaRule struct");
 
-        let is_valid = initial_rule_validation(&rule.unwrap());
-        assert_eq!(is_valid, true, "Sigma rule is valid");
+        let is_valid = initial_rule_validation(&rule.unwrap()[0]);
+        assert_eq!(is_valid, Ok(()), "Sigma rule is valid");
         Ok(())
     }
 
     #[test]
-    fn invalid_title_rule_initial_validation() -> Result<(), Error> {
+    fn invalid_title_rule_initial_validation() -> Result<(), RuleError> {
         let rule =
             read_rule_file("src/sigma_file/test/assets/invalid_rules/invalid_title.yml");
-        assert_eq!(rule.is_ok(), true, "yml returns as SigmaRule struct");
+        assert!(rule.is_ok(), "yml returns as SigmaRule struct");
 
-        let is_invalid = initial_rule_validation(&rule.unwrap());
-        assert_eq!(is_invalid, false, "Sigma rule is invalid due to the title");
+        let is_invalid = initial_rule_validation(&rule.unwrap()[0]);
+        assert_eq!(is_invalid, Err(RuleError::MissingField("title".to_string())), "Sigma rule is invalid due to the title");
         Ok(())
     }
 
     #[test]
-    fn invalid_id_rule_initial_validation() -> Result<(), Error> {
+    fn invalid_id_rule_initial_validation() -> Result<(), RuleError> {
         let rule =
             read_rule_file("src/sigma_file/test/assets/invalid_rules/invalid_id.yml");
-        assert_eq!(rule.is_ok(), true, "yml returns as SigmaRule struct");
+        assert!(rule.is_ok(), "yml returns as SigmaRule struct");
 
-        let is_invalid = initial_rule_validation(&rule.unwrap());
-        assert_eq!(is_invalid, false, "Sigma rule is invalid  due to the id");
+        let is_invalid = initial_rule_validation(&rule.unwrap()[0]);
+        assert_eq!(is_invalid, Err(RuleError::MissingField("id".to_string())), "Sigma rule is invalid  due to the id");
         Ok(())
     }
 
     #[test]
-    fn invalid_detection_rule_initial_validation() -> Result<(), Error> {
+    fn invalid_detection_rule_initial_validation() -> Result<(), RuleError> {
         let rule = read_rule_file(
             "src/sigma_file/test/assets/invalid_rules/invalid_detection.yml",
         );
-        assert_eq!(rule.is_ok(), true, "yml returns as SigmaRule struct");
+        assert!(rule.is_ok(), "yml returns as SigmaRule struct");
 
-        let is_invalid = initial_rule_validation(&rule.unwrap());
+        let is_invalid = initial_rule_validation(&rule.unwrap()[0]);
         assert_eq!(
-            is_invalid, false,
+            is_invalid,
+            Err(RuleError::MissingField("detection".to_string())),
             "Sigma rule is invalid due to the detection"
         );
         Ok(())
     }
+
+    #[test]
+    fn read_multi_document_rule_file() -> Result<(), RuleError> {
+        let rules = read_rule_file("src/sigma_file/test/assets/correlation/failed_logons.yml")?;
+        assert_eq!(rules.len(), 2);
+
+        assert!(rules[0].is_correlation());
+        assert_eq!(initial_rule_validation(&rules[0]), Ok(()), "Correlation rule is valid without a detection");
+        assert!(rules[1].is_referenced_by("failed_logon"));
+        assert!(rules[1].is_referenced_by(&rules[1].id));
+        assert_eq!(initial_rule_validation(&rules[1]), Ok(()));
+        Ok(())
+    }
+
+    #[test]
+    fn read_conditions_of_every_yml_type() {
+        let condition = |yml: &str| serde_yaml::from_str::<YmlTypes>(yml).unwrap();
+
+        assert_eq!(read_condition(&condition("selection and not filter")), Ok("selection and not filter".to_string()));
+        assert_eq!(read_condition(&condition("[selection | count() by IpAddress > 10]")), Ok("selection | count() by IpAddress > 10".to_string()));
+        assert_eq!(read_conditions(&condition("[selection1, selection2 and not filter]")), Ok(vec!["selection1", "selection2 and not filter"]));
+        assert_eq!(read_condition(&condition("[selection1, selection2 and not filter]")), Ok("(selection1) or (selection2 and not filter)".to_string()));
+
+        assert_eq!(read_condition(&condition("true")), Err(RuleError::InvalidCondition("true".to_string())));
+        assert_eq!(read_condition(&condition("10")), Err(RuleError::InvalidCondition("10".to_string())));
+        assert_eq!(read_condition(&condition("{selection: filter}")), Err(RuleError::InvalidCondition("selection: filter".to_string())));
+        assert_eq!(read_condition(&condition("[selection, [filter]]")), Err(RuleError::InvalidCondition("- filter".to_string())));
+        assert_eq!(read_condition(&condition("[]")), Err(RuleError::InvalidCondition("[]".to_string())));
+        assert_eq!(read_condition(&condition("null")), Err(RuleError::MissingCondition));
+    }
+
+    #[test]
+    fn read_rule_file_that_does_not_exist() {
+        match read_rule_file("src/sigma_file/test/assets/does_not_exist.yml") {
+            Err(RuleError::Io { kind, .. }) => assert_eq!(kind, std::io::ErrorKind::NotFound),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn process_rules_dir_that_does_not_exist() {
+        assert!(matches!(process_sigma_rules("src/sigma_file/test/does_not_exist".to_string()), Err(RuleError::Io { .. })));
+    }
 }
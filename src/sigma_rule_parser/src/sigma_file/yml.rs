@@ -20,7 +20,7 @@ mod test_yml_deserialization {
             .filter_map(|file| file.ok())
         {
             let is_yml = is_yml(&file);
-            assert_eq!(is_yml, true, "Is a yml file")
+            assert!(is_yml, "Is a yml file")
         }
     }
 
@@ -31,7 +31,7 @@ mod test_yml_deserialization {
             .filter_map(|file| file.ok())
         {
             let is_yml = is_yml(&file);
-            assert_eq!(is_yml, true, "Is a yml file")
+            assert!(is_yml, "Is a yml file")
         }
     }
 
@@ -42,7 +42,7 @@ mod test_yml_deserialization {
             .filter_map(|file| file.ok())
         {
             let is_yml = is_yml(&file);
-            assert_eq!(is_yml, false, "Is not a yml file")
+            assert!(!is_yml, "Is not a yml file")
         }
     }
 }
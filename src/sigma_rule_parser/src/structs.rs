@@ -1,5 +1,8 @@
+pub mod aggregation;
+pub mod correlation;
 pub mod detection_condition;
 pub mod detection;
+pub mod detection_field;
 pub mod detection_logic;
 pub mod detection_metadata;
 pub mod sigma_rule;
@@ -0,0 +1,56 @@
+use std::time::Duration;
+
+/// The Aggregation of a Sigma rule is the expression that follows the pipe in the condition, i.e. `selection | count(User) by SourceIp > 10`
+/// The detection must match first, then the events that matched are aggregated per group within the timeframe of the rule.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Aggregation {
+    pub function: AggregationFunction,
+    pub field: Option<String>,
+    pub group_by: Option<String>,
+    pub operator: ComparisonOperator,
+    pub threshold: f64,
+    pub timeframe: Option<Duration>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AggregationFunction {
+    Count,
+    Min,
+    Max,
+    Avg,
+    Sum,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ComparisonOperator {
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+    Eq,
+}
+
+impl Aggregation {
+    pub fn init() -> Aggregation {
+        Aggregation {
+            function: AggregationFunction::Count,
+            field: None,
+            group_by: None,
+            operator: ComparisonOperator::Gt,
+            threshold: 0.0,
+            timeframe: None,
+        }
+    }
+}
+
+impl ComparisonOperator {
+    pub fn compare(&self, value: f64, threshold: f64) -> bool {
+        match self {
+            ComparisonOperator::Lt => value < threshold,
+            ComparisonOperator::Lte => value <= threshold,
+            ComparisonOperator::Gt => value > threshold,
+            ComparisonOperator::Gte => value >= threshold,
+            ComparisonOperator::Eq => value == threshold,
+        }
+    }
+}
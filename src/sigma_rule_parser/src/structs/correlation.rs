@@ -0,0 +1,98 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+
+/// The correlation field of a Sigma v2 correlation rule.
+/// A correlation rule does not have a detection of its own, it references other rules by their name or id
+///     and matches when the events of those rules occur together within the timespan, per group.
+/// https://github.com/SigmaHQ/sigma-specification/blob/main/specification/sigma-correlation-rules-specification.md
+#[derive(Default, Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(default)]
+pub struct Correlation {
+    #[serde(rename = "type")]
+    pub correlation_type: CorrelationType,
+    pub rules: Vec<String>,
+    #[serde(rename = "group-by")]
+    pub group_by: Vec<String>,
+    pub timespan: String,
+    pub condition: Option<CorrelationCondition>,
+    /// Maps a field name that is used in group-by to the field name of each referenced rule, i.e. {"user": {"failed_logon": "TargetUserName"}}
+    pub aliases: BTreeMap<String, BTreeMap<String, String>>,
+    pub generate: bool,
+}
+
+#[derive(Default, Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum CorrelationType {
+    #[default]
+    EventCount,
+    ValueCount,
+    Temporal,
+    TemporalOrdered,
+}
+
+/// The condition of a correlation rule, i.e. `gte: 100`. Multiple comparisons form a range: `gt: 10, lte: 20`
+/// value_count correlations also name the field whose distinct values are counted.
+#[derive(Default, Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(default)]
+pub struct CorrelationCondition {
+    pub gt: Option<f64>,
+    pub gte: Option<f64>,
+    pub lt: Option<f64>,
+    pub lte: Option<f64>,
+    pub eq: Option<f64>,
+    pub field: Option<String>,
+}
+
+impl CorrelationCondition {
+    pub fn matches(&self, value: f64) -> bool {
+        self.gt.is_none_or(|gt| value > gt)
+            && self.gte.is_none_or(|gte| value >= gte)
+            && self.lt.is_none_or(|lt| value < lt)
+            && self.lte.is_none_or(|lte| value <= lte)
+            && self.eq.is_none_or(|eq| value == eq)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.gt.is_none() && self.gte.is_none() && self.lt.is_none() && self.lte.is_none() && self.eq.is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_correlation() {
+        let correlation: Correlation = serde_yaml::from_str(r#"
+            type: value_count
+            rules:
+                - failed_logon
+            group-by:
+                - ComputerName
+            timespan: 15m
+            condition:
+                gte: 10
+                field: TargetUserName
+        "#).unwrap();
+
+        assert_eq!(correlation.correlation_type, CorrelationType::ValueCount);
+        assert_eq!(correlation.rules, vec!["failed_logon".to_string()]);
+        assert_eq!(correlation.group_by, vec!["ComputerName".to_string()]);
+        assert_eq!(correlation.timespan, "15m");
+
+        let condition = correlation.condition.unwrap();
+        assert_eq!(condition.field, Some("TargetUserName".to_string()));
+        assert!(condition.matches(10.0));
+        assert!(!condition.matches(9.0));
+    }
+
+    #[test]
+    fn condition_ranges() {
+        let condition = CorrelationCondition { gt: Some(10.0), lte: Some(20.0), ..Default::default() };
+        assert!(!condition.matches(10.0));
+        assert!(condition.matches(15.0));
+        assert!(condition.matches(20.0));
+        assert!(!condition.matches(21.0));
+        assert!(CorrelationCondition::default().is_empty());
+    }
+}
@@ -0,0 +1,93 @@
+use crate::rule_error::RuleError;
+use std::str::FromStr;
+
+/// The DetectionField struct is the key of a DetectionLogic mapping, i.e. `TargetFilename|contains|all`.
+/// The name of the field is compared to the Log (and Field Mappings), while the modifiers change how the values are compared.
+/// https://github.com/SigmaHQ/sigma/wiki/Specification#value-modifiers
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DetectionField {
+    pub name: String,
+    pub modifiers: Vec<Modifier>,
+}
+
+/// Modifiers are applied in the order they are written in the Sigma rule.
+///     - Transformation modifiers (base64, utf16le, windash, ...) change the value of the Sigma rule before it is compared
+///     - Comparison modifiers (contains, re, cidr, lt, ...) change how the transformed value is compared to the Log
+///     - The all modifier requires every value of a list to match, instead of any value
+///     - The cased modifier compares the values case-sensitively, as encoded values are
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Modifier {
+    Contains,
+    StartsWith,
+    EndsWith,
+    All,
+    Re,
+    Base64,
+    Base64Offset,
+    Utf16le,
+    Utf16be,
+    Utf16,
+    Wide,
+    Windash,
+    Cidr,
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+    Exists,
+    Cased,
+}
+
+impl DetectionField {
+    pub fn init() -> DetectionField {
+        DetectionField {
+            name: String::from(""),
+            modifiers: Vec::new(),
+        }
+    }
+
+    pub fn new(name: String, modifiers: Vec<Modifier>) -> Self {
+        Self { name, modifiers }
+    }
+
+    pub fn has_modifier(&self, modifier: &Modifier) -> bool {
+        self.modifiers.contains(modifier)
+    }
+}
+
+impl From<&str> for DetectionField {
+    fn from(name: &str) -> Self {
+        DetectionField::new(name.to_string(), Vec::new())
+    }
+}
+
+impl FromStr for Modifier {
+    type Err = RuleError;
+
+    fn from_str(modifier: &str) -> Result<Self, Self::Err> {
+        let modifier = match modifier.to_lowercase().as_str() {
+            "contains" => Modifier::Contains,
+            "startswith" => Modifier::StartsWith,
+            "endswith" => Modifier::EndsWith,
+            "all" => Modifier::All,
+            "re" => Modifier::Re,
+            "base64" => Modifier::Base64,
+            "base64offset" => Modifier::Base64Offset,
+            "utf16le" => Modifier::Utf16le,
+            "utf16be" => Modifier::Utf16be,
+            "utf16" => Modifier::Utf16,
+            "wide" => Modifier::Wide,
+            "windash" => Modifier::Windash,
+            "cidr" => Modifier::Cidr,
+            "lt" => Modifier::Lt,
+            "lte" => Modifier::Lte,
+            "gt" => Modifier::Gt,
+            "gte" => Modifier::Gte,
+            "exists" => Modifier::Exists,
+            "cased" => Modifier::Cased,
+            _ => return Err(RuleError::UnsupportedModifier(modifier.to_string())),
+        };
+
+        Ok(modifier)
+    }
+}
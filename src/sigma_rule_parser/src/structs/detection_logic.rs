@@ -1,10 +1,12 @@
 use std::collections::BTreeMap;
+use crate::structs::detection_field::DetectionField;
 
 /// The name of the field corresponds to the logic that will take place when comparing a Detection to a Log.
-/// The key for "and" and "key_value" fields is used to compare the Detection to the Field Mappings, and the Log to the Field Mappings.
+/// The key for "and" is the field name and its modifiers, and is used to compare the Detection to the Field Mappings, and the Log to the Field Mappings.
+/// A value that is null in the yml has neither "and", "or" nor "value", and matches a field that is missing or null, while '' matches an empty string.
 #[derive(Clone, Debug, PartialEq)]
 pub struct DetectionLogic {
-    pub and: Option<BTreeMap<String, DetectionLogic>>,
+    pub and: Option<BTreeMap<DetectionField, DetectionLogic>>,
     pub or: Option<Vec<DetectionLogic>>,
     pub value: Option<String>,
 }
@@ -17,4 +19,12 @@ impl DetectionLogic {
             value: None,
         }
     }
+
+    pub fn null() -> DetectionLogic {
+        DetectionLogic::init()
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.and.is_none() && self.or.is_none() && self.value.is_none()
+    }
 }
@@ -1,5 +1,6 @@
 use std::collections::BTreeMap;
 use serde::{Deserialize, Serialize};
+use crate::structs::correlation::Correlation;
 
 // Next Steps:
 // Add DetectionLogic to DetectionCondition
@@ -13,6 +14,9 @@ pub struct SigmaRule {
     pub title: String,
     #[serde(default)]
     pub id: String,
+    /// Unique name that correlation rules may use to reference this rule, instead of the id
+    #[serde(default)]
+    pub name: String,
     #[serde(default)]
     pub status: String,
     #[serde(default)]
@@ -33,12 +37,29 @@ pub struct SigmaRule {
     pub related: Vec<YmlTypes>,
     #[serde(default)]
     pub detection: BTreeMap<String, YmlTypes>,
+    /// Only set for Sigma correlation rules, which do not have a detection
+    #[serde(default)]
+    pub correlation: Option<Correlation>,
     #[serde(default)]
     pub fields: Vec<String>,
     #[serde(default)]
     pub falsepositives: Vec<String>,
     #[serde(default)]
     pub level: String,
+    /// The file the rule was read from, which is not a field of the rule itself
+    #[serde(skip)]
+    pub path: String,
+}
+
+impl SigmaRule {
+    pub fn is_correlation(&self) -> bool {
+        self.correlation.is_some()
+    }
+
+    /// Returns true when a reference of a correlation rule is the name or the id of this rule.
+    pub fn is_referenced_by(&self, reference: &str) -> bool {
+        (!self.name.is_empty() && self.name == reference) || (!self.id.is_empty() && self.id == reference)
+    }
 }
 
 #[derive(Default, Serialize, Deserialize, PartialEq, Debug, Clone)]